@@ -0,0 +1,170 @@
+//! Measures the throughput of the `INSTREAM` framing over a connection which discards what is
+//! written, and checks that the content is written straight from the chunks of the input.
+//!
+//! Run with `cargo bench --bench instream`.
+
+use bytes::Bytes;
+use clamav_stream::{protocol::ChunkSize, AsyncScannedStream, Error, ScannedStream};
+use std::{
+    io::{self, Cursor, IoSlice, Read, Write},
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_stream::StreamExt;
+
+const CONTENT_LEN: usize = 8 * 1024 * 1024;
+const INPUT_CHUNK_LEN: usize = 256 * 1024;
+const ROUNDS: usize = 32;
+
+/// A clamav which discards the request, counting the bytes written from the memory of the
+/// content, and replies `stream: OK` once the terminating chunk has been written.
+struct Sink {
+    content: Range<usize>,
+    borrowed: usize,
+    ended: bool,
+    reply: Cursor<&'static [u8]>,
+    reader: Option<Waker>,
+}
+
+impl Sink {
+    fn new(content: &[u8]) -> Self {
+        let start = content.as_ptr() as usize;
+        Self {
+            content: start..start + content.len(),
+            borrowed: 0,
+            ended: false,
+            reply: Cursor::new(b"stream: OK\0"),
+            reader: None,
+        }
+    }
+
+    fn record(&mut self, bufs: &[IoSlice<'_>]) -> usize {
+        for buf in bufs {
+            let start = buf.as_ptr() as usize;
+            if self.content.contains(&start) {
+                self.borrowed += buf.len();
+            }
+            self.ended = **buf == [0, 0, 0, 0];
+        }
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+        bufs.iter().map(|buf| buf.len()).sum()
+    }
+}
+
+impl Read for Sink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reply.read(buf)
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(self.record(&[IoSlice::new(buf)]))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        Ok(self.record(bufs))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for Sink {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.ended {
+            this.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = this.reply.read(buf.initialize_unfilled())?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Sink {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write_vectored(bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn input(content: &Bytes) -> tokio_stream::Iter<std::vec::IntoIter<Result<Bytes, Error>>> {
+    let chunks = (0..content.len())
+        .step_by(INPUT_CHUNK_LEN)
+        .map(|start| Ok(content.slice(start..content.len().min(start + INPUT_CHUNK_LEN))))
+        .collect::<Vec<_>>();
+    tokio_stream::iter(chunks)
+}
+
+fn report(name: &str, elapsed: Duration, borrowed: usize) {
+    let bytes = (CONTENT_LEN * ROUNDS) as f64;
+    println!(
+        "{name}: {:.0} MiB/s, {borrowed} of {CONTENT_LEN} content bytes written without a copy",
+        bytes / elapsed.as_secs_f64() / (1024.0 * 1024.0),
+    );
+    assert_eq!(borrowed, CONTENT_LEN, "{name} copied the content");
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let content = Bytes::from(vec![0x5a; CONTENT_LEN]);
+    let chunk_size = ChunkSize::new(64 * 1024).unwrap();
+
+    let (mut elapsed, mut borrowed) = (Duration::ZERO, 0);
+    for _ in 0..ROUNDS {
+        let mut sink = Sink::new(&content);
+        let started = Instant::now();
+        let stream = ScannedStream::new(input(&content), &mut sink).with_chunk_size(chunk_size);
+        stream.collect::<Result<Vec<_>, _>>().await.unwrap();
+        elapsed += started.elapsed();
+        borrowed = sink.borrowed;
+    }
+    report("ScannedStream", elapsed, borrowed);
+
+    let (mut elapsed, mut borrowed) = (Duration::ZERO, 0);
+    for _ in 0..ROUNDS {
+        let mut sink = Sink::new(&content);
+        let started = Instant::now();
+        let stream =
+            AsyncScannedStream::new(input(&content), &mut sink).with_chunk_size(chunk_size);
+        stream.collect::<Result<Vec<_>, _>>().await.unwrap();
+        elapsed += started.elapsed();
+        borrowed = sink.borrowed;
+    }
+    report("AsyncScannedStream", elapsed, borrowed);
+}
@@ -0,0 +1,118 @@
+//! A multipart form endpoint which scans every file field while reading it.
+//!
+//! ```sh
+//! docker compose up -d
+//! cargo run --example actix_multipart --features examples
+//! curl -F file=@tests/clean.txt -F file=@tests/eicar.txt localhost:3000/upload
+//! ```
+
+use actix_multipart::Multipart;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use clamav_stream::{Error, Scanner};
+use std::io;
+use tokio_stream::StreamExt;
+
+const CLAMD_ADDRESS: &str = "localhost:3310";
+
+async fn upload(scanner: web::Data<Scanner>, mut form: Multipart) -> HttpResponse {
+    let mut infected = vec![];
+
+    while let Some(field) = form.next().await {
+        let field = match field {
+            Ok(field) => field,
+            Err(err) => return HttpResponse::BadRequest().body(err.to_string()),
+        };
+        let name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .unwrap_or_default()
+            .to_string();
+
+        // A multipart error is not `Send`, unlike the errors a `ScannedStream` returns.
+        let field = field.map(|chunk| chunk.map_err(|err| io::Error::other(err.to_string())));
+        let mut stream = match scanner.wrap(field) {
+            Ok(stream) => stream,
+            Err(err) => return HttpResponse::ServiceUnavailable().body(err.to_string()),
+        };
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                // Store the chunk here.
+                Ok(_) => {}
+                Err(Error::Scan(_)) => infected.push(name.clone()),
+                Err(err) => return HttpResponse::BadGateway().body(err.to_string()),
+            }
+        }
+    }
+
+    match infected.is_empty() {
+        true => HttpResponse::Ok().finish(),
+        false => HttpResponse::UnprocessableEntity().body(infected.join(", ")),
+    }
+}
+
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    let scanner = web::Data::new(Scanner::tcp(CLAMD_ADDRESS).unwrap());
+    HttpServer::new(move || {
+        App::new()
+            .app_data(scanner.clone())
+            .route("/upload", web::post().to(upload))
+    })
+    .bind("127.0.0.1:3000")?
+    .run()
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test};
+
+    const BOUNDARY: &str = "clamav-stream-boundary";
+
+    async fn post_files(paths: &[&str]) -> (StatusCode, String) {
+        let scanner = web::Data::new(Scanner::tcp(CLAMD_ADDRESS).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(scanner)
+                .route("/upload", web::post().to(upload)),
+        )
+        .await;
+
+        let mut body = vec![];
+        for path in paths {
+            body.extend(format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{path}\"\r\n\r\n"
+            ).as_bytes());
+            body.extend(std::fs::read(path).unwrap());
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{BOUNDARY}--\r\n").as_bytes());
+
+        let req = test::TestRequest::post()
+            .uri("/upload")
+            .insert_header((
+                "content-type",
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            ))
+            .set_payload(body)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let status = res.status();
+        let body = test::read_body(res).await;
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[actix_web::test]
+    async fn it_accepts_clean_files() {
+        let (status, _) = post_files(&["tests/clean.txt"]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn it_names_the_infected_files() {
+        let (status, body) = post_files(&["tests/clean.txt", "tests/eicar.txt"]).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body, "tests/eicar.txt");
+    }
+}
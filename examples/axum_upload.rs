@@ -0,0 +1,69 @@
+//! An upload endpoint which scans the request body while reading it.
+//!
+//! ```sh
+//! docker compose up -d
+//! cargo run --example axum_upload --features examples
+//! curl --data-binary @tests/eicar.txt localhost:3000/upload
+//! ```
+
+use axum::{body::Body, extract::State, http::StatusCode, routing::post, Router};
+use clamav_stream::{Error, Scanner};
+use tokio_stream::StreamExt;
+
+const CLAMD_ADDRESS: &str = "localhost:3310";
+
+async fn upload(State(scanner): State<Scanner>, body: Body) -> (StatusCode, String) {
+    let mut stream = match scanner.wrap(body.into_data_stream()) {
+        Ok(stream) => stream,
+        Err(err) => return (StatusCode::SERVICE_UNAVAILABLE, err.to_string()),
+    };
+
+    let mut len = 0;
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            // Store the chunk here.
+            Ok(chunk) => len += chunk.len(),
+            Err(Error::Scan(message)) => return (StatusCode::UNPROCESSABLE_ENTITY, message),
+            Err(err) => return (StatusCode::BAD_GATEWAY, err.to_string()),
+        }
+    }
+    (StatusCode::OK, format!("{len} bytes stored"))
+}
+
+fn app(scanner: Scanner) -> Router {
+    Router::new()
+        .route("/upload", post(upload))
+        .with_state(scanner)
+}
+
+#[tokio::main]
+async fn main() {
+    let scanner = Scanner::tcp(CLAMD_ADDRESS).unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
+        .await
+        .unwrap();
+    axum::serve(listener, app(scanner)).await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn post_file(path: &str) -> (StatusCode, String) {
+        let scanner = Scanner::tcp(CLAMD_ADDRESS).unwrap();
+        let body = Body::from(std::fs::read(path).unwrap());
+        upload(State(scanner), body).await
+    }
+
+    #[tokio::test]
+    async fn it_stores_clean_uploads() {
+        let (status, message) = post_file("tests/clean.txt").await;
+        assert_eq!(status, StatusCode::OK, "{message}");
+    }
+
+    #[tokio::test]
+    async fn it_rejects_infected_uploads() {
+        let (status, _) = post_file("tests/eicar.txt").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
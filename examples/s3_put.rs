@@ -0,0 +1,117 @@
+//! Upload a file to an S3-like object store under a temporary key while scanning it, then move
+//! it to its final key only if it is clean, with a [`ScanGate`].
+//!
+//! The in-memory `Bucket` stands in for the object store. With the AWS SDK, `stage` would send
+//! the parts of a multipart upload to the temporary key, `commit` would copy the object to the
+//! final key, and `rollback` would delete the temporary one.
+//!
+//! ```sh
+//! docker compose up -d
+//! cargo run --example s3_put --features examples -- tests/clean.txt
+//! ```
+
+use bytes::Bytes;
+use clamav_stream::{Error, ScanGate, Scanner};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::ready,
+    sync::{Arc, Mutex},
+};
+use tokio::fs::File;
+use tokio_util::io::ReaderStream;
+
+const CLAMD_ADDRESS: &str = "localhost:3310";
+
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl Bucket {
+    fn append(&self, key: &str, chunk: &[u8]) {
+        let mut objects = self.objects.lock().unwrap();
+        objects
+            .entry(key.into())
+            .or_default()
+            .extend_from_slice(chunk);
+    }
+
+    fn rename(&self, from: &str, to: &str) {
+        let mut objects = self.objects.lock().unwrap();
+        if let Some(object) = objects.remove(from) {
+            objects.insert(to.into(), object);
+        }
+    }
+
+    fn delete(&self, key: &str) {
+        self.objects.lock().unwrap().remove(key);
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.objects.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+async fn put(scanner: &Scanner, bucket: &Bucket, key: &str, path: &str) -> Result<(), Error> {
+    let temp_key = format!("incoming/{key}");
+    let file = File::open(path).await?;
+    let stream = scanner.wrap(ReaderStream::new(file))?;
+
+    let (stage, commit, rollback) = (bucket.clone(), bucket.clone(), bucket.clone());
+    let (staged, committed, rolled_back) = (temp_key.clone(), temp_key.clone(), temp_key);
+    ScanGate::new(
+        move |chunk: Bytes| {
+            stage.append(&staged, &chunk);
+            ready(Ok::<_, Infallible>(()))
+        },
+        move || {
+            commit.rename(&committed, key);
+            ready(Ok(()))
+        },
+        move || {
+            rollback.delete(&rolled_back);
+            ready(Ok(()))
+        },
+    )
+    .run(stream)
+    .await
+}
+
+#[tokio::main]
+async fn main() {
+    let path = std::env::args().nth(1).expect("a file to upload");
+    let scanner = Scanner::tcp(CLAMD_ADDRESS).unwrap();
+    let bucket = Bucket::default();
+
+    match put(&scanner, &bucket, "uploads/file", &path).await {
+        Ok(()) => println!("stored as {:?}", bucket.keys()),
+        Err(err) => println!("rejected: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_moves_clean_files_to_the_final_key() {
+        let scanner = Scanner::tcp(CLAMD_ADDRESS).unwrap();
+        let bucket = Bucket::default();
+
+        put(&scanner, &bucket, "clean.txt", "tests/clean.txt")
+            .await
+            .unwrap();
+        assert_eq!(bucket.keys(), vec!["clean.txt".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn it_deletes_infected_files() {
+        let scanner = Scanner::tcp(CLAMD_ADDRESS).unwrap();
+        let bucket = Bucket::default();
+
+        let result = put(&scanner, &bucket, "eicar.txt", "tests/eicar.txt").await;
+        assert!(matches!(result, Err(Error::Scan(_))));
+        assert!(bucket.keys().is_empty());
+    }
+}
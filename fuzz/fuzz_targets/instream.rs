@@ -0,0 +1,25 @@
+#![no_main]
+
+use clamav_stream::protocol::{decode_chunks, InstreamEncoder};
+use libfuzzer_sys::fuzz_target;
+
+// The first byte picks the length of the parts the rest of the input is pushed in.
+fuzz_target!(|input: &[u8]| {
+    let Some((part_len, content)) = input.split_first() else {
+        return;
+    };
+
+    let mut whole = InstreamEncoder::new();
+    let mut expected = whole.push(content);
+    expected.extend(whole.finish());
+
+    let mut encoder = InstreamEncoder::new();
+    let mut frames = vec![];
+    for part in content.chunks(usize::from(*part_len).max(1)) {
+        frames.extend(encoder.push(part));
+    }
+    frames.extend(encoder.finish());
+
+    assert_eq!(frames, expected);
+    assert_eq!(decode_chunks(&frames).as_deref(), Some(content));
+});
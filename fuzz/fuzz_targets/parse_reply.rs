@@ -0,0 +1,15 @@
+#![no_main]
+
+use clamav_stream::{
+    protocol::{decode_chunks, split_request_id, Reply, Version},
+    ClamdParser, ResponseParser,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|reply: &[u8]| {
+    let _ = Reply::parse(reply);
+    let _ = Version::parse(reply);
+    let _ = split_request_id(reply);
+    let _ = ClamdParser.parse(reply);
+    let _ = decode_chunks(reply);
+});
@@ -0,0 +1,152 @@
+use crate::protocol::{ChunkSize, CHUNK_SIZE};
+use crate::{protocol::STREAM_MAX_LENGTH, ConfigIssue};
+
+use std::time::Duration;
+
+/// The default largest chunk of an [`AdaptiveChunkSize`].
+pub const ADAPTIVE_MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Adapts the size of the `INSTREAM` chunks to the throughput of the connection instead of
+/// sending them at a fixed [`ChunkSize`].
+///
+/// The chunks start at the smallest size. The size doubles after every chunk the clamav
+/// accepts in full and quickly, up to the largest size, and halves whenever a write takes
+/// longer than the slow write threshold or leaves part of a chunk queued for a non-blocking
+/// connection, down to the smallest size. A chunk is never longer than the input chunk it
+/// comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdaptiveChunkSize {
+    min: ChunkSize,
+    max: ChunkSize,
+    slow_write: Duration,
+}
+
+impl AdaptiveChunkSize {
+    /// Adapt the chunk size between `min` and `max`.
+    pub fn new(min: ChunkSize, max: ChunkSize) -> Self {
+        Self {
+            min,
+            max,
+            slow_write: Duration::from_millis(10),
+        }
+    }
+
+    /// Take a write of a chunk which lasts at least the threshold as a sign that the clamav
+    /// does not keep up. Defaults to 10ms.
+    pub fn slow_write(mut self, threshold: Duration) -> Self {
+        self.slow_write = threshold;
+        self
+    }
+
+    /// The smallest chunk size.
+    pub fn min(&self) -> ChunkSize {
+        self.min
+    }
+
+    /// The largest chunk size.
+    pub fn max(&self) -> ChunkSize {
+        self.max
+    }
+
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        if self.max.get() > STREAM_MAX_LENGTH {
+            issues.push(ConfigIssue::ChunkSizeTooLarge {
+                size: self.max.get(),
+                max: STREAM_MAX_LENGTH,
+            });
+        }
+        if self.min.get() > self.max.get() {
+            issues.push(ConfigIssue::ChunkSizeMinExceedsMax {
+                min: self.min.get(),
+                max: self.max.get(),
+            });
+        }
+        if self.slow_write.is_zero() {
+            issues.push(ConfigIssue::ZeroDuration("adaptive chunk slow write"));
+        }
+    }
+}
+
+impl Default for AdaptiveChunkSize {
+    /// Adapt the chunk size between [`CHUNK_SIZE`] and [`ADAPTIVE_MAX_CHUNK_SIZE`].
+    fn default() -> Self {
+        Self::new(
+            ChunkSize::new(CHUNK_SIZE).unwrap(),
+            ChunkSize::new(ADAPTIVE_MAX_CHUNK_SIZE).unwrap(),
+        )
+    }
+}
+
+/// The current chunk size of a scan with an [`AdaptiveChunkSize`].
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkSizer {
+    config: AdaptiveChunkSize,
+    current: usize,
+}
+
+impl ChunkSizer {
+    pub(crate) fn new(config: AdaptiveChunkSize) -> Self {
+        Self {
+            current: config.min.get(),
+            config,
+        }
+    }
+
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Adapt the size after a chunk of `len` bytes was written in `elapsed`, with part of it
+    /// left queued if `pressured`.
+    pub(crate) fn record(&mut self, len: usize, elapsed: Duration, pressured: bool) {
+        let min = self.config.min.get();
+        let max = self.config.max.get().max(min);
+
+        if pressured || elapsed >= self.config.slow_write {
+            self.current = (self.current / 2).max(min);
+        } else if len >= self.current {
+            self.current = self.current.saturating_mul(2).min(max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizer() -> ChunkSizer {
+        ChunkSizer::new(AdaptiveChunkSize::new(
+            ChunkSize::new(4).unwrap(),
+            ChunkSize::new(16).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn it_grows_up_to_the_max_while_the_writes_are_fast() {
+        let mut sizer = sizer();
+        for expected in [8, 16, 16] {
+            sizer.record(sizer.current(), Duration::ZERO, false);
+            assert_eq!(sizer.current(), expected);
+        }
+
+        // A short chunk says nothing about a larger one.
+        let mut sizer = self::sizer();
+        sizer.record(2, Duration::ZERO, false);
+        assert_eq!(sizer.current(), 4);
+    }
+
+    #[test]
+    fn it_shrinks_down_to_the_min_under_write_pressure() {
+        let mut sizer = sizer();
+        sizer.record(4, Duration::ZERO, false);
+        sizer.record(8, Duration::ZERO, false);
+        assert_eq!(sizer.current(), 16);
+
+        sizer.record(16, Duration::from_millis(10), false);
+        assert_eq!(sizer.current(), 8);
+        sizer.record(8, Duration::ZERO, true);
+        assert_eq!(sizer.current(), 4);
+        sizer.record(4, Duration::ZERO, true);
+        assert_eq!(sizer.current(), 4);
+    }
+}
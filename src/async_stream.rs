@@ -0,0 +1,1387 @@
+#[cfg(feature = "dedup")]
+use crate::dedup::{BlockDedup, Deduper};
+#[cfg(feature = "passthrough-check")]
+use crate::integrity::{Crc32, Passthrough};
+#[cfg(feature = "journal")]
+use crate::journal::Recording;
+#[cfg(feature = "protocol-debug")]
+use crate::trace::Frame;
+use crate::{
+    adaptive::{AdaptiveChunkSize, ChunkSizer},
+    buffer::BufferPool,
+    circuit::Circuit,
+    drop_behavior::{DropBehavior, DropResult},
+    error::StreamErrors,
+    latency::Timing,
+    limiter::ScanPermit,
+    lookahead::Lookahead,
+    protocol::{chunk_header, ChunkSize, Command, END_OF_STREAM},
+    quota::QuotaCharge,
+    report::Warning,
+    response::{ClamdParser, ResponseParser, ScanOutcome, TrailingNotes},
+    shutdown::InFlight,
+    task::DROP_COMPLETION_TASK,
+    Error, Phase, Progress, StreamErrorAction,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    collections::VecDeque,
+    error::Error as StdError,
+    future::{poll_fn, Future},
+    io::{self, IoSlice},
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    net::{TcpStream, ToSocketAddrs},
+    time::Sleep,
+};
+use tokio_stream::Stream;
+
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// A [`ScannedStream`](crate::ScannedStream) over an asynchronous connection to the clamav.
+///
+/// The connection is driven by the reactor instead of blocking calls inside `poll_next`, so
+/// the task is woken when the socket becomes writable, and a reply the clamav sends before the
+/// end of the content, such as `INSTREAM size limit exceeded. ERROR`, ends the stream early.
+///
+/// Each chunk is yielded as soon as it has been queued, and is sent before the next one is
+/// polled from the input. A transport error is therefore returned in place of the chunk after
+/// the one which failed.
+///
+/// Any [`AsyncRead`] + [`AsyncWrite`] transport can carry the scan, e.g. a TLS stream to a
+/// remote clamav or an in-memory [`tokio::io::duplex`] in tests, see also
+/// [`Scanner::wrap_transport`](crate::Scanner::wrap_transport).
+///
+/// Unlike a [`ScannedStream`](crate::ScannedStream), it does not offer `finish`, the decoding
+/// of the content, the spool or the memory budget.
+#[pin_project(PinnedDrop)]
+pub struct AsyncScannedStream<St, IO> {
+    #[pin]
+    input: St,
+    /// The connection, or `None` for a stream passed through without being scanned.
+    io: Option<IO>,
+    state: State,
+    out: Frames,
+    reply: Vec<u8>,
+    bytes_sent: u64,
+    progress: Progress,
+    parser: Arc<dyn ResponseParser>,
+    chunk_size: ChunkSize,
+    sizer: Option<ChunkSizer>,
+    /// The longest frame of the chunk being written, when it was queued, and whether the
+    /// connection stopped accepting it meanwhile.
+    sizing: Option<(usize, Instant, bool)>,
+    #[cfg(feature = "dedup")]
+    dedup: Option<Deduper>,
+    buffers: BufferPool,
+    /// The blocks of a [`BlockDedup`] being written, to be returned to the buffer pool.
+    lent: Vec<Bytes>,
+    lookahead: Option<Lookahead>,
+    trailing_notes: TrailingNotes,
+    /// Whether the clamav replied before the end of the content.
+    early_reply: bool,
+    stream_errors: StreamErrors,
+    deadline: Option<Pin<Box<Sleep>>>,
+    write_timeout: Option<Duration>,
+    verdict_timeout: Option<Duration>,
+    /// Fires once the connection has accepted nothing for the write timeout, or has not
+    /// replied for the verdict timeout.
+    timer: Option<Pin<Box<Sleep>>>,
+    drop_behavior: DropBehavior,
+    abandon: Option<Abandon<IO>>,
+    circuit: Option<Arc<Circuit>>,
+    /// Released once the verdict has been read.
+    permit: Option<ScanPermit>,
+    /// Released once the verdict has been read.
+    quota: Option<QuotaCharge>,
+    /// Released once the verdict has been read.
+    guard: Option<InFlight>,
+    timing: Option<Timing>,
+    /// When the end of the content was sent, to time the verdict.
+    finished_at: Option<Instant>,
+    #[cfg(feature = "journal")]
+    recording: Option<Recording>,
+    #[cfg(feature = "passthrough-check")]
+    passthrough: Passthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Streaming,
+    Finishing,
+    Reading,
+    Done,
+}
+
+/// Applies the [`DropBehavior`] to the connection of a dropped stream. A function pointer,
+/// since the [`Drop`] of the stream cannot require the connection to be [`Send`].
+type Abandon<IO> = fn(Abandoned<IO>);
+
+impl<St, IO, B, E> AsyncScannedStream<St, IO>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    IO: AsyncRead + AsyncWrite + Unpin,
+    E: StdError + Send + Sync + 'static,
+{
+    /// Create a new [`AsyncScannedStream`].
+    pub fn new(input: St, io: IO) -> Self {
+        Self::with_io(input, Some(io))
+    }
+
+    /// A stream which passes the content through without sending it to the clamav.
+    pub(crate) fn bypass(input: St) -> Self {
+        Self::with_io(input, None)
+    }
+
+    fn with_io(input: St, io: Option<IO>) -> Self {
+        let progress = Progress::default();
+        #[cfg(feature = "protocol-debug")]
+        progress.trace(Frame::Command(Command::Instream.as_bytes().to_vec()));
+
+        Self {
+            input,
+            io,
+            state: State::Streaming,
+            out: Frames::new(Command::Instream.as_bytes()),
+            reply: vec![],
+            bytes_sent: 0,
+            progress,
+            parser: Arc::new(ClamdParser),
+            chunk_size: ChunkSize::default(),
+            sizer: None,
+            sizing: None,
+            #[cfg(feature = "dedup")]
+            dedup: None,
+            buffers: BufferPool::unpooled(),
+            lent: vec![],
+            lookahead: None,
+            trailing_notes: TrailingNotes::default(),
+            early_reply: false,
+            stream_errors: StreamErrors::default(),
+            deadline: None,
+            write_timeout: None,
+            verdict_timeout: None,
+            timer: None,
+            drop_behavior: DropBehavior::default(),
+            abandon: None,
+            circuit: None,
+            permit: None,
+            quota: None,
+            guard: None,
+            timing: None,
+            finished_at: None,
+            #[cfg(feature = "journal")]
+            recording: None,
+            #[cfg(feature = "passthrough-check")]
+            passthrough: Passthrough::default(),
+        }
+    }
+
+    /// Split the content into chunks of at most the given size. Defaults to
+    /// [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE).
+    pub fn with_chunk_size(mut self, chunk_size: ChunkSize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Adapt the size of the chunks sent to the throughput of the connection instead of
+    /// splitting the content at a fixed [`ChunkSize`]. See [`AdaptiveChunkSize`].
+    pub fn with_adaptive_chunk_size(mut self, config: AdaptiveChunkSize) -> Self {
+        self.sizer = Some(ChunkSizer::new(config));
+        self
+    }
+
+    /// Skip the blocks of the content identical to a block already sent to the clamav. See
+    /// [`BlockDedup`] for what the clamav misses then. Set it before the stream is polled.
+    #[cfg(feature = "dedup")]
+    pub fn with_block_dedup(mut self, config: BlockDedup) -> Self {
+        self.dedup = Some(Deduper::new(&config));
+        self
+    }
+
+    /// Take the blocks of a [`BlockDedup`] from the pool instead of allocating them. See
+    /// [`BufferPool`].
+    pub fn with_buffer_pool(mut self, buffers: BufferPool) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    /// Hold back the chunks from the consumer until the clamav has been sent at least `bytes`
+    /// more of the content after them, and the last ones until the content has been found
+    /// clean, see [`ScannedStream::with_lookahead`](crate::ScannedStream::with_lookahead). A
+    /// reply of the clamav before the end of the content drops the chunks held back.
+    pub fn with_lookahead(mut self, bytes: usize) -> Self {
+        self.lookahead = Some(Lookahead::new(bytes));
+        self
+    }
+
+    /// Use the given parser instead of [`ClamdParser`] to map the reply from the clamav to a
+    /// [`ScanOutcome`].
+    pub fn with_response_parser(mut self, parser: impl ResponseParser + 'static) -> Self {
+        self.parser = Arc::new(parser);
+        self
+    }
+
+    /// Choose what the scan does with the notes the clamav appends to a clean reply. Defaults
+    /// to [`TrailingNotes::Report`].
+    pub fn with_trailing_notes(mut self, notes: TrailingNotes) -> Self {
+        self.trailing_notes = notes;
+        self
+    }
+
+    /// Decide what an error of the input becomes, see
+    /// [`ScannedStream::with_stream_errors`](crate::ScannedStream::with_stream_errors).
+    pub fn with_stream_errors<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(E) -> StreamErrorAction + Send + Sync + 'static,
+    {
+        self.stream_errors = StreamErrors::new(hook);
+        self
+    }
+
+    pub(crate) fn with_parser(mut self, parser: Arc<dyn ResponseParser>) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Fail with [`Error::DeadlineExceeded`] if the verdict has not been read by the deadline.
+    pub(crate) fn with_deadline(mut self, deadline: Instant) -> Self {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        self.deadline = Some(Box::pin(tokio::time::sleep_until(deadline)));
+        self
+    }
+
+    /// Fail with [`Error::WriteTimeout`] once the connection has accepted nothing for the write
+    /// timeout, and with [`Error::VerdictTimeout`] once the clamav has not replied within the
+    /// verdict timeout after the end of the content.
+    pub(crate) fn with_timeouts(
+        mut self,
+        write_timeout: Option<Duration>,
+        verdict_timeout: Option<Duration>,
+    ) -> Self {
+        self.write_timeout = write_timeout;
+        self.verdict_timeout = verdict_timeout;
+        self
+    }
+
+    /// Report the verdicts and the transport errors to the circuit breaker.
+    pub(crate) fn with_circuit(mut self, circuit: Arc<Circuit>) -> Self {
+        self.circuit = Some(circuit);
+        self
+    }
+
+    pub(crate) fn with_permit(mut self, permit: ScanPermit) -> Self {
+        self.permit = Some(permit);
+        self
+    }
+
+    pub(crate) fn with_quota(mut self, mut quota: QuotaCharge) -> Self {
+        quota.track(self.progress.clone());
+        self.quota = Some(quota);
+        self
+    }
+
+    pub(crate) fn with_guard(mut self, guard: InFlight) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    pub(crate) fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    #[cfg(feature = "journal")]
+    pub(crate) fn with_recording(mut self, recording: Recording) -> Self {
+        self.recording = Some(recording);
+        self
+    }
+
+    /// Whether the content is sent to the clamav, rather than passed through unscanned.
+    pub(crate) fn is_scanned(&self) -> bool {
+        self.io.is_some()
+    }
+
+    /// A clonable handle to follow the scan while the stream is consumed.
+    pub fn progress(&self) -> Progress {
+        self.progress.clone()
+    }
+
+    fn poll_scan(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Error>>> {
+        let mut me = self.project();
+        if *me.state == State::Done {
+            // The chunks held back are left only once the content has been found clean.
+            let released = me.lookahead.as_mut().and_then(|held| held.release(true));
+            return Poll::Ready(released.map(Ok));
+        }
+
+        let Some(io) = me.io.as_mut() else {
+            // Passed through without being scanned, see `Scanner::wrap_async`.
+            loop {
+                return match ready!(me.input.as_mut().poll_next(cx)) {
+                    Some(Ok(bytes)) => {
+                        let bytes: Bytes = bytes.into();
+                        me.progress.add(bytes.len() as u64);
+                        #[cfg(feature = "journal")]
+                        if let Some(recording) = me.recording.as_mut() {
+                            recording.update(&bytes);
+                        }
+                        Poll::Ready(Some(Ok(bytes)))
+                    }
+                    Some(Err(err)) => match me.stream_errors.handle(err) {
+                        Some(err) => Poll::Ready(Some(Err(err))),
+                        None => continue,
+                    },
+                    None => {
+                        me.progress.finish();
+                        *me.state = State::Done;
+                        Poll::Ready(None)
+                    }
+                };
+            }
+        };
+
+        if let Some(deadline) = me.deadline.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                *me.state = State::Done;
+                return Poll::Ready(Some(Err(Error::deadline_exceeded(*me.bytes_sent))));
+            }
+        }
+
+        loop {
+            match *me.state {
+                State::Streaming => {
+                    if let Some(result) = ready!(poll_early_reply(&mut *io, me.reply, cx)) {
+                        *me.state = State::Reading;
+                        *me.early_reply = true;
+                        *me.timer = me
+                            .verdict_timeout
+                            .map(|timeout| Box::pin(tokio::time::sleep(timeout)));
+                        if let Err(err) = result {
+                            *me.state = State::Done;
+                            return Poll::Ready(Some(Err(Error::send(
+                                err,
+                                *me.bytes_sent,
+                                Phase::Chunk,
+                            ))));
+                        }
+                        continue;
+                    }
+
+                    let queued = me.out.len();
+                    let flushed = poll_flush_out(&mut *io, me.out, cx);
+                    #[allow(unused_mut)]
+                    let mut buffered = me.out.len();
+                    #[cfg(feature = "dedup")]
+                    {
+                        buffered += me.dedup.as_ref().map_or(0, Deduper::buffered);
+                    }
+                    me.progress.set_buffered(buffered);
+                    match flushed {
+                        Poll::Pending => {
+                            if let Some((_, _, pressured)) = me.sizing.as_mut() {
+                                *pressured = true;
+                            }
+                            let progressed = me.out.len() < queued;
+                            if poll_stalled(me.timer, *me.write_timeout, progressed, cx) {
+                                *me.state = State::Done;
+                                return Poll::Ready(Some(Err(Error::WriteTimeout {
+                                    timeout: me.write_timeout.unwrap_or_default(),
+                                    bytes_sent: *me.bytes_sent,
+                                    tenant: None,
+                                })));
+                            }
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            *me.state = State::Done;
+                            return Poll::Ready(Some(Err(Error::send(
+                                err,
+                                *me.bytes_sent,
+                                Phase::Chunk,
+                            ))));
+                        }
+                        Poll::Ready(Ok(())) => {
+                            *me.timer = None;
+                            if let (Some(sizer), Some((len, queued_at, pressured))) =
+                                (me.sizer.as_mut(), me.sizing.take())
+                            {
+                                sizer.record(len, queued_at.elapsed(), pressured);
+                            }
+                            for block in me.lent.drain(..) {
+                                if let Ok(block) = block.try_into_mut() {
+                                    me.buffers.give(block);
+                                }
+                            }
+                        }
+                    }
+
+                    match ready!(me.input.as_mut().poll_next(cx)) {
+                        Some(Ok(bytes)) => {
+                            let bytes: Bytes = bytes.into();
+                            let frame_len = me
+                                .sizer
+                                .as_ref()
+                                .map_or(me.chunk_size.get(), |sizer| sizer.current());
+                            #[cfg(feature = "dedup")]
+                            let deduped = me.dedup.as_mut().map(|dedup| {
+                                let (blocks, skipped) = dedup.push(&bytes, me.buffers);
+                                me.progress.add_deduplicated(skipped);
+                                let mut longest = 0;
+                                for block in blocks {
+                                    let block = block.freeze();
+                                    longest = longest.max(me.out.push_content(
+                                        block.clone(),
+                                        frame_len,
+                                        me.progress,
+                                    ));
+                                    me.lent.push(block);
+                                }
+                                longest
+                            });
+                            #[cfg(not(feature = "dedup"))]
+                            let deduped = None;
+                            let longest = match deduped {
+                                Some(longest) => longest,
+                                None => me.out.push_content(bytes.clone(), frame_len, me.progress),
+                            };
+                            if me.sizer.is_some() && longest > 0 {
+                                *me.sizing = Some((longest, Instant::now(), false));
+                            }
+                            *me.bytes_sent += bytes.len() as u64;
+                            me.progress.add(bytes.len() as u64);
+                            #[allow(unused_mut)]
+                            let mut buffered = me.out.len();
+                            #[cfg(feature = "dedup")]
+                            {
+                                buffered += me.dedup.as_ref().map_or(0, Deduper::buffered);
+                            }
+                            me.progress.set_buffered(buffered);
+                            #[cfg(feature = "journal")]
+                            if let Some(recording) = me.recording.as_mut() {
+                                recording.update(&bytes);
+                            }
+                            #[cfg(feature = "passthrough-check")]
+                            me.passthrough.passed(&bytes);
+                            let Some(lookahead) = me.lookahead.as_mut() else {
+                                return Poll::Ready(Some(Ok(bytes)));
+                            };
+                            lookahead.hold(bytes);
+                            if let Some(bytes) = lookahead.release(false) {
+                                return Poll::Ready(Some(Ok(bytes)));
+                            }
+                        }
+                        Some(Err(err)) => match me.stream_errors.handle(err) {
+                            Some(err) => return Poll::Ready(Some(Err(err))),
+                            None => continue,
+                        },
+                        None => {
+                            #[cfg(feature = "dedup")]
+                            if let Some(dedup) = me.dedup.as_mut() {
+                                let (block, skipped) = dedup.finish();
+                                me.progress.add_deduplicated(skipped);
+                                if let Some(block) = block {
+                                    let frame_len = me.chunk_size.get();
+                                    me.out.push_content(block.freeze(), frame_len, me.progress);
+                                }
+                            }
+                            #[cfg(feature = "protocol-debug")]
+                            me.progress.trace(Frame::EndOfStream);
+                            me.out.push(&END_OF_STREAM);
+                            me.progress.finish();
+                            *me.state = State::Finishing;
+                        }
+                    }
+                }
+                State::Finishing => {
+                    let queued = me.out.len();
+                    match poll_flush_out(&mut *io, me.out, cx) {
+                        Poll::Pending => {
+                            let progressed = me.out.len() < queued;
+                            if poll_stalled(me.timer, *me.write_timeout, progressed, cx) {
+                                *me.state = State::Done;
+                                return Poll::Ready(Some(Err(Error::WriteTimeout {
+                                    timeout: me.write_timeout.unwrap_or_default(),
+                                    bytes_sent: *me.bytes_sent,
+                                    tenant: None,
+                                })));
+                            }
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            *me.state = State::Done;
+                            return Poll::Ready(Some(Err(Error::send(
+                                err,
+                                *me.bytes_sent,
+                                Phase::Finish,
+                            ))));
+                        }
+                        Poll::Ready(Ok(())) => {}
+                    }
+                    *me.finished_at = Some(Instant::now());
+                    *me.timer = me
+                        .verdict_timeout
+                        .map(|timeout| Box::pin(tokio::time::sleep(timeout)));
+                    *me.state = State::Reading;
+                }
+                State::Reading => {
+                    let mut buf = [0u8; 256];
+                    let mut read_buf = ReadBuf::new(&mut buf);
+                    match Pin::new(&mut *io).poll_read(cx, &mut read_buf) {
+                        Poll::Pending => {
+                            let timed_out = me
+                                .timer
+                                .as_mut()
+                                .is_some_and(|timer| timer.as_mut().poll(cx).is_ready());
+                            if let (true, Some(timeout)) = (timed_out, *me.verdict_timeout) {
+                                *me.state = State::Done;
+                                return Poll::Ready(Some(Err(Error::VerdictTimeout {
+                                    timeout,
+                                    tenant: None,
+                                })));
+                            }
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            *me.state = State::Done;
+                            return Poll::Ready(Some(Err(Error::send(
+                                err,
+                                *me.bytes_sent,
+                                Phase::Reply,
+                            ))));
+                        }
+                        Poll::Ready(Ok(())) => {}
+                    }
+                    if !read_buf.filled().is_empty() {
+                        me.reply.extend_from_slice(read_buf.filled());
+                        continue;
+                    }
+
+                    *me.state = State::Done;
+                    if let Some(finished_at) = me.finished_at.take() {
+                        let elapsed = finished_at.elapsed();
+                        me.progress.set_time_to_verdict(elapsed);
+                        let slow = me
+                            .timing
+                            .as_ref()
+                            .and_then(|timing| timing.record(elapsed, me.progress.bytes_scanned()));
+                        if let Some(warning) = slow {
+                            me.progress.warn(warning);
+                        }
+                    }
+                    #[cfg(feature = "protocol-debug")]
+                    me.progress.trace(Frame::Reply(me.reply.clone()));
+                    let outcome = me.parser.parse(me.reply).and_then(|outcome| {
+                        if outcome == ScanOutcome::Clean {
+                            me.trailing_notes
+                                .apply(&**me.parser, me.reply, me.progress)?;
+                        }
+                        Ok(outcome)
+                    });
+                    return match outcome {
+                        // A clean verdict would only cover the content received so far.
+                        Ok(ScanOutcome::Clean | ScanOutcome::Skipped) if *me.early_reply => {
+                            Poll::Ready(Some(Err(Error::send(
+                                io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    "verdict before the end of the content",
+                                ),
+                                *me.bytes_sent,
+                                Phase::Chunk,
+                            ))))
+                        }
+                        Ok(ScanOutcome::Clean | ScanOutcome::Skipped) => {
+                            #[cfg(feature = "passthrough-check")]
+                            {
+                                // The content written differs from the content by design.
+                                #[cfg(feature = "dedup")]
+                                let scanned = me.dedup.is_none().then_some(me.out.scanned);
+                                #[cfg(not(feature = "dedup"))]
+                                let scanned = Some(me.out.scanned);
+                                me.passthrough.expect(scanned);
+                                if let Err(err) = me.passthrough.verify() {
+                                    return Poll::Ready(Some(Err(err)));
+                                }
+                            }
+                            let released =
+                                me.lookahead.as_mut().and_then(|held| held.release(true));
+                            Poll::Ready(released.map(Ok))
+                        }
+                        Ok(ScanOutcome::Infected(message)) => {
+                            Poll::Ready(Some(Err(Error::Scan(message))))
+                        }
+                        Err(err) => Poll::Ready(Some(Err(err))),
+                    };
+                }
+                State::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl<St, IO, B, E> AsyncScannedStream<St, IO>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    E: StdError + Send + Sync + 'static,
+{
+    /// Choose what the scan does if the stream is dropped before the end of the input.
+    /// Defaults to [`DropBehavior::Abort`].
+    ///
+    /// The content is terminated and the verdict read on a task spawned on the tokio runtime,
+    /// so that the dropping task is not held up by the clamav. Without a runtime, the scan is
+    /// reported [`DropResult::Failed`].
+    pub fn with_drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.drop_behavior = behavior;
+        self.abandon = Some(|abandoned| {
+            if tokio::runtime::Handle::try_current().is_err() {
+                let result = DropResult::Failed("no tokio runtime".into());
+                abandoned.progress.warn(Warning::Dropped {
+                    behavior: abandoned.behavior,
+                    result,
+                });
+                return;
+            }
+            drop(crate::task::spawn(DROP_COMPLETION_TASK, abandoned.settle()));
+        });
+        self
+    }
+}
+
+impl<St, B, E> AsyncScannedStream<St, TcpStream>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    /// Create a new [`AsyncScannedStream`] connecting to clamav server with tcp socket.
+    pub async fn tcp(input: St, addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let io = TcpStream::connect(addr).await?;
+        Ok(Self::new(input, io))
+    }
+}
+
+#[cfg(unix)]
+impl<St, B, E> AsyncScannedStream<St, UnixStream>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    /// Create a new [`AsyncScannedStream`] connecting to clamav server with unix socket.
+    pub async fn socket(input: St, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let io = UnixStream::connect(path).await?;
+        Ok(Self::new(input, io))
+    }
+}
+
+impl<St, IO, B, E> Stream for AsyncScannedStream<St, IO>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    IO: AsyncRead + AsyncWrite + Unpin,
+    E: StdError + Send + Sync + 'static,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut polled = self.as_mut().poll_scan(cx);
+
+        let me = self.project();
+        if *me.state == State::Done {
+            if let Poll::Ready(Some(Err(err))) = polled {
+                if let Some(lookahead) = me.lookahead.as_mut() {
+                    lookahead.discard();
+                }
+                let tenant = me.progress.tenant();
+                polled = Poll::Ready(Some(Err(err.with_tenant(tenant.as_deref()))));
+            }
+            #[cfg(feature = "journal")]
+            {
+                let recorded = match &polled {
+                    Poll::Ready(None) if me.io.is_none() => Some(Ok(ScanOutcome::Skipped)),
+                    Poll::Ready(None) => Some(Ok(ScanOutcome::Clean)),
+                    Poll::Ready(Some(Err(Error::Scan(message)))) => {
+                        Some(Ok(ScanOutcome::Infected(message.clone())))
+                    }
+                    Poll::Ready(Some(Err(err))) => Some(Err(err)),
+                    Poll::Ready(Some(Ok(_))) | Poll::Pending => None,
+                };
+                if let (Some(recorded), Some(recording)) = (recorded, me.recording.take()) {
+                    let size = me.progress.bytes_scanned();
+                    let tenant = me.progress.tenant();
+                    if let Err(err) =
+                        recording.record(size, recorded.as_ref().map_err(|err| *err), tenant)
+                    {
+                        polled = Poll::Ready(Some(Err(err)));
+                    }
+                }
+            }
+            if let Some(circuit) = me.circuit.take() {
+                match &polled {
+                    Poll::Ready(Some(Err(
+                        Error::Send { .. }
+                        | Error::WriteTimeout { .. }
+                        | Error::VerdictTimeout { .. },
+                    ))) => circuit.failure(),
+                    // The clamav is not to blame for the deadline of the request.
+                    Poll::Ready(Some(Err(Error::DeadlineExceeded { .. }))) => {}
+                    _ => circuit.success(),
+                }
+            }
+            *me.guard = None;
+            *me.permit = None;
+            *me.quota = None;
+        }
+        polled
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The chunks held back are yielded once the content has been found clean.
+        let held = self.lookahead.as_ref().map_or(0, Lookahead::chunks);
+        if self.state == State::Done {
+            return (held, Some(held));
+        }
+        // The scan may end the stream before its input, e.g. with a detection, an error or
+        // the deadline, and the verdict may add an error after the last chunk.
+        let upper = self
+            .input
+            .size_hint()
+            .1
+            .and_then(|upper| upper.checked_add(held));
+        (0, upper.and_then(|upper| upper.checked_add(1)))
+    }
+}
+
+#[pinned_drop]
+impl<St, IO> PinnedDrop for AsyncScannedStream<St, IO> {
+    fn drop(self: Pin<&mut Self>) {
+        let me = self.project();
+        // A stream not polled yet has sent nothing, and one whose content has ended is left
+        // to the clamav.
+        let started = *me.bytes_sent > 0 || *me.state == State::Finishing;
+        if !started || !matches!(*me.state, State::Streaming | State::Finishing) {
+            return;
+        }
+        let Some(io) = me.io.take() else {
+            return;
+        };
+
+        let behavior = *me.drop_behavior;
+        let abandon = match (behavior, *me.abandon) {
+            (DropBehavior::Abort, _) | (_, None) => {
+                me.progress.warn(Warning::Dropped {
+                    behavior: DropBehavior::Abort,
+                    result: DropResult::Aborted,
+                });
+                return;
+            }
+            (_, Some(abandon)) => abandon,
+        };
+        abandon(Abandoned {
+            io,
+            out: mem::replace(me.out, Frames::new(&[])),
+            finishing: *me.state == State::Finishing,
+            #[cfg(feature = "dedup")]
+            dedup: me.dedup.take(),
+            behavior,
+            verdict_timeout: *me.verdict_timeout,
+            progress: me.progress.clone(),
+            parser: Arc::clone(me.parser),
+            trailing_notes: *me.trailing_notes,
+            _permit: me.permit.take(),
+            _quota: me.quota.take(),
+            _guard: me.guard.take(),
+            #[cfg(feature = "journal")]
+            recording: me.recording.take(),
+        });
+    }
+}
+
+/// The connection of a stream dropped in the middle of the content, with what it takes to
+/// apply its [`DropBehavior`] after the stream is gone.
+struct Abandoned<IO> {
+    io: IO,
+    out: Frames,
+    /// Whether the end of the content has been queued already.
+    finishing: bool,
+    #[cfg(feature = "dedup")]
+    dedup: Option<Deduper>,
+    behavior: DropBehavior,
+    verdict_timeout: Option<Duration>,
+    progress: Progress,
+    parser: Arc<dyn ResponseParser>,
+    trailing_notes: TrailingNotes,
+    /// Released once the scan has been settled.
+    _permit: Option<ScanPermit>,
+    /// Released once the scan has been settled.
+    _quota: Option<QuotaCharge>,
+    /// Released once the scan has been settled.
+    _guard: Option<InFlight>,
+    #[cfg(feature = "journal")]
+    recording: Option<Recording>,
+}
+
+impl<IO: AsyncRead + AsyncWrite + Unpin> Abandoned<IO> {
+    /// Terminate the content, and read the verdict with [`DropBehavior::Complete`].
+    async fn settle(mut self) {
+        let behavior = self.behavior;
+        let result = match self.terminate().await {
+            Ok(()) if behavior == DropBehavior::Finish => DropResult::Finished,
+            Ok(()) => {
+                let outcome = self.verdict().await;
+                #[cfg(feature = "journal")]
+                if let Some(recording) = self.recording.take() {
+                    let size = self.progress.bytes_scanned();
+                    let recorded = recording.record(size, outcome.as_ref(), self.progress.tenant());
+                    if let Err(err) = recorded {
+                        self.progress.warn(Warning::Dropped {
+                            behavior,
+                            result: DropResult::Failed(err.to_string()),
+                        });
+                        return;
+                    }
+                }
+                match outcome {
+                    Ok(outcome) => DropResult::Completed(outcome),
+                    Err(err) => DropResult::Failed(err.to_string()),
+                }
+            }
+            Err(err) => DropResult::Failed(err.to_string()),
+        };
+        self.progress.warn(Warning::Dropped { behavior, result });
+    }
+
+    async fn terminate(&mut self) -> io::Result<()> {
+        if !self.finishing {
+            #[cfg(feature = "dedup")]
+            if let Some(mut dedup) = self.dedup.take() {
+                if let (Some(block), _) = dedup.finish() {
+                    let frame_len = crate::protocol::CHUNK_SIZE;
+                    self.out
+                        .push_content(block.freeze(), frame_len, &self.progress);
+                }
+            }
+            self.out.push(&END_OF_STREAM);
+        }
+        poll_fn(|cx| poll_flush_out(&mut self.io, &mut self.out, cx)).await
+    }
+
+    async fn verdict(&mut self) -> Result<ScanOutcome, Error> {
+        let mut reply = vec![];
+        let read = self.io.read_to_end(&mut reply);
+        let read =
+            match self.verdict_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, read).await.map_err(|_| {
+                    Error::VerdictTimeout {
+                        timeout,
+                        tenant: None,
+                    }
+                })?,
+                None => read.await,
+            };
+        read.map_err(|err| Error::send(err, self.progress.bytes_scanned(), Phase::Reply))?;
+
+        let outcome = self.parser.parse(&reply)?;
+        if outcome == ScanOutcome::Clean {
+            self.trailing_notes
+                .apply(&*self.parser, &reply, &self.progress)?;
+        }
+        Ok(outcome)
+    }
+}
+
+/// Whether the connection has accepted nothing for the write timeout. The timer is armed on
+/// the first stall, and re-armed whenever part of the frames has been written since.
+fn poll_stalled(
+    timer: &mut Option<Pin<Box<Sleep>>>,
+    timeout: Option<Duration>,
+    progressed: bool,
+    cx: &mut Context<'_>,
+) -> bool {
+    let Some(timeout) = timeout else {
+        return false;
+    };
+    let deadline = tokio::time::Instant::now() + timeout;
+    match timer.as_mut() {
+        Some(timer) if progressed => timer.as_mut().reset(deadline),
+        Some(_) => {}
+        None => *timer = Some(Box::pin(tokio::time::sleep_until(deadline))),
+    }
+    timer
+        .as_mut()
+        .is_some_and(|timer| timer.as_mut().poll(cx).is_ready())
+}
+
+/// Check whether the clamav has replied before the end of the content. Returns `Some` once it
+/// has, with an error if it closed the connection without a reply.
+fn poll_early_reply<IO: AsyncRead + Unpin>(
+    io: &mut IO,
+    reply: &mut Vec<u8>,
+    cx: &mut Context<'_>,
+) -> Poll<Option<io::Result<()>>> {
+    let mut buf = [0u8; 256];
+    let mut read_buf = ReadBuf::new(&mut buf);
+    match Pin::new(io).poll_read(cx, &mut read_buf) {
+        Poll::Pending => Poll::Ready(None),
+        Poll::Ready(Ok(())) if read_buf.filled().is_empty() => {
+            Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())))
+        }
+        Poll::Ready(Ok(())) => {
+            reply.extend_from_slice(read_buf.filled());
+            Poll::Ready(Some(Ok(())))
+        }
+        Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err))),
+    }
+}
+
+/// The most frames written at once with a vectored write.
+const MAX_SLICES: usize = 64;
+
+/// The frames queued for the connection. The content of a chunk refers to the chunk of the
+/// input instead of being copied, and is written after its length prefix with a vectored
+/// write.
+#[derive(Debug)]
+struct Frames {
+    /// The frames, and whether each is a chunk of the content.
+    queue: VecDeque<(Bytes, bool)>,
+    len: usize,
+    /// The length prefixes and commands, split off as they are queued.
+    small: BytesMut,
+    /// The checksum of the content written so far.
+    #[cfg(feature = "passthrough-check")]
+    scanned: Crc32,
+}
+
+impl Frames {
+    fn new(command: &[u8]) -> Self {
+        let mut frames = Self {
+            queue: VecDeque::new(),
+            len: 0,
+            small: BytesMut::new(),
+            #[cfg(feature = "passthrough-check")]
+            scanned: Crc32::default(),
+        };
+        frames.push(command);
+        frames
+    }
+
+    /// The number of bytes queued.
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Queue a copy of a few bytes, e.g. the terminating chunk.
+    fn push(&mut self, bytes: &[u8]) {
+        self.small.extend_from_slice(bytes);
+        let bytes = self.small.split().freeze();
+        self.push_bytes(bytes, false);
+    }
+
+    /// Queue the content split into chunks of at most `frame_len` bytes, each after its length
+    /// prefix and without copying it. Returns the length of the longest chunk.
+    fn push_content(&mut self, bytes: Bytes, frame_len: usize, progress: &Progress) -> usize {
+        let mut longest = 0;
+        let mut start = 0;
+        while start < bytes.len() {
+            let end = bytes.len().min(start + frame_len);
+            let chunk = bytes.slice(start..end);
+            #[cfg(feature = "protocol-debug")]
+            progress.trace(Frame::Chunk {
+                len: chunk.len() as u32,
+            });
+            longest = longest.max(chunk.len());
+            self.push(&chunk_header(chunk.len() as u32));
+            self.push_bytes(chunk, true);
+            start = end;
+        }
+        #[cfg(not(feature = "protocol-debug"))]
+        let _ = progress;
+        longest
+    }
+
+    fn push_bytes(&mut self, bytes: Bytes, content: bool) {
+        if !bytes.is_empty() {
+            self.len += bytes.len();
+            self.queue.push_back((bytes, content));
+        }
+    }
+
+    /// The first frames left to write.
+    fn slices(&self) -> ([IoSlice<'_>; MAX_SLICES], usize) {
+        let mut slices = [IoSlice::new(&[]); MAX_SLICES];
+        let mut n = 0;
+        for (slice, (bytes, _)) in slices.iter_mut().zip(&self.queue) {
+            *slice = IoSlice::new(bytes);
+            n += 1;
+        }
+        (slices, n)
+    }
+
+    /// Drop the bytes written from the front of the queue.
+    fn advance(&mut self, mut n: usize) {
+        self.len -= n;
+        while n > 0 {
+            #[cfg(feature = "passthrough-check")]
+            if let Some((front, true)) = self.queue.front() {
+                self.scanned.update(&front[..n.min(front.len())]);
+            }
+            let Some((front, _)) = self.queue.front_mut() else {
+                return;
+            };
+            if n < front.len() {
+                front.advance(n);
+                return;
+            }
+            n -= front.len();
+            self.queue.pop_front();
+        }
+    }
+}
+
+/// Write the queued frames until none are left or the connection is not writable.
+fn poll_flush_out<IO: AsyncWrite + Unpin>(
+    io: &mut IO,
+    out: &mut Frames,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<()>> {
+    while out.len() > 0 {
+        let (slices, n) = out.slices();
+        match ready!(Pin::new(&mut *io).poll_write_vectored(cx, &slices[..n])) {
+            Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+            Ok(n) => out.advance(n),
+            Err(err) => return Poll::Ready(Err(err)),
+        }
+    }
+    Pin::new(io).poll_flush(cx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_writes_the_content_without_copying_it() {
+        let content = Bytes::from(vec![7u8; 100_000]);
+        let probe = crate::test_util::ZeroCopyProbe::new(&content);
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(content.clone())]);
+        let mut stream =
+            AsyncScannedStream::new(input, probe).with_chunk_size(ChunkSize::new(4096).unwrap());
+        while let Some(chunk) = stream.next().await {
+            chunk.unwrap();
+        }
+
+        let probe = stream.io.take().unwrap();
+        assert_eq!(probe.borrowed, content.len());
+        assert_eq!(probe.written, 10 + 25 * 4 + content.len() + 4);
+    }
+
+    #[tokio::test]
+    async fn it_scans_over_an_async_connection() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+
+        let clamd = tokio::spawn(async move {
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&END_OF_STREAM) {
+                let n = server.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            server
+                .write_all(b"stream: Eicar-Signature FOUND\0")
+                .await
+                .unwrap();
+            received
+        });
+
+        let stream = AsyncScannedStream::new(input, client);
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(
+            items,
+            vec![
+                Ok(Bytes::from("Hello ")),
+                Ok(Bytes::from("World")),
+                Err(Error::Scan("stream: Eicar-Signature FOUND\0".into())),
+            ]
+        );
+
+        let received = clamd.await.unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0\0\0\0\x06Hello "));
+    }
+
+    #[tokio::test]
+    async fn it_hints_the_size_of_the_input_with_the_verdict() {
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let (client, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&END_OF_STREAM) {
+                let n = server.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            server.write_all(b"stream: OK\0").await.unwrap();
+        });
+
+        let mut stream = AsyncScannedStream::new(input, client);
+        assert_eq!(stream.size_hint(), (0, Some(3)));
+
+        while stream.next().await.is_some() {}
+        assert_eq!(stream.size_hint(), (0, Some(0)));
+    }
+
+    #[tokio::test]
+    async fn it_ends_early_when_the_clamav_replies_before_the_end() {
+        let (client, mut server) = tokio::io::duplex(64);
+        server
+            .write_all(b"INSTREAM size limit exceeded. ERROR\0")
+            .await
+            .unwrap();
+        drop(server);
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello"))]);
+        let mut stream = AsyncScannedStream::new(input, client);
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::Clamd { .. }))
+        ));
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_a_clean_reply_before_the_end() {
+        let (client, mut server) = tokio::io::duplex(64);
+        server.write_all(b"stream: OK\0").await.unwrap();
+        drop(server);
+
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello")),
+            Ok(Bytes::from(" ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let items: Vec<_> = AsyncScannedStream::new(input, client).collect().await;
+        assert!(matches!(
+            items.as_slice(),
+            [Err(Error::Send { source, during: Phase::Chunk, .. })]
+                if source.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+
+    /// A fake clamav which reads the content until its end, then sends the reply. Returns what
+    /// it received, and does not reply if the connection is closed before the end.
+    fn reply_after_the_end(
+        mut server: tokio::io::DuplexStream,
+        reply: &'static [u8],
+    ) -> tokio::task::JoinHandle<Vec<u8>> {
+        tokio::spawn(async move {
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&END_OF_STREAM) {
+                let n = server.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    return received;
+                }
+                received.extend_from_slice(&buf[..n]);
+            }
+            // A stream dropped with `DropBehavior::Finish` does not wait for the reply.
+            let _ = server.write_all(reply).await;
+            received
+        })
+    }
+
+    #[tokio::test]
+    async fn it_times_out_when_the_clamav_stops_reading() {
+        let (client, _server) = tokio::io::duplex(16);
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from(vec![0u8; 1024]))]);
+
+        let timeout = Duration::from_millis(50);
+        let items: Vec<_> = AsyncScannedStream::new(input, client)
+            .with_timeouts(Some(timeout), None)
+            .collect()
+            .await;
+        assert!(matches!(
+            items.last(),
+            Some(Err(Error::WriteTimeout { timeout: t, .. })) if *t == timeout
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_times_out_waiting_for_the_verdict() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let clamd = tokio::spawn(async move {
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&END_OF_STREAM) {
+                let n = server.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            // Keep the connection open without replying.
+            server
+        });
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello"))]);
+        let timeout = Duration::from_millis(50);
+        let items: Vec<_> = AsyncScannedStream::new(input, client)
+            .with_timeouts(None, Some(timeout))
+            .collect()
+            .await;
+        assert_eq!(
+            items,
+            vec![
+                Ok(Bytes::from("Hello")),
+                Err(Error::VerdictTimeout {
+                    timeout,
+                    tenant: None
+                }),
+            ]
+        );
+        drop(clamd.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn it_holds_back_the_lookahead_of_an_infected_content() {
+        let (client, server) = tokio::io::duplex(64);
+        let clamd = reply_after_the_end(server, b"stream: Eicar-Signature FOUND\0");
+
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let items: Vec<_> = AsyncScannedStream::new(input, client)
+            .with_lookahead(6)
+            .collect()
+            .await;
+        // Fewer than 6 bytes have been sent after the first chunk.
+        assert_eq!(
+            items,
+            vec![Err(Error::Scan("stream: Eicar-Signature FOUND\0".into()))]
+        );
+        clamd.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_releases_the_lookahead_of_a_clean_content() {
+        let (client, server) = tokio::io::duplex(64);
+        let clamd = reply_after_the_end(server, b"stream: OK\0");
+
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let items: Vec<_> = AsyncScannedStream::new(input, client)
+            .with_lookahead(1024)
+            .collect()
+            .await;
+        assert_eq!(
+            items,
+            vec![Ok(Bytes::from("Hello ")), Ok(Bytes::from("World"))]
+        );
+        clamd.await.unwrap();
+    }
+
+    #[cfg(feature = "dedup")]
+    #[tokio::test]
+    async fn it_skips_blocks_already_sent_with_block_dedup() {
+        let run: Vec<u8> = (0u32..256 * 1024)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let content = Bytes::from([&run[..], &run[..], &run[..]].concat());
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let clamd = reply_after_the_end(server, b"stream: OK\0");
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(content.clone())]);
+        let stream = AsyncScannedStream::new(input, client)
+            .with_block_dedup(BlockDedup::default())
+            .with_buffer_pool(BufferPool::new(BlockDedup::default().block_size(), 4));
+        let progress = stream.progress();
+        let chunks: Vec<Bytes> = stream.collect::<Result<_, _>>().await.unwrap();
+        assert_eq!(chunks.concat(), content);
+
+        let received = clamd.await.unwrap();
+        assert!(progress.deduplicated_bytes() > 0);
+        assert!(received.len() < content.len());
+    }
+
+    #[tokio::test]
+    async fn it_adapts_the_chunk_size_to_the_connection() {
+        let (client, server) = tokio::io::duplex(64);
+        let clamd = reply_after_the_end(server, b"stream: OK\0");
+
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let items: Vec<_> = AsyncScannedStream::new(input, client)
+            .with_adaptive_chunk_size(AdaptiveChunkSize::default())
+            .collect()
+            .await;
+        assert_eq!(
+            items,
+            vec![Ok(Bytes::from("Hello ")), Ok(Bytes::from("World"))]
+        );
+        let received = clamd.await.unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0\0\0\0\x06Hello "));
+    }
+
+    #[tokio::test]
+    async fn it_applies_the_drop_behavior_to_a_stream_dropped_mid_content() {
+        const REPLY: &[u8] = b"stream: Eicar-Signature FOUND\0";
+        let cases = [
+            (DropBehavior::Abort, DropResult::Aborted),
+            (DropBehavior::Finish, DropResult::Finished),
+            (
+                DropBehavior::Complete,
+                DropResult::Completed(ScanOutcome::Infected(
+                    "stream: Eicar-Signature FOUND\0".into(),
+                )),
+            ),
+        ];
+        for (behavior, result) in cases {
+            let (client, server) = tokio::io::duplex(64);
+            let clamd = reply_after_the_end(server, REPLY);
+
+            let input = tokio_stream::iter(vec![
+                Ok::<_, Error>(Bytes::from("Hello ")),
+                Ok(Bytes::from("World")),
+            ]);
+            let mut stream = AsyncScannedStream::new(input, client).with_drop_behavior(behavior);
+            let progress = stream.progress();
+            assert!(stream.next().await.is_some());
+            drop(stream);
+
+            let received = clamd.await.unwrap();
+            let terminated = received.ends_with(&END_OF_STREAM);
+            assert_eq!(terminated, behavior != DropBehavior::Abort);
+            while progress.warnings().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(
+                progress.warnings(),
+                vec![Warning::Dropped { behavior, result }]
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{ConfigIssue, Error};
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+use std::{io, sync::Mutex, time::Instant};
+
+/// A jittered exponential backoff between the attempts to reconnect to a clamav which refuses
+/// connections, e.g. while it restarts to load new signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    jitter: bool,
+}
+
+impl Backoff {
+    /// Wait `initial` after the first failure and double the delay after every consecutive
+    /// failure, up to `max`.
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            jitter: true,
+        }
+    }
+
+    /// Randomize each delay between half and the whole of its value, so that the clients of a
+    /// restarted clamav don't reconnect all at once. Enabled by default.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay after the given number of consecutive failures.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exp = failures.saturating_sub(1).min(31);
+        let delay = self.initial.saturating_mul(1 << exp).min(self.max);
+
+        if self.jitter {
+            let half = delay / 2;
+            half + half.mul_f64(random())
+        } else {
+            delay
+        }
+    }
+
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        if self.initial.is_zero() {
+            issues.push(ConfigIssue::ZeroDuration("backoff initial delay"));
+        }
+        if self.initial > self.max {
+            issues.push(ConfigIssue::BackoffInitialExceedsMax {
+                initial: self.initial,
+                max: self.max,
+            });
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+/// The health of the connections to the clamav, as seen by a [`Scanner`](crate::Scanner).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScannerHealth {
+    /// The last connection attempt succeeded.
+    Healthy,
+    /// The last connection attempts failed. New streams are refused with
+    /// [`Error::Unavailable`](crate::Error::Unavailable) until the next attempt in `retry_in`.
+    Degraded {
+        /// The number of consecutive failed attempts.
+        failures: u32,
+        /// The time until the next attempt is allowed.
+        retry_in: Duration,
+    },
+}
+
+/// Refuses connection attempts while backing off after consecutive failures.
+#[derive(Debug)]
+pub(crate) struct Breaker {
+    backoff: Backoff,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Breaker {
+    pub(crate) fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Open a connection with the function unless backing off. Returns the connection with the
+    /// number of failed attempts before it.
+    pub(crate) fn connect<C>(
+        &self,
+        connect: impl FnOnce() -> io::Result<C>,
+    ) -> Result<(C, u32), Error> {
+        self.check()?;
+        self.record(connect())
+    }
+
+    /// Like [`Breaker::connect`], for an asynchronous connection.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn connect_async<C>(
+        &self,
+        connect: impl Future<Output = io::Result<C>>,
+    ) -> Result<(C, u32), Error> {
+        self.check()?;
+        self.record(connect.await)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        match self.health() {
+            ScannerHealth::Degraded { retry_in, .. } if !retry_in.is_zero() => {
+                Err(Error::unavailable(retry_in))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record<C>(&self, result: io::Result<C>) -> Result<(C, u32), Error> {
+        let mut state = self.state.lock().unwrap();
+        match result {
+            Ok(conn) => {
+                let failures = state.failures;
+                *state = State::default();
+                Ok((conn, failures))
+            }
+            Err(err) => {
+                state.failures = state.failures.saturating_add(1);
+                state.retry_at = Some(Instant::now() + self.backoff.delay(state.failures));
+                Err(Error::connect(err))
+            }
+        }
+    }
+
+    pub(crate) fn health(&self) -> ScannerHealth {
+        let state = self.state.lock().unwrap();
+        match state.retry_at {
+            Some(retry_at) => ScannerHealth::Degraded {
+                failures: state.failures,
+                retry_in: retry_at.saturating_duration_since(Instant::now()),
+            },
+            None => ScannerHealth::Healthy,
+        }
+    }
+}
+
+/// A random number in `[0, 1)`, good enough to spread the reconnection attempts and to sample
+/// the streams.
+pub(crate) fn random() -> f64 {
+    let hasher = RandomState::new().build_hasher();
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_doubles_the_delay_up_to_the_max() {
+        let backoff =
+            Backoff::new(Duration::from_millis(100), Duration::from_millis(500)).jitter(false);
+
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(4), Duration::from_millis(500));
+        assert_eq!(backoff.delay(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn it_jitters_between_half_and_the_whole_delay() {
+        let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+
+        for _ in 0..100 {
+            let delay = backoff.delay(2);
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+}
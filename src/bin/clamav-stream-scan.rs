@@ -0,0 +1,214 @@
+//! Scan files or stdin with a clamav server and print one JSON result per line.
+//!
+//! ```text
+//! clamav-stream-scan [--tcp <host:port> | --socket <path>] [--] [<file>...]
+//! ```
+//!
+//! The content is read from stdin if no file is given, or for `-`. The arguments after `--` are
+//! files even if they start with `-`. The exit code is 0 if every
+//! content is clean, 1 if any is infected and 2 if any could not be scanned, like `clamdscan`.
+
+use clamav_stream::{Address, Error, ScanOutcome, Scanner};
+
+use serde::Serialize;
+use std::{
+    env,
+    io::{self, Write},
+    process::ExitCode,
+};
+use tokio_util::io::ReaderStream;
+
+const USAGE: &str =
+    "usage: clamav-stream-scan [--tcp <host:port> | --socket <path>] [--] [<file>...]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = parse_args(env::args().skip(1));
+    let (address, files) = match check_args(args, &mut io::stdout(), &mut io::stderr()) {
+        Ok(args) => args,
+        Err(code) => return code,
+    };
+    let scanner = Scanner::new(address);
+
+    let mut code = 0;
+    for file in files {
+        let outcome = scan(&scanner, &file).await;
+        code = code.max(match &outcome {
+            Ok(ScanOutcome::Infected(_)) => 1,
+            Ok(_) => 0,
+            Err(_) => 2,
+        });
+        println!("{}", to_json(&file, &outcome));
+    }
+
+    ExitCode::from(code)
+}
+
+/// The address and the files to scan, or `None` if the help was asked for.
+fn parse_args(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Option<(Address, Vec<String>)>, String> {
+    let mut address = None;
+    let mut files = vec![];
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tcp" => {
+                let addr = args.next().ok_or("--tcp needs an address")?;
+                address = Some(Address::tcp(addr).map_err(|err| err.to_string())?);
+            }
+            #[cfg(unix)]
+            "--socket" => {
+                let path = args.next().ok_or("--socket needs a path")?;
+                address = Some(Address::Unix(path.into()));
+            }
+            "-h" | "--help" => return Ok(None),
+            "--" => {
+                files.extend(args);
+                break;
+            }
+            "-" => files.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ => files.push(arg),
+        }
+    }
+
+    let address = match address {
+        Some(address) => address,
+        None => Address::tcp("localhost:3310").map_err(|err| err.to_string())?,
+    };
+    if files.is_empty() {
+        files.push("-".into());
+    }
+
+    Ok(Some((address, files)))
+}
+
+/// The address and the files to scan, or the exit code after printing the help to stdout or
+/// the error with the usage to stderr.
+fn check_args(
+    args: Result<Option<(Address, Vec<String>)>, String>,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(Address, Vec<String>), ExitCode> {
+    match args {
+        Ok(Some(args)) => Ok(args),
+        Ok(None) => {
+            let _ = writeln!(stdout, "scan files or stdin with a clamav server\n{USAGE}");
+            Err(ExitCode::SUCCESS)
+        }
+        Err(message) => {
+            let _ = writeln!(stderr, "{message}\n{USAGE}");
+            Err(ExitCode::from(2))
+        }
+    }
+}
+
+async fn scan(scanner: &Scanner, file: &str) -> Result<ScanOutcome, Error> {
+    if file == "-" {
+        scanner
+            .scan_stream(ReaderStream::new(tokio::io::stdin()))
+            .await
+    } else {
+        let file = tokio::fs::File::open(file).await?;
+        scanner.scan_stream(ReaderStream::new(file)).await
+    }
+}
+
+/// The JSON result of a file.
+#[derive(Debug, Serialize)]
+struct Output<'a> {
+    path: &'a str,
+    status: &'static str,
+    detections: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+fn to_json(file: &str, outcome: &Result<ScanOutcome, Error>) -> String {
+    let (status, detections, message) = match outcome {
+        Ok(ScanOutcome::Clean) => ("clean", vec![], None),
+        Ok(ScanOutcome::Skipped) => ("skipped", vec![], None),
+        Ok(outcome @ ScanOutcome::Infected(message)) => {
+            ("infected", outcome.detections(), Some(message.clone()))
+        }
+        Err(err) => ("error", vec![], Some(err.to_string())),
+    };
+
+    let output = Output {
+        path: file,
+        status,
+        detections: detections.into_iter().map(|d| d.signature).collect(),
+        message,
+    };
+    serde_json::to_string(&output).expect("the output is serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<(Address, Vec<String>)>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_scans_the_arguments_after_the_separator_as_files() {
+        let (address, files) = parse(&["--tcp", "127.0.0.1:3310", "--", "-v", "--tcp"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(address, Address::tcp("127.0.0.1:3310").unwrap());
+        assert_eq!(files, vec!["-v", "--tcp"]);
+    }
+
+    #[test]
+    fn it_scans_stdin_for_a_dash_or_without_files() {
+        let (_, files) = parse(&["a.txt", "-"]).unwrap().unwrap();
+        assert_eq!(files, vec!["a.txt", "-"]);
+
+        let (address, files) = parse(&[]).unwrap().unwrap();
+        assert_eq!(address, Address::tcp("localhost:3310").unwrap());
+        assert_eq!(files, vec!["-"]);
+    }
+
+    #[test]
+    fn it_prints_an_unknown_option_with_the_usage_to_stderr() {
+        let args = parse(&["--verbose", "a.txt"]);
+        assert_eq!(args, Err("unknown option: --verbose".to_string()));
+
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let code = check_args(args, &mut stdout, &mut stderr).unwrap_err();
+        assert_eq!(code, ExitCode::from(2));
+        assert!(stdout.is_empty());
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            format!("unknown option: --verbose\n{USAGE}\n")
+        );
+    }
+
+    #[test]
+    fn it_prints_the_help_to_stdout() {
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let code = check_args(parse(&["--help"]), &mut stdout, &mut stderr).unwrap_err();
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert!(String::from_utf8(stdout)
+            .unwrap()
+            .ends_with(&format!("{USAGE}\n")));
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    fn it_prints_the_result_as_json() {
+        let outcome = Ok(ScanOutcome::Infected(
+            "stream: Eicar-Signature FOUND".into(),
+        ));
+        assert_eq!(
+            to_json("dir/\"a\".txt", &outcome),
+            r#"{"path":"dir/\"a\".txt","status":"infected","detections":["Eicar-Signature"],"message":"stream: Eicar-Signature FOUND"}"#
+        );
+        assert_eq!(
+            to_json("-", &Ok(ScanOutcome::Clean)),
+            r#"{"path":"-","status":"clean","detections":[]}"#
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{scan::Scan, Error, LengthPolicy, Progress};
+
+use bytes::{Buf, Bytes};
+use http_body::{Body, Frame, SizeHint};
+use pin_project::pin_project;
+use std::{
+    error::Error as StdError,
+    io::{Read, Write},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio_stream::Stream;
+
+/// A wrapper of an [`http_body::Body`] which scans its data frames while passing them through.
+///
+/// Trailer frames are passed through untouched and are not scanned, so gRPC and chunked bodies
+/// with trailers keep their framing. If a virus is detected, an [`Error`] is returned after all
+/// frames are consumed.
+#[pin_project]
+pub struct ScannedBody<B, RW> {
+    #[pin]
+    body: B,
+    scan: Scan<RW>,
+}
+
+impl<B, RW> ScannedBody<B, RW>
+where
+    B: Body,
+    RW: Read + Write,
+{
+    /// Create a new [`ScannedBody`].
+    pub fn new(body: B, inner: RW) -> Self {
+        Self::with_scan(body, Scan::new(inner))
+    }
+
+    pub(crate) fn with_scan(body: B, scan: Scan<RW>) -> Self {
+        Self { body, scan }
+    }
+
+    /// A clonable handle to follow the scan while the body is consumed.
+    pub fn progress(&self) -> Progress {
+        self.scan.progress().clone()
+    }
+
+    /// Declare the length of the whole body, e.g. from the `Content-Length` header, see
+    /// [`ScannedStream::with_expected_len`](crate::ScannedStream::with_expected_len).
+    pub fn with_expected_len(self, len: u64) -> Self {
+        self.scan.progress().set_expected_len(len);
+        self
+    }
+
+    /// Choose what the scan does when the body does not match the
+    /// [expected length](Self::with_expected_len). Defaults to [`LengthPolicy::Warn`].
+    pub fn with_length_policy(mut self, policy: LengthPolicy) -> Self {
+        self.scan.set_length_policy(policy);
+        self
+    }
+}
+
+impl<B, RW> Body for ScannedBody<B, RW>
+where
+    B: Body,
+    B::Error: StdError + Send + Sync + 'static,
+    RW: Read + Write,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        me.body
+            .poll_frame(cx)
+            .map(|frame| scan_frame(me.scan, frame))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // The verdict is only read once the inner body has been polled to its end.
+        self.scan.is_finished()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// A wrapper of a stream of [`Frame`]s which scans their data while passing every frame
+/// through, e.g. over hyper 1.0's `Incoming` turned into a stream by
+/// `http_body_util::BodyStream`, without mapping the frames to bytes first.
+///
+/// Like [`ScannedBody`], trailer frames are not scanned, and an [`Error`] is returned after all
+/// frames are consumed if a virus is detected. It is also a [`Body`] itself, so it can be
+/// passed on as the body of a request or a response.
+#[pin_project]
+pub struct ScannedFrames<St, RW> {
+    #[pin]
+    input: St,
+    scan: Scan<RW>,
+}
+
+impl<St, RW, D, E> ScannedFrames<St, RW>
+where
+    St: Stream<Item = Result<Frame<D>, E>>,
+    D: Buf,
+    RW: Read + Write,
+{
+    /// Create a new [`ScannedFrames`].
+    pub fn new(input: St, inner: RW) -> Self {
+        Self::with_scan(input, Scan::new(inner))
+    }
+
+    pub(crate) fn with_scan(input: St, scan: Scan<RW>) -> Self {
+        Self { input, scan }
+    }
+
+    /// A clonable handle to follow the scan while the frames are consumed.
+    pub fn progress(&self) -> Progress {
+        self.scan.progress().clone()
+    }
+
+    /// Declare the length of the whole body, e.g. from the `Content-Length` header, see
+    /// [`ScannedStream::with_expected_len`](crate::ScannedStream::with_expected_len).
+    pub fn with_expected_len(self, len: u64) -> Self {
+        self.scan.progress().set_expected_len(len);
+        self
+    }
+
+    /// Choose what the scan does when the body does not match the
+    /// [expected length](Self::with_expected_len). Defaults to [`LengthPolicy::Warn`].
+    pub fn with_length_policy(mut self, policy: LengthPolicy) -> Self {
+        self.scan.set_length_policy(policy);
+        self
+    }
+}
+
+impl<St, RW, D, E> Stream for ScannedFrames<St, RW>
+where
+    St: Stream<Item = Result<Frame<D>, E>>,
+    D: Buf,
+    E: StdError + Send + Sync + 'static,
+    RW: Read + Write,
+{
+    type Item = Result<Frame<Bytes>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.project();
+        me.input
+            .poll_next(cx)
+            .map(|frame| scan_frame(me.scan, frame))
+    }
+}
+
+impl<St, RW, D, E> Body for ScannedFrames<St, RW>
+where
+    St: Stream<Item = Result<Frame<D>, E>>,
+    D: Buf,
+    E: StdError + Send + Sync + 'static,
+    RW: Read + Write,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.poll_next(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.scan.is_finished()
+    }
+}
+
+/// Scan the data of a frame polled from the input, or read the verdict at its end.
+fn scan_frame<RW, D, E>(
+    scan: &mut Scan<RW>,
+    frame: Option<Result<Frame<D>, E>>,
+) -> Option<Result<Frame<Bytes>, Error>>
+where
+    RW: Read + Write,
+    D: Buf,
+    E: StdError + Send + Sync + 'static,
+{
+    match frame {
+        Some(Ok(frame)) => {
+            let frame = frame.map_data(|mut data| data.copy_to_bytes(data.remaining()));
+
+            if let Some(data) = frame.data_ref() {
+                if let Err(err) = scan.send(data) {
+                    return Some(Err(err));
+                }
+            }
+
+            Some(Ok(frame))
+        }
+        Some(Err(err)) => Some(Err(Error::Stream(Box::new(err)))),
+        None => match scan.finish() {
+            Some(Err(err)) => Some(Err(err)),
+            Some(Ok(())) | None => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderMap;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::io::{self, Cursor};
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_scans_data_frames_and_passes_trailers_through() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        let frames: Vec<Result<_, io::Error>> = vec![
+            Ok(Frame::data(Bytes::from("Hello "))),
+            Ok(Frame::data(Bytes::from("World"))),
+            Ok(Frame::trailers(trailers.clone())),
+        ];
+        let body = StreamBody::new(tokio_stream::iter(frames));
+        let inner = Mock::new("stream: OK\0");
+
+        let collected = ScannedBody::new(body, inner).collect().await.unwrap();
+        assert_eq!(collected.trailers(), Some(&trailers));
+        assert_eq!(collected.to_bytes(), "Hello World");
+    }
+
+    #[tokio::test]
+    async fn it_returns_an_error_after_the_frames_when_infected() {
+        let frames: Vec<Result<_, io::Error>> = vec![Ok(Frame::data(Bytes::from("Hello")))];
+        let body = StreamBody::new(tokio_stream::iter(frames));
+        let inner = Mock::new("stream: Eicar-Signature FOUND\0");
+
+        let mut body = ScannedBody::new(body, inner);
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "Hello");
+
+        let err = body.frame().await.unwrap().unwrap_err();
+        assert_eq!(err, Error::Scan("stream: Eicar-Signature FOUND\0".into()));
+        assert!(body.is_end_stream());
+    }
+
+    #[tokio::test]
+    async fn it_fails_a_truncated_body_under_the_fail_policy() {
+        let frames: Vec<Result<_, io::Error>> = vec![Ok(Frame::data(Bytes::from("Hello")))];
+        let body = StreamBody::new(tokio_stream::iter(frames));
+        let inner = Mock::new("stream: OK\0");
+
+        let body = ScannedBody::new(body, inner)
+            .with_expected_len(11)
+            .with_length_policy(LengthPolicy::Fail);
+        assert_eq!(
+            body.collect().await.unwrap_err(),
+            Error::LengthMismatch {
+                expected_len: 11,
+                bytes_scanned: 5,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_scans_a_stream_of_frames() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        let frames: Vec<Result<_, io::Error>> = vec![
+            Ok(Frame::data(Bytes::from("Hello "))),
+            Ok(Frame::data(Bytes::from("World"))),
+            Ok(Frame::trailers(trailers.clone())),
+        ];
+        let inner = Mock::new("stream: Eicar-Signature FOUND\0");
+        let mut frames = ScannedFrames::new(tokio_stream::iter(frames), inner);
+
+        let mut data = vec![];
+        loop {
+            match frames.next().await.unwrap() {
+                Ok(frame) if frame.is_data() => data.push(frame.into_data().unwrap()),
+                Ok(frame) => assert_eq!(frame.trailers_ref(), Some(&trailers)),
+                Err(err) => {
+                    assert_eq!(err, Error::Scan("stream: Eicar-Signature FOUND\0".into()));
+                    break;
+                }
+            }
+        }
+        assert_eq!(data, vec![Bytes::from("Hello "), Bytes::from("World")]);
+        assert_eq!(frames.progress().bytes_scanned(), 11);
+        assert!(frames.next().await.is_none());
+    }
+
+    struct Mock {
+        written: Vec<u8>,
+        output: Cursor<Vec<u8>>,
+    }
+
+    impl Mock {
+        fn new(reply: &str) -> Self {
+            Self {
+                written: vec![],
+                output: Cursor::new(reply.as_bytes().to_vec()),
+            }
+        }
+    }
+
+    impl Read for Mock {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.output.read(buf)
+        }
+    }
+
+    impl Write for Mock {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use bytes::BytesMut;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// Reuses the buffers a scan copies the content into, i.e. the decoded content of a
+/// [`Decoding`](crate::Decoding) and the blocks of a [`BlockDedup`](crate::BlockDedup), instead
+/// of allocating new ones for every chunk, to spare the allocator on large contents.
+///
+/// Set with [`ScannedStream::with_buffer_pool`](crate::ScannedStream::with_buffer_pool) or
+/// [`ScannerBuilder::buffer_pool`](crate::ScannerBuilder::buffer_pool). Cloning a
+/// [`BufferPool`] shares the same buffers, e.g. between the scans of a scanner.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    buffer_size: usize,
+    max_idle: usize,
+    idle: Mutex<Vec<BytesMut>>,
+    allocated: AtomicU64,
+    reused: AtomicU64,
+}
+
+impl BufferPool {
+    /// Allocate buffers of at least `buffer_size` bytes, and keep at most `max_idle` of them
+    /// for reuse once they have been returned. A buffer which has grown beyond four times
+    /// the size is dropped instead of being kept.
+    pub fn new(buffer_size: usize, max_idle: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                buffer_size,
+                max_idle,
+                idle: Mutex::default(),
+                allocated: AtomicU64::new(0),
+                reused: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// A pool which keeps no buffers, so that every buffer is allocated.
+    pub(crate) fn unpooled() -> Self {
+        Self::new(0, 0)
+    }
+
+    /// The buffers allocated and reused so far.
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            allocated: self.inner.allocated.load(Ordering::Relaxed),
+            reused: self.inner.reused.load(Ordering::Relaxed),
+            idle: self.inner.idle.lock().unwrap().len(),
+        }
+    }
+
+    /// An empty buffer of at least `capacity` bytes, reused if one is idle.
+    pub(crate) fn take(&self, capacity: usize) -> BytesMut {
+        let idle = self.inner.idle.lock().unwrap().pop();
+        match idle {
+            Some(mut buf) => {
+                self.inner.reused.fetch_add(1, Ordering::Relaxed);
+                buf.reserve(capacity);
+                buf
+            }
+            None => {
+                self.inner.allocated.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(capacity.max(self.inner.buffer_size))
+            }
+        }
+    }
+
+    /// Return a buffer taken from the pool once its content has been sent.
+    pub(crate) fn give(&self, mut buf: BytesMut) {
+        let size = self.inner.buffer_size;
+        if buf.capacity() < size || buf.capacity() > size.saturating_mul(4) {
+            return;
+        }
+        buf.clear();
+        let mut idle = self.inner.idle.lock().unwrap();
+        if idle.len() < self.inner.max_idle {
+            idle.push(buf);
+        }
+    }
+}
+
+/// The use of a [`BufferPool`], see [`BufferPool::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// The number of buffers allocated since the pool was created.
+    pub allocated: u64,
+    /// The number of times an idle buffer was reused instead of allocating one.
+    pub reused: u64,
+    /// The number of buffers kept for reuse now.
+    pub idle: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reuses_the_returned_buffers() {
+        let pool = BufferPool::new(1024, 1);
+
+        let first = pool.take(16);
+        assert!(first.capacity() >= 1024);
+        let second = pool.take(16);
+        pool.give(first);
+        pool.give(second);
+        assert_eq!(
+            pool.stats(),
+            BufferPoolStats {
+                allocated: 2,
+                reused: 0,
+                idle: 1,
+            }
+        );
+
+        let mut reused = pool.take(16);
+        assert!(reused.is_empty());
+        assert_eq!(pool.stats().reused, 1);
+
+        // A buffer grown far beyond the size is not kept.
+        reused.reserve(64 * 1024);
+        pool.give(reused);
+        assert_eq!(pool.stats().idle, 0);
+    }
+}
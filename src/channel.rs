@@ -0,0 +1,166 @@
+use bytes::{Buf, Bytes};
+use std::{
+    io::{self, Read},
+    sync::mpsc as std_mpsc,
+};
+#[cfg(feature = "tokio")]
+use {
+    std::{
+        convert::Infallible,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    tokio::sync::mpsc,
+    tokio_stream::Stream,
+};
+
+/// The receiving half of a bounded or an unbounded tokio channel.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+enum Rx<T> {
+    Bounded(mpsc::Receiver<T>),
+    Unbounded(mpsc::UnboundedReceiver<T>),
+}
+
+#[cfg(feature = "tokio")]
+impl<T> Rx<T> {
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self {
+            Self::Bounded(rx) => rx.poll_recv(cx),
+            Self::Unbounded(rx) => rx.poll_recv(cx),
+        }
+    }
+}
+
+/// An input stream over the receiving half of a tokio channel of chunks, which ends once every
+/// sender has been dropped.
+///
+/// It is [`Unpin`], so a [`ScannedStream`](crate::ScannedStream) over it can be polled without
+/// pinning. See [`ScannedStream::from_channel`](crate::ScannedStream::from_channel).
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct ChannelInput<B> {
+    rx: Rx<B>,
+}
+
+#[cfg(feature = "tokio")]
+impl<B> From<mpsc::Receiver<B>> for ChannelInput<B> {
+    fn from(rx: mpsc::Receiver<B>) -> Self {
+        Self {
+            rx: Rx::Bounded(rx),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<B> From<mpsc::UnboundedReceiver<B>> for ChannelInput<B> {
+    fn from(rx: mpsc::UnboundedReceiver<B>) -> Self {
+        Self {
+            rx: Rx::Unbounded(rx),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<B> Stream for ChannelInput<B> {
+    type Item = Result<B, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx).map(|chunk| chunk.map(Ok))
+    }
+}
+
+/// An input stream over the receiving half of a tokio channel of fallible chunks, such as
+/// `Result<Bytes, io::Error>`, which ends once every sender has been dropped.
+///
+/// A producer reports a failure by sending an `Err`, which the
+/// [`ScannedStream`](crate::ScannedStream) returns as [`Error::Stream`](crate::Error::Stream).
+/// See [`ScannedStream::from_try_channel`](crate::ScannedStream::from_try_channel).
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct TryChannelInput<B, E> {
+    rx: Rx<Result<B, E>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<B, E> From<mpsc::Receiver<Result<B, E>>> for TryChannelInput<B, E> {
+    fn from(rx: mpsc::Receiver<Result<B, E>>) -> Self {
+        Self {
+            rx: Rx::Bounded(rx),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<B, E> From<mpsc::UnboundedReceiver<Result<B, E>>> for TryChannelInput<B, E> {
+    fn from(rx: mpsc::UnboundedReceiver<Result<B, E>>) -> Self {
+        Self {
+            rx: Rx::Unbounded(rx),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<B, E> Stream for TryChannelInput<B, E> {
+    type Item = Result<B, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// A [`Read`] over the receiving half of a std channel of chunks, e.g. to feed a
+/// [`ScannedReader`](crate::ScannedReader) from a producer thread. Reading blocks until a
+/// chunk is received, and returns the end of file once every sender has been dropped.
+#[derive(Debug)]
+pub struct ChannelReader<B> {
+    rx: std_mpsc::Receiver<B>,
+    chunk: Bytes,
+}
+
+impl<B> ChannelReader<B> {
+    /// Read the chunks received from the channel.
+    pub fn new(rx: std_mpsc::Receiver<B>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl<B: Into<Bytes>> Read for ChannelReader<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.recv() {
+                Ok(chunk) => self.chunk = chunk.into(),
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn it_reads_the_chunks_until_the_senders_are_dropped() {
+        let (tx, rx) = std_mpsc::channel();
+        let producer = thread::spawn(move || {
+            for chunk in ["Hello", "", " World"] {
+                tx.send(chunk).unwrap();
+            }
+        });
+
+        let mut content = String::new();
+        ChannelReader::new(rx).read_to_string(&mut content).unwrap();
+        producer.join().unwrap();
+        assert_eq!(content, "Hello World");
+    }
+}
@@ -0,0 +1,133 @@
+use crate::Error;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// The expected digest of a content, verified once the content has been passed through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// A SHA-256 digest.
+    Sha256([u8; 32]),
+    /// A MD5 digest.
+    Md5([u8; 16]),
+}
+
+impl Checksum {
+    /// Parse a hex encoded SHA-256 digest.
+    pub fn sha256_hex(hex: &str) -> Option<Self> {
+        decode_hex(hex).map(Self::Sha256)
+    }
+
+    /// Parse a hex encoded MD5 digest.
+    pub fn md5_hex(hex: &str) -> Option<Self> {
+        decode_hex(hex).map(Self::Md5)
+    }
+
+    /// Parse an `ETag` holding the MD5 digest of the content, as returned by S3 for single part
+    /// uploads. Quotes and the weak validator prefix are ignored.
+    pub fn from_etag(etag: &str) -> Option<Self> {
+        let etag = etag.trim().trim_start_matches("W/").trim_matches('"');
+        Self::md5_hex(etag)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Sha256(digest) => digest,
+            Self::Md5(digest) => digest,
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_bytes()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// Computes the digest of the content while it is passed through.
+pub(crate) struct Hasher {
+    expected: Checksum,
+    state: State,
+}
+
+enum State {
+    Sha256(Sha256),
+    Md5(Md5),
+}
+
+impl Hasher {
+    pub(crate) fn new(expected: Checksum) -> Self {
+        let state = match expected {
+            Checksum::Sha256(_) => State::Sha256(Sha256::new()),
+            Checksum::Md5(_) => State::Md5(Md5::new()),
+        };
+        Self { expected, state }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        match &mut self.state {
+            State::Sha256(hasher) => hasher.update(bytes),
+            State::Md5(hasher) => hasher.update(bytes),
+        }
+    }
+
+    pub(crate) fn verify(self) -> Result<(), Error> {
+        let actual = match self.state {
+            State::Sha256(hasher) => Checksum::Sha256(hasher.finalize().into()),
+            State::Md5(hasher) => Checksum::Md5(hasher.finalize().into()),
+        };
+
+        if actual == self.expected {
+            Ok(())
+        } else {
+            Err(Error::ChecksumMismatch {
+                expected: self.expected.to_string(),
+                actual: actual.to_string(),
+            })
+        }
+    }
+}
+
+fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 {
+        return None;
+    }
+
+    let mut digest = [0u8; N];
+    for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_WORLD_MD5: &str = "b10a8db164e0754105b7a99be72e3fe5";
+
+    #[test]
+    fn it_parses_etags() {
+        let checksum = Checksum::from_etag(&format!("W/\"{HELLO_WORLD_MD5}\"")).unwrap();
+        assert_eq!(checksum.to_string(), HELLO_WORLD_MD5);
+        assert!(Checksum::from_etag("\"abc\"").is_none());
+    }
+
+    #[test]
+    fn it_verifies_the_digest() {
+        let mut hasher = Hasher::new(Checksum::md5_hex(HELLO_WORLD_MD5).unwrap());
+        hasher.update(b"Hello ");
+        hasher.update(b"World");
+        assert!(hasher.verify().is_ok());
+
+        let mut hasher = Hasher::new(Checksum::md5_hex(HELLO_WORLD_MD5).unwrap());
+        hasher.update(b"Hello");
+        assert!(matches!(
+            hasher.verify(),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+}
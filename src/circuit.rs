@@ -0,0 +1,256 @@
+use crate::ConfigIssue;
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// What a [`Scanner`](crate::Scanner) does with new streams while its circuit is open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Refuse new streams with [`Error::Unavailable`](crate::Error::Unavailable).
+    #[default]
+    FailClosed,
+    /// Pass new streams through without scanning them. Their verdict is
+    /// [`ScanOutcome::Skipped`](crate::ScanOutcome::Skipped).
+    FailOpen,
+}
+
+/// Configuration of the circuit breaker of a [`Scanner`](crate::Scanner).
+///
+/// After `threshold` consecutive transport failures the circuit opens, and new streams are
+/// handled by the [`FailurePolicy`] without attempting a connection. Once the cooldown has
+/// elapsed, the next stream probes the clamav with `PING` and the circuit closes if it answers.
+/// The streams arriving while it probes are handled by the [`FailurePolicy`] as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    policy: FailurePolicy,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit for `cooldown` after `threshold` consecutive transport failures.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            policy: FailurePolicy::default(),
+        }
+    }
+
+    /// Choose what to do with new streams while the circuit is open. Defaults to
+    /// [`FailurePolicy::FailClosed`].
+    pub fn policy(mut self, policy: FailurePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        if self.threshold == 0 {
+            issues.push(ConfigIssue::ZeroCircuitThreshold);
+        }
+        if self.cooldown.is_zero() {
+            issues.push(ConfigIssue::ZeroDuration("circuit breaker cooldown"));
+        }
+    }
+}
+
+/// The state of the circuit breaker of a [`Scanner`](crate::Scanner).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Streams are scanned as usual.
+    Closed,
+    /// Streams are handled by the [`FailurePolicy`] until the cooldown elapses.
+    Open {
+        /// The time until the next probe.
+        retry_in: Duration,
+    },
+    /// The cooldown has elapsed, and the next stream probes the clamav.
+    HalfOpen,
+}
+
+/// Counts the consecutive transport failures of the scans of a [`Scanner`](crate::Scanner).
+#[derive(Debug)]
+pub(crate) struct Circuit {
+    config: CircuitBreaker,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    failures: u32,
+    opened_at: Option<Instant>,
+    /// Whether a scan is probing the clamav.
+    probing: bool,
+}
+
+impl Circuit {
+    pub(crate) fn new(config: CircuitBreaker) -> Self {
+        Self {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> FailurePolicy {
+        self.config.policy
+    }
+
+    pub(crate) fn state(&self) -> CircuitState {
+        self.state_of(&self.state.lock().unwrap())
+    }
+
+    fn state_of(&self, state: &State) -> CircuitState {
+        match state.opened_at {
+            Some(opened_at) => match self.config.cooldown.checked_sub(opened_at.elapsed()) {
+                Some(retry_in) if !retry_in.is_zero() => CircuitState::Open { retry_in },
+                _ => CircuitState::HalfOpen,
+            },
+            None => CircuitState::Closed,
+        }
+    }
+
+    /// Decide whether a new scan may connect, probing the clamav with the function once the
+    /// cooldown has elapsed. Returns the time until the next probe if it may not, or zero while
+    /// another scan probes.
+    pub(crate) fn admit(&self, probe: impl FnOnce() -> bool) -> Result<(), Duration> {
+        match self.enter()? {
+            Some(probing) => self.probed(probing, probe()),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`Circuit::admit`], for a probe which does not block the thread.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn admit_async(
+        &self,
+        probe: impl Future<Output = bool>,
+    ) -> Result<(), Duration> {
+        match self.enter()? {
+            Some(probing) => self.probed(probing, probe.await),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the guard of the probe if the scan has to probe the clamav before connecting.
+    fn enter(&self) -> Result<Option<Probing<'_>>, Duration> {
+        let mut state = self.state.lock().unwrap();
+        match self.state_of(&state) {
+            CircuitState::Closed => Ok(None),
+            CircuitState::Open { retry_in } => Err(retry_in),
+            CircuitState::HalfOpen if state.probing => Err(Duration::ZERO),
+            CircuitState::HalfOpen => {
+                state.probing = true;
+                Ok(Some(Probing(&self.state)))
+            }
+        }
+    }
+
+    fn probed(&self, probing: Probing<'_>, alive: bool) -> Result<(), Duration> {
+        let result = if alive {
+            self.success();
+            Ok(())
+        } else {
+            self.state.lock().unwrap().opened_at = Some(Instant::now());
+            Err(self.config.cooldown)
+        };
+        drop(probing);
+        result
+    }
+
+    pub(crate) fn success(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    pub(crate) fn failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.config.threshold && state.opened_at.is_none() {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Lets the next scan probe the clamav, even if the probe panics.
+struct Probing<'a>(&'a Mutex<State>);
+
+impl Drop for Probing<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().probing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread};
+
+    #[test]
+    fn it_opens_after_consecutive_failures() {
+        let circuit = Circuit::new(CircuitBreaker::new(2, Duration::from_secs(60)));
+
+        circuit.failure();
+        assert_eq!(circuit.state(), CircuitState::Closed);
+        circuit.success();
+        circuit.failure();
+        assert_eq!(circuit.state(), CircuitState::Closed);
+        circuit.failure();
+        assert!(matches!(circuit.state(), CircuitState::Open { .. }));
+        assert!(circuit.admit(|| unreachable!()).is_err());
+    }
+
+    #[test]
+    fn it_closes_after_a_successful_probe() {
+        let circuit = Circuit::new(CircuitBreaker::new(1, Duration::ZERO));
+
+        circuit.failure();
+        assert_eq!(circuit.state(), CircuitState::HalfOpen);
+        assert_eq!(circuit.admit(|| false), Err(Duration::ZERO));
+        assert_eq!(circuit.admit(|| true), Ok(()));
+        assert_eq!(circuit.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn it_lets_a_single_scan_probe_at_a_time() {
+        let circuit = Circuit::new(CircuitBreaker::new(1, Duration::ZERO));
+        circuit.failure();
+
+        let (probing_tx, probing_rx) = mpsc::channel();
+        let (answer_tx, answer_rx) = mpsc::channel();
+        thread::scope(|scope| {
+            let circuit = &circuit;
+            let prober = scope.spawn(move || {
+                circuit.admit(|| {
+                    probing_tx.send(()).unwrap();
+                    answer_rx.recv().unwrap()
+                })
+            });
+
+            probing_rx.recv().unwrap();
+            assert_eq!(circuit.admit(|| unreachable!()), Err(Duration::ZERO));
+            answer_tx.send(true).unwrap();
+            assert_eq!(prober.join().unwrap(), Ok(()));
+        });
+        assert_eq!(circuit.state(), CircuitState::Closed);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn it_lets_the_next_scan_probe_once_an_async_probe_is_dropped() {
+        use std::{future::poll_fn, task::Poll};
+
+        let circuit = Circuit::new(CircuitBreaker::new(1, Duration::ZERO));
+        circuit.failure();
+
+        let mut probe = Box::pin(circuit.admit_async(std::future::pending()));
+        assert!(poll_fn(|cx| Poll::Ready(probe.as_mut().poll(cx).is_pending())).await);
+        assert_eq!(circuit.admit(|| unreachable!()), Err(Duration::ZERO));
+
+        drop(probe);
+        assert_eq!(circuit.admit_async(async { true }).await, Ok(()));
+        assert_eq!(circuit.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{Error, Progress, ScannedStream};
+
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use bytes::Bytes;
+use pin_project::pin_project;
+use std::{
+    error::Error as StdError,
+    io::{self, Read, Write},
+    mem,
+    net::{TcpStream, ToSocketAddrs},
+    pin::Pin,
+    task::{ready, Context, Poll, Waker},
+};
+use tokio::io::AsyncWrite;
+use tokio_stream::Stream;
+
+/// The compression format of a [`CompressedStream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// A gzip member, as written by `gzip`.
+    Gzip,
+    /// A zstd frame, as written by `zstd`.
+    Zstd,
+}
+
+/// Scan the raw content while compressing it with the codec, e.g. to store an upload
+/// compressed while the clamav scans what was uploaded, not its compressed form.
+pub fn scan_then_compress<St, B, E>(
+    input: St,
+    addr: impl ToSocketAddrs,
+    codec: Codec,
+) -> Result<CompressedStream<St, TcpStream>, Error>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    let inner = TcpStream::connect(addr)?;
+    Ok(CompressedStream::new(
+        ScannedStream::new(input, inner),
+        codec,
+    ))
+}
+
+/// A wrapper of a [`ScannedStream`] which yields the content compressed instead of raw.
+///
+/// The compressed frames are yielded as the compressor produces them, but the end of the
+/// compressed output is only yielded once the clamav has found the content clean. If a virus
+/// is detected, the stream ends with the [`Error`] instead, and the output is not a complete
+/// archive.
+#[pin_project]
+pub struct CompressedStream<St, RW: Read + Write> {
+    #[pin]
+    scanned: ScannedStream<St, RW>,
+    /// `None` once the output has been completed or has failed.
+    encoder: Option<Encoder>,
+}
+
+impl<St, RW, B, E> CompressedStream<St, RW>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    RW: Read + Write,
+    E: StdError,
+{
+    /// Compress the content passed through by the stream with the codec.
+    pub fn new(scanned: ScannedStream<St, RW>, codec: Codec) -> Self {
+        Self {
+            scanned,
+            encoder: Some(Encoder::new(codec)),
+        }
+    }
+
+    /// A clonable handle to follow the scan while the stream is consumed.
+    pub fn progress(&self) -> Progress {
+        self.scanned.progress()
+    }
+}
+
+impl<St, RW, B, E> Stream for CompressedStream<St, RW>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    RW: Read + Write,
+    E: StdError + Send + Sync + 'static,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut me = self.project();
+
+        loop {
+            let Some(encoder) = me.encoder.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            let result = match ready!(me.scanned.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => match encoder.write(&chunk) {
+                    // The compressor keeps small chunks until it has a block to write.
+                    Ok(frame) if frame.is_empty() => continue,
+                    Ok(frame) => return Poll::Ready(Some(Ok(frame))),
+                    Err(err) => Err(err.into()),
+                },
+                Some(Err(err)) => Err(err),
+                None => encoder.finish().map_err(Error::from),
+            };
+
+            *me.encoder = None;
+            return Poll::Ready(Some(result));
+        }
+    }
+}
+
+/// A compressor writing to memory, whose output is taken after every write.
+enum Encoder {
+    Gzip(GzipEncoder<Vec<u8>>),
+    Zstd(ZstdEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(codec: Codec) -> Self {
+        match codec {
+            Codec::Gzip => Self::Gzip(GzipEncoder::new(vec![])),
+            Codec::Zstd => Self::Zstd(ZstdEncoder::new(vec![])),
+        }
+    }
+
+    /// Compress the chunk, and return the compressed bytes produced so far.
+    fn write(&mut self, mut chunk: &[u8]) -> io::Result<Bytes> {
+        while !chunk.is_empty() {
+            let written = match self {
+                Self::Gzip(encoder) => complete(Pin::new(encoder).poll_write(&mut noop(), chunk)),
+                Self::Zstd(encoder) => complete(Pin::new(encoder).poll_write(&mut noop(), chunk)),
+            }?;
+            chunk = &chunk[written..];
+        }
+        Ok(self.take())
+    }
+
+    /// Write the end of the compressed output, and return the compressed bytes left.
+    fn finish(&mut self) -> io::Result<Bytes> {
+        match self {
+            Self::Gzip(encoder) => complete(Pin::new(encoder).poll_shutdown(&mut noop())),
+            Self::Zstd(encoder) => complete(Pin::new(encoder).poll_shutdown(&mut noop())),
+        }?;
+        Ok(self.take())
+    }
+
+    fn take(&mut self) -> Bytes {
+        let output = match self {
+            Self::Gzip(encoder) => encoder.get_mut(),
+            Self::Zstd(encoder) => encoder.get_mut(),
+        };
+        Bytes::from(mem::take(output))
+    }
+}
+
+fn noop() -> Context<'static> {
+    Context::from_waker(Waker::noop())
+}
+
+/// The result of a write to the memory, which never has to wait.
+fn complete<T>(poll: Poll<io::Result<T>>) -> io::Result<T> {
+    match poll {
+        Poll::Ready(result) => result,
+        Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_clamd;
+    use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+    use tokio::io::AsyncReadExt;
+    use tokio_stream::StreamExt;
+
+    fn input() -> tokio_stream::Iter<std::vec::IntoIter<Result<Bytes, Error>>> {
+        tokio_stream::iter(vec![Ok(Bytes::from("Hello ")), Ok(Bytes::from("World"))])
+    }
+
+    async fn compressed<St>(stream: St) -> Result<Vec<u8>, Error>
+    where
+        St: Stream<Item = Result<Bytes, Error>>,
+    {
+        let mut stream = std::pin::pin!(stream);
+        let mut output = vec![];
+        while let Some(frame) = stream.next().await {
+            output.extend_from_slice(&frame?);
+        }
+        Ok(output)
+    }
+
+    #[tokio::test]
+    async fn it_scans_the_raw_content_and_yields_it_compressed() {
+        for codec in [Codec::Gzip, Codec::Zstd] {
+            let (addr, server) = fake_clamd(b"stream: OK\0");
+            let stream = scan_then_compress(input(), addr, codec).unwrap();
+            let output = compressed(stream).await.unwrap();
+
+            let mut raw = String::new();
+            match codec {
+                Codec::Gzip => GzipDecoder::new(output.as_slice())
+                    .read_to_string(&mut raw)
+                    .await
+                    .unwrap(),
+                Codec::Zstd => ZstdDecoder::new(output.as_slice())
+                    .read_to_string(&mut raw)
+                    .await
+                    .unwrap(),
+            };
+            assert_eq!(raw, "Hello World");
+
+            let received = server.join().unwrap();
+            assert!(received.windows(6).any(|w| w == b"Hello "));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_does_not_complete_the_output_of_an_infected_content() {
+        let (addr, _server) = fake_clamd(b"stream: Eicar-Signature FOUND\0");
+        let stream = scan_then_compress(input(), addr, Codec::Gzip).unwrap();
+        assert!(matches!(compressed(stream).await, Err(Error::Scan(_))));
+    }
+}
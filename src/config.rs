@@ -0,0 +1,128 @@
+use std::{fmt, path::PathBuf, time::Duration};
+
+/// A problem found by [`ScannerBuilder::validate`](crate::ScannerBuilder::validate).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigIssue {
+    /// The tcp address resolved to no socket address, so every connection would fail.
+    NoTcpAddress,
+
+    /// The unix socket of the clamav does not exist.
+    #[cfg(unix)]
+    SocketNotFound(PathBuf),
+
+    /// The chunk size is larger than the default `StreamMaxLength` of clamd, so that a single
+    /// chunk may already be rejected.
+    ChunkSizeTooLarge {
+        /// The configured chunk size.
+        size: usize,
+        /// The limit, [`STREAM_MAX_LENGTH`](crate::protocol::STREAM_MAX_LENGTH).
+        max: usize,
+    },
+
+    /// The smallest chunk size of an [`AdaptiveChunkSize`](crate::AdaptiveChunkSize) is larger
+    /// than its largest one.
+    ChunkSizeMinExceedsMax {
+        /// The smallest chunk size.
+        min: usize,
+        /// The largest chunk size.
+        max: usize,
+    },
+
+    /// A duration which must be positive is zero.
+    ZeroDuration(&'static str),
+
+    /// The initial delay of the [`Backoff`](crate::Backoff) is longer than its maximum.
+    BackoffInitialExceedsMax {
+        /// The initial delay.
+        initial: Duration,
+        /// The maximum delay.
+        max: Duration,
+    },
+
+    /// The [`CircuitBreaker`](crate::CircuitBreaker) opens after zero failures, i.e. never
+    /// lets a stream through.
+    ZeroCircuitThreshold,
+
+    /// The connection pool keeps no idle connection, so nothing is ever reused.
+    ZeroPoolSize,
+
+    /// A directory to create temp files in does not exist.
+    DirNotFound {
+        /// What the directory is for.
+        purpose: &'static str,
+        /// The missing directory.
+        dir: PathBuf,
+    },
+
+    /// A [`ShardedScanner`](crate::ShardedScanner) is given no scanner to route to.
+    NoShards,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoTcpAddress => write!(f, "the tcp address resolved to no socket address"),
+            #[cfg(unix)]
+            Self::SocketNotFound(path) => {
+                write!(f, "the unix socket {} does not exist", path.display())
+            }
+            Self::ChunkSizeTooLarge { size, max } => write!(
+                f,
+                "chunk size {size} exceeds the default StreamMaxLength {max} of clamd"
+            ),
+            Self::ChunkSizeMinExceedsMax { min, max } => {
+                write!(f, "adaptive chunk size {min} exceeds its maximum {max}")
+            }
+            Self::ZeroDuration(name) => write!(f, "{name} must be longer than zero"),
+            Self::BackoffInitialExceedsMax { initial, max } => write!(
+                f,
+                "backoff initial delay {initial:?} exceeds its maximum {max:?}"
+            ),
+            Self::ZeroCircuitThreshold => write!(f, "circuit breaker threshold must be at least 1"),
+            Self::ZeroPoolSize => write!(f, "pool must keep at least 1 idle connection"),
+            Self::DirNotFound { purpose, dir } => {
+                write!(f, "{purpose} directory {} does not exist", dir.display())
+            }
+            Self::NoShards => write!(f, "a sharded scanner needs at least one scanner"),
+        }
+    }
+}
+
+/// The error returned by [`ScannerBuilder::validate`](crate::ScannerBuilder::validate),
+/// [`ScannerBuilder::try_build`](crate::ScannerBuilder::try_build) and
+/// [`ShardedScanner::new`](crate::ShardedScanner::new), listing every problem of the
+/// configuration at once instead of only the first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    issues: Vec<ConfigIssue>,
+}
+
+impl ConfigError {
+    pub(crate) fn from_issues(issues: Vec<ConfigIssue>) -> Result<(), Self> {
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(Self { issues })
+        }
+    }
+
+    /// The problems found, in the order they were checked.
+    pub fn issues(&self) -> &[ConfigIssue] {
+        &self.issues
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid scanner configuration: ")?;
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
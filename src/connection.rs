@@ -0,0 +1,782 @@
+use crate::ConfigIssue;
+
+#[cfg(feature = "tcp-options")]
+use socket2::{SockRef, TcpKeepalive};
+#[cfg(all(feature = "tokio", unix))]
+use std::os::fd::{AsFd, OwnedFd};
+#[cfg(all(feature = "tokio", windows))]
+use std::os::windows::io::{AsSocket, OwnedSocket};
+use std::{
+    fmt,
+    io::{self, IoSlice, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+#[cfg(feature = "tokio")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[cfg(unix)]
+use crate::Error;
+#[cfg(target_os = "linux")]
+use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr as UnixSocketAddr};
+#[cfg(unix)]
+use std::{
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::Path,
+};
+#[cfg(unix)]
+use std::{
+    os::unix::{
+        io::{AsRawFd, RawFd},
+        net::UnixStream,
+    },
+    path::PathBuf,
+};
+
+/// The address of a clamav server.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Address {
+    /// Tcp socket addresses, tried in order until one of them accepts the connection.
+    Tcp(Vec<SocketAddr>),
+
+    /// Path to a unix socket.
+    #[cfg(unix)]
+    Unix(PathBuf),
+
+    /// Name of a unix socket in the abstract namespace of Linux, which has no file, without
+    /// the leading nul byte.
+    #[cfg(target_os = "linux")]
+    UnixAbstract(Vec<u8>),
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addrs) => match addrs.first() {
+                Some(addr) => write!(f, "{addr}"),
+                None => write!(f, "tcp"),
+            },
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            #[cfg(target_os = "linux")]
+            Self::UnixAbstract(name) => write!(f, "@{}", String::from_utf8_lossy(name)),
+        }
+    }
+}
+
+impl Address {
+    /// Resolve the given value to tcp socket addresses.
+    pub fn tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self::Tcp(addr.to_socket_addrs()?.collect()))
+    }
+
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        match self {
+            Self::Tcp(addrs) if addrs.is_empty() => issues.push(ConfigIssue::NoTcpAddress),
+            Self::Tcp(_) => {}
+            #[cfg(unix)]
+            Self::Unix(path) if !path.exists() => {
+                issues.push(ConfigIssue::SocketNotFound(path.clone()))
+            }
+            #[cfg(unix)]
+            Self::Unix(_) => {}
+            #[cfg(target_os = "linux")]
+            Self::UnixAbstract(_) => {}
+        }
+    }
+
+    /// Open a new [`Connection`] to the address.
+    pub fn connect(&self) -> io::Result<Connection> {
+        self.connect_with(&TcpOptions::default())
+    }
+
+    /// Open a new [`Connection`] to the address, applying the options to tcp sockets.
+    pub fn connect_with(&self, options: &TcpOptions) -> io::Result<Connection> {
+        match self {
+            Self::Tcp(addrs) => {
+                let stream = TcpStream::connect(addrs.as_slice())?;
+                options.apply_std(&stream)?;
+                Ok(Connection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Self::Unix(path) => UnixStream::connect(path).map(Connection::Unix),
+            #[cfg(target_os = "linux")]
+            Self::UnixAbstract(name) => connect_abstract(name).map(Connection::Unix),
+        }
+    }
+
+    /// Open a new [`AsyncConnection`] to the address, applying the options to tcp sockets.
+    #[cfg(feature = "tokio")]
+    pub async fn connect_async(&self, options: &TcpOptions) -> io::Result<AsyncConnection> {
+        match self {
+            Self::Tcp(addrs) => {
+                let stream = tokio::net::TcpStream::connect(addrs.as_slice()).await?;
+                options.apply_async(&stream)?;
+                Ok(AsyncConnection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Self::Unix(path) => tokio::net::UnixStream::connect(path)
+                .await
+                .map(AsyncConnection::Unix),
+            // Connecting to a local socket does not wait for the clamav to accept.
+            #[cfg(target_os = "linux")]
+            Self::UnixAbstract(name) => {
+                let stream = connect_abstract(name)?;
+                stream.set_nonblocking(true)?;
+                tokio::net::UnixStream::from_std(stream).map(AsyncConnection::Unix)
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(name: &[u8]) -> io::Result<UnixStream> {
+    UnixStream::connect_addr(&UnixSocketAddr::from_abstract_name(name)?)
+}
+
+/// Socket options of the tcp connections to a clamav server.
+///
+/// The defaults leave the socket as the OS opens it. Setting `TCP_NODELAY` avoids the delay
+/// Nagle's algorithm adds between the 4 bytes length prefix and the chunk it precedes.
+///
+/// The keepalive and the send buffer size are only available with the `tcp-options` feature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TcpOptions {
+    nodelay: bool,
+    #[cfg(feature = "tcp-options")]
+    keepalive: Option<Duration>,
+    #[cfg(feature = "tcp-options")]
+    send_buffer_size: Option<usize>,
+}
+
+impl TcpOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `TCP_NODELAY` on the socket.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enable `SO_KEEPALIVE`, probing the connection after it has been idle for the given time.
+    #[cfg(feature = "tcp-options")]
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Set `SO_SNDBUF` on the socket.
+    #[cfg(feature = "tcp-options")]
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    #[cfg(feature = "tcp-options")]
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        if self.keepalive.is_some_and(|idle| idle.is_zero()) {
+            issues.push(ConfigIssue::ZeroDuration("tcp keepalive"));
+        }
+    }
+
+    /// Apply the options to a blocking tcp socket.
+    fn apply_std(&self, stream: &TcpStream) -> io::Result<()> {
+        #[cfg(feature = "tcp-options")]
+        return self.apply(SockRef::from(stream));
+
+        #[cfg(not(feature = "tcp-options"))]
+        {
+            if self.nodelay {
+                stream.set_nodelay(true)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Apply the options to an asynchronous tcp socket.
+    #[cfg(feature = "tokio")]
+    fn apply_async(&self, stream: &tokio::net::TcpStream) -> io::Result<()> {
+        #[cfg(feature = "tcp-options")]
+        return self.apply(SockRef::from(stream));
+
+        #[cfg(not(feature = "tcp-options"))]
+        {
+            if self.nodelay {
+                stream.set_nodelay(true)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tcp-options")]
+    fn apply(&self, socket: SockRef<'_>) -> io::Result<()> {
+        if self.nodelay {
+            socket.set_tcp_nodelay(true)?;
+        }
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks on the peer of a unix socket connection to the clamav, for hardened multi-tenant
+/// hosts where another user could have bound the socket path.
+///
+/// The credentials of the process listening on the socket are read with `SO_PEERCRED`, or
+/// `getpeereid` on the BSDs and macOS, right after connecting and before anything is sent.
+/// The socket file itself can be checked before connecting, see
+/// [`UnixSocketOptions::check_socket`].
+#[cfg(unix)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnixSocketOptions {
+    uid: Option<u32>,
+    gid: Option<u32>,
+    check_socket: bool,
+    owner: Option<u32>,
+}
+
+#[cfg(unix)]
+impl UnixSocketOptions {
+    /// Create the default options, which check nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse the connection unless the peer runs as the given user id, e.g. the one of the
+    /// `clamav` user.
+    pub fn expected_uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Refuse the connection unless the peer runs with the given group id.
+    pub fn expected_gid(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Check that the socket file exists before connecting, failing with
+    /// [`Error::SocketNotFound`](crate::Error::SocketNotFound) instead of the io error of the
+    /// connection. Sockets in the abstract namespace have no file to check.
+    pub fn check_socket(mut self, check: bool) -> Self {
+        self.check_socket = check;
+        self
+    }
+
+    /// Check that the socket file is owned by the given user id before connecting, failing
+    /// with [`io::ErrorKind::PermissionDenied`] otherwise. Implies
+    /// [`UnixSocketOptions::check_socket`].
+    pub fn expected_owner(mut self, uid: u32) -> Self {
+        self.owner = Some(uid);
+        self.check_socket = true;
+        self
+    }
+
+    /// Check the socket file at the path, see [`UnixSocketOptions::check_socket`].
+    pub(crate) fn check_path(&self, path: &Path) -> Result<(), Error> {
+        if !self.check_socket {
+            return Ok(());
+        }
+
+        let metadata = match path.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::SocketNotFound {
+                    path: path.to_path_buf(),
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("clamav socket {} is not a socket", path.display()),
+            )
+            .into());
+        }
+        if let Some(expected) = self.owner.filter(|expected| *expected != metadata.uid()) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "clamav socket {} is owned by uid {}, expected {expected}",
+                    path.display(),
+                    metadata.uid()
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Check the credentials of the peer of the socket, failing with
+    /// [`io::ErrorKind::PermissionDenied`] if they differ from the expected ones.
+    pub(crate) fn verify(&self, socket: &impl AsRawFd) -> io::Result<()> {
+        if self.uid.is_none() && self.gid.is_none() {
+            return Ok(());
+        }
+
+        let (uid, gid) = peer_cred(socket.as_raw_fd())?;
+        if let Some(expected) = self.uid.filter(|expected| *expected != uid) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("clamav socket peer runs as uid {uid}, expected {expected}"),
+            ));
+        }
+        if let Some(expected) = self.gid.filter(|expected| *expected != gid) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("clamav socket peer runs as gid {gid}, expected {expected}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The user and group ids of the peer of a unix socket.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_cred(fd: RawFd) -> io::Result<(u32, u32)> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and `len` holds the size of `cred`.
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    match ret {
+        0 => Ok((cred.uid, cred.gid)),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The user and group ids of the peer of a unix socket.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+fn peer_cred(fd: RawFd) -> io::Result<(u32, u32)> {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: `uid` and `gid` are valid for writes.
+    match unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } {
+        0 => Ok((uid, gid)),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The user and group ids of the peer of a unix socket.
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+))]
+fn peer_cred(_fd: RawFd) -> io::Result<(u32, u32)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "peer credentials are not supported on this platform",
+    ))
+}
+
+/// A byte stream to a clamav server opened by a user-supplied connector, e.g. a TLS stream or a
+/// tunnel through a proxy. See [`ScannerBuilder::connector`](crate::ScannerBuilder::connector).
+pub trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+impl fmt::Debug for dyn Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transport")
+    }
+}
+
+/// A connection to a clamav server opened from an [`Address`].
+#[derive(Debug)]
+pub enum Connection {
+    /// Connection over a tcp socket.
+    Tcp(TcpStream),
+
+    /// Connection over a unix socket.
+    #[cfg(unix)]
+    Unix(UnixStream),
+
+    /// Connection over a [`Transport`] opened by a user-supplied connector.
+    ///
+    /// It can neither be cloned, shut down from another handle nor moved into nonblocking mode.
+    Custom(Box<dyn Transport>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.read(buf),
+            Self::Custom(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Read for &Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => (&*stream).read(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => (&*stream).read(buf),
+            Connection::Custom(_) => Err(unsupported()),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write(buf),
+            Self::Custom(stream) => stream.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write_vectored(bufs),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.write_vectored(bufs),
+            Self::Custom(stream) => stream.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.flush(),
+            Self::Custom(stream) => stream.flush(),
+        }
+    }
+}
+
+/// The asynchronous counterpart of [`Transport`]. See
+/// [`ScannerBuilder::async_connector`](crate::ScannerBuilder::async_connector).
+#[cfg(feature = "tokio")]
+pub trait AsyncTransport: AsyncRead + AsyncWrite + Send + Unpin {}
+
+#[cfg(feature = "tokio")]
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncTransport for T {}
+
+#[cfg(feature = "tokio")]
+impl fmt::Debug for dyn AsyncTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AsyncTransport")
+    }
+}
+
+/// An asynchronous connection to a clamav server opened from an [`Address`], driven by the
+/// tokio reactor.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub enum AsyncConnection {
+    /// Connection over a tcp socket.
+    Tcp(tokio::net::TcpStream),
+
+    /// Connection over a unix socket.
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+
+    /// Connection over an [`AsyncTransport`] opened by a user-supplied connector.
+    Custom(Box<dyn AsyncTransport>),
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for AsyncConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Custom(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for AsyncConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Custom(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Self::Custom(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.is_write_vectored(),
+            Self::Custom(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Custom(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Custom(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+impl Connection {
+    /// Create a new independently owned handle to the underlying socket.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.try_clone().map(Self::Unix),
+            Self::Custom(_) => Err(unsupported()),
+        }
+    }
+
+    /// Set the timeout of a blocking read, see [`TcpStream::set_read_timeout`].
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+            Self::Custom(_) => Err(unsupported()),
+        }
+    }
+
+    /// Set the timeout of a blocking write, see [`TcpStream::set_write_timeout`].
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_write_timeout(timeout),
+            Self::Custom(_) => Err(unsupported()),
+        }
+    }
+
+    /// Move the connection into or out of nonblocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_nonblocking(nonblocking),
+            Self::Custom(_) => Err(unsupported()),
+        }
+    }
+
+    /// Check the peer of a unix socket connection, see [`UnixSocketOptions`]. Tcp and custom
+    /// connections are not checked.
+    #[cfg(unix)]
+    pub fn verify_peer(&self, options: &UnixSocketOptions) -> io::Result<()> {
+        match self {
+            Self::Tcp(_) | Self::Custom(_) => Ok(()),
+            Self::Unix(stream) => options.verify(stream),
+        }
+    }
+
+    /// Shut down both the read and write halves of the connection.
+    pub fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.shutdown(Shutdown::Both),
+            Self::Custom(_) => Err(unsupported()),
+        }
+    }
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported by a custom connection",
+    )
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncConnection {
+    /// Check the peer of a unix socket connection, see [`UnixSocketOptions`]. Tcp and custom
+    /// connections are not checked.
+    #[cfg(unix)]
+    pub fn verify_peer(&self, options: &UnixSocketOptions) -> io::Result<()> {
+        match self {
+            Self::Tcp(_) | Self::Custom(_) => Ok(()),
+            Self::Unix(stream) => options.verify(stream),
+        }
+    }
+
+    /// A blocking handle to the same socket, e.g. to shut it down from another thread.
+    pub(crate) fn try_clone_blocking(&self) -> io::Result<Connection> {
+        match self {
+            Self::Tcp(stream) => duplicate(stream).map(|socket| Connection::Tcp(socket.into())),
+            #[cfg(unix)]
+            Self::Unix(stream) => duplicate(stream).map(|socket| Connection::Unix(socket.into())),
+            Self::Custom(_) => Err(unsupported()),
+        }
+    }
+}
+
+/// Duplicate the descriptor of a socket.
+#[cfg(all(feature = "tokio", unix))]
+fn duplicate(socket: &impl AsFd) -> io::Result<OwnedFd> {
+    socket.as_fd().try_clone_to_owned()
+}
+
+/// Duplicate the handle of a socket.
+#[cfg(all(feature = "tokio", windows))]
+fn duplicate(socket: &impl AsSocket) -> io::Result<OwnedSocket> {
+    socket.as_socket().try_clone_to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "tcp-options")]
+    use std::net::TcpListener;
+
+    #[cfg(feature = "tcp-options")]
+    #[test]
+    fn it_applies_the_tcp_options_on_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = Address::tcp(listener.local_addr().unwrap()).unwrap();
+
+        let options = TcpOptions::new()
+            .nodelay(true)
+            .keepalive(Duration::from_secs(30))
+            .send_buffer_size(64 * 1024);
+        let Connection::Tcp(stream) = address.connect_with(&options).unwrap() else {
+            panic!("expected a tcp connection");
+        };
+
+        let socket = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_verifies_the_credentials_of_the_unix_socket_peer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clamd.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let conn = Address::Unix(path).connect().unwrap();
+
+        // SAFETY: getuid and getgid cannot fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let matching = UnixSocketOptions::new().expected_uid(uid).expected_gid(gid);
+        assert!(conn.verify_peer(&matching).is_ok());
+
+        let other = UnixSocketOptions::new().expected_uid(uid + 1);
+        let err = conn.verify_peer(&other).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_checks_the_socket_file_before_connecting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clamd.sock");
+        let options = UnixSocketOptions::new().check_socket(true);
+        assert_eq!(
+            options.check_path(&path),
+            Err(Error::SocketNotFound { path: path.clone() })
+        );
+
+        let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert!(options.check_path(&path).is_ok());
+
+        // SAFETY: getuid cannot fail.
+        let uid = unsafe { libc::getuid() };
+        assert!(UnixSocketOptions::new()
+            .expected_owner(uid)
+            .check_path(&path)
+            .is_ok());
+        let Err(Error::Io(err)) = UnixSocketOptions::new()
+            .expected_owner(uid + 1)
+            .check_path(&path)
+        else {
+            panic!("expected an io error");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn it_connects_to_a_socket_in_the_abstract_namespace() {
+        let name = format!("clamav-stream-test-{}", std::process::id());
+        let addr = UnixSocketAddr::from_abstract_name(&name).unwrap();
+        let _listener = std::os::unix::net::UnixListener::bind_addr(&addr).unwrap();
+
+        let address = Address::UnixAbstract(name.clone().into_bytes());
+        assert_eq!(address.to_string(), format!("@{name}"));
+        assert!(matches!(address.connect(), Ok(Connection::Unix(_))));
+    }
+}
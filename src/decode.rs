@@ -0,0 +1,173 @@
+use bytes::{BufMut, BytesMut};
+use std::io;
+
+/// A decoding applied to the content before it is sent to the clamav, while the consumer still
+/// receives the encoded original.
+///
+/// Scanning encoded text, e.g. a base64 file field of a JSON body, misses most signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoding {
+    /// Base64 with the standard or the URL safe alphabet. Whitespace and padding are ignored.
+    Base64,
+    /// Quoted-printable as used in MIME bodies. Soft line breaks are removed.
+    QuotedPrintable,
+}
+
+/// Decodes the content chunk by chunk, keeping the incomplete sequence at the end of a chunk
+/// until the next one arrives.
+#[derive(Debug)]
+pub(crate) struct Decoder {
+    decoding: Decoding,
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub(crate) fn new(decoding: Decoding) -> Self {
+        Self {
+            decoding,
+            pending: vec![],
+        }
+    }
+
+    /// Decode the chunk, appending the decoded bytes to the output.
+    pub(crate) fn decode(&mut self, input: &[u8], output: &mut BytesMut) -> io::Result<()> {
+        match self.decoding {
+            Decoding::Base64 => self.decode_base64(input, output),
+            Decoding::QuotedPrintable => {
+                self.decode_quoted_printable(input, output);
+                Ok(())
+            }
+        }
+    }
+
+    /// Decode what is left at the end of the content.
+    pub(crate) fn finish(&mut self) -> io::Result<Vec<u8>> {
+        let pending = std::mem::take(&mut self.pending);
+        match self.decoding {
+            Decoding::Base64 => match pending.len() {
+                0 => Ok(vec![]),
+                1 => Err(invalid("truncated base64 content")),
+                _ => Ok(sextets_to_bytes(&pending)),
+            },
+            // A soft line break at the very end of the content.
+            Decoding::QuotedPrintable if pending == b"=\n" => Ok(vec![]),
+            Decoding::QuotedPrintable => Ok(pending),
+        }
+    }
+
+    fn decode_base64(&mut self, input: &[u8], output: &mut BytesMut) -> io::Result<()> {
+        output.reserve((self.pending.len() + input.len()) / 4 * 3);
+
+        for &byte in input {
+            let value = match byte {
+                b'A'..=b'Z' => byte - b'A',
+                b'a'..=b'z' => byte - b'a' + 26,
+                b'0'..=b'9' => byte - b'0' + 52,
+                b'+' | b'-' => 62,
+                b'/' | b'_' => 63,
+                b'=' | b' ' | b'\t' | b'\r' | b'\n' => continue,
+                _ => return Err(invalid("invalid base64 content")),
+            };
+
+            self.pending.push(value);
+            if self.pending.len() == 4 {
+                output.extend_from_slice(&sextets_to_bytes(&self.pending));
+                self.pending.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_quoted_printable(&mut self, input: &[u8], output: &mut BytesMut) {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(input);
+
+        output.reserve(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != b'=' {
+                output.put_u8(bytes[i]);
+                i += 1;
+                continue;
+            }
+
+            // An escape needs the two following bytes, which may be in the next chunk.
+            let (Some(&first), Some(&second)) = (bytes.get(i + 1), bytes.get(i + 2)) else {
+                self.pending = bytes[i..].to_vec();
+                break;
+            };
+
+            match (first, second) {
+                (b'\r', b'\n') => i += 3,
+                (b'\n', _) => i += 2,
+                _ => match (hex(first), hex(second)) {
+                    (Some(high), Some(low)) => {
+                        output.put_u8(high << 4 | low);
+                        i += 3;
+                    }
+                    // Keep malformed escapes as they are.
+                    _ => {
+                        output.put_u8(b'=');
+                        i += 1;
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn sextets_to_bytes(sextets: &[u8]) -> Vec<u8> {
+    let bits = sextets
+        .iter()
+        .fold(0u32, |acc, &value| acc << 6 | value as u32)
+        << (6 * (4 - sextets.len()));
+    let bytes = bits.to_be_bytes();
+    bytes[1..sextets.len()].to_vec()
+}
+
+fn hex(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_base64_split_across_chunks() {
+        let mut decoder = Decoder::new(Decoding::Base64);
+        let mut output = BytesMut::new();
+        for chunk in [&b"SGVsbG8g"[..], b"V29y\nbG", b"Q="] {
+            decoder.decode(chunk, &mut output).unwrap();
+        }
+        output.extend(decoder.finish().unwrap());
+        assert_eq!(&output[..], b"Hello World");
+    }
+
+    #[test]
+    fn it_rejects_invalid_base64() {
+        let mut output = BytesMut::new();
+        let mut decoder = Decoder::new(Decoding::Base64);
+        assert!(decoder.decode(b"SGVs*", &mut output).is_err());
+
+        let mut decoder = Decoder::new(Decoding::Base64);
+        decoder.decode(b"SGVsb", &mut output).unwrap();
+        assert!(decoder.finish().is_err());
+    }
+
+    #[test]
+    fn it_decodes_quoted_printable_split_across_chunks() {
+        let mut decoder = Decoder::new(Decoding::QuotedPrintable);
+        let mut output = BytesMut::new();
+        for chunk in [&b"caf=C3="[..], b"A9 =\r", b"\nau lait=3", b"D"] {
+            decoder.decode(chunk, &mut output).unwrap();
+        }
+        output.extend(decoder.finish().unwrap());
+        assert_eq!(&output[..], "café au lait=".as_bytes());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::BufferPool;
+
+use bytes::{BufMut, BytesMut};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// The default average block size of a [`BlockDedup`].
+pub const DEDUP_BLOCK_SIZE: usize = 64 * 1024;
+
+/// The smallest average block size of a [`BlockDedup`].
+const MIN_DEDUP_BLOCK_SIZE: usize = 4 * 1024;
+
+/// Skips the blocks of a content which are identical to a block already sent to the clamav in
+/// the same scan, for highly repetitive contents such as VM images or backups.
+///
+/// The content is cut into blocks where a rolling hash of the last bytes matches a pattern, so
+/// that a repeated run of bytes yields the same blocks wherever it starts. The blocks are
+/// between a quarter and four times the average block size long.
+///
+/// This changes what the clamav sees: a signature spanning a skipped block and its neighbours
+/// may be missed, so it is only enabled on request, with
+/// [`ScannedStream::with_block_dedup`](crate::ScannedStream::with_block_dedup) or
+/// [`ScannerBuilder::block_dedup`](crate::ScannerBuilder::block_dedup).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDedup {
+    block_size: usize,
+}
+
+impl BlockDedup {
+    /// Cut the content into blocks of about `block_size` bytes, rounded up to a power of two
+    /// of at least 4 KiB.
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            block_size: block_size.max(MIN_DEDUP_BLOCK_SIZE).next_power_of_two(),
+        }
+    }
+
+    /// The average block size.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+}
+
+impl Default for BlockDedup {
+    /// Cut the content into blocks of about [`DEDUP_BLOCK_SIZE`] bytes.
+    fn default() -> Self {
+        Self::new(DEDUP_BLOCK_SIZE)
+    }
+}
+
+/// Cuts the content of a scan into blocks and drops those already sent.
+#[derive(Debug)]
+pub(crate) struct Deduper {
+    mask: u64,
+    min: usize,
+    max: usize,
+    hash: u64,
+    block: BytesMut,
+    seen: HashSet<[u8; 32]>,
+}
+
+impl Deduper {
+    pub(crate) fn new(config: &BlockDedup) -> Self {
+        let size = config.block_size;
+        // The top bits of the gear hash depend on the most bytes.
+        let bits = size.trailing_zeros();
+        Self {
+            mask: (u64::MAX >> (64 - bits)) << (64 - bits),
+            min: size / 4,
+            max: size * 4,
+            hash: 0,
+            block: BytesMut::with_capacity(size),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// The bytes kept until the end of their block is found.
+    pub(crate) fn buffered(&self) -> usize {
+        self.block.len()
+    }
+
+    /// Add the bytes to the current block, and return the blocks completed by them which have
+    /// not been sent before, with the number of bytes of the blocks skipped. The blocks are
+    /// taken from the pool, to be returned once they have been sent.
+    pub(crate) fn push(&mut self, bytes: &[u8], buffers: &BufferPool) -> (Vec<BytesMut>, u64) {
+        let mut blocks = vec![];
+        let mut skipped = 0;
+
+        for &byte in bytes {
+            self.block.put_u8(byte);
+            self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+
+            let len = self.block.len();
+            if (len >= self.min && self.hash & self.mask == 0) || len >= self.max {
+                let block = std::mem::replace(&mut self.block, buffers.take(self.min));
+                self.hash = 0;
+                match self.first_seen(&block) {
+                    true => blocks.push(block),
+                    false => {
+                        skipped += block.len() as u64;
+                        buffers.give(block);
+                    }
+                }
+            }
+        }
+
+        (blocks, skipped)
+    }
+
+    /// Return the last block at the end of the content, unless it has been sent before, with
+    /// the number of bytes skipped.
+    pub(crate) fn finish(&mut self) -> (Option<BytesMut>, u64) {
+        let block = std::mem::take(&mut self.block);
+        match block.is_empty() || self.first_seen(&block) {
+            true => (Some(block).filter(|block| !block.is_empty()), 0),
+            false => (None, block.len() as u64),
+        }
+    }
+
+    fn first_seen(&mut self, block: &[u8]) -> bool {
+        self.seen.insert(Sha256::digest(block).into())
+    }
+}
+
+/// The random values the gear hash adds for each byte, from a fixed splitmix64 sequence.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes which do not repeat, from a xorshift sequence.
+    fn noise(len: usize, mut state: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn dedup(content: &[u8]) -> (usize, u64) {
+        let mut deduper = Deduper::new(&BlockDedup::default());
+        let (mut sent, mut skipped) = (0, 0);
+        // Feed odd sized chunks, since the blocks must not depend on them.
+        for chunk in content.chunks(10_000) {
+            let (blocks, bytes) = deduper.push(chunk, &BufferPool::unpooled());
+            sent += blocks.iter().map(BytesMut::len).sum::<usize>();
+            skipped += bytes;
+        }
+        let (block, bytes) = deduper.finish();
+        sent += block.map_or(0, |block| block.len());
+        (sent, skipped + bytes)
+    }
+
+    #[test]
+    fn it_skips_the_blocks_of_repeated_runs() {
+        let run = noise(512 * 1024, 7);
+        let content = [&run[..], b"shift", &run[..], &run[..]].concat();
+
+        let (sent, skipped) = dedup(&content);
+        assert_eq!(sent as u64 + skipped, content.len() as u64);
+        // Only the blocks around the ends of the runs are sent again.
+        assert!(sent < run.len() * 3 / 2, "{sent} bytes sent");
+    }
+
+    #[test]
+    fn it_sends_every_byte_of_a_content_without_repetitions() {
+        let content = noise(1024 * 1024, 11);
+        assert_eq!(dedup(&content), (content.len(), 0));
+    }
+}
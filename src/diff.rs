@@ -0,0 +1,207 @@
+use crate::{tee, Detection, Error, ScanOutcome, Scanner, DEFAULT_TEE_CAPACITY};
+
+use bytes::Bytes;
+use std::{
+    collections::BTreeSet,
+    error::Error as StdError,
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+};
+use tokio_stream::Stream;
+
+/// Scans the same content with two scanners, e.g. a stable and a release candidate clamav or
+/// two signature databases, and reports whether their verdicts disagree, for signature QA.
+#[derive(Debug, Clone)]
+pub struct DiffScan {
+    baseline: Scanner,
+    candidate: Scanner,
+}
+
+impl DiffScan {
+    /// Compare the verdicts of the candidate scanner against the baseline one.
+    pub fn new(baseline: Scanner, candidate: Scanner) -> Self {
+        Self {
+            baseline,
+            candidate,
+        }
+    }
+
+    /// Consume the input once and scan it with both scanners at the same time.
+    pub async fn scan<St, B, E>(&self, input: St) -> DiffReport
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let (left, right) = tee(input, DEFAULT_TEE_CAPACITY);
+        let (baseline, candidate) = join(
+            self.baseline.scan_stream(left),
+            self.candidate.scan_stream(right),
+        )
+        .await;
+
+        DiffReport {
+            baseline,
+            candidate,
+        }
+    }
+}
+
+/// The verdicts of both scanners of a [`DiffScan`] on a content.
+#[derive(Debug)]
+pub struct DiffReport {
+    /// The verdict of the baseline scanner.
+    pub baseline: Result<ScanOutcome, Error>,
+    /// The verdict of the candidate scanner.
+    pub candidate: Result<ScanOutcome, Error>,
+}
+
+impl DiffReport {
+    /// How the verdicts disagree, or `None` if they agree. The order of the signatures found
+    /// by an all-match scan does not matter.
+    pub fn disagreement(&self) -> Option<Disagreement> {
+        match (verdict(&self.baseline), verdict(&self.candidate)) {
+            (None, None) => None,
+            (None, Some(_)) | (Some(_), None) => Some(Disagreement::OneFailed),
+            (Some(baseline), Some(candidate)) if baseline == candidate => None,
+            (Some(baseline), Some(candidate)) if baseline.is_empty() => {
+                Some(Disagreement::NewDetection(detections(candidate)))
+            }
+            (Some(baseline), Some(candidate)) if candidate.is_empty() => {
+                Some(Disagreement::MissedDetection(detections(baseline)))
+            }
+            (Some(baseline), Some(candidate)) => Some(Disagreement::Signatures {
+                baseline: detections(baseline),
+                candidate: detections(candidate),
+            }),
+        }
+    }
+
+    /// Returns `true` if the verdicts agree.
+    pub fn agrees(&self) -> bool {
+        self.disagreement().is_none()
+    }
+}
+
+/// How the verdicts of a [`DiffReport`] disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Disagreement {
+    /// Only the candidate detected the content, with the given signatures.
+    NewDetection(Vec<Detection>),
+
+    /// Only the baseline detected the content, with the given signatures.
+    MissedDetection(Vec<Detection>),
+
+    /// Both detected the content, but with different signatures.
+    Signatures {
+        /// The signatures found by the baseline.
+        baseline: Vec<Detection>,
+        /// The signatures found by the candidate.
+        candidate: Vec<Detection>,
+    },
+
+    /// Only one of the scanners returned a verdict. The other one failed, or skipped the
+    /// content with [`FailurePolicy::FailOpen`](crate::FailurePolicy::FailOpen).
+    OneFailed,
+}
+
+/// The signatures found, empty if the content is clean, or `None` without a verdict.
+fn verdict(result: &Result<ScanOutcome, Error>) -> Option<BTreeSet<String>> {
+    match result {
+        Ok(ScanOutcome::Clean) => Some(BTreeSet::new()),
+        Ok(outcome @ ScanOutcome::Infected(_)) => Some(
+            outcome
+                .detections()
+                .into_iter()
+                .map(|detection| detection.signature)
+                .collect(),
+        ),
+        Ok(ScanOutcome::Skipped) | Err(_) => None,
+    }
+}
+
+/// Poll both futures until both are ready, so that neither branch of the tee starves.
+async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (pin!(a), pin!(b));
+    let (mut out_a, mut out_b) = (None, None);
+
+    poll_fn(|cx| {
+        if out_a.is_none() {
+            if let Poll::Ready(out) = a.as_mut().poll(cx) {
+                out_a = Some(out);
+            }
+        }
+        if out_b.is_none() {
+            if let Poll::Ready(out) = b.as_mut().poll(cx) {
+                out_b = Some(out);
+            }
+        }
+        match (out_a.take(), out_b.take()) {
+            (Some(a), Some(b)) => Poll::Ready((a, b)),
+            (a, b) => {
+                (out_a, out_b) = (a, b);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+fn detections(signatures: BTreeSet<String>) -> Vec<Detection> {
+    signatures
+        .into_iter()
+        .map(|signature| Detection { signature })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd, Address};
+
+    #[tokio::test]
+    async fn it_reports_a_detection_only_the_candidate_makes() {
+        let (stable, stable_server) = fake_clamd(b"stream: OK\0");
+        let (rc, rc_server) = fake_clamd(b"stream: Win.Test.EICAR_HDB-1 FOUND\0");
+        let diff = DiffScan::new(
+            Scanner::new(Address::tcp(stable).unwrap()),
+            Scanner::new(Address::tcp(rc).unwrap()),
+        );
+
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let report = diff.scan(input).await;
+        assert!(!report.agrees());
+        assert_eq!(
+            report.disagreement(),
+            Some(Disagreement::NewDetection(vec![Detection {
+                signature: "Win.Test.EICAR_HDB-1".into(),
+            }]))
+        );
+
+        // Both scanners received the whole content.
+        assert_eq!(stable_server.join().unwrap(), rc_server.join().unwrap());
+    }
+
+    #[test]
+    fn it_ignores_the_order_of_the_signatures() {
+        let report = DiffReport {
+            baseline: Ok(ScanOutcome::Infected(
+                "stream: A FOUND\nstream: B FOUND\n".into(),
+            )),
+            candidate: Ok(ScanOutcome::Infected(
+                "stream: B FOUND\nstream: A FOUND\n".into(),
+            )),
+        };
+        assert!(report.agrees());
+
+        let report = DiffReport {
+            baseline: Ok(ScanOutcome::Clean),
+            candidate: Err(Error::Shutdown),
+        };
+        assert_eq!(report.disagreement(), Some(Disagreement::OneFailed));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{connection::Address, task::SCAN_DIR_TASK, RescanQueue, RescanReports, Scanner};
+
+use bytes::Bytes;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tokio::{fs::File, sync::mpsc};
+use tokio_stream::Once;
+use tokio_util::{either::Either, io::ReaderStream};
+
+/// The number of bytes read from a file at a time.
+const READ_SIZE: usize = 64 * 1024;
+
+/// What [`scan_dir`] does with symbolic links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Skip symbolic links.
+    #[default]
+    Skip,
+    /// Scan the files and walk the directories the links point to, once each.
+    Follow,
+}
+
+/// Options of [`scan_dir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanDirOptions {
+    concurrency: usize,
+    ignore: Vec<String>,
+    symlinks: SymlinkPolicy,
+}
+
+impl ScanDirOptions {
+    /// Scan up to 4 files at a time, skipping symbolic links and ignoring nothing.
+    pub fn new() -> Self {
+        Self {
+            concurrency: 4,
+            ignore: vec![],
+            symlinks: SymlinkPolicy::default(),
+        }
+    }
+
+    /// Scan up to `concurrency` files at a time, at least one.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Ignore the files and directories whose name or path relative to the walked directory
+    /// matches the glob, where `*` matches any characters and `?` matches a single one.
+    pub fn ignore(mut self, glob: impl Into<String>) -> Self {
+        self.ignore.push(glob.into());
+        self
+    }
+
+    /// Choose what to do with symbolic links. Defaults to [`SymlinkPolicy::Skip`].
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    fn is_ignored(&self, relative: &Path) -> bool {
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let relative = relative.to_string_lossy();
+
+        self.ignore
+            .iter()
+            .any(|glob| glob_match(glob, &name) || glob_match(glob, &relative))
+    }
+}
+
+impl Default for ScanDirOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Walk the directory and scan each file on its own connection to the clamav, like
+/// `clamdscan`. The [`RescanReport`](crate::RescanReport) of each file is yielded with its
+/// path as it completes.
+///
+/// This must be called within a tokio runtime, on which the walk and the scans are spawned.
+/// The walk runs on the blocking pool and stays at most `concurrency` files ahead of the
+/// scans. Directories which can't be read are reported with their error in place of a verdict.
+pub fn scan_dir(
+    address: &Address,
+    path: impl AsRef<Path>,
+    options: ScanDirOptions,
+) -> RescanReports<PathBuf> {
+    let (queue, reports) = RescanQueue::new(Scanner::new(address.clone()), options.concurrency);
+    let (found, mut files) = mpsc::channel(options.concurrency);
+    let root = path.as_ref().to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut visited = HashSet::new();
+        walk(&root, &root, &options, &mut visited, &mut |file| {
+            found.blocking_send(file).is_ok()
+        });
+    });
+
+    crate::task::spawn(SCAN_DIR_TASK, async move {
+        while let Some(file) = files.recv().await {
+            drop(queue.push_ready(file.clone(), read_file(file)).await);
+        }
+    });
+
+    reports
+}
+
+/// The content of the file, or the error opening it.
+async fn read_file(path: PathBuf) -> Either<ReaderStream<File>, Once<io::Result<Bytes>>> {
+    match File::open(&path).await {
+        Ok(file) => Either::Left(ReaderStream::with_capacity(file, READ_SIZE)),
+        Err(err) => Either::Right(tokio_stream::once(Err(err))),
+    }
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    options: &ScanDirOptions,
+    visited: &mut HashSet<PathBuf>,
+    found: &mut impl FnMut(PathBuf) -> bool,
+) -> bool {
+    if let Ok(canonical) = dir.canonicalize() {
+        if !visited.insert(canonical) {
+            return true;
+        }
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // The error surfaces when the directory is opened as a file.
+        Err(_) => return found(dir.to_path_buf()),
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if options.is_ignored(path.strip_prefix(root).unwrap_or(&path)) {
+            continue;
+        }
+
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let is_dir = if file_type.is_symlink() {
+            match options.symlinks {
+                SymlinkPolicy::Skip => continue,
+                SymlinkPolicy::Follow => path.is_dir(),
+            }
+        } else {
+            file_type.is_dir()
+        };
+
+        // Stop once the scans are no longer consumed.
+        let walking = if is_dir {
+            walk(root, &path, options, visited, found)
+        } else {
+            found(path)
+        };
+        if !walking {
+            return false;
+        }
+    }
+    true
+}
+
+/// Match the text against a glob of `*` and `?` wildcards.
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                backtrack = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    g = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd_many, ScanOutcome};
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn it_matches_globs() {
+        assert!(glob_match("*.log", "app.log"));
+        assert!(glob_match("node_modules", "node_modules"));
+        assert!(glob_match("a?c*", "abcdef"));
+        assert!(!glob_match("*.log", "app.txt"));
+        assert!(!glob_match("a?c", "ac"));
+    }
+
+    #[test]
+    fn it_scans_at_least_one_file_at_a_time() {
+        let options = ScanDirOptions::new().concurrency(0);
+        assert_eq!(options, ScanDirOptions::new().concurrency(1));
+    }
+
+    #[tokio::test]
+    async fn it_scans_every_file_not_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::create_dir(dir.path().join("cache")).unwrap();
+        fs::write(dir.path().join("a.txt"), "Hello").unwrap();
+        fs::write(dir.path().join("sub/b.txt"), "World").unwrap();
+        fs::write(dir.path().join("sub/c.log"), "ignored").unwrap();
+        fs::write(dir.path().join("cache/d.txt"), "ignored").unwrap();
+
+        let (addr, server) = fake_clamd_many(b"stream: OK\0", 2);
+        // The fake clamav serves one connection at a time.
+        let options = ScanDirOptions::new()
+            .concurrency(1)
+            .ignore("*.log")
+            .ignore("cache");
+        let reports = scan_dir(&Address::tcp(addr).unwrap(), dir.path(), options);
+
+        let mut reports: Vec<_> = reports.collect().await;
+        reports.sort_by(|a, b| a.id.cmp(&b.id));
+        let paths: Vec<_> = reports.iter().map(|report| report.id.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![dir.path().join("a.txt"), dir.path().join("sub/b.txt")]
+        );
+        assert!(reports
+            .iter()
+            .all(|report| report.outcome == Ok(ScanOutcome::Clean)));
+
+        server.join().unwrap();
+    }
+}
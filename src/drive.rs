@@ -0,0 +1,76 @@
+use crate::{
+    async_stream::AsyncScannedStream,
+    connection::{Address, TcpOptions},
+    Error, ScanOutcome,
+};
+
+use bytes::Bytes;
+use std::error::Error as StdError;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_stream::{Stream, StreamExt};
+
+/// Consume the input only to scan it, and return the verdict of the clamav.
+///
+/// This opens a new asynchronous connection to the address. Nothing is passed through, so it
+/// suits jobs which re-scan stored contents, e.g. after a signature update.
+pub async fn scan_stream<St, B, E>(input: St, address: &Address) -> Result<ScanOutcome, Error>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    let io = address.connect_async(&TcpOptions::default()).await?;
+    drive(AsyncScannedStream::new(input, io)).await
+}
+
+/// Consume the stream, discarding the content it passes through, for its verdict.
+pub(crate) async fn drive<St, IO, B, E>(
+    stream: AsyncScannedStream<St, IO>,
+) -> Result<ScanOutcome, Error>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    IO: AsyncRead + AsyncWrite + Unpin,
+    E: StdError + Send + Sync + 'static,
+{
+    let scanned = stream.is_scanned();
+    let mut stream = std::pin::pin!(stream);
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(_) => {}
+            Err(Error::Scan(message)) => return Ok(ScanOutcome::Infected(message)),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(if scanned {
+        ScanOutcome::Clean
+    } else {
+        ScanOutcome::Skipped
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_clamd;
+
+    #[tokio::test]
+    async fn it_scans_the_whole_input_without_passing_it_through() {
+        let (addr, server) = fake_clamd(b"stream: Eicar-Signature FOUND\0");
+        let address = Address::tcp(addr).unwrap();
+
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let outcome = scan_stream(input, &address).await.unwrap();
+        assert_eq!(
+            outcome,
+            ScanOutcome::Infected("stream: Eicar-Signature FOUND\0".into())
+        );
+
+        let received = server.join().unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0"));
+        assert!(received.ends_with(b"World\0\0\0\0"));
+    }
+}
@@ -0,0 +1,39 @@
+use crate::ScanOutcome;
+
+/// What a scan does when its stream is dropped in the middle of the content, e.g. when the
+/// client of an upload disconnects. The behavior and its [`DropResult`] are reported as a
+/// [`Warning::Dropped`](crate::Warning::Dropped) by the [`Progress`](crate::Progress) of the
+/// scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DropBehavior {
+    /// Close the connection without terminating the content. The clamav discards it.
+    #[default]
+    Abort,
+
+    /// Terminate the content, then close the connection without reading the verdict, so that
+    /// the clamav scans the content received so far, e.g. for its own logging. The dropping
+    /// thread waits for a non-blocking connection to accept the end of the content.
+    Finish,
+
+    /// Terminate the content and read the verdict. The streams of a [`Scanner`](crate::Scanner)
+    /// read it on a blocking task of the tokio runtime, and an
+    /// [`AsyncScannedStream`](crate::AsyncScannedStream) on a task of its own, others on the
+    /// dropping thread.
+    Complete,
+}
+
+/// What became of a scan dropped in the middle of its content, see [`DropBehavior`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropResult {
+    /// The connection was closed without terminating the content.
+    Aborted,
+
+    /// The content was terminated, and the connection closed without reading the verdict.
+    Finished,
+
+    /// The verdict on the content received before the drop.
+    Completed(ScanOutcome),
+
+    /// Terminating the content or reading the verdict failed with the error.
+    Failed(String),
+}
@@ -0,0 +1,56 @@
+use crate::{scan::Scan, Error, ScannedStream};
+
+use std::net::{TcpStream, ToSocketAddrs};
+use tokio::io::{self, DuplexStream};
+use tokio_util::io::ReaderStream;
+
+/// The writer and the scanned reader returned by [`scanned_duplex`].
+pub type ScannedDuplex<RW> = (DuplexStream, ScannedStream<ReaderStream<DuplexStream>, RW>);
+
+/// Create a pipe whose content is scanned: anything written to the returned writer is scanned
+/// and can be read from the returned [`ScannedStream`].
+///
+/// This bridges producer-style APIs which write to an `AsyncWrite`, such as zip writers, into
+/// the scanning pipeline. Up to `max_buf_size` bytes are buffered in the pipe, and the stream
+/// ends once the writer is shut down or dropped.
+pub fn scanned_duplex(
+    max_buf_size: usize,
+    addr: impl ToSocketAddrs,
+) -> Result<ScannedDuplex<TcpStream>, Error> {
+    let inner = TcpStream::connect(addr)?;
+    Ok(duplex_with(max_buf_size, Scan::new(inner)))
+}
+
+pub(crate) fn duplex_with<RW>(max_buf_size: usize, scan: Scan<RW>) -> ScannedDuplex<RW>
+where
+    RW: std::io::Read + std::io::Write,
+{
+    let (writer, reader) = io::duplex(max_buf_size);
+    let stream = ScannedStream::with_scan(ReaderStream::new(reader), scan);
+    (writer, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_clamd;
+    use bytes::Bytes;
+    use tokio::io::AsyncWriteExt;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_scans_the_content_written_to_the_pipe() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let (mut writer, mut stream) = scanned_duplex(64, addr).unwrap();
+
+        tokio::spawn(async move {
+            writer.write_all(b"Hello World").await.unwrap();
+        });
+
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+        assert_eq!(stream.next().await, None);
+
+        let received = server.join().unwrap();
+        assert!(received.windows(11).any(|w| w == b"Hello World"));
+    }
+}
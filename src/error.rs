@@ -1,4 +1,9 @@
-use std::{error::Error as StdError, io, str::Utf8Error};
+#[cfg(feature = "tokio")]
+use crate::response::DetectionError;
+use crate::Quota;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::{error::Error as StdError, fmt, io, str::Utf8Error, sync::Arc, time::Duration};
 
 /// The error type returned by [`ScannedStream`](crate::ScannedStream).
 #[derive(Debug, thiserror::Error)]
@@ -11,13 +16,359 @@ pub enum Error {
     #[error("utf8 error: {0}")]
     Utf8(Utf8Error),
 
+    /// A [`Scanner`](crate::Scanner) failed to connect to the clamav.
+    #[error("failed to connect to clamav: {source}")]
+    Connect {
+        /// The underlying io error.
+        source: io::Error,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
+    /// A transport error while communicating with the clamav in the middle of a scan.
+    #[error("failed to communicate with clamav during {during} after sending {bytes_sent} bytes: {source}")]
+    Send {
+        /// The underlying io error.
+        source: io::Error,
+        /// The number of content bytes sent to the clamav before the error.
+        bytes_sent: u64,
+        /// What was being sent or received when the error occurred.
+        during: Phase,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
     /// An error returned while consuming the inner stream.
     #[error("stream error: {0}")]
     Stream(Box<dyn StdError + Send + Sync>),
 
+    /// An error returned by the staging, commit or rollback step of a
+    /// [`ScanGate`](crate::ScanGate).
+    #[error("staging error: {0}")]
+    Staging(Box<dyn StdError + Send + Sync>),
+
+    /// The clamav failed to scan the content, e.g. `INSTREAM size limit exceeded. ERROR`.
+    #[error("clamav error: {message}")]
+    Clamd {
+        /// The message of the `ERROR` reply, without the `ERROR` suffix.
+        message: String,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
+    /// The reply of the clamav does not match the grammar of clamd replies, with
+    /// [`ReplyGrammar::Strict`](crate::ReplyGrammar::Strict).
+    #[error("unexpected reply from clamav: {reply:?}")]
+    Protocol {
+        /// The whole reply.
+        reply: String,
+    },
+
     /// Infected stream error with message from the clamav.
     #[error("{0}")]
     Scan(String),
+
+    /// The digest of the content differs from the expected [`Checksum`](crate::Checksum).
+    #[error("checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        /// The hex encoded expected digest.
+        expected: String,
+        /// The hex encoded digest of the content passed through.
+        actual: String,
+    },
+
+    /// The content passed through differs from the content sent to the clamav. Only checked
+    /// with the `passthrough-check` feature.
+    #[error("passthrough mismatch: crc32 {scanned:08x} scanned, {passed:08x} passed through")]
+    PassthroughMismatch {
+        /// The CRC-32 of the content sent to the clamav.
+        scanned: u32,
+        /// The CRC-32 of the content passed through to the consumer.
+        passed: u32,
+    },
+
+    /// The [`Scanner`](crate::Scanner) is backing off after failing to connect to the clamav, or
+    /// its circuit is open with [`FailurePolicy::FailClosed`](crate::FailurePolicy::FailClosed).
+    #[error("clamav is unavailable, retrying in {retry_in:?}")]
+    Unavailable {
+        /// The time until the next connection attempt is allowed.
+        retry_in: Duration,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
+    /// The tenant of the [`Scanner`](crate::Scanner) handle, see
+    /// [`Scanner::for_tenant`](crate::Scanner::for_tenant), has exhausted its quota in the
+    /// [`QuotaManager`](crate::QuotaManager).
+    #[error("tenant {tenant} exceeded its quota of {quota}, retrying in {retry_in:?}")]
+    QuotaExceeded {
+        /// The tenant of the handle.
+        tenant: String,
+        /// The exhausted limit.
+        quota: Quota,
+        /// The time until the window of the limit elapses.
+        retry_in: Duration,
+    },
+
+    /// The clamav accepted none of the content for the
+    /// [`write_timeout`](crate::ScannerBuilder::write_timeout) in the middle of a scan.
+    #[error("clamav accepted no content for {timeout:?} after {bytes_sent} bytes were sent")]
+    WriteTimeout {
+        /// The configured write timeout.
+        timeout: Duration,
+        /// The number of content bytes sent to the clamav before the stall.
+        bytes_sent: u64,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
+    /// The clamav did not reply with its verdict within the
+    /// [`verdict_timeout`](crate::ScannerBuilder::verdict_timeout) after the end of the content.
+    #[error("clamav did not reply with a verdict within {timeout:?}")]
+    VerdictTimeout {
+        /// The configured verdict timeout.
+        timeout: Duration,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
+    /// The deadline of the enclosing request, set with
+    /// [`ScannedStream::with_deadline`](crate::ScannedStream::with_deadline) or
+    /// [`Scanner::with_deadline`](crate::Scanner::with_deadline), passed before the verdict.
+    #[error("deadline exceeded after {bytes_sent} bytes were sent")]
+    DeadlineExceeded {
+        /// The number of content bytes sent to the clamav before the deadline.
+        bytes_sent: u64,
+        /// The tenant of the scan, see [`Error::tenant`].
+        tenant: Option<Arc<str>>,
+    },
+
+    /// The unix socket of the clamav does not exist, found before connecting with
+    /// [`UnixSocketOptions::check_socket`](crate::UnixSocketOptions::check_socket).
+    #[cfg(unix)]
+    #[error("clamav socket {} does not exist", path.display())]
+    SocketNotFound {
+        /// The path of the missing socket.
+        path: PathBuf,
+    },
+
+    /// The [`Scanner`](crate::Scanner) has been shut down and accepts no more streams.
+    #[error("scanner has been shut down")]
+    Shutdown,
+
+    /// The input yielded content after it had ended and the verdict had been read, with
+    /// [`ScannedStream::with_strict`](crate::ScannedStream::with_strict).
+    #[error("{len} bytes of content after the end of the scanned stream")]
+    TrailingData {
+        /// The length of the trailing chunk.
+        len: usize,
+    },
+
+    /// More or fewer content bytes than the declared length were scanned, with
+    /// [`LengthPolicy::Fail`](crate::LengthPolicy::Fail).
+    #[error("{bytes_scanned} bytes of content, but {expected_len} bytes were declared")]
+    LengthMismatch {
+        /// The declared length of the content.
+        expected_len: u64,
+        /// The number of content bytes scanned, including the chunk which overran the
+        /// declared length.
+        bytes_scanned: u64,
+    },
+
+    /// A range of a [`ResumableScan`](crate::ResumableScan) starting after the end of the
+    /// content received so far, which would leave a gap in the scanned content.
+    #[error("range at offset {offset} leaves a gap after the {received} bytes received")]
+    RangeGap {
+        /// The number of content bytes received so far, i.e. the offset expected next.
+        received: u64,
+        /// The offset of the rejected range.
+        offset: u64,
+    },
+
+    /// A chunk size which the `u32` length prefix of an `INSTREAM` chunk cannot hold, or zero.
+    #[error("invalid chunk size {size}: must be between 1 and {}", u32::MAX)]
+    InvalidChunkSize {
+        /// The invalid size.
+        size: usize,
+    },
+}
+
+impl Error {
+    pub(crate) fn connect(source: io::Error) -> Self {
+        Self::Connect {
+            source,
+            tenant: None,
+        }
+    }
+
+    pub(crate) fn send(source: io::Error, bytes_sent: u64, during: Phase) -> Self {
+        Self::Send {
+            source,
+            bytes_sent,
+            during,
+            tenant: None,
+        }
+    }
+
+    pub(crate) fn clamd(message: impl Into<String>) -> Self {
+        Self::Clamd {
+            message: message.into(),
+            tenant: None,
+        }
+    }
+
+    pub(crate) fn unavailable(retry_in: Duration) -> Self {
+        Self::Unavailable {
+            retry_in,
+            tenant: None,
+        }
+    }
+
+    pub(crate) fn deadline_exceeded(bytes_sent: u64) -> Self {
+        Self::DeadlineExceeded {
+            bytes_sent,
+            tenant: None,
+        }
+    }
+
+    /// The tenant of the [`Scanner`](crate::Scanner) handle whose scan failed, see
+    /// [`Scanner::for_tenant`](crate::Scanner::for_tenant). Only set on the errors which carry
+    /// a `tenant` field, i.e. the failures of the clamav, of connecting to it and of the
+    /// connection in the middle of a scan, the timeouts, the deadline and the admission of a
+    /// scan. A detection, [`Error::Scan`], does not carry it: the tenant of its scan is
+    /// [`Progress::tenant`](crate::Progress::tenant) and
+    /// [`ScanReport::tenant`](crate::ScanReport::tenant).
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Self::Connect { tenant, .. }
+            | Self::Send { tenant, .. }
+            | Self::Clamd { tenant, .. }
+            | Self::Unavailable { tenant, .. }
+            | Self::WriteTimeout { tenant, .. }
+            | Self::VerdictTimeout { tenant, .. }
+            | Self::DeadlineExceeded { tenant, .. } => tenant.as_deref(),
+            Self::QuotaExceeded { tenant, .. } => Some(tenant),
+            _ => None,
+        }
+    }
+
+    /// Label the error with the tenant of the scan, if it is one which carries it.
+    pub(crate) fn with_tenant(mut self, name: Option<&str>) -> Self {
+        if let (
+            Self::Connect { tenant, .. }
+            | Self::Send { tenant, .. }
+            | Self::Clamd { tenant, .. }
+            | Self::Unavailable { tenant, .. }
+            | Self::WriteTimeout { tenant, .. }
+            | Self::VerdictTimeout { tenant, .. }
+            | Self::DeadlineExceeded { tenant, .. },
+            Some(name),
+        ) = (&mut self, name)
+        {
+            *tenant = Some(name.into());
+        }
+        self
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn staging(err: impl StdError + Send + Sync + 'static) -> Self {
+        Self::Staging(Box::new(err))
+    }
+
+    /// The [`io::Error`] a reader fails with, carrying a [`DetectionError`] for an infected
+    /// content.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn into_io(self) -> io::Error {
+        match self {
+            Self::Io(err) => err,
+            Self::Scan(message) => {
+                io::Error::new(io::ErrorKind::InvalidData, DetectionError::new(message))
+            }
+            err => io::Error::other(err),
+        }
+    }
+}
+
+/// The step of the clamav protocol in progress when a [`Error::Send`] occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Sending the `zINSTREAM` command. Nothing has reached the clamav yet.
+    Start,
+    /// Sending a chunk of the content. The command has already been sent.
+    Chunk,
+    /// Sending the zero-length chunk terminating the content.
+    Finish,
+    /// Reading the reply. The whole content and the terminating chunk have been sent.
+    Reply,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            Self::Start => "start",
+            Self::Chunk => "chunk",
+            Self::Finish => "finish",
+            Self::Reply => "reply",
+        };
+        f.write_str(phase)
+    }
+}
+
+/// What a scan does with an error of its input, decided by the hook set with
+/// [`ScannedStream::with_stream_errors`](crate::ScannedStream::with_stream_errors).
+#[derive(Debug)]
+pub enum StreamErrorAction {
+    /// Return the given payload as an [`Error::Stream`], e.g. the input error converted into
+    /// an error type of the application.
+    Fail(Box<dyn StdError + Send + Sync>),
+    /// Drop the error and poll the input again, for an input which goes on after a transient
+    /// error, e.g. one retrying the read which failed.
+    Ignore,
+}
+
+type StreamErrorHook =
+    Box<dyn FnMut(Box<dyn StdError + Send + Sync>) -> StreamErrorAction + Send + Sync>;
+
+/// Converts the errors of the input of a scan, wrapping them into [`Error::Stream`] as they
+/// are unless a hook has been set.
+#[derive(Default)]
+pub(crate) struct StreamErrors {
+    hook: Option<StreamErrorHook>,
+}
+
+impl StreamErrors {
+    pub(crate) fn new<E, F>(mut hook: F) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+        F: FnMut(E) -> StreamErrorAction + Send + Sync + 'static,
+    {
+        Self {
+            hook: Some(Box::new(move |err| match err.downcast::<E>() {
+                Ok(err) => hook(*err),
+                Err(err) => StreamErrorAction::Fail(err),
+            })),
+        }
+    }
+
+    /// The error to return for an error of the input, `None` if it is to be ignored.
+    pub(crate) fn handle(&mut self, err: impl StdError + Send + Sync + 'static) -> Option<Error> {
+        let err = Box::new(err);
+        match &mut self.hook {
+            Some(hook) => match hook(err) {
+                StreamErrorAction::Fail(err) => Some(Error::Stream(err)),
+                StreamErrorAction::Ignore => None,
+            },
+            None => Some(Error::Stream(err)),
+        }
+    }
+}
+
+impl fmt::Debug for StreamErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamErrors")
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
 }
 
 impl From<io::Error> for Error {
@@ -32,8 +383,10 @@ impl From<Utf8Error> for Error {
     }
 }
 
+/// Two errors are equal if they display the same message and carry the same tenant, see
+/// [`Error::tenant`].
 impl PartialEq for Error {
     fn eq(&self, other: &Self) -> bool {
-        format!("{self}").as_str() == format!("{other}").as_str()
+        format!("{self}").as_str() == format!("{other}").as_str() && self.tenant() == other.tenant()
     }
 }
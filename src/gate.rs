@@ -0,0 +1,152 @@
+use crate::Error;
+
+use bytes::Bytes;
+use std::{error::Error as StdError, future::Future, pin::pin};
+use tokio_stream::{Stream, StreamExt};
+
+/// Streams the content to a staging destination while it is scanned, then commits or rolls
+/// back the staged content depending on the verdict.
+///
+/// This encapsulates the common "upload to a temp key, then copy it if clean" pattern.
+/// ```rust,no_run
+/// use clamav_stream::{ScanGate, Scanner};
+///
+/// use bytes::Bytes;
+/// use std::io;
+/// use tokio::fs::File;
+/// use tokio_util::io::ReaderStream;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let scanner = Scanner::tcp("localhost:3310").unwrap();
+///     let file = File::open("tests/clean.txt").await.unwrap();
+///     let stream = scanner.wrap(ReaderStream::new(file)).unwrap();
+///
+///     let gate = ScanGate::new(
+///         |_chunk: Bytes| async move { /* upload to the temp key */ Ok::<_, io::Error>(()) },
+///         || async { /* copy the temp key to the final one */ Ok::<_, io::Error>(()) },
+///         || async { /* delete the temp key */ Ok::<_, io::Error>(()) },
+///     );
+///     gate.run(stream).await.unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ScanGate<S, C, R> {
+    stage: S,
+    commit: C,
+    rollback: R,
+}
+
+impl<S, C, R> ScanGate<S, C, R> {
+    /// Create a new [`ScanGate`] with a closure staging each chunk, and closures committing and
+    /// rolling back the staged content.
+    pub fn new(stage: S, commit: C, rollback: R) -> Self {
+        Self {
+            stage,
+            commit,
+            rollback,
+        }
+    }
+
+    /// Consume the scanned stream, staging every chunk. Once the stream ends, the staged content
+    /// is committed if it is clean and rolled back otherwise.
+    ///
+    /// Returns the value of the commit, or the error which caused the rollback. An error of the
+    /// rollback itself is ignored in favor of its cause.
+    pub async fn run<St, SFut, CFut, RFut, T, E>(mut self, stream: St) -> Result<T, Error>
+    where
+        St: Stream<Item = Result<Bytes, Error>>,
+        S: FnMut(Bytes) -> SFut,
+        SFut: Future<Output = Result<(), E>>,
+        C: FnOnce() -> CFut,
+        CFut: Future<Output = Result<T, E>>,
+        R: FnOnce() -> RFut,
+        RFut: Future<Output = Result<(), E>>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let mut stream = pin!(stream);
+
+        while let Some(chunk) = stream.next().await {
+            let result = match chunk {
+                Ok(chunk) => (self.stage)(chunk).await.map_err(Error::staging),
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                let _ = (self.rollback)().await;
+                return Err(err);
+            }
+        }
+
+        (self.commit)().await.map_err(Error::staging)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        future::ready,
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    macro_rules! gate {
+        ($log:expr) => {{
+            let (stage, commit, rollback) = ($log.clone(), $log.clone(), $log.clone());
+            ScanGate::new(
+                move |chunk: Bytes| {
+                    stage.push(String::from_utf8_lossy(&chunk));
+                    ready(Ok::<_, io::Error>(()))
+                },
+                move || {
+                    commit.push("commit");
+                    ready(Ok("committed"))
+                },
+                move || {
+                    rollback.push("rollback");
+                    ready(Ok(()))
+                },
+            )
+        }};
+    }
+
+    #[tokio::test]
+    async fn it_commits_clean_contents() {
+        let log = Log::default();
+        let input = tokio_stream::iter(vec![Ok(Bytes::from("Hello ")), Ok(Bytes::from("World"))]);
+
+        let result = gate!(log).run(input).await;
+        assert_eq!(result.unwrap(), "committed");
+        assert_eq!(log.entries(), vec!["Hello ", "World", "commit"]);
+    }
+
+    #[tokio::test]
+    async fn it_rolls_back_infected_contents() {
+        let log = Log::default();
+        let input = tokio_stream::iter(vec![
+            Ok(Bytes::from("Hello World")),
+            Err(Error::Scan("stream: Eicar-Signature FOUND\0".into())),
+        ]);
+
+        let result = gate!(log).run(input).await;
+        assert_eq!(
+            result.unwrap_err(),
+            Error::Scan("stream: Eicar-Signature FOUND\0".into())
+        );
+        assert_eq!(log.entries(), vec!["Hello World", "rollback"]);
+    }
+
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl Log {
+        fn push(&self, entry: impl Into<String>) {
+            self.0.lock().unwrap().push(entry.into());
+        }
+
+        fn entries(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Error, HashLookup, LookupKey, ScanOutcome};
+
+/// The name of the header carrying [`HttpVerdict::scan_status`].
+pub const SCAN_STATUS_HEADER: &str = "x-scan-status";
+
+/// The HTTP answer of a proxy to a verdict: the status code of the response and the value of
+/// the [`SCAN_STATUS_HEADER`], see [`HttpPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpVerdict {
+    /// The status code of the response, e.g. `204` for a clean content.
+    pub status: u16,
+    /// The value of the [`SCAN_STATUS_HEADER`]: `clean`, `skipped`, `error`, or `infected`
+    /// followed by the signature found, e.g. `infected; signature="Win.Test.EICAR_HDB-1"`.
+    pub scan_status: String,
+}
+
+impl HttpVerdict {
+    /// Returns `true` if the status code lets the content through, i.e. is a `2xx`.
+    pub fn is_allowed(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Maps verdicts, whether cached in a [`HashLookup`] such as a
+/// [`VerdictCache`](crate::VerdictCache) or returned by a scan, to an [`HttpVerdict`], so that
+/// proxies answer them the same way.
+///
+/// Defaults to `204 No Content` for a clean or skipped content, `403 Forbidden` for an
+/// infected one, and `502 Bad Gateway` when the scan failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpPolicy {
+    allowed: u16,
+    infected: u16,
+    failed: u16,
+}
+
+impl Default for HttpPolicy {
+    fn default() -> Self {
+        Self {
+            allowed: 204,
+            infected: 403,
+            failed: 502,
+        }
+    }
+}
+
+impl HttpPolicy {
+    /// Answer a clean or skipped content with the given status code.
+    pub fn with_allowed_status(mut self, status: u16) -> Self {
+        self.allowed = status;
+        self
+    }
+
+    /// Answer an infected content with the given status code.
+    pub fn with_infected_status(mut self, status: u16) -> Self {
+        self.infected = status;
+        self
+    }
+
+    /// Answer a failed scan with the given status code.
+    pub fn with_failed_status(mut self, status: u16) -> Self {
+        self.failed = status;
+        self
+    }
+
+    /// The answer to a verdict.
+    pub fn verdict(&self, outcome: &ScanOutcome) -> HttpVerdict {
+        match outcome {
+            ScanOutcome::Clean => self.allowed("clean"),
+            ScanOutcome::Skipped => self.allowed("skipped"),
+            ScanOutcome::Infected(_) => {
+                let signature = outcome
+                    .detections()
+                    .into_iter()
+                    .next()
+                    .map_or_else(String::new, |detection| detection.signature);
+                HttpVerdict {
+                    status: self.infected,
+                    scan_status: format!("infected; signature=\"{}\"", quoted(&signature)),
+                }
+            }
+        }
+    }
+
+    /// The answer to the verdict cached for the key, `None` if there is none and the content
+    /// has to be scanned.
+    pub fn lookup(&self, lookup: &dyn HashLookup, key: &LookupKey) -> Option<HttpVerdict> {
+        lookup.lookup(key).map(|outcome| self.verdict(&outcome))
+    }
+
+    /// The answer to a scan ending with the error, which is [`Error::Scan`] for an infected
+    /// content.
+    pub fn error(&self, err: &Error) -> HttpVerdict {
+        match err {
+            Error::Scan(message) => self.verdict(&ScanOutcome::Infected(message.clone())),
+            _ => HttpVerdict {
+                status: self.failed,
+                scan_status: "error".into(),
+            },
+        }
+    }
+
+    fn allowed(&self, scan_status: &str) -> HttpVerdict {
+        HttpVerdict {
+            status: self.allowed,
+            scan_status: scan_status.into(),
+        }
+    }
+}
+
+/// The content of a quoted string in a header value, dropping the characters a header cannot
+/// carry and escaping the quotes.
+fn quoted(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len());
+    for c in value.chars().filter(|c| c.is_ascii_graphic() || *c == ' ') {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VerdictCache;
+
+    #[test]
+    fn it_maps_the_verdicts_to_http() {
+        let policy = HttpPolicy::default();
+
+        let clean = policy.verdict(&ScanOutcome::Clean);
+        assert_eq!(clean.status, 204);
+        assert_eq!(clean.scan_status, "clean");
+        assert!(clean.is_allowed());
+
+        let infected = policy.error(&Error::Scan("stream: Win.Test.EICAR_HDB-1 FOUND\0".into()));
+        assert_eq!(
+            infected,
+            HttpVerdict {
+                status: 403,
+                scan_status: "infected; signature=\"Win.Test.EICAR_HDB-1\"".into(),
+            }
+        );
+        assert!(!infected.is_allowed());
+
+        let failed = policy
+            .with_failed_status(503)
+            .error(&Error::clamd("INSTREAM size limit exceeded."));
+        assert_eq!(failed.status, 503);
+        assert_eq!(failed.scan_status, "error");
+    }
+
+    #[test]
+    fn it_answers_from_the_cached_verdicts() {
+        let cache = VerdictCache::new(8);
+        let key = LookupKey {
+            digest: [7; 32],
+            database: Some(27000),
+        };
+        let policy = HttpPolicy::default();
+        assert_eq!(policy.lookup(&cache, &key), None);
+
+        cache.record(&key, &ScanOutcome::Clean);
+        assert_eq!(
+            policy.lookup(&cache, &key).map(|verdict| verdict.status),
+            Some(204)
+        );
+    }
+}
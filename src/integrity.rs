@@ -0,0 +1,122 @@
+use crate::Error;
+
+/// The CRC-32 (IEEE) lookup table.
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A running CRC-32 of a content fed in any chunking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self(!0)
+    }
+}
+
+impl Crc32 {
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn value(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// The checksum of the content passed through to the consumer, compared with the checksum of
+/// the content written to the clamav once the content has been found clean, to catch a wrapper
+/// altering, reordering or dropping the chunks it passes through.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Passthrough {
+    passed: Crc32,
+    /// The checksum of the content written to the clamav, until it has been compared.
+    scanned: Option<Crc32>,
+}
+
+impl Passthrough {
+    /// Record bytes yielded to the consumer.
+    pub(crate) fn passed(&mut self, bytes: &[u8]) {
+        self.passed.update(bytes);
+    }
+
+    /// Compare the content yielded so far with the checksum of the content written to the
+    /// clamav, once every chunk has been yielded. `None` if the content written differs from
+    /// the content by design, e.g. decoded or deduplicated, or has not been written at all.
+    pub(crate) fn expect(&mut self, scanned: Option<Crc32>) {
+        self.scanned = scanned;
+    }
+
+    /// Compare the checksums expected with [`Passthrough::expect`], at most once.
+    pub(crate) fn verify(&mut self) -> Result<(), Error> {
+        match self.scanned.take() {
+            Some(scanned) if scanned != self.passed => Err(Error::PassthroughMismatch {
+                scanned: scanned.value(),
+                passed: self.passed.value(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_the_crc32_of_the_content() {
+        let mut crc = Crc32::default();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+
+    fn crc(chunks: &[&[u8]]) -> Crc32 {
+        let mut crc = Crc32::default();
+        for chunk in chunks {
+            crc.update(chunk);
+        }
+        crc
+    }
+
+    #[test]
+    fn it_accepts_the_same_content_in_another_chunking() {
+        let mut passthrough = Passthrough::default();
+        passthrough.passed(b"Hello");
+        passthrough.passed(b" World");
+        passthrough.expect(Some(crc(&[b"Hello World"])));
+        assert!(passthrough.verify().is_ok());
+    }
+
+    #[test]
+    fn it_detects_reordered_chunks() {
+        let mut passthrough = Passthrough::default();
+        passthrough.passed(b" World");
+        passthrough.passed(b"Hello");
+        passthrough.expect(Some(crc(&[b"Hello", b" World"])));
+        assert!(matches!(
+            passthrough.verify(),
+            Err(Error::PassthroughMismatch { .. })
+        ));
+        // The mismatch is reported once.
+        assert!(passthrough.verify().is_ok());
+    }
+}
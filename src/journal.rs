@@ -0,0 +1,422 @@
+use crate::Error;
+use crate::{lookup::Sha256Digest, ScanOutcome};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use std::{sync::Arc, time::Instant};
+
+/// What a scan recorded in a [`ScanJournal`] produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanRecord {
+    /// The SHA-256 digest of the content.
+    pub digest: Sha256Digest,
+    /// The number of content bytes scanned.
+    pub size: u64,
+    /// The verdict on the content, or the message of the error the scan failed with.
+    pub outcome: Result<ScanOutcome, String>,
+    /// The time from the start of the scan until its verdict.
+    pub duration: Duration,
+    /// The tenant of the [`Scanner`](crate::Scanner) handle, see
+    /// [`Scanner::for_tenant`](crate::Scanner::for_tenant).
+    pub tenant: Option<String>,
+}
+
+/// A scan being recorded in a [`ScanJournal`], hashing its content until it ends.
+pub(crate) struct Recording {
+    journal: Arc<dyn ScanJournal>,
+    hasher: Sha256,
+    started: Instant,
+}
+
+impl Recording {
+    pub(crate) fn new(journal: Arc<dyn ScanJournal>) -> Self {
+        Self {
+            journal,
+            hasher: Sha256::new(),
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+    }
+
+    /// Append the scan to the journal, failing with [`Error::Io`] if it cannot be recorded.
+    pub(crate) fn record(
+        self,
+        size: u64,
+        outcome: Result<&ScanOutcome, &Error>,
+        tenant: Option<String>,
+    ) -> Result<(), Error> {
+        self.journal.record(ScanRecord {
+            digest: self.hasher.finalize().into(),
+            size,
+            outcome: outcome.cloned().map_err(ToString::to_string),
+            duration: self.started.elapsed(),
+            tenant,
+        })?;
+        Ok(())
+    }
+}
+
+/// A [`ScanRecord`] kept by a [`ScanJournal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// The sequence number of the entry, assigned by the journal.
+    pub id: u64,
+    /// The time the entry was recorded.
+    pub recorded_at: SystemTime,
+    /// The scan.
+    pub scan: ScanRecord,
+}
+
+/// An audit trail of the scans of a [`Scanner`](crate::Scanner), e.g. to retain the verdicts on
+/// the uploads of an application without an external database.
+///
+/// See [`ScannerBuilder::journal`](crate::ScannerBuilder::journal).
+pub trait ScanJournal: Send + Sync {
+    /// Append the scan to the journal and return the id of its entry.
+    fn record(&self, scan: ScanRecord) -> io::Result<u64>;
+
+    /// Read every entry of the journal, oldest first.
+    fn replay(&self) -> io::Result<Vec<JournalEntry>>;
+
+    /// Read the entries matching the predicate, oldest first.
+    fn query(&self, predicate: &dyn Fn(&JournalEntry) -> bool) -> io::Result<Vec<JournalEntry>> {
+        let mut entries = self.replay()?;
+        entries.retain(|entry| predicate(entry));
+        Ok(entries)
+    }
+}
+
+/// A [`ScanJournal`] appending an entry per line of a JSON Lines file, such as:
+///
+/// ```json
+/// {"id":1,"recorded_at_ms":1704067200000,"sha256":"275a021b...","size":68,"outcome":"infected","message":"stream: Win.Test.EICAR_HDB-1 FOUND","duration_ms":3}
+/// ```
+///
+/// The file is only ever appended to, so it can be shipped to a log pipeline or rotated by an
+/// external tool. The ids continue from the last entry of an existing file, and a last line
+/// torn by a crash while it was appended is dropped when the file is opened again.
+pub struct JsonlJournal {
+    path: PathBuf,
+    writer: Mutex<Writer>,
+    sync: bool,
+}
+
+struct Writer {
+    file: File,
+    next_id: u64,
+}
+
+impl JsonlJournal {
+    /// Open the journal file, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        repair(&path)?;
+        let next_id = read_entries(&path)?.last().map_or(1, |entry| entry.id + 1);
+
+        Ok(Self {
+            path,
+            writer: Mutex::new(Writer { file, next_id }),
+            sync: false,
+        })
+    }
+
+    /// Sync each entry to the disk before it is acknowledged, so that no verdict is lost to a
+    /// power failure. Off by default, since the scan which records the entry waits for the
+    /// disk meanwhile.
+    pub fn sync_each_entry(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// The path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The entries of the scans of the content with the digest, oldest first.
+    pub fn find(&self, digest: &Sha256Digest) -> io::Result<Vec<JournalEntry>> {
+        self.query(&|entry| &entry.scan.digest == digest)
+    }
+}
+
+impl ScanJournal for JsonlJournal {
+    fn record(&self, scan: ScanRecord) -> io::Result<u64> {
+        let mut writer = self.writer.lock().unwrap();
+        let entry = JournalEntry {
+            id: writer.next_id,
+            recorded_at: SystemTime::now(),
+            scan,
+        };
+
+        let mut line = serde_json::to_vec(&Line::from(&entry))?;
+        line.push(b'\n');
+        // The lines of the writers are not interleaved, since they hold the lock. A crash may
+        // still tear the line, which is dropped when the file is opened again.
+        writer.file.write_all(&line)?;
+        if self.sync {
+            writer.file.sync_data()?;
+        }
+        writer.next_id += 1;
+        Ok(entry.id)
+    }
+
+    fn replay(&self) -> io::Result<Vec<JournalEntry>> {
+        // Hold the writer, so that no entry is read half written.
+        let _writer = self.writer.lock().unwrap();
+        read_entries(&self.path)
+    }
+}
+
+impl fmt::Debug for JsonlJournal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonlJournal")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Drop the last line of the file if a crash tore it before its newline was written. A line
+/// which is whole but for the newline is kept.
+fn repair(path: &Path) -> io::Result<()> {
+    let content = fs::read(path)?;
+    if content.is_empty() || content.ends_with(b"\n") {
+        return Ok(());
+    }
+
+    let start = content
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    match serde_json::from_slice::<Line>(&content[start..]) {
+        Ok(_) => {
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(b"\n")?;
+        }
+        Err(_) => file.set_len(start as u64)?,
+    }
+    file.sync_data()
+}
+
+fn read_entries(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let mut entries = vec![];
+    for (n, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str::<Line>(&line)
+            .ok()
+            .and_then(|line| line.into_entry())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid journal entry on line {}", n + 1),
+                )
+            })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// A line of a [`JsonlJournal`].
+#[derive(Serialize, Deserialize)]
+struct Line {
+    id: u64,
+    recorded_at_ms: u64,
+    sha256: String,
+    size: u64,
+    outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+}
+
+impl From<&JournalEntry> for Line {
+    fn from(entry: &JournalEntry) -> Self {
+        let (outcome, message) = match &entry.scan.outcome {
+            Ok(ScanOutcome::Clean) => ("clean", None),
+            Ok(ScanOutcome::Infected(message)) => ("infected", Some(message.clone())),
+            Ok(ScanOutcome::Skipped) => ("skipped", None),
+            Err(message) => ("error", Some(message.clone())),
+        };
+        let recorded_at = entry
+            .recorded_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Self {
+            id: entry.id,
+            recorded_at_ms: recorded_at.as_millis() as u64,
+            sha256: entry
+                .scan
+                .digest
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+            size: entry.scan.size,
+            outcome: outcome.into(),
+            message,
+            duration_ms: entry.scan.duration.as_millis() as u64,
+            tenant: entry.scan.tenant.clone(),
+        }
+    }
+}
+
+impl Line {
+    fn into_entry(self) -> Option<JournalEntry> {
+        let outcome = match (self.outcome.as_str(), self.message) {
+            ("clean", _) => Ok(ScanOutcome::Clean),
+            ("infected", Some(message)) => Ok(ScanOutcome::Infected(message)),
+            ("skipped", _) => Ok(ScanOutcome::Skipped),
+            ("error", Some(message)) => Err(message),
+            _ => return None,
+        };
+
+        if self.sha256.len() != 64 {
+            return None;
+        }
+        let mut digest = [0u8; 32];
+        for (byte, hex) in digest.iter_mut().zip(self.sha256.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+        }
+
+        Some(JournalEntry {
+            id: self.id,
+            recorded_at: UNIX_EPOCH + Duration::from_millis(self.recorded_at_ms),
+            scan: ScanRecord {
+                digest,
+                size: self.size,
+                outcome,
+                duration: Duration::from_millis(self.duration_ms),
+                tenant: self.tenant,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(digest: u8, outcome: Result<ScanOutcome, String>) -> ScanRecord {
+        ScanRecord {
+            digest: [digest; 32],
+            size: 68,
+            outcome,
+            duration: Duration::from_millis(3),
+            tenant: None,
+        }
+    }
+
+    #[test]
+    fn it_replays_the_recorded_scans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        let journal = JsonlJournal::open(&path).unwrap();
+
+        let infected = Ok(ScanOutcome::Infected(
+            "stream: Eicar-Signature FOUND".into(),
+        ));
+        assert_eq!(journal.record(scan(1, Ok(ScanOutcome::Clean))).unwrap(), 1);
+        assert_eq!(journal.record(scan(2, infected.clone())).unwrap(), 2);
+
+        let entries = journal.replay().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].scan, scan(1, Ok(ScanOutcome::Clean)));
+        assert_eq!(entries[1].scan, scan(2, infected.clone()));
+
+        let found = journal.find(&[2; 32]).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, 2);
+        assert_eq!(found[0].scan.outcome, infected);
+
+        let failed = Err("failed to send a chunk".to_string());
+        assert_eq!(journal.record(scan(3, failed.clone())).unwrap(), 3);
+        assert_eq!(journal.find(&[3; 32]).unwrap()[0].scan.outcome, failed);
+    }
+
+    #[test]
+    fn it_syncs_each_entry_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        let journal = JsonlJournal::open(&path).unwrap().sync_each_entry(true);
+
+        assert_eq!(journal.record(scan(1, Ok(ScanOutcome::Clean))).unwrap(), 1);
+        assert_eq!(journal.replay().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn it_continues_the_ids_of_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        JsonlJournal::open(&path)
+            .unwrap()
+            .record(scan(1, Ok(ScanOutcome::Clean)))
+            .unwrap();
+
+        let journal = JsonlJournal::open(&path).unwrap();
+        assert_eq!(journal.record(scan(2, Ok(ScanOutcome::Clean))).unwrap(), 2);
+        assert_eq!(journal.replay().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn it_drops_a_last_line_torn_by_a_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        let journal = JsonlJournal::open(&path).unwrap();
+        journal.record(scan(1, Ok(ScanOutcome::Clean))).unwrap();
+        journal.record(scan(2, Ok(ScanOutcome::Clean))).unwrap();
+        drop(journal);
+
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 10]).unwrap();
+
+        let journal = JsonlJournal::open(&path).unwrap();
+        assert_eq!(journal.record(scan(3, Ok(ScanOutcome::Clean))).unwrap(), 2);
+        let entries = journal.replay().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].scan, scan(3, Ok(ScanOutcome::Clean)));
+    }
+
+    #[test]
+    fn it_keeps_a_last_line_missing_only_its_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        JsonlJournal::open(&path)
+            .unwrap()
+            .record(scan(1, Ok(ScanOutcome::Clean)))
+            .unwrap();
+
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 1]).unwrap();
+
+        let journal = JsonlJournal::open(&path).unwrap();
+        assert_eq!(journal.record(scan(2, Ok(ScanOutcome::Clean))).unwrap(), 2);
+        assert_eq!(journal.replay().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn it_refuses_a_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        std::fs::write(&path, "not json\n").unwrap();
+
+        let err = JsonlJournal::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
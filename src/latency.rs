@@ -0,0 +1,182 @@
+use crate::report::Warning;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The upper bounds of the buckets of [`ResponseTimes`]. Longer times fall into a last,
+/// unbounded bucket.
+pub const RESPONSE_TIME_BUCKETS: [Duration; 9] = [
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// A clonable histogram of the time the clamav took to reply with the verdict after the end of
+/// the content, obtained from [`Scanner::response_times`](crate::Scanner::response_times).
+///
+/// The times of the scans of a tenant, see [`Scanner::for_tenant`](crate::Scanner::for_tenant),
+/// are also recorded in a histogram of the tenant.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseTimes {
+    state: Arc<Mutex<State>>,
+    tenants: Arc<Mutex<HashMap<String, ResponseTimes>>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    counts: [u64; RESPONSE_TIME_BUCKETS.len() + 1],
+    total: Duration,
+    max: Duration,
+}
+
+impl ResponseTimes {
+    /// The number of verdicts recorded.
+    pub fn count(&self) -> u64 {
+        self.state.lock().unwrap().counts.iter().sum()
+    }
+
+    /// The mean time to verdict. Returns `None` until a verdict is recorded.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        let state = self.state.lock().unwrap();
+        (count > 0).then(|| state.total / count as u32)
+    }
+
+    /// The longest time to verdict recorded.
+    pub fn max(&self) -> Duration {
+        self.state.lock().unwrap().max
+    }
+
+    /// The number of verdicts per bucket, keyed by the upper bound of the bucket as in
+    /// [`RESPONSE_TIME_BUCKETS`], or `None` for the last, unbounded one.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        let state = self.state.lock().unwrap();
+        RESPONSE_TIME_BUCKETS
+            .iter()
+            .map(|bound| Some(*bound))
+            .chain([None])
+            .zip(state.counts)
+            .collect()
+    }
+
+    /// The histogram of the scans of the tenant, empty until one of its verdicts is recorded.
+    pub fn for_tenant(&self, tenant: &str) -> ResponseTimes {
+        let mut tenants = self.tenants.lock().unwrap();
+        tenants.entry(tenant.into()).or_default().clone()
+    }
+
+    /// The tenants whose scans have been recorded, in no particular order.
+    pub fn tenants(&self) -> Vec<String> {
+        let tenants = self.tenants.lock().unwrap();
+        tenants
+            .iter()
+            .filter(|(_, times)| times.count() > 0)
+            .map(|(tenant, _)| tenant.clone())
+            .collect()
+    }
+
+    pub(crate) fn record(&self, elapsed: Duration, tenant: Option<&str>) {
+        if let Some(tenant) = tenant {
+            self.for_tenant(tenant).record(elapsed, None);
+        }
+
+        let bucket = RESPONSE_TIME_BUCKETS
+            .iter()
+            .position(|bound| elapsed <= *bound)
+            .unwrap_or(RESPONSE_TIME_BUCKETS.len());
+
+        let mut state = self.state.lock().unwrap();
+        state.counts[bucket] += 1;
+        state.total += elapsed;
+        state.max = state.max.max(elapsed);
+    }
+}
+
+/// How the time to verdict of a scan is recorded.
+#[derive(Debug, Clone)]
+pub(crate) struct Timing {
+    pub(crate) times: ResponseTimes,
+    pub(crate) slow_threshold: Option<Duration>,
+    pub(crate) backend: String,
+    pub(crate) tenant: Option<String>,
+}
+
+impl Timing {
+    /// Record the time to verdict, and return a [`Warning::SlowScan`] beyond the threshold.
+    pub(crate) fn record(&self, elapsed: Duration, bytes_scanned: u64) -> Option<Warning> {
+        self.times.record(elapsed, self.tenant.as_deref());
+        self.slow_threshold
+            .filter(|threshold| elapsed > *threshold)
+            .map(|_| Warning::SlowScan {
+                elapsed,
+                bytes_scanned,
+                backend: self.backend.clone(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_counts_times_per_bucket() {
+        let times = ResponseTimes::default();
+        assert_eq!(times.mean(), None);
+
+        times.record(Duration::from_millis(5), None);
+        times.record(Duration::from_millis(75), None);
+        times.record(Duration::from_secs(60), None);
+
+        let buckets = times.buckets();
+        assert_eq!(buckets[0], (Some(Duration::from_millis(10)), 1));
+        assert_eq!(buckets[2], (Some(Duration::from_millis(100)), 1));
+        assert_eq!(buckets[9], (None, 1));
+        assert_eq!(times.count(), 3);
+        assert_eq!(times.max(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn it_warns_of_slow_scans_beyond_the_threshold() {
+        let timing = Timing {
+            times: ResponseTimes::default(),
+            slow_threshold: Some(Duration::from_secs(1)),
+            backend: "127.0.0.1:3310".into(),
+            tenant: None,
+        };
+
+        assert_eq!(timing.record(Duration::from_millis(20), 11), None);
+        assert_eq!(
+            timing.record(Duration::from_secs(2), 11),
+            Some(Warning::SlowScan {
+                elapsed: Duration::from_secs(2),
+                bytes_scanned: 11,
+                backend: "127.0.0.1:3310".into(),
+            })
+        );
+        assert_eq!(timing.times.count(), 2);
+    }
+
+    #[test]
+    fn it_labels_the_times_with_the_tenant() {
+        let times = ResponseTimes::default();
+        times.record(Duration::from_millis(5), Some("acme"));
+        times.record(Duration::from_millis(75), None);
+
+        assert_eq!(times.count(), 2);
+        assert_eq!(times.tenants(), vec!["acme".to_string()]);
+        assert_eq!(times.for_tenant("acme").count(), 1);
+        assert_eq!(times.for_tenant("acme").max(), Duration::from_millis(5));
+        assert_eq!(times.for_tenant("globex").count(), 0);
+        assert_eq!(times.tenants(), vec!["acme".to_string()]);
+    }
+}
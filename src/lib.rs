@@ -7,7 +7,8 @@
 //! ## When the byte stream is clean
 //!
 //! There are no deferences between consuming [`ScannedStream`] and its inner stream.
-//! ```rust,no_run
+#![cfg_attr(feature = "tokio", doc = "```rust,no_run")]
+#![cfg_attr(not(feature = "tokio"), doc = "```rust,ignore")]
 //! use clamav_stream::ScannedStream;
 //!
 //! use bytes::Bytes;
@@ -36,7 +37,8 @@
 //! ## When the byte stream is infected
 //!
 //! An Err is returned after all contents are consumed.
-//! ```rust,no_run
+#![cfg_attr(feature = "tokio", doc = "```rust,no_run")]
+#![cfg_attr(not(feature = "tokio"), doc = "```rust,ignore")]
 //! use clamav_stream::{Error, ScannedStream};
 //!
 //! use bytes::Bytes;
@@ -62,238 +64,240 @@
 //!     assert_eq!(stream.next().await, None);
 //! }
 //! ```
-
+//!
+//! ## Sharing a scanner between requests
+//!
+//! A [`Scanner`] is a cheap, clonable handle which can live in the application state. It opens a
+//! new connection for every stream it wraps.
+#![cfg_attr(feature = "tokio", doc = "```rust,no_run")]
+#![cfg_attr(not(feature = "tokio"), doc = "```rust,ignore")]
+//! use clamav_stream::Scanner;
+//!
+//! use tokio::fs::File;
+//! use tokio_stream::StreamExt;
+//! use tokio_util::io::ReaderStream;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let scanner = Scanner::tcp("localhost:3310").unwrap();
+//!
+//!     let file = File::open("tests/clean.txt").await.unwrap();
+//!     let mut input = ReaderStream::new(file);
+//!     let mut stream = scanner.wrap(&mut input).unwrap();
+//!
+//!     while let Some(chunk) = stream.next().await {
+//!         // ... consume the chunk ...
+//!     }
+//! }
+//! ```
+//!
+//! ## Asynchronous transport
+//!
+//! A [`ScannedStream`] talks to the clamav server over a blocking std socket, so its
+//! `poll_next` blocks the task while a chunk is written and while the verdict is read. Only the
+//! socket of a [`Scanner`] built with [`ScannerBuilder::early_verdict`] is switched to
+//! non-blocking mode.
+//!
+//! An [`AsyncScannedStream`], returned by [`AsyncScannedStream::tcp`] and
+//! [`Scanner::wrap_async`], talks to it over a tokio transport instead, so it never blocks the
+//! runtime. It does not offer [`ScannedStream::finish`], the decoding of the content, the spool
+//! or the memory budget.
+
+mod adaptive;
+#[cfg(feature = "tokio")]
+mod async_stream;
+mod backoff;
+#[cfg(feature = "http-body")]
+mod body;
+mod buffer;
+mod channel;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod circuit;
+#[cfg(feature = "compress")]
+mod compress;
+mod config;
+mod connection;
+mod decode;
+#[cfg(feature = "dedup")]
+mod dedup;
+#[cfg(feature = "tokio")]
+mod diff;
+#[cfg(feature = "tokio")]
+mod dir;
+#[cfg(feature = "tokio")]
+mod drive;
+mod drop_behavior;
+#[cfg(feature = "tokio")]
+mod duplex;
 mod error;
-pub use error::Error;
-
-use pin_project::pin_project;
-use std::{
-    error::Error as StdError,
-    io::{Read, Write},
-    net::{TcpStream, ToSocketAddrs},
-    path::Path,
-    pin::{pin, Pin},
-    task::{Context, Poll},
-};
-use tokio_stream::Stream;
-
-#[cfg(unix)]
-use std::os::unix::net::UnixStream;
-
-const START: &[u8; 10] = b"zINSTREAM\0";
-const FINISH: &[u8; 4] = &[0, 0, 0, 0];
-const CHUNK_SIZE: usize = 4096;
-
-/// A wrapper stream holding byte stream. This sends the inner stream to [clamav](https://www.clamav.net/) to scan it while passes it through to the consumer.
-#[pin_project]
-pub struct ScannedStream<'a, St: ?Sized, RW: Read + Write> {
-    #[pin]
-    input: &'a mut St,
-    inner: RW,
-    started: bool,
-    finished: bool,
-}
-
-macro_rules! write_clamav {
-    ($stream:expr, $bytes:expr) => {
-        if let Err(err) = write_stream($stream, $bytes) {
-            return Poll::Ready(Some(Err(err)));
-        }
-    };
-}
-
-macro_rules! read_clamav {
-    ($stream:expr) => {
-        if let Err(err) = read_stream_response($stream) {
-            return Poll::Ready(Some(Err(err)));
-        }
-    };
-}
-
-impl<'a, St, RW, E> Stream for ScannedStream<'a, St, RW>
-where
-    St: Stream<Item = Result<bytes::Bytes, E>> + Unpin + ?Sized,
-    RW: Read + Write,
-    E: StdError + Send + Sync + 'static,
-{
-    type Item = Result<bytes::Bytes, Error>;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let me = self.project();
-        match me.input.poll_next(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(Some(Ok(bytes))) => {
-                if !*me.started {
-                    *me.started = true;
-                    write_clamav!(me.inner, START);
-                }
-
-                for chunk in bytes.as_ref().chunks(CHUNK_SIZE) {
-                    let len = chunk.len() as u32;
-                    write_clamav!(me.inner, &len.to_be_bytes());
-                    write_clamav!(me.inner, chunk);
-                }
-
-                Poll::Ready(Some(Ok(bytes)))
-            }
-            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(Error::Stream(Box::new(err))))),
-            Poll::Ready(None) => {
-                if *me.finished {
-                    return Poll::Ready(None);
-                }
-
-                *me.finished = true;
-                write_clamav!(me.inner, FINISH);
-                read_clamav!(me.inner);
-
-                Poll::Ready(None)
-            }
-        }
-    }
-}
-
-impl<'a, St, RW, E> ScannedStream<'a, St, RW>
-where
-    St: Stream<Item = Result<bytes::Bytes, E>> + Unpin + ?Sized,
-    RW: Read + Write,
-    E: StdError,
-{
-    /// Create a new [`ScannedStream`]
-    pub fn new(input: &'a mut St, inner: RW) -> Self {
-        Self {
-            input,
-            inner,
-            started: false,
-            finished: false,
-        }
-    }
-
-    /// Create a new [`ScannedStream`] connecting to clamav server with tcp socket.
-    pub fn tcp(
-        input: &'a mut St,
-        addr: impl ToSocketAddrs,
-    ) -> Result<ScannedStream<'a, St, TcpStream>, Error> {
-        let inner = TcpStream::connect(addr)?;
-        Ok(ScannedStream::new(input, inner))
-    }
-
-    /// Create a new [`ScannedStream`] connecting to clamav server with unix socket.
-    #[cfg(unix)]
-    pub fn socket(
-        input: &'a mut St,
-        path: impl AsRef<Path>,
-    ) -> Result<ScannedStream<'a, St, UnixStream>, Error> {
-        let inner = UnixStream::connect(path)?;
-        Ok(ScannedStream::new(input, inner))
-    }
-}
-
-fn write_stream(stream: &mut impl Write, buf: &[u8]) -> Result<(), Error> {
-    stream.write_all(buf)?;
-    Ok(())
-}
-
-fn read_stream_response(stream: &mut impl Read) -> Result<(), Error> {
-    let mut body: Vec<u8> = vec![];
-    stream.read_to_end(&mut body)?;
-
-    let res = std::str::from_utf8(&body)?;
-
-    if res.contains("OK") && !res.contains("FOUND") {
-        Ok(())
-    } else {
-        Err(Error::Scan(res.to_string()))
-    }
-}
-
+#[cfg(feature = "tokio")]
+mod gate;
+mod http_verdict;
+#[cfg(feature = "passthrough-check")]
+mod integrity;
+#[cfg(feature = "journal")]
+mod journal;
+mod latency;
+#[cfg(feature = "tokio")]
+mod limiter;
+mod lookahead;
+mod lookup;
+#[cfg(feature = "mail")]
+mod mail;
+mod manual;
+mod memory;
+mod mode;
+#[cfg(all(feature = "spool", feature = "tokio"))]
+mod multipart;
+mod pool;
+mod progress;
+pub mod protocol;
+mod quota;
+mod reader;
+mod record;
+mod report;
+mod reputation;
+#[cfg(feature = "tokio")]
+mod rescan;
+mod response;
+#[cfg(feature = "tokio")]
+mod resumable;
+mod retry;
+mod sample;
+#[cfg(all(feature = "spool", feature = "tokio"))]
+mod sanitize;
+mod scan;
+mod scanner;
+#[cfg(feature = "tokio")]
+mod scope;
+#[cfg(feature = "tokio")]
+mod session;
+#[cfg(feature = "shard")]
+mod shard;
+mod shutdown;
+mod signature;
+#[cfg(feature = "spool")]
+mod spool;
+mod stream;
+#[cfg(feature = "tokio")]
+mod task;
+#[cfg(feature = "tokio")]
+mod tee;
 #[cfg(test)]
-mod tests {
-    use super::*;
-    use bytes::Bytes;
-    use std::io::{self, Cursor};
-    use tokio_stream::StreamExt;
-
-    #[tokio::test]
-    async fn it_returns_original_inputs_when_success() {
-        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
-        let mut inner = MockStream::new("OK");
-
-        let stream = ScannedStream::new(&mut input, &mut inner);
-        let result = consume(stream).await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "Hello World");
-
-        assert_eq!(inner.written.len(), 4);
-        assert_eq!(inner.written.first().unwrap(), "zINSTREAM\0");
-        assert_eq!(
-            inner.written.get(1).unwrap(),
-            &String::from_utf8(("Hello World".len() as u32).to_be_bytes().to_vec()).unwrap(),
-        );
-        assert_eq!(inner.written.get(2).unwrap(), "Hello World");
-        assert_eq!(
-            inner.written.get(3).unwrap(),
-            &String::from_utf8(vec![0, 0, 0, 0]).unwrap(),
-        );
-    }
-
-    #[tokio::test]
-    async fn it_returns_an_error_when_found_any_virus() {
-        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
-        let mut inner = MockStream::new("FOUND test virus");
-
-        let stream = ScannedStream::new(&mut input, &mut inner);
-        let result = consume(stream).await;
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().to_string(), "FOUND test virus");
-    }
-
-    struct MockStream {
-        written: Vec<String>,
-        output: Cursor<Vec<u8>>,
-    }
-
-    impl MockStream {
-        fn new(value: &str) -> Self {
-            Self {
-                written: vec![],
-                output: Cursor::new(value.as_bytes().to_vec()),
-            }
-        }
-    }
-
-    impl Read for MockStream {
-        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-            self.output.read(buf)
-        }
-    }
-
-    impl Write for MockStream {
-        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            self.written.push(String::from_utf8(buf.to_vec()).unwrap());
-            Ok(buf.len())
-        }
-
-        fn flush(&mut self) -> io::Result<()> {
-            Ok(())
-        }
-    }
-
-    fn stream_from_str(value: &'static str) -> impl Iterator<Item = Result<Bytes, Error>> {
-        [Ok(Bytes::from(value))].into_iter()
-    }
-
-    async fn consume<S>(mut stream: S) -> Result<String, Error>
-    where
-        S: Stream<Item = Result<Bytes, Error>> + Unpin,
-    {
-        let mut bytes: Vec<u8> = vec![];
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            bytes.append(&mut chunk.into());
-        }
-
-        let res = std::str::from_utf8(&bytes)?;
-        Ok(res.to_string())
-    }
-}
+mod test_util;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+#[cfg(feature = "protocol-debug")]
+mod trace;
+#[cfg(feature = "tokio")]
+mod update;
+#[cfg(feature = "tokio")]
+mod verdict;
+#[cfg(feature = "webhook")]
+mod webhook;
+#[cfg(feature = "ws")]
+mod ws;
+
+pub use adaptive::{AdaptiveChunkSize, ADAPTIVE_MAX_CHUNK_SIZE};
+#[cfg(feature = "tokio")]
+pub use async_stream::AsyncScannedStream;
+pub use backoff::{Backoff, ScannerHealth};
+#[cfg(feature = "http-body")]
+pub use body::{ScannedBody, ScannedFrames};
+pub use buffer::{BufferPool, BufferPoolStats};
+pub use channel::ChannelReader;
+#[cfg(feature = "tokio")]
+pub use channel::{ChannelInput, TryChannelInput};
+#[cfg(feature = "checksum")]
+pub use checksum::Checksum;
+pub use circuit::{CircuitBreaker, CircuitState, FailurePolicy};
+#[cfg(feature = "compress")]
+pub use compress::{scan_then_compress, Codec, CompressedStream};
+pub use config::{ConfigError, ConfigIssue};
+#[cfg(unix)]
+pub use connection::UnixSocketOptions;
+pub use connection::{Address, Connection, TcpOptions, Transport};
+#[cfg(feature = "tokio")]
+pub use connection::{AsyncConnection, AsyncTransport};
+pub use decode::Decoding;
+#[cfg(feature = "dedup")]
+pub use dedup::{BlockDedup, DEDUP_BLOCK_SIZE};
+#[cfg(feature = "tokio")]
+pub use diff::{DiffReport, DiffScan, Disagreement};
+#[cfg(feature = "tokio")]
+pub use dir::{scan_dir, ScanDirOptions, SymlinkPolicy};
+#[cfg(feature = "tokio")]
+pub use drive::scan_stream;
+pub use drop_behavior::{DropBehavior, DropResult};
+#[cfg(feature = "tokio")]
+pub use duplex::{scanned_duplex, ScannedDuplex};
+pub use error::{Error, Phase, StreamErrorAction};
+#[cfg(feature = "tokio")]
+pub use gate::ScanGate;
+pub use http_verdict::{HttpPolicy, HttpVerdict, SCAN_STATUS_HEADER};
+#[cfg(feature = "journal")]
+pub use journal::{JournalEntry, JsonlJournal, ScanJournal, ScanRecord};
+pub use latency::{ResponseTimes, RESPONSE_TIME_BUCKETS};
+#[cfg(feature = "tokio")]
+pub use limiter::{Priority, ScanLimiter, ScanPermit};
+pub use lookup::{HashLookup, LookupKey, Sha256Digest, VerdictCache};
+#[cfg(feature = "mail")]
+pub use mail::AttachmentReport;
+pub use manual::ManualScan;
+pub use memory::MemoryBudget;
+pub use mode::ScanMode;
+#[cfg(all(feature = "spool", feature = "tokio"))]
+pub use multipart::{MultipartReport, MultipartScan, PartReport};
+pub use progress::Progress;
+pub use quota::{Quota, QuotaManager, QuotaPolicy, TenantQuota, TenantUsage};
+pub use reader::{scan_reader, ScannedReader};
+pub use record::{RecordingTransport, ReplayTransport};
+pub use report::{LengthPolicy, ScanReport, Warning};
+pub use reputation::{
+    NoReputation, Reputation, ReputationFuture, ReputationPolicy, ReputationProvider,
+    ReputationVerdict,
+};
+#[cfg(feature = "tokio")]
+pub use rescan::{RescanQueue, RescanReport, RescanReports};
+pub use response::{
+    ClamdParser, Detection, DetectionCategory, DetectionError, ReplyGrammar, ResponseParser,
+    ScanOutcome, StrictClamdParser, TrailingNotes,
+};
+#[cfg(feature = "tokio")]
+pub use resumable::{ResumableScan, ResumedInput};
+pub use sample::{SampleHint, SampleRate};
+#[cfg(all(feature = "spool", feature = "tokio"))]
+pub use sanitize::{OnDetection, ReleasedStream};
+pub use scan::ScanPhase;
+pub use scanner::{Scanner, ScannerBuilder, WeakScanner};
+#[cfg(feature = "tokio")]
+pub use scope::{MemberReport, MemberVerdict, ScanScope, ScopeReport};
+#[cfg(feature = "tokio")]
+pub use session::SessionMux;
+#[cfg(feature = "shard")]
+pub use shard::ShardedScanner;
+#[cfg(feature = "tokio")]
+pub use shutdown::ShutdownReport;
+pub use signature::{BundledSignatures, Severity, SignatureMetadata, SignatureMetadataProvider};
+#[cfg(feature = "spool")]
+pub use spool::{Spool, SpoolConfig};
+pub use stream::{
+    BoxScannedStream, ScannedStream, DEFAULT_FRAMES_PER_POLL, DEFAULT_READ_BUFFER_SIZE,
+};
+#[cfg(feature = "tokio")]
+pub use task::{
+    DATABASE_WATCHER_TASK, DROP_COMPLETION_TASK, RESCAN_TASK, SCAN_DIR_TASK, WEBHOOK_TASK,
+};
+#[cfg(feature = "tokio")]
+pub use tee::{tee, tee_bounded, tee_scanned, ScannedTee, Tee, TeeError, DEFAULT_TEE_CAPACITY};
+#[cfg(feature = "protocol-debug")]
+pub use trace::{Frame, ProtocolTrace};
+#[cfg(feature = "tokio")]
+pub use update::{DatabaseUpdate, DatabaseUpdates, DatabaseWatcher};
+#[cfg(feature = "tokio")]
+pub use verdict::StaticVerdict;
+#[cfg(feature = "webhook")]
+pub use webhook::{NotifyOn, WebhookError, WebhookNotifier};
+#[cfg(feature = "ws")]
+pub use ws::{MessagePolicy, ScannedMessages};
@@ -0,0 +1,160 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    mem,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+/// The priority of a scan waiting for a [`ScanLimiter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Background work, e.g. the entries of a [`RescanQueue`](crate::RescanQueue).
+    Low,
+    /// The default priority.
+    #[default]
+    Normal,
+    /// Interactive work, e.g. an upload a user is waiting for.
+    High,
+}
+
+/// Limits the number of concurrent scans, letting the scans of higher [`Priority`] go first
+/// when the capacity of the clamav is exhausted. Scans of the same priority go in the order
+/// they asked.
+///
+/// Cloning a [`ScanLimiter`] shares the same capacity.
+#[derive(Debug, Clone)]
+pub struct ScanLimiter {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    available: usize,
+    next_seq: u64,
+    waiters: BTreeMap<(Reverse<Priority>, u64), oneshot::Sender<ScanPermit>>,
+}
+
+impl ScanLimiter {
+    /// Allow up to `capacity` concurrent scans.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    available: capacity,
+                    ..State::default()
+                }),
+            }),
+        }
+    }
+
+    /// Wait for a scan to be allowed with the given priority. The scan is counted until the
+    /// returned [`ScanPermit`] is dropped.
+    pub async fn acquire(&self, priority: Priority) -> ScanPermit {
+        let rx = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                return self.permit();
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.insert((Reverse(priority), seq), tx);
+            rx
+        };
+
+        // The permit is handed over by the one released before, which stays alive until then.
+        rx.await
+            .expect("waiters are only removed to be handed a permit")
+    }
+
+    /// The number of scans waiting for a permit.
+    pub fn waiting(&self) -> usize {
+        self.inner.state.lock().unwrap().waiters.len()
+    }
+
+    fn permit(&self) -> ScanPermit {
+        ScanPermit {
+            limiter: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        while let Some((_, tx)) = state.waiters.pop_first() {
+            match tx.send(self.permit()) {
+                Ok(()) => return,
+                // The waiter has given up. Its permit is still ours to hand over.
+                Err(permit) => mem::forget(permit),
+            }
+        }
+        state.available += 1;
+    }
+}
+
+/// Allows a scan to run until it is dropped. Created by [`ScanLimiter::acquire`].
+#[derive(Debug)]
+pub struct ScanPermit {
+    limiter: ScanLimiter,
+}
+
+impl Drop for ScanPermit {
+    fn drop(&mut self) {
+        self.limiter.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_lets_higher_priorities_go_first() {
+        let limiter = ScanLimiter::new(1);
+        let order = Arc::new(Mutex::new(vec![]));
+        let permit = limiter.acquire(Priority::Normal).await;
+
+        let mut tasks = vec![];
+        for (name, priority) in [("low", Priority::Low), ("high", Priority::High)] {
+            let limiter = limiter.clone();
+            let order = Arc::clone(&order);
+            tasks.push(tokio::spawn(async move {
+                let _permit = limiter.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(limiter.waiting(), 2);
+
+        drop(permit);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["high", "low"]);
+    }
+
+    #[tokio::test]
+    async fn it_returns_the_permits_of_waiters_which_gave_up() {
+        let limiter = ScanLimiter::new(1);
+        let permit = limiter.acquire(Priority::Normal).await;
+
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(Priority::High).await }
+        });
+        tokio::task::yield_now().await;
+        waiter.abort();
+        let _ = waiter.await;
+
+        drop(permit);
+        let _permit = limiter.acquire(Priority::Low).await;
+        assert_eq!(limiter.waiting(), 0);
+    }
+}
@@ -0,0 +1,50 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+/// The chunks a [`ScannedStream`](crate::ScannedStream) holds back from the consumer after
+/// sending them to the clamav, see
+/// [`ScannedStream::with_lookahead`](crate::ScannedStream::with_lookahead).
+#[derive(Debug)]
+pub(crate) struct Lookahead {
+    limit: usize,
+    held: VecDeque<Bytes>,
+    held_len: usize,
+}
+
+impl Lookahead {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            held: VecDeque::new(),
+            held_len: 0,
+        }
+    }
+
+    /// Hold back a chunk which has been sent to the clamav.
+    pub(crate) fn hold(&mut self, bytes: Bytes) {
+        self.held_len += bytes.len();
+        self.held.push_back(bytes);
+    }
+
+    /// The oldest chunk held, once at least the limit has been sent after it, or as soon as
+    /// the content has been found clean.
+    pub(crate) fn release(&mut self, clean: bool) -> Option<Bytes> {
+        let front = self.held.front()?;
+        if !clean && self.held_len - front.len() < self.limit {
+            return None;
+        }
+        self.held_len -= front.len();
+        self.held.pop_front()
+    }
+
+    /// The number of chunks held.
+    pub(crate) fn chunks(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Drop the chunks held, which are never to reach the consumer.
+    pub(crate) fn discard(&mut self) {
+        self.held.clear();
+        self.held_len = 0;
+    }
+}
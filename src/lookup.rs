@@ -0,0 +1,271 @@
+use crate::ScanOutcome;
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+use crate::{protocol::Version, Error};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+/// A SHA-256 digest of a content.
+pub type Sha256Digest = [u8; 32];
+
+/// The key a [`HashLookup`] is asked with: the digest of a content and the version of the
+/// signature database the clamav had loaded, so that verdicts are not reused once new
+/// signatures have been loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LookupKey {
+    /// The SHA-256 digest of the content.
+    pub digest: Sha256Digest,
+    /// The version of the signature database reported by `VERSION`, if it is loaded.
+    pub database: Option<u64>,
+}
+
+/// A service which may know the verdict on a content by its SHA-256 digest, asked before the
+/// content is sent to the clamav, e.g. an allowlist of known-good files or a reputation
+/// database.
+///
+/// See [`ScannerBuilder::hash_lookup`](crate::ScannerBuilder::hash_lookup).
+pub trait HashLookup: Send + Sync {
+    /// The verdict on the content, if known. The clamav is not asked when this returns `Some`.
+    fn lookup(&self, key: &LookupKey) -> Option<ScanOutcome>;
+
+    /// Called with the verdict of the clamav on a content which was not known. Does nothing
+    /// by default.
+    fn record(&self, _key: &LookupKey, _outcome: &ScanOutcome) {}
+}
+
+/// An in-memory [`HashLookup`] which remembers the verdicts of the clamav, so that identical
+/// contents skip the clamav until the cache is full and their entries are evicted, oldest
+/// first.
+///
+/// The verdicts are keyed by the version of the signature database too, so a content is
+/// scanned again after a signature update instead of keeping a stale clean verdict.
+#[derive(Debug)]
+pub struct VerdictCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    verdicts: HashMap<LookupKey, ScanOutcome>,
+    order: VecDeque<LookupKey>,
+}
+
+impl VerdictCache {
+    /// Remember up to `capacity` verdicts.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// The number of verdicts remembered.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().verdicts.len()
+    }
+
+    /// Returns `true` if no verdict is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every verdict, e.g. to free the entries of older signature databases.
+    pub fn clear(&self) {
+        *self.entries.lock().unwrap() = Entries::default();
+    }
+}
+
+impl HashLookup for VerdictCache {
+    fn lookup(&self, key: &LookupKey) -> Option<ScanOutcome> {
+        self.entries.lock().unwrap().verdicts.get(key).cloned()
+    }
+
+    fn record(&self, key: &LookupKey, outcome: &ScanOutcome) {
+        if self.capacity == 0 || *outcome == ScanOutcome::Skipped {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.verdicts.insert(*key, outcome.clone()).is_none() {
+            entries.order.push_back(*key);
+        }
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.verdicts.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// The version of the signature database of the clamav, asked with `VERSION` at most once
+/// per refresh interval.
+///
+/// A single caller asks at a time, without holding the lock, while the others go on with the
+/// version asked before. A failure is kept for the refresh interval too, so that a clamav which
+/// does not reply is not asked again by every scan.
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+#[derive(Debug)]
+pub(crate) struct DatabaseVersion {
+    refresh: Duration,
+    state: Mutex<State>,
+}
+
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+#[derive(Debug, Default)]
+struct State {
+    /// The version, or why it is unknown, and when it was asked.
+    latest: Option<(Result<Option<u64>, String>, Instant)>,
+    fetching: bool,
+}
+
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+impl DatabaseVersion {
+    pub(crate) fn new(refresh: Duration) -> Self {
+        Self {
+            refresh,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The cached version, or the one fetched if it is older than the refresh interval. Fails
+    /// with the reason the version is unknown, e.g. the error of the last `VERSION`.
+    pub(crate) async fn get<F>(&self, fetch: impl FnOnce() -> F) -> Result<Option<u64>, String>
+    where
+        F: Future<Output = Result<Version, Error>>,
+    {
+        {
+            let state = &mut *self.state.lock().unwrap();
+            match &state.latest {
+                Some((latest, at)) if at.elapsed() < self.refresh || state.fetching => {
+                    return latest.clone();
+                }
+                None if state.fetching => {
+                    return Err("the database version is being asked".to_string());
+                }
+                _ => state.fetching = true,
+            }
+        }
+
+        let fetching = Fetching(&self.state);
+        let latest = fetch()
+            .await
+            .map(|version| version.database)
+            .map_err(|err| err.to_string());
+        self.state.lock().unwrap().latest = Some((latest.clone(), Instant::now()));
+        drop(fetching);
+        latest
+    }
+}
+
+/// Lets the next caller ask for the version, even if the future asking is dropped.
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+struct Fetching<'a>(&'a Mutex<State>);
+
+#[cfg(any(test, all(feature = "spool", feature = "tokio")))]
+impl Drop for Fetching<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().fetching = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn version(database: u64) -> impl FnOnce() -> std::future::Ready<Result<Version, Error>> {
+        move || {
+            std::future::ready(Ok(Version {
+                program: "ClamAV 1.0.0".into(),
+                database: Some(database),
+                database_time: None,
+            }))
+        }
+    }
+
+    fn key(byte: u8, database: u64) -> LookupKey {
+        LookupKey {
+            digest: [byte; 32],
+            database: Some(database),
+        }
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_verdicts() {
+        let cache = VerdictCache::new(2);
+        cache.record(&key(1, 27000), &ScanOutcome::Clean);
+        cache.record(&key(2, 27000), &ScanOutcome::Infected("Eicar".into()));
+        cache.record(&key(3, 27000), &ScanOutcome::Clean);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.lookup(&key(1, 27000)), None);
+        assert_eq!(
+            cache.lookup(&key(2, 27000)),
+            Some(ScanOutcome::Infected("Eicar".into()))
+        );
+    }
+
+    #[test]
+    fn it_misses_verdicts_of_other_databases() {
+        let cache = VerdictCache::new(2);
+        cache.record(&key(1, 27000), &ScanOutcome::Clean);
+
+        assert_eq!(cache.lookup(&key(1, 27000)), Some(ScanOutcome::Clean));
+        assert_eq!(cache.lookup(&key(1, 27001)), None);
+    }
+
+    #[tokio::test]
+    async fn it_refreshes_the_database_version_after_the_interval() {
+        let cached = DatabaseVersion::new(Duration::from_secs(60));
+        assert_eq!(cached.get(version(27000)).await, Ok(Some(27000)));
+        assert_eq!(cached.get(version(27001)).await, Ok(Some(27000)));
+
+        let uncached = DatabaseVersion::new(Duration::ZERO);
+        assert_eq!(uncached.get(version(27000)).await, Ok(Some(27000)));
+        assert_eq!(uncached.get(version(27001)).await, Ok(Some(27001)));
+    }
+
+    #[tokio::test]
+    async fn it_asks_for_the_version_once_at_a_time() {
+        let cached = DatabaseVersion::new(Duration::ZERO);
+        let asked = cached
+            .get(|| async {
+                // Another scan meanwhile goes on without the version.
+                assert!(cached.get(version(27001)).await.is_err());
+                version(27000)().await
+            })
+            .await;
+        assert_eq!(asked, Ok(Some(27000)));
+
+        // Then with the one asked before while the version is asked again.
+        let asked = cached
+            .get(|| async {
+                assert_eq!(cached.get(version(27002)).await, Ok(Some(27000)));
+                version(27001)().await
+            })
+            .await;
+        assert_eq!(asked, Ok(Some(27001)));
+    }
+
+    #[tokio::test]
+    async fn it_keeps_a_failure_for_the_refresh_interval() {
+        let cached = DatabaseVersion::new(Duration::from_secs(60));
+        let failed = cached
+            .get(|| async { Err(Error::Io(io::ErrorKind::ConnectionRefused.into())) })
+            .await;
+        assert!(failed.is_err());
+
+        let again = cached
+            .get(|| async { panic!("the failure is not kept") })
+            .await;
+        assert_eq!(again, failed);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{Error, ScanOutcome, Scanner};
+
+use bytes::Bytes;
+use mail_parser::{MessageParser, MimeHeaders};
+use std::{error::Error as StdError, io};
+use tokio_stream::{Stream, StreamExt};
+
+/// The outcome of the scan of an attachment of a mail message.
+#[derive(Debug)]
+pub struct AttachmentReport {
+    /// The file name of the attachment, if any.
+    pub name: Option<String>,
+    /// The content type of the attachment, e.g. `application/pdf`.
+    pub content_type: Option<String>,
+    /// The number of decoded bytes scanned.
+    pub size: usize,
+    /// The verdict of the clamav, or the error which prevented the scan.
+    pub outcome: Result<ScanOutcome, Error>,
+}
+
+impl AttachmentReport {
+    /// Returns `true` if the clamav found a virus in the attachment.
+    pub fn is_infected(&self) -> bool {
+        matches!(self.outcome, Ok(ScanOutcome::Infected(_)))
+    }
+}
+
+pub(crate) async fn scan_mail<St, B, E>(
+    scanner: &Scanner,
+    input: St,
+) -> Result<Vec<AttachmentReport>, Error>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    let mut input = std::pin::pin!(input);
+    let mut raw = vec![];
+    while let Some(chunk) = input.next().await {
+        let chunk = chunk.map_err(|err| Error::Stream(Box::new(err)))?;
+        raw.extend_from_slice(&chunk.into());
+    }
+
+    let message = MessageParser::default()
+        .parse(&raw)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid RFC822 message"))?;
+
+    let reports = message
+        .attachments()
+        .map(|part| {
+            let contents = part.contents();
+            AttachmentReport {
+                name: part.attachment_name().map(Into::into),
+                content_type: part.content_type().map(|ct| match ct.subtype() {
+                    Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+                    None => ct.ctype().into(),
+                }),
+                size: contents.len(),
+                outcome: scan_attachment(scanner, contents),
+            }
+        })
+        .collect();
+
+    Ok(reports)
+}
+
+fn scan_attachment(scanner: &Scanner, contents: &[u8]) -> Result<ScanOutcome, Error> {
+    let mut scan = scanner.scan()?;
+    scan.send(contents)?;
+
+    scan.conclude()
+        .expect("the scan is finished only once, after the attachment")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_clamd;
+
+    const MESSAGE: &str = "From: alice@example.com\r\n\
+        To: bob@example.com\r\n\
+        Subject: report\r\n\
+        MIME-Version: 1.0\r\n\
+        Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        See the attachment.\r\n\
+        --b\r\n\
+        Content-Type: application/octet-stream\r\n\
+        Content-Disposition: attachment; filename=\"report.bin\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        SGVsbG8gV29ybGQ=\r\n\
+        --b--\r\n";
+
+    #[tokio::test]
+    async fn it_scans_each_attachment() {
+        let (addr, server) = fake_clamd(b"stream: Eicar-Signature FOUND\0");
+        let scanner = Scanner::tcp(addr).unwrap();
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from(MESSAGE))]);
+
+        let reports = scanner.scan_mail(input).await.unwrap();
+        assert_eq!(reports.len(), 1);
+
+        let report = &reports[0];
+        assert_eq!(report.name.as_deref(), Some("report.bin"));
+        assert_eq!(
+            report.content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(report.size, 11);
+        assert!(report.is_infected());
+
+        // The decoded content is scanned.
+        assert!(server.join().unwrap().ends_with(b"Hello World\0\0\0\0"));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{
+    protocol::ChunkSize,
+    response::{ResponseParser, ScanOutcome},
+    scan::{Scan, ScanPhase},
+    Error, Progress,
+};
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// A scan driven by pushing the content manually instead of wrapping a stream, for callers
+/// with their own polling loop, such as a custom reactor or an FFI boundary.
+///
+/// Push the content with [`ManualScan::feed`], then poll [`ManualScan::poll_complete`] for the
+/// verdict. With a non-blocking connection, the chunks it does not accept yet are kept and
+/// written by [`ManualScan::poll_flush`] or the next call.
+pub struct ManualScan<RW> {
+    scan: Scan<RW>,
+}
+
+impl<RW: Read + Write> ManualScan<RW> {
+    /// Create a new [`ManualScan`] over a connection to the clamav.
+    pub fn new(inner: RW) -> Self {
+        Self::with_scan(Scan::new(inner))
+    }
+
+    pub(crate) fn with_scan(scan: Scan<RW>) -> Self {
+        Self { scan }
+    }
+
+    /// Split the content into chunks of at most the given size before sending it. Defaults to
+    /// [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE).
+    pub fn with_chunk_size(mut self, chunk_size: ChunkSize) -> Self {
+        self.scan.set_chunk_size(chunk_size);
+        self
+    }
+
+    /// Use the given parser instead of [`ClamdParser`](crate::ClamdParser) to map the reply
+    /// from the clamav to a [`ScanOutcome`].
+    pub fn with_response_parser(mut self, parser: impl ResponseParser + 'static) -> Self {
+        self.scan.set_parser(Arc::new(parser));
+        self
+    }
+
+    /// Send the next part of the content. Content fed after the verdict has been read is
+    /// ignored.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.scan.send(bytes)
+    }
+
+    /// Write the chunks a non-blocking connection has not accepted yet.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        match self.scan.resume() {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    /// Terminate the content and read the verdict. Returns `None` once the scan has ended,
+    /// i.e. after the verdict or an error has been returned.
+    pub fn poll_complete(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ScanOutcome, Error>>> {
+        if self.scan.is_finished() {
+            return Poll::Ready(None);
+        }
+        if let Err(err) = std::task::ready!(self.poll_flush(cx)) {
+            return Poll::Ready(Some(Err(err)));
+        }
+        let polled = self.scan.poll_conclude();
+        if polled.is_pending() {
+            cx.waker().wake_by_ref();
+        }
+        polled
+    }
+
+    /// Where the scan is in the clamav protocol.
+    pub fn phase(&self) -> ScanPhase {
+        self.scan.phase()
+    }
+
+    /// A clonable handle to follow the scan.
+    pub fn progress(&self) -> Progress {
+        self.scan.progress().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTransport;
+    use std::future::poll_fn;
+
+    #[tokio::test]
+    async fn it_scans_the_content_fed_manually() {
+        let mut transport = FakeTransport::new("stream: Eicar-Signature FOUND\0")
+            .with_max_write(3)
+            .with_would_block();
+        let mut scan = ManualScan::new(&mut transport);
+
+        scan.feed(b"Hello ").unwrap();
+        scan.feed(b"World").unwrap();
+        assert_eq!(scan.phase(), ScanPhase::Streaming { bytes_sent: 11 });
+
+        let outcome = poll_fn(|cx| scan.poll_complete(cx)).await;
+        assert_eq!(
+            outcome.unwrap().unwrap(),
+            ScanOutcome::Infected("stream: Eicar-Signature FOUND\0".into())
+        );
+        assert!(poll_fn(|cx| scan.poll_complete(cx)).await.is_none());
+        drop(scan);
+
+        assert!(transport.blocked() > 0);
+        assert_eq!(transport.chunks(), vec!["Hello ", "World"]);
+        assert!(transport.is_terminated());
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// A cap on the bytes the scanned streams buffer in memory, shared by every stream it is given
+/// to, so that the scans cannot run the service out of memory under load.
+///
+/// A stream over its share of the budget degrades instead of failing: its [`Spool`] moves to
+/// its temp file early, and it waits for the clamav to accept the chunks already queued before
+/// queuing more. A [`ScannedStream`] waits by returning `Poll::Pending` rather than blocking its
+/// task, so it may go over its share by the chunks of a single poll. See [`ScannerBuilder::memory_budget`] and
+/// [`ScannedStream::with_memory_budget`].
+///
+/// [`Spool`]: crate::Spool
+/// [`ScannerBuilder::memory_budget`]: crate::ScannerBuilder::memory_budget
+/// [`ScannedStream::with_memory_budget`]: crate::ScannedStream::with_memory_budget
+/// [`ScannedStream`]: crate::ScannedStream
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    limit: usize,
+    used: Arc<AtomicUsize>,
+}
+
+impl MemoryBudget {
+    /// Allow up to `limit` bytes to be buffered at once.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: Arc::default(),
+        }
+    }
+
+    /// The number of bytes which can be buffered at once.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The number of bytes buffered now.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// The number of bytes which can still be buffered.
+    pub fn available(&self) -> usize {
+        self.limit.saturating_sub(self.used())
+    }
+
+    fn try_reserve(&self, bytes: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes).filter(|used| *used <= self.limit)
+            })
+            .is_ok()
+    }
+
+    fn reserve(&self, bytes: usize) {
+        self.used.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+/// The bytes a single buffer holds against a [`MemoryBudget`], released when it is dropped.
+/// Without a budget, every reservation succeeds.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryCharge {
+    budget: Option<MemoryBudget>,
+    held: usize,
+}
+
+impl MemoryCharge {
+    pub(crate) fn new(budget: Option<MemoryBudget>) -> Self {
+        Self { budget, held: 0 }
+    }
+
+    /// Reserve more bytes for the buffer. Returns `false` if the budget would be exceeded.
+    pub(crate) fn grow(&mut self, bytes: usize) -> bool {
+        if let Some(budget) = &self.budget {
+            if !budget.try_reserve(bytes) {
+                return false;
+            }
+        }
+        self.held += bytes;
+        true
+    }
+
+    /// Reserve more bytes for the buffer even past the budget, leaving less of it to the others
+    /// until they are released.
+    pub(crate) fn overdraw(&mut self, bytes: usize) {
+        if let Some(budget) = &self.budget {
+            budget.reserve(bytes);
+        }
+        self.held += bytes;
+    }
+
+    /// Release bytes the buffer no longer holds.
+    pub(crate) fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.held);
+        if let Some(budget) = &self.budget {
+            budget.release(bytes);
+        }
+        self.held -= bytes;
+    }
+
+    /// Release every byte held.
+    pub(crate) fn clear(&mut self) {
+        self.shrink(self.held);
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_shares_the_limit_between_the_charges() {
+        let budget = MemoryBudget::new(10);
+        let mut first = MemoryCharge::new(Some(budget.clone()));
+        let mut second = MemoryCharge::new(Some(budget.clone()));
+
+        assert!(first.grow(6));
+        assert!(!second.grow(6));
+        assert!(second.grow(4));
+        assert_eq!(budget.available(), 0);
+
+        first.shrink(2);
+        assert_eq!(budget.used(), 8);
+        drop(second);
+        assert_eq!(budget.used(), 4);
+    }
+}
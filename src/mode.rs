@@ -0,0 +1,87 @@
+#[cfg(feature = "spool")]
+use crate::ConfigIssue;
+
+#[cfg(feature = "spool")]
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+#[cfg(feature = "spool")]
+use tempfile::NamedTempFile;
+
+/// How the content is sent to the clamav.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ScanMode {
+    /// Send the content over the connection with the `INSTREAM` command.
+    #[default]
+    Instream,
+
+    /// Write the content to a temp file while passing it through, then ask the clamav to scan
+    /// the file by its path with the `SCAN` command.
+    ///
+    /// This avoids the `StreamMaxLength` limit of `INSTREAM` for huge contents, but only works
+    /// when the clamav runs on the same host and can read the directory. The temp file is
+    /// created in the given directory, or [`std::env::temp_dir`] if `None`, and removed when
+    /// the stream is dropped. Only available with the `spool` feature.
+    #[cfg(feature = "spool")]
+    LocalFile(Option<PathBuf>),
+}
+
+impl ScanMode {
+    #[cfg(feature = "spool")]
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        if let Self::LocalFile(Some(dir)) = self {
+            if !dir.is_dir() {
+                issues.push(ConfigIssue::DirNotFound {
+                    purpose: "local file",
+                    dir: dir.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// The temp file of a [`ScanMode::LocalFile`] scan.
+#[cfg(feature = "spool")]
+#[derive(Debug)]
+pub(crate) struct LocalFile {
+    dir: Option<PathBuf>,
+    file: Option<NamedTempFile>,
+}
+
+#[cfg(feature = "spool")]
+impl LocalFile {
+    pub(crate) fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir, file: None }
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file()?.write_all(bytes)
+    }
+
+    /// Flush the content and return the path of the file to scan.
+    pub(crate) fn finish(&mut self) -> io::Result<PathBuf> {
+        let file = self.file()?;
+        file.flush()?;
+        Ok(file.path().to_path_buf())
+    }
+
+    fn file(&mut self) -> io::Result<&mut NamedTempFile> {
+        if self.file.is_none() {
+            let file = match &self.dir {
+                Some(dir) => NamedTempFile::new_in(dir)?,
+                None => NamedTempFile::new()?,
+            };
+            // The clamav usually runs as another user.
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                file.as_file()
+                    .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+            }
+            self.file = Some(file);
+        }
+
+        Ok(self.file.as_mut().unwrap())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{lookup::Sha256Digest, spool::Spool, Error, ScanOutcome, ScanReport, Scanner};
+
+use bytes::Bytes;
+use std::{collections::BTreeMap, error::Error as StdError};
+use tokio_stream::Stream;
+
+/// Scans a multipart upload, such as an S3 multipart upload, whose parts arrive as separate
+/// streams, obtained from [`Scanner::multipart`].
+///
+/// Every part is scanned on its own as it arrives. With [`MultipartScan::whole_object`], the
+/// parts are also spooled and the object assembled in part number order is scanned once more
+/// on [`MultipartScan::complete`], so that a signature spanning two parts is not missed.
+#[derive(Debug)]
+pub struct MultipartScan {
+    scanner: Scanner,
+    whole_object: bool,
+    parts: BTreeMap<u32, (PartReport, Option<Spool>)>,
+}
+
+impl MultipartScan {
+    pub(crate) fn new(scanner: Scanner) -> Self {
+        Self {
+            scanner,
+            whole_object: false,
+            parts: BTreeMap::new(),
+        }
+    }
+
+    /// Also scan the whole object assembled from the parts on completion. The parts are kept
+    /// in the spool of the [`Scanner`] until then, see
+    /// [`ScannerBuilder::spool`](crate::ScannerBuilder::spool).
+    pub fn whole_object(mut self, whole_object: bool) -> Self {
+        self.whole_object = whole_object;
+        self
+    }
+
+    /// Scan a part. Parts may arrive in any order, and a part uploaded again replaces the
+    /// previous one with the same number.
+    pub async fn scan_part<St, B, E>(
+        &mut self,
+        part_number: u32,
+        input: St,
+    ) -> Result<&PartReport, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let spooled = self.scanner.scan_spooled(input).await?;
+        let part = PartReport {
+            digest: spooled.digest,
+            outcome: spooled.outcome,
+            report: spooled.report,
+        };
+        let spool = self.whole_object.then_some(spooled.spool);
+
+        self.parts.insert(part_number, (part, spool));
+        Ok(&self.parts[&part_number].0)
+    }
+
+    /// The reports of the parts scanned so far, keyed by part number.
+    pub fn parts(&self) -> impl Iterator<Item = (u32, &PartReport)> {
+        self.parts.iter().map(|(number, (part, _))| (*number, part))
+    }
+
+    /// Finish the upload, scanning the whole object if enabled, and return the combined report.
+    pub async fn complete(self) -> Result<MultipartReport, Error> {
+        let whole = match self.whole_object {
+            true => {
+                let mut scan = self.scanner.scan()?;
+                for (_, spool) in self.parts.values() {
+                    if let Some(spool) = spool {
+                        scan.send_reader(spool.reader()?)?;
+                    }
+                }
+                let outcome = scan
+                    .conclude()
+                    .expect("the scan is finished only once, after the parts")?;
+                Some(outcome)
+            }
+            false => None,
+        };
+
+        Ok(MultipartReport {
+            parts: self
+                .parts
+                .into_iter()
+                .map(|(number, (part, _))| (number, part))
+                .collect(),
+            whole,
+        })
+    }
+}
+
+/// The scan of a part of a [`MultipartScan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartReport {
+    /// The SHA-256 digest of the part.
+    pub digest: Sha256Digest,
+
+    /// The verdict of the clamav on the part.
+    pub outcome: ScanOutcome,
+
+    /// The summary of the scan of the part.
+    pub report: ScanReport,
+}
+
+/// The combined report of a [`MultipartScan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipartReport {
+    /// The reports of the parts, keyed by part number.
+    pub parts: BTreeMap<u32, PartReport>,
+
+    /// The verdict on the whole object, if [`MultipartScan::whole_object`] is enabled.
+    pub whole: Option<ScanOutcome>,
+}
+
+impl MultipartReport {
+    /// The numbers of the infected parts, in order.
+    pub fn infected_parts(&self) -> Vec<u32> {
+        self.parts
+            .iter()
+            .filter(|(_, part)| matches!(part.outcome, ScanOutcome::Infected(_)))
+            .map(|(number, _)| *number)
+            .collect()
+    }
+
+    /// Returns `true` if neither a part nor the whole object is infected.
+    pub fn is_clean(&self) -> bool {
+        self.infected_parts().is_empty() && !matches!(self.whole, Some(ScanOutcome::Infected(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd_many, Address};
+
+    #[tokio::test]
+    async fn it_scans_every_part_and_the_whole_object() {
+        let (addr, server) = fake_clamd_many(b"stream: OK\0", 3);
+        let scanner = Scanner::new(Address::tcp(addr).unwrap());
+        let mut upload = scanner.multipart().whole_object(true);
+
+        for (number, content) in [(2, "World"), (1, "Hello ")] {
+            let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from(content))]);
+            let part = upload.scan_part(number, input).await.unwrap();
+            assert_eq!(part.outcome, ScanOutcome::Clean);
+        }
+
+        let report = upload.complete().await.unwrap();
+        assert_eq!(report.parts.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(report.parts[&1].report.bytes_scanned, 6);
+        assert_eq!(report.whole, Some(ScanOutcome::Clean));
+        assert!(report.is_clean());
+
+        // The whole object is assembled in part number order.
+        let received = server.join().unwrap();
+        let find = |needle: &[u8]| received[2].windows(needle.len()).position(|w| w == needle);
+        assert!(find(b"Hello ").unwrap() < find(b"World").unwrap());
+    }
+}
@@ -0,0 +1,71 @@
+use crate::connection::Connection;
+
+use std::{
+    io::{self, Read},
+    sync::Mutex,
+};
+
+/// Idle `IDSESSION` connections kept by a [`Scanner`](crate::Scanner) for the next scans.
+#[derive(Debug)]
+pub(crate) struct Pool {
+    max_idle: usize,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl Pool {
+    pub(crate) fn new(max_idle: usize) -> Self {
+        Self {
+            max_idle,
+            idle: Mutex::default(),
+        }
+    }
+
+    /// Take an idle connection which the clamav has not closed meanwhile, e.g. after its
+    /// `IdleTimeout`.
+    pub(crate) fn take(&self) -> Option<Connection> {
+        let mut idle = self.idle.lock().unwrap();
+        while let Some(conn) = idle.pop() {
+            if is_open(&conn) {
+                return Some(conn);
+            }
+        }
+        None
+    }
+
+    /// Keep the connection for the next scan, or drop it if enough are kept already.
+    pub(crate) fn put(&self, conn: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(conn);
+        }
+    }
+
+    /// Close the idle connections.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// An idle session connection has nothing to read, unless the clamav has closed it.
+///
+/// A custom connection cannot be probed, so a closed one fails the scan it is taken for.
+fn is_open(conn: &Connection) -> bool {
+    if let Connection::Custom(_) = conn {
+        return true;
+    }
+    if conn.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let mut probe = conn;
+    let open = matches!(
+        probe.read(&mut [0u8; 1]),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock
+    );
+    open && conn.set_nonblocking(false).is_ok()
+}
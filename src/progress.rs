@@ -0,0 +1,193 @@
+use crate::report::{ScanReport, Warning};
+#[cfg(feature = "protocol-debug")]
+use crate::trace::{Frame, ProtocolTrace};
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A clonable handle to follow a [`ScannedStream`](crate::ScannedStream) while it is consumed
+/// elsewhere, obtained from [`ScannedStream::progress`](crate::ScannedStream::progress).
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    bytes_scanned: u64,
+    expected_len: Option<u64>,
+    warnings: Vec<Warning>,
+    time_to_verdict: Option<Duration>,
+    buffered_bytes: usize,
+    peak_buffered_bytes: usize,
+    deduplicated_bytes: u64,
+    tenant: Option<String>,
+    report: Option<ScanReport>,
+    #[cfg(feature = "protocol-debug")]
+    trace: ProtocolTrace,
+}
+
+impl Progress {
+    /// The number of content bytes passed through so far.
+    pub fn bytes_scanned(&self) -> u64 {
+        self.state.lock().unwrap().bytes_scanned
+    }
+
+    /// The expected length of the whole content, if declared.
+    pub fn expected_len(&self) -> Option<u64> {
+        self.state.lock().unwrap().expected_len
+    }
+
+    /// The percentage of the expected length passed through so far, capped at 100.
+    /// Returns `None` if no length was declared.
+    pub fn percent(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        state.expected_len.map(|expected| match expected {
+            0 => 100.0,
+            _ => (state.bytes_scanned as f64 / expected as f64 * 100.0).min(100.0),
+        })
+    }
+
+    /// The number of bytes the scan buffers in memory now, i.e. the chunks queued for a
+    /// non-blocking connection and the part of the [`Spool`](crate::Spool) kept in memory.
+    pub fn buffered_bytes(&self) -> usize {
+        self.state.lock().unwrap().buffered_bytes
+    }
+
+    /// The highest number of bytes the scan has buffered in memory at once.
+    pub fn peak_buffered_bytes(&self) -> usize {
+        self.state.lock().unwrap().peak_buffered_bytes
+    }
+
+    /// The number of content bytes not sent to the clamav, because a block with the same
+    /// bytes had been sent before, see [`BlockDedup`](crate::BlockDedup).
+    pub fn deduplicated_bytes(&self) -> u64 {
+        self.state.lock().unwrap().deduplicated_bytes
+    }
+
+    /// The tenant of the [`Scanner`](crate::Scanner) handle which opened the scan, see
+    /// [`Scanner::for_tenant`](crate::Scanner::for_tenant).
+    pub fn tenant(&self) -> Option<String> {
+        self.state.lock().unwrap().tenant.clone()
+    }
+
+    /// The non-fatal events which occurred so far, such as a reconnection.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.state.lock().unwrap().warnings.clone()
+    }
+
+    /// The summary of the scan. Returns `None` until the input has been consumed.
+    pub fn report(&self) -> Option<ScanReport> {
+        self.state.lock().unwrap().report.clone()
+    }
+
+    /// The frames exchanged with the clamav so far, with the content redacted.
+    #[cfg(feature = "protocol-debug")]
+    pub fn protocol_trace(&self) -> ProtocolTrace {
+        self.state.lock().unwrap().trace.clone()
+    }
+
+    #[cfg(feature = "protocol-debug")]
+    pub(crate) fn trace(&self, frame: Frame) {
+        self.state.lock().unwrap().trace.push(frame);
+    }
+
+    pub(crate) fn set_expected_len(&self, len: u64) {
+        self.state.lock().unwrap().expected_len = Some(len);
+    }
+
+    pub(crate) fn set_tenant(&self, tenant: &str) {
+        self.state.lock().unwrap().tenant = Some(tenant.to_string());
+    }
+
+    pub(crate) fn add(&self, bytes: u64) {
+        self.state.lock().unwrap().bytes_scanned += bytes;
+    }
+
+    pub(crate) fn set_buffered(&self, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.buffered_bytes = bytes;
+        state.peak_buffered_bytes = state.peak_buffered_bytes.max(bytes);
+    }
+
+    #[cfg(feature = "dedup")]
+    pub(crate) fn add_deduplicated(&self, bytes: u64) {
+        self.state.lock().unwrap().deduplicated_bytes += bytes;
+    }
+
+    pub(crate) fn warn(&self, warning: Warning) {
+        self.state.lock().unwrap().warnings.push(warning);
+    }
+
+    pub(crate) fn set_time_to_verdict(&self, elapsed: Duration) {
+        self.state.lock().unwrap().time_to_verdict = Some(elapsed);
+    }
+
+    pub(crate) fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(expected_len) = state.expected_len {
+            let bytes_scanned = state.bytes_scanned;
+            if bytes_scanned < expected_len {
+                state.warnings.push(Warning::Truncated {
+                    bytes_scanned,
+                    expected_len,
+                });
+            } else if bytes_scanned > expected_len {
+                state.warnings.push(Warning::Overrun {
+                    bytes_scanned,
+                    expected_len,
+                });
+            }
+        }
+
+        state.report = Some(ScanReport {
+            bytes_scanned: state.bytes_scanned,
+            expected_len: state.expected_len,
+            warnings: state.warnings.clone(),
+            reputation: None,
+            signatures: vec![],
+            time_to_verdict: state.time_to_verdict,
+            tenant: state.tenant.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_percent_complete_of_the_expected_length() {
+        let progress = Progress::default();
+        assert_eq!(progress.percent(), None);
+
+        progress.set_expected_len(200);
+        progress.add(50);
+        assert_eq!(progress.percent(), Some(25.0));
+
+        progress.add(250);
+        assert_eq!(progress.percent(), Some(100.0));
+    }
+
+    #[test]
+    fn it_flags_truncated_inputs_in_the_report() {
+        let progress = Progress::default();
+        progress.set_expected_len(100);
+        progress.add(60);
+        assert_eq!(progress.report(), None);
+
+        progress.finish();
+        let report = progress.report().unwrap();
+        assert_eq!(report.bytes_scanned, 60);
+        assert!(report.is_truncated());
+        assert_eq!(
+            progress.warnings(),
+            vec![Warning::Truncated {
+                bytes_scanned: 60,
+                expected_len: 100,
+            }]
+        );
+    }
+}
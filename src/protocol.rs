@@ -0,0 +1,463 @@
+//! Pure functions and types for the [clamd protocol](https://docs.clamav.net/manual/Usage/Scanning.html#clamd),
+//! independent of any stream wrapper.
+//!
+//! A scan with the `INSTREAM` command sends [`Command::Instream`], then the content as chunks
+//! encoded with [`encode_chunk`], then [`END_OF_STREAM`], and finally reads a [`Reply`].
+//! ```rust
+//! use clamav_stream::protocol::{encode_chunk, Command, Reply, END_OF_STREAM};
+//!
+//! let mut request = Command::Instream.as_bytes().to_vec();
+//! request.extend(encode_chunk(b"Hello World"));
+//! request.extend(END_OF_STREAM);
+//! assert_eq!(&request[..10], b"zINSTREAM\0");
+//!
+//! let reply = Reply::parse(b"stream: Eicar-Signature FOUND\0").unwrap();
+//! assert_eq!(reply, Reply::Found("Eicar-Signature".into()));
+//! ```
+
+use crate::Error;
+
+use std::path::Path;
+
+/// The maximum length of a chunk sent by this crate.
+pub const CHUNK_SIZE: usize = 4096;
+
+/// The maximum length of the chunks a content is split into before it is sent with the
+/// `INSTREAM` command. The length prefix of a chunk is a `u32`, and a zero-length chunk ends
+/// the content, so it is between 1 and [`u32::MAX`]. Defaults to [`CHUNK_SIZE`].
+///
+/// A larger size means fewer chunks, but clamd buffers a whole chunk before scanning it and
+/// rejects a content longer than its `StreamMaxLength` anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkSize(usize);
+
+impl ChunkSize {
+    /// Returns [`Error::InvalidChunkSize`] unless the size is between 1 and [`u32::MAX`].
+    pub fn new(size: usize) -> Result<Self, Error> {
+        match u32::try_from(size) {
+            Ok(1..) => Ok(Self(size)),
+            _ => Err(Error::InvalidChunkSize { size }),
+        }
+    }
+
+    /// The maximum length of a chunk.
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+impl Default for ChunkSize {
+    fn default() -> Self {
+        Self(CHUNK_SIZE)
+    }
+}
+
+/// The default `StreamMaxLength` of clamd, beyond which it rejects the content of an
+/// `INSTREAM` command.
+pub const STREAM_MAX_LENGTH: usize = 25 * 1024 * 1024;
+
+/// The zero-length chunk terminating the content of an `INSTREAM` command.
+pub const END_OF_STREAM: [u8; 4] = [0, 0, 0, 0];
+
+/// A command to the clamav, in the null-terminated `z` form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Check the server is alive. Replies `PONG`.
+    Ping,
+    /// Print the program and database versions.
+    Version,
+    /// Scan a stream of chunks sent after the command.
+    Instream,
+    /// Start a session in which several commands share the connection.
+    IdSession,
+    /// End a session.
+    End,
+}
+
+impl Command {
+    /// The bytes to send for the command.
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Self::Ping => b"zPING\0",
+            Self::Version => b"zVERSION\0",
+            Self::Instream => b"zINSTREAM\0",
+            Self::IdSession => b"zIDSESSION\0",
+            Self::End => b"zEND\0",
+        }
+    }
+}
+
+/// How a command is delimited. Replies are delimited the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommandFormat {
+    /// The `z` prefixed, null-terminated form, e.g. `zINSTREAM\0`.
+    #[default]
+    Null,
+    /// The `n` prefixed, newline-terminated form, e.g. `nINSTREAM\n`.
+    Newline,
+}
+
+impl Command {
+    /// The bytes to send for the command in the given format.
+    pub fn encode(&self, format: CommandFormat) -> Vec<u8> {
+        let bytes = self.as_bytes();
+        match format {
+            CommandFormat::Null => bytes.to_vec(),
+            CommandFormat::Newline => {
+                let mut command = vec![b'n'];
+                command.extend_from_slice(&bytes[1..bytes.len() - 1]);
+                command.push(b'\n');
+                command
+            }
+        }
+    }
+}
+
+/// The `SCAN` command for a file or directory on the host of the clamav.
+pub fn scan_command(path: &Path) -> Vec<u8> {
+    path_command(b"zSCAN ", path)
+}
+
+/// The `ALLMATCHSCAN` command for a file or directory on the host of the clamav. Unlike `SCAN`,
+/// the clamav keeps scanning after the first match and replies every signature found.
+pub fn all_match_scan_command(path: &Path) -> Vec<u8> {
+    path_command(b"zALLMATCHSCAN ", path)
+}
+
+fn path_command(prefix: &[u8], path: &Path) -> Vec<u8> {
+    let mut command = prefix.to_vec();
+    command.extend_from_slice(path.to_string_lossy().as_bytes());
+    command.push(b'\0');
+    command
+}
+
+/// The 4 bytes length prefix of a chunk, in network byte order.
+pub fn chunk_header(len: u32) -> [u8; 4] {
+    len.to_be_bytes()
+}
+
+/// Encode a chunk of content as its length prefix followed by the content itself.
+///
+/// The chunk must not be empty, because clamav takes a zero-length chunk as the end of the
+/// content, and must not be longer than [`u32::MAX`]. See [`try_encode_chunk`].
+///
+/// # Panics
+///
+/// Panics if the chunk is longer than [`u32::MAX`], rather than truncating its length prefix.
+pub fn encode_chunk(chunk: &[u8]) -> Vec<u8> {
+    let len = u32::try_from(chunk.len()).expect("a chunk is not longer than u32::MAX");
+    let mut frame = Vec::with_capacity(chunk.len() + 4);
+    frame.extend(chunk_header(len));
+    frame.extend_from_slice(chunk);
+    frame
+}
+
+/// Encode a chunk like [`encode_chunk`], or return [`Error::InvalidChunkSize`] if it is empty or
+/// longer than [`u32::MAX`].
+pub fn try_encode_chunk(chunk: &[u8]) -> Result<Vec<u8>, Error> {
+    ChunkSize::new(chunk.len()).map(|_| encode_chunk(chunk))
+}
+
+/// Encodes a content into `INSTREAM` chunks of exactly [`CHUNK_SIZE`] bytes but the last,
+/// however the content is split when pushed, so that the same content always produces the
+/// same bytes.
+/// ```rust
+/// use clamav_stream::protocol::{decode_chunks, InstreamEncoder};
+///
+/// let mut encoder = InstreamEncoder::new();
+/// let mut frames = encoder.push(b"Hello ");
+/// frames.extend(encoder.push(b"World"));
+/// frames.extend(encoder.finish());
+/// assert_eq!(decode_chunks(&frames), Some(b"Hello World".to_vec()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InstreamEncoder {
+    pending: Vec<u8>,
+}
+
+impl InstreamEncoder {
+    /// Create an encoder with no pending content.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append content, returning the chunks completed by it. The rest is kept until more
+    /// content is pushed or the encoder is finished.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(bytes);
+
+        let complete = self.pending.len() - self.pending.len() % CHUNK_SIZE;
+        let frames = self.pending[..complete]
+            .chunks(CHUNK_SIZE)
+            .flat_map(encode_chunk)
+            .collect();
+        self.pending.drain(..complete);
+        frames
+    }
+
+    /// Encode the pending content, if any, followed by [`END_OF_STREAM`].
+    pub fn finish(self) -> Vec<u8> {
+        let mut frames = match self.pending.is_empty() {
+            true => vec![],
+            false => encode_chunk(&self.pending),
+        };
+        frames.extend(END_OF_STREAM);
+        frames
+    }
+}
+
+/// Decode `INSTREAM` chunks up to [`END_OF_STREAM`] and return their content. Returns `None`
+/// if the chunks are truncated or not terminated.
+pub fn decode_chunks(mut frames: &[u8]) -> Option<Vec<u8>> {
+    let mut content = vec![];
+    loop {
+        let (header, rest) = frames.split_first_chunk::<4>()?;
+        let len = u32::from_be_bytes(*header) as usize;
+        if len == 0 {
+            return Some(content);
+        }
+
+        content.extend_from_slice(rest.get(..len)?);
+        frames = &rest[len..];
+    }
+}
+
+/// Split the request id off a reply received inside a session, e.g. `1: stream: OK`.
+/// Returns `None` if the reply has no request id.
+pub fn split_request_id(reply: &[u8]) -> Option<(u64, &[u8])> {
+    let pos = reply.windows(2).position(|w| w == b": ")?;
+    let id = std::str::from_utf8(&reply[..pos]).ok()?.parse().ok()?;
+    Some((id, &reply[pos + 2..]))
+}
+
+/// A reply from the clamav.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// `stream: OK`. No virus was found.
+    Clean,
+    /// `stream: <signature> FOUND`. Holds the signature name.
+    Found(String),
+    /// `<message> ERROR`. Holds the message.
+    Error(String),
+    /// Any other reply, such as the ones to [`Command::Ping`] or [`Command::Version`].
+    Other(String),
+}
+
+impl Reply {
+    /// Parse a reply. The trailing terminator and the `stream: ` prefix (with the request id
+    /// inside sessions, e.g. `1: stream: `) are stripped.
+    pub fn parse(reply: &[u8]) -> Result<Self, Error> {
+        let reply = std::str::from_utf8(reply)?.trim_end_matches(['\0', '\n']);
+
+        if let Some(message) = reply.strip_suffix(" ERROR") {
+            return Ok(Self::Error(message.to_string()));
+        }
+
+        let body = match reply.rsplit_once("stream: ") {
+            Some((_, body)) => body,
+            None => reply,
+        };
+
+        if body == "OK" {
+            Ok(Self::Clean)
+        } else if let Some(signature) = body.strip_suffix(" FOUND") {
+            Ok(Self::Found(signature.to_string()))
+        } else {
+            Ok(Self::Other(reply.to_string()))
+        }
+    }
+}
+
+/// The reply to [`Command::Version`], e.g. `ClamAV 1.0.0/27000/Mon Jan  1 09:00:00 2024`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// The program and its version, e.g. `ClamAV 1.0.0`.
+    pub program: String,
+    /// The version of the signature database, if it is loaded.
+    pub database: Option<u64>,
+    /// The build time of the signature database, if it is loaded.
+    pub database_time: Option<String>,
+}
+
+impl Version {
+    /// Parse a reply to [`Command::Version`]. The trailing terminator is stripped.
+    pub fn parse(reply: &[u8]) -> Result<Self, Error> {
+        let reply = std::str::from_utf8(reply)?.trim_end_matches(['\0', '\n']);
+        let mut fields = reply.splitn(3, '/');
+
+        Ok(Self {
+            program: fields.next().unwrap_or_default().to_string(),
+            database: fields.next().and_then(|version| version.parse().ok()),
+            database_time: fields.next().map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{response::ClamdParser, ResponseParser};
+    use proptest::prelude::*;
+
+    /// Split the content at the given points, in any order and possibly out of range.
+    fn split(content: &[u8], mut points: Vec<usize>) -> Vec<&[u8]> {
+        points.iter_mut().for_each(|p| *p %= content.len() + 1);
+        points.sort_unstable();
+        points.push(content.len());
+
+        let mut start = 0;
+        points
+            .into_iter()
+            .map(|end| {
+                let part = &content[start..end];
+                start = end;
+                part
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn it_encodes_the_same_bytes_for_any_chunking(
+            content in prop::collection::vec(any::<u8>(), 0..3 * CHUNK_SIZE),
+            points in prop::collection::vec(any::<usize>(), 0..8),
+        ) {
+            let mut whole = InstreamEncoder::new();
+            let mut expected = whole.push(&content);
+            expected.extend(whole.finish());
+
+            let mut encoder = InstreamEncoder::new();
+            let mut frames = vec![];
+            for part in split(&content, points) {
+                frames.extend(encoder.push(part));
+            }
+            frames.extend(encoder.finish());
+
+            prop_assert_eq!(&frames, &expected);
+            prop_assert_eq!(decode_chunks(&frames), Some(content));
+        }
+
+        #[test]
+        fn it_decodes_the_content_of_chunks_as_sent(
+            content in prop::collection::vec(any::<u8>(), 0..2 * CHUNK_SIZE),
+            points in prop::collection::vec(any::<usize>(), 0..8),
+        ) {
+            // Empty parts are skipped as when a stream is scanned.
+            let mut frames: Vec<u8> = split(&content, points)
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .flat_map(encode_chunk)
+                .collect();
+            frames.extend(END_OF_STREAM);
+
+            prop_assert_eq!(decode_chunks(&frames), Some(content));
+        }
+
+        #[test]
+        fn it_never_panics_on_arbitrary_replies(
+            reply in prop::collection::vec(any::<u8>(), 0..256),
+        ) {
+            let _ = Reply::parse(&reply);
+            let _ = Version::parse(&reply);
+            let _ = split_request_id(&reply);
+            let _ = ClamdParser.parse(&reply);
+            let _ = decode_chunks(&reply);
+        }
+    }
+
+    #[test]
+    fn it_encodes_chunks_with_length_prefix() {
+        assert_eq!(encode_chunk(b"abc"), vec![0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(chunk_header(4096), [0, 0, 16, 0]);
+    }
+
+    #[test]
+    fn it_rejects_chunk_sizes_the_length_prefix_cannot_hold() {
+        assert_eq!(ChunkSize::new(4).unwrap().get(), 4);
+        assert_eq!(ChunkSize::default().get(), CHUNK_SIZE);
+        assert_eq!(
+            ChunkSize::new(0).unwrap_err(),
+            Error::InvalidChunkSize { size: 0 }
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert!(ChunkSize::new(u32::MAX as usize + 1).is_err());
+        assert!(try_encode_chunk(b"").is_err());
+        assert_eq!(try_encode_chunk(b"abc").unwrap(), encode_chunk(b"abc"));
+    }
+
+    #[test]
+    fn it_encodes_commands_in_either_format() {
+        assert_eq!(
+            Command::Instream.encode(CommandFormat::Null),
+            b"zINSTREAM\0".to_vec()
+        );
+        assert_eq!(
+            Command::Instream.encode(CommandFormat::Newline),
+            b"nINSTREAM\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn it_encodes_scan_commands_with_the_path() {
+        assert_eq!(
+            scan_command(Path::new("/tmp/file")),
+            b"zSCAN /tmp/file\0".to_vec()
+        );
+        assert_eq!(
+            all_match_scan_command(Path::new("/tmp/file")),
+            b"zALLMATCHSCAN /tmp/file\0".to_vec()
+        );
+    }
+
+    #[test]
+    fn it_splits_request_ids_off_session_replies() {
+        assert_eq!(
+            split_request_id(b"12: stream: OK\0"),
+            Some((12, &b"stream: OK\0"[..]))
+        );
+        assert_eq!(split_request_id(b"stream: OK\0"), None);
+    }
+
+    #[test]
+    fn it_parses_clean_replies() {
+        assert_eq!(Reply::parse(b"stream: OK\0").unwrap(), Reply::Clean);
+        assert_eq!(Reply::parse(b"1: stream: OK\n").unwrap(), Reply::Clean);
+    }
+
+    #[test]
+    fn it_parses_found_replies() {
+        assert_eq!(
+            Reply::parse(b"stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+            Reply::Found("Win.Test.EICAR_HDB-1".into()),
+        );
+    }
+
+    #[test]
+    fn it_parses_error_replies() {
+        assert_eq!(
+            Reply::parse(b"INSTREAM size limit exceeded. ERROR\0").unwrap(),
+            Reply::Error("INSTREAM size limit exceeded.".into()),
+        );
+    }
+
+    #[test]
+    fn it_parses_version_replies() {
+        assert_eq!(
+            Version::parse(b"ClamAV 1.0.0/27000/Mon Jan  1 09:00:00 2024\0").unwrap(),
+            Version {
+                program: "ClamAV 1.0.0".into(),
+                database: Some(27000),
+                database_time: Some("Mon Jan  1 09:00:00 2024".into()),
+            }
+        );
+        assert_eq!(Version::parse(b"ClamAV 1.0.0\n").unwrap().database, None);
+    }
+
+    #[test]
+    fn it_parses_other_replies() {
+        assert_eq!(
+            Reply::parse(b"PONG\0").unwrap(),
+            Reply::Other("PONG".into())
+        );
+    }
+}
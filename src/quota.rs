@@ -0,0 +1,354 @@
+use crate::{Error, Progress, Warning};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const SECOND: Duration = Duration::from_secs(1);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Tracks the scans and the scanned bytes of each tenant, i.e. of the handles created with
+/// [`Scanner::for_tenant`](crate::Scanner::for_tenant), and refuses the scans of a tenant
+/// beyond its [`TenantQuota`], e.g. for the plans of a SaaS upload API.
+///
+/// Set with [`ScannerBuilder::quotas`](crate::ScannerBuilder::quotas). Cloning a
+/// [`QuotaManager`] shares the same accounting.
+#[derive(Debug, Clone)]
+pub struct QuotaManager {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    quota: TenantQuota,
+    policy: QuotaPolicy,
+    tenants: Mutex<HashMap<Arc<str>, Usage>>,
+}
+
+impl QuotaManager {
+    /// Limit the scans of every tenant to the quota, unless set otherwise with
+    /// [`QuotaManager::set_quota`].
+    pub fn new(quota: TenantQuota) -> Self {
+        Self::with_policy(quota, QuotaPolicy::default())
+    }
+
+    /// Like [`QuotaManager::new`], but handle the scans beyond the quota with the policy.
+    pub fn with_policy(quota: TenantQuota, policy: QuotaPolicy) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                quota,
+                policy,
+                tenants: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Limit the scans of the tenant to its own quota instead, e.g. after it changed plans.
+    pub fn set_quota(&self, tenant: &str, quota: TenantQuota) {
+        let mut tenants = self.inner.tenants.lock().unwrap();
+        match tenants.get_mut(tenant) {
+            Some(usage) => usage.quota = Some(quota),
+            None => {
+                let mut usage = Usage::new(Instant::now());
+                usage.quota = Some(quota);
+                tenants.insert(tenant.into(), usage);
+            }
+        }
+    }
+
+    /// The scans and the bytes of the tenant counted so far.
+    pub fn usage(&self, tenant: &str) -> TenantUsage {
+        let mut tenants = self.inner.tenants.lock().unwrap();
+        tenants
+            .get_mut(tenant)
+            .map_or_else(TenantUsage::default, |usage| {
+                usage.roll(Instant::now()).to_public()
+            })
+    }
+
+    /// The tenants which have scanned anything or have their own quota, in no particular
+    /// order.
+    pub fn tenants(&self) -> Vec<String> {
+        let tenants = self.inner.tenants.lock().unwrap();
+        tenants.keys().map(|tenant| tenant.to_string()).collect()
+    }
+
+    /// Count a scan of the tenant, or refuse it if the tenant has exhausted its quota and the
+    /// policy is [`QuotaPolicy::FailClosed`]. The bytes of the scan are counted once the
+    /// returned [`QuotaCharge`] is released.
+    pub(crate) fn admit(&self, tenant: &Arc<str>) -> Result<QuotaCharge, Error> {
+        let now = Instant::now();
+        let mut tenants = self.inner.tenants.lock().unwrap();
+        let usage = tenants
+            .entry(Arc::clone(tenant))
+            .or_insert_with(|| Usage::new(now))
+            .roll(now);
+
+        let exceeded = usage.exceeded(usage.quota.unwrap_or(self.inner.quota), now);
+        let warning = match (exceeded, self.inner.policy) {
+            (None, _) => None,
+            (Some((quota, retry_in)), QuotaPolicy::FailClosed) => {
+                return Err(Error::QuotaExceeded {
+                    tenant: tenant.to_string(),
+                    quota,
+                    retry_in,
+                });
+            }
+            (Some((quota, _)), QuotaPolicy::FailOpen) => Some(Warning::QuotaExceeded { quota }),
+        };
+
+        usage.scans += 1;
+        usage.scans_total += 1;
+        Ok(QuotaCharge {
+            manager: self.clone(),
+            tenant: Arc::clone(tenant),
+            warning,
+            progress: None,
+        })
+    }
+
+    fn charge(&self, tenant: &str, bytes: u64) {
+        let mut tenants = self.inner.tenants.lock().unwrap();
+        if let Some(usage) = tenants.get_mut(tenant) {
+            let usage = usage.roll(Instant::now());
+            usage.bytes += bytes;
+            usage.bytes_total += bytes;
+        }
+    }
+}
+
+/// The limits on the scans of each tenant of a [`QuotaManager`]. The default limits nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantQuota {
+    scans_per_sec: Option<u32>,
+    bytes_per_day: Option<u64>,
+}
+
+impl TenantQuota {
+    /// Create a quota which limits nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse the scans of a tenant beyond the given number within a second.
+    pub fn max_scans_per_sec(mut self, scans: u32) -> Self {
+        self.scans_per_sec = Some(scans);
+        self
+    }
+
+    /// Refuse the scans of a tenant once the given number of bytes has been scanned for it
+    /// within a day. The bytes of a scan are counted once its verdict has been read, so that
+    /// the scans in flight may exceed the quota.
+    pub fn max_bytes_per_day(mut self, bytes: u64) -> Self {
+        self.bytes_per_day = Some(bytes);
+        self
+    }
+}
+
+/// What a [`QuotaManager`] does with the scans of a tenant which has exhausted its quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Refuse the scans with [`Error::QuotaExceeded`](crate::Error::QuotaExceeded).
+    #[default]
+    FailClosed,
+    /// Scan the content anyway and report
+    /// [`Warning::QuotaExceeded`](crate::Warning::QuotaExceeded), e.g. to bill the overage
+    /// rather than to reject uploads.
+    FailOpen,
+}
+
+/// The limit of a [`TenantQuota`] exhausted by a tenant, see
+/// [`Error::QuotaExceeded`](crate::Error::QuotaExceeded).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    /// The number of scans within a second.
+    ScansPerSecond(u32),
+    /// The number of bytes scanned within a day.
+    BytesPerDay(u64),
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ScansPerSecond(scans) => write!(f, "{scans} scans per second"),
+            Self::BytesPerDay(bytes) => write!(f, "{bytes} bytes per day"),
+        }
+    }
+}
+
+/// The scans of a tenant counted by a [`QuotaManager`], see [`QuotaManager::usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantUsage {
+    /// The number of scans started within the current second.
+    pub scans_this_second: u32,
+    /// The number of bytes scanned within the current day.
+    pub bytes_today: u64,
+    /// The number of scans started since the tenant was first seen.
+    pub scans_total: u64,
+    /// The number of bytes scanned since the tenant was first seen.
+    pub bytes_total: u64,
+}
+
+/// The windows of the quota of a tenant, which start with its first scan after the previous
+/// ones have elapsed.
+#[derive(Debug)]
+struct Usage {
+    quota: Option<TenantQuota>,
+    second: Instant,
+    scans: u32,
+    day: Instant,
+    bytes: u64,
+    scans_total: u64,
+    bytes_total: u64,
+}
+
+impl Usage {
+    fn new(now: Instant) -> Self {
+        Self {
+            quota: None,
+            second: now,
+            scans: 0,
+            day: now,
+            bytes: 0,
+            scans_total: 0,
+            bytes_total: 0,
+        }
+    }
+
+    fn roll(&mut self, now: Instant) -> &mut Self {
+        if now.duration_since(self.second) >= SECOND {
+            self.second = now;
+            self.scans = 0;
+        }
+        if now.duration_since(self.day) >= DAY {
+            self.day = now;
+            self.bytes = 0;
+        }
+        self
+    }
+
+    /// The limit of the quota exhausted in the current windows, and the time until its window
+    /// elapses.
+    fn exceeded(&self, quota: TenantQuota, now: Instant) -> Option<(Quota, Duration)> {
+        if let Some(max) = quota.bytes_per_day.filter(|max| self.bytes >= *max) {
+            let retry_in = (self.day + DAY).saturating_duration_since(now);
+            return Some((Quota::BytesPerDay(max), retry_in));
+        }
+        if let Some(max) = quota.scans_per_sec.filter(|max| self.scans >= *max) {
+            let retry_in = (self.second + SECOND).saturating_duration_since(now);
+            return Some((Quota::ScansPerSecond(max), retry_in));
+        }
+        None
+    }
+
+    fn to_public(&self) -> TenantUsage {
+        TenantUsage {
+            scans_this_second: self.scans,
+            bytes_today: self.bytes,
+            scans_total: self.scans_total,
+            bytes_total: self.bytes_total,
+        }
+    }
+}
+
+/// Counts the bytes of a scan of a tenant once it is released. Created by
+/// [`QuotaManager::admit`].
+#[derive(Debug)]
+pub(crate) struct QuotaCharge {
+    manager: QuotaManager,
+    tenant: Arc<str>,
+    warning: Option<Warning>,
+    progress: Option<Progress>,
+}
+
+impl QuotaCharge {
+    /// Count the bytes scanned by the scan followed by the progress, and report to it whether
+    /// the quota was exceeded.
+    pub(crate) fn track(&mut self, progress: Progress) {
+        if let Some(warning) = self.warning.take() {
+            progress.warn(warning);
+        }
+        self.progress = Some(progress);
+    }
+}
+
+impl Drop for QuotaCharge {
+    fn drop(&mut self) {
+        if let Some(progress) = &self.progress {
+            self.manager.charge(&self.tenant, progress.bytes_scanned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_refuses_the_scans_of_a_tenant_beyond_its_quota() {
+        let quotas = QuotaManager::new(TenantQuota::new().max_scans_per_sec(2));
+        let (acme, other): (Arc<str>, Arc<str>) = ("acme".into(), "other".into());
+
+        let _first = quotas.admit(&acme).unwrap();
+        let _second = quotas.admit(&acme).unwrap();
+        let Err(Error::QuotaExceeded {
+            tenant,
+            quota,
+            retry_in,
+        }) = quotas.admit(&acme)
+        else {
+            panic!("expected the quota to be exceeded");
+        };
+        assert_eq!(tenant, "acme");
+        assert_eq!(quota, Quota::ScansPerSecond(2));
+        assert!(retry_in <= SECOND);
+
+        // The quota applies to each tenant on its own, and can be set per tenant.
+        assert!(quotas.admit(&other).is_ok());
+        quotas.set_quota("acme", TenantQuota::new());
+        assert!(quotas.admit(&acme).is_ok());
+        assert_eq!(quotas.usage("acme").scans_total, 3);
+    }
+
+    #[test]
+    fn it_counts_the_bytes_of_a_tenant_once_the_scan_is_released() {
+        let quotas = QuotaManager::new(TenantQuota::new().max_bytes_per_day(10));
+        let acme: Arc<str> = "acme".into();
+
+        let progress = Progress::default();
+        let mut charge = quotas.admit(&acme).unwrap();
+        charge.track(progress.clone());
+        progress.add(11);
+        assert_eq!(quotas.usage("acme").bytes_today, 0);
+
+        drop(charge);
+        assert_eq!(quotas.usage("acme").bytes_today, 11);
+        assert!(matches!(
+            quotas.admit(&acme),
+            Err(Error::QuotaExceeded {
+                quota: Quota::BytesPerDay(10),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_warns_instead_of_failing_open() {
+        let quota = TenantQuota::new().max_scans_per_sec(0);
+        let quotas = QuotaManager::with_policy(quota, QuotaPolicy::FailOpen);
+        let acme: Arc<str> = "acme".into();
+
+        let progress = Progress::default();
+        quotas.admit(&acme).unwrap().track(progress.clone());
+        assert_eq!(
+            progress.warnings(),
+            vec![Warning::QuotaExceeded {
+                quota: Quota::ScansPerSecond(0)
+            }]
+        );
+        assert_eq!(quotas.usage("acme").scans_total, 1);
+    }
+}
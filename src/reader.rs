@@ -0,0 +1,125 @@
+use crate::{
+    connection::Address,
+    protocol::ChunkSize,
+    response::{ResponseParser, ScanOutcome},
+    scan::{Scan, ScanPhase},
+    Error, Progress,
+};
+
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+/// A [`Read`] wrapper which sends the content to the clamav while it is read, the blocking
+/// counterpart of a `ScannedStream` which needs no async runtime.
+///
+/// The verdict is read once the inner reader is exhausted. An infected content fails that last
+/// read with an [`io::Error`] wrapping [`Error::Scan`] instead of returning the end of file, so
+/// e.g. [`io::copy`] fails too.
+pub struct ScannedReader<R, RW> {
+    reader: R,
+    scan: Scan<RW>,
+}
+
+impl<R: Read, RW: Read + Write> ScannedReader<R, RW> {
+    /// Create a new [`ScannedReader`] over a connection to the clamav.
+    pub fn new(reader: R, inner: RW) -> Self {
+        Self {
+            reader,
+            scan: Scan::new(inner),
+        }
+    }
+
+    /// Split the content into chunks of at most the given size before sending it. Defaults to
+    /// [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE).
+    pub fn with_chunk_size(mut self, chunk_size: ChunkSize) -> Self {
+        self.scan.set_chunk_size(chunk_size);
+        self
+    }
+
+    /// Use the given parser instead of [`ClamdParser`](crate::ClamdParser) to map the reply
+    /// from the clamav to a [`ScanOutcome`].
+    pub fn with_response_parser(mut self, parser: impl ResponseParser + 'static) -> Self {
+        self.scan.set_parser(Arc::new(parser));
+        self
+    }
+
+    /// Where the scan is in the clamav protocol.
+    pub fn phase(&self) -> ScanPhase {
+        self.scan.phase()
+    }
+
+    /// A clonable handle to follow the scan while the content is read.
+    pub fn progress(&self) -> Progress {
+        self.scan.progress().clone()
+    }
+}
+
+impl<R: Read, RW: Read + Write> Read for ScannedReader<R, RW> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // The error of a failed scan has been returned already.
+        if self.scan.is_cut_short() {
+            return Ok(0);
+        }
+
+        let n = self.reader.read(buf)?;
+        if n == 0 {
+            return match self.scan.finish() {
+                Some(Err(err)) => Err(io::Error::other(err)),
+                Some(Ok(())) | None => Ok(0),
+            };
+        }
+
+        self.scan.send(&buf[..n]).map_err(io::Error::other)?;
+        Ok(n)
+    }
+}
+
+/// Read the content to the end only to scan it, and return the verdict of the clamav. The
+/// blocking counterpart of `scan_stream`.
+pub fn scan_reader(reader: impl Read, address: &Address) -> Result<ScanOutcome, Error> {
+    let mut scan = Scan::new(address.connect()?);
+    scan.send_reader(reader)?;
+    scan.conclude()
+        .expect("the scan is finished only once, after the reader")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd, testing::FakeTransport};
+
+    #[test]
+    fn it_fails_the_last_read_of_an_infected_content() {
+        let mut transport = FakeTransport::new("stream: Eicar-Signature FOUND\0");
+        let mut reader = ScannedReader::new(&b"Hello World"[..], &mut transport);
+
+        let mut content = vec![];
+        let err = reader.read_to_end(&mut content).unwrap_err();
+        assert_eq!(content, b"Hello World");
+        assert_eq!(
+            err.into_inner()
+                .unwrap()
+                .downcast::<Error>()
+                .unwrap()
+                .to_string(),
+            "stream: Eicar-Signature FOUND\0"
+        );
+        assert_eq!(reader.read(&mut [0u8; 8]).unwrap(), 0);
+        drop(reader);
+
+        assert_eq!(transport.chunks(), vec!["Hello World"]);
+        assert!(transport.is_terminated());
+    }
+
+    #[test]
+    fn it_scans_a_reader_to_the_end() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let address = Address::tcp(addr).unwrap();
+
+        let outcome = scan_reader(&b"Hello World"[..], &address).unwrap();
+        assert_eq!(outcome, ScanOutcome::Clean);
+        assert!(server.join().unwrap().ends_with(b"Hello World\0\0\0\0"));
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+#[cfg(feature = "tokio")]
+use {
+    std::{
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    },
+    tokio::io::{AsyncRead, AsyncWrite, ReadBuf},
+};
+
+/// The direction of the bytes sent to the clamav in a recording.
+const SENT: u8 = b'>';
+/// The direction of the bytes received from the clamav in a recording.
+const RECEIVED: u8 = b'<';
+
+/// A transport wrapper which records every byte exchanged with the clamav to a file, to
+/// reproduce a protocol issue later with a [`ReplayTransport`], e.g. with the
+/// [`connector`](crate::ScannerBuilder::connector) of a scanner:
+///
+#[cfg_attr(feature = "tokio", doc = "```no_run")]
+#[cfg_attr(not(feature = "tokio"), doc = "```ignore")]
+/// # use clamav_stream::{Address, RecordingTransport, Scanner};
+/// # use std::net::TcpStream;
+/// let scanner = Scanner::builder(Address::Tcp(vec![]))
+///     .connector(|| {
+///         let stream = TcpStream::connect("localhost:3310")?;
+///         RecordingTransport::create(stream, "/tmp/clamd.rec")
+///     })
+///     .build();
+/// ```
+///
+/// A recording is a sequence of frames, each a direction byte, `>` for the bytes sent and `<`
+/// for the bytes received, followed by the big-endian `u32` length of the bytes and the bytes.
+/// Every frame is written as soon as the bytes are exchanged, so the recording survives a
+/// crash. Each connection needs its own file.
+#[derive(Debug)]
+pub struct RecordingTransport<T> {
+    inner: T,
+    file: File,
+}
+
+impl<T> RecordingTransport<T> {
+    /// Record the bytes exchanged through the transport to the file, replacing its content.
+    pub fn create(inner: T, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            file: File::create(path)?,
+        })
+    }
+
+    /// Stop recording and return the transport.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&mut self, direction: u8, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let mut frame = Vec::with_capacity(5 + bytes.len());
+        frame.push(direction);
+        frame.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        frame.extend_from_slice(bytes);
+        self.file.write_all(&frame)
+    }
+}
+
+impl<T: Read> Read for RecordingTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.record(RECEIVED, &buf[..n])?;
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for RecordingTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.record(SENT, &buf[..n])?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The file is written blocking, which is fine for debugging but stalls the runtime thread
+/// for the time of each write.
+#[cfg(feature = "tokio")]
+impl<T: AsyncRead + Unpin> AsyncRead for RecordingTransport<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        if let Err(err) = std::task::ready!(Pin::new(&mut this.inner).poll_read(cx, buf)) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(this.record(RECEIVED, &buf.filled()[filled..]))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: AsyncWrite + Unpin> AsyncWrite for RecordingTransport<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = std::task::ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        Poll::Ready(this.record(SENT, &buf[..n]).map(|_| n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// A transport which replays the replies of a recording made with a [`RecordingTransport`],
+/// to reproduce a protocol issue without access to the original clamav.
+///
+/// Each reply is served once as many bytes have been written as had been sent before it in
+/// the recording. Until then, reads fail with [`io::ErrorKind::WouldBlock`], or are pending
+/// when used asynchronously. The connection is closed after the last reply.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    /// The bytes sent in the recording.
+    recorded: Vec<u8>,
+    /// The replies of the recording, each with the number of bytes sent before it.
+    replies: Vec<(usize, Vec<u8>)>,
+    /// The index of the reply and the bytes of it served so far.
+    position: (usize, usize),
+    written: Vec<u8>,
+    #[cfg(feature = "tokio")]
+    reader: Option<Waker>,
+}
+
+impl ReplayTransport {
+    /// Replay the recording in the file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_recording(&std::fs::read(path)?)
+    }
+
+    /// Replay the recording in memory, failing with [`io::ErrorKind::InvalidData`] if it is
+    /// not one.
+    pub fn from_recording(mut recording: &[u8]) -> io::Result<Self> {
+        let mut recorded = vec![];
+        let mut replies = vec![];
+
+        while let Some((&direction, rest)) = recording.split_first() {
+            let (len, rest) = rest
+                .split_first_chunk::<4>()
+                .ok_or_else(|| invalid("truncated frame length"))?;
+            let len = u32::from_be_bytes(*len) as usize;
+            if rest.len() < len {
+                return Err(invalid("truncated frame"));
+            }
+            let (bytes, rest) = rest.split_at(len);
+            match direction {
+                SENT => recorded.extend_from_slice(bytes),
+                RECEIVED => replies.push((recorded.len(), bytes.to_vec())),
+                _ => return Err(invalid("unknown frame direction")),
+            }
+            recording = rest;
+        }
+
+        Ok(Self {
+            recorded,
+            replies,
+            position: (0, 0),
+            written: vec![],
+            #[cfg(feature = "tokio")]
+            reader: None,
+        })
+    }
+
+    /// The bytes sent to the clamav in the recording.
+    pub fn recorded(&self) -> &[u8] {
+        &self.recorded
+    }
+
+    /// The bytes written during the replay, to compare with [`ReplayTransport::recorded`].
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// The next reply bytes due, `None` if the replay waits for more bytes to be written, or
+    /// an empty slice once every reply has been served.
+    fn due(&self) -> Option<&[u8]> {
+        let (index, served) = self.position;
+        match self.replies.get(index) {
+            Some((sent, reply)) if *sent <= self.written.len() => Some(&reply[served..]),
+            Some(_) => None,
+            None => Some(&[]),
+        }
+    }
+
+    fn serve(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let due = self.due()?;
+        let n = due.len().min(buf.len());
+        buf[..n].copy_from_slice(&due[..n]);
+
+        let (index, served) = &mut self.position;
+        *served += n;
+        if *served == self.replies.get(*index).map_or(0, |(_, reply)| reply.len()) {
+            self.position = (*index + 1, 0);
+        }
+        Some(n)
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.serve(buf)
+            .ok_or_else(|| io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        #[cfg(feature = "tokio")]
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for ReplayTransport {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.serve(buf.initialize_unfilled()) {
+            Some(n) => {
+                buf.advance(n);
+                Poll::Ready(Ok(()))
+            }
+            None => {
+                this.reader = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for ReplayTransport {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid recording: {reason}"),
+    )
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd, Error, ScannedStream};
+    use bytes::Bytes;
+    use std::net::TcpStream;
+    use tokio_stream::StreamExt;
+
+    fn input() -> tokio_stream::Iter<std::vec::IntoIter<Result<Bytes, Error>>> {
+        tokio_stream::iter(vec![Ok(Bytes::from("Hello ")), Ok(Bytes::from("World"))])
+    }
+
+    #[tokio::test]
+    async fn it_replays_a_recorded_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clamd.rec");
+
+        let (addr, _server) = fake_clamd(b"stream: Eicar-Signature FOUND\0");
+        let transport = RecordingTransport::create(TcpStream::connect(addr).unwrap(), &path);
+        let stream = ScannedStream::new(input(), transport.unwrap());
+        let recorded: Result<Vec<_>, _> = stream.collect().await;
+        assert!(matches!(recorded, Err(Error::Scan(_))));
+
+        let replay = ReplayTransport::open(&path).unwrap();
+        assert!(replay.recorded().starts_with(b"zINSTREAM\0"));
+        let stream = ScannedStream::new(input(), replay);
+        let replayed: Result<Vec<_>, _> = stream.collect().await;
+        assert_eq!(replayed, recorded);
+    }
+
+    #[tokio::test]
+    async fn it_holds_the_replies_until_the_recorded_bytes_are_written() {
+        let recording = [&b">\0\0\0\x05zPING"[..], b"<\0\0\0\x05PONG\0"].concat();
+        let mut replay = ReplayTransport::from_recording(&recording).unwrap();
+        let mut buf = [0u8; 16];
+
+        let err = replay.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        replay.write_all(b"zPING\0").unwrap();
+        assert_eq!(replay.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"PONG\0");
+        // The connection is closed after the last reply.
+        assert_eq!(replay.read(&mut buf).unwrap(), 0);
+        assert_eq!(replay.written(), b"zPING\0");
+
+        let err = ReplayTransport::from_recording(b"<\0\0\0\x09PONG").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
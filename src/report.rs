@@ -0,0 +1,150 @@
+use crate::{
+    drop_behavior::{DropBehavior, DropResult},
+    quota::Quota,
+    reputation::Reputation,
+    signature::SignatureMetadata,
+};
+
+use std::time::Duration;
+
+/// A summary of a finished scan, available from [`Progress::report`](crate::Progress::report).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanReport {
+    /// The number of content bytes passed through and sent to the clamav.
+    pub bytes_scanned: u64,
+
+    /// The length declared with [`ScannedStream::with_expected_len`](crate::ScannedStream::with_expected_len).
+    pub expected_len: Option<u64>,
+
+    /// The non-fatal events which occurred during the scan, in order.
+    pub warnings: Vec<Warning>,
+
+    /// The answer of the [`ReputationProvider`](crate::ReputationProvider), see
+    /// [`Scanner::scan_stream_report`](crate::Scanner::scan_stream_report).
+    pub reputation: Option<Reputation>,
+
+    /// The metadata of the signatures found, from the
+    /// [`SignatureMetadataProvider`](crate::SignatureMetadataProvider) of the scanner, see
+    /// [`ScannerBuilder::signature_metadata`](crate::ScannerBuilder::signature_metadata).
+    pub signatures: Vec<SignatureMetadata>,
+
+    /// The time the clamav took to reply with the verdict after the end of the content.
+    /// `None` if no verdict was read.
+    pub time_to_verdict: Option<Duration>,
+
+    /// The tenant of the [`Scanner`](crate::Scanner) handle which opened the scan, see
+    /// [`Scanner::for_tenant`](crate::Scanner::for_tenant).
+    pub tenant: Option<String>,
+}
+
+impl ScanReport {
+    /// Returns `true` if the input ended before the expected length was reached, which is a
+    /// common sign of an aborted upload.
+    pub fn is_truncated(&self) -> bool {
+        self.expected_len
+            .is_some_and(|expected| self.bytes_scanned < expected)
+    }
+
+    /// Returns `true` if the input yielded more than the expected length.
+    pub fn is_overrun(&self) -> bool {
+        self.expected_len
+            .is_some_and(|expected| self.bytes_scanned > expected)
+    }
+}
+
+/// What a scan does when the content is longer or shorter than its declared length, see
+/// [`ScannedStream::with_expected_len`](crate::ScannedStream::with_expected_len).
+///
+/// A truncated upload may hide a malicious tail which a resumable upload completes later,
+/// out of sight of the scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LengthPolicy {
+    /// Report [`Warning::Truncated`] or [`Warning::Overrun`], and keep the verdict.
+    #[default]
+    Warn,
+
+    /// Fail with [`Error::LengthMismatch`](crate::Error::LengthMismatch): as soon as the
+    /// content overruns the declared length, without passing the overrunning chunk through,
+    /// or instead of a clean verdict on a truncated content.
+    Fail,
+}
+
+/// A non-fatal event of a scan, reported by [`Progress::warnings`](crate::Progress::warnings)
+/// rather than as an [`Error`](crate::Error) of the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The connection to the clamav was opened after failed attempts, see
+    /// [`ScannerBuilder::reconnect_backoff`](crate::ScannerBuilder::reconnect_backoff).
+    Reconnected {
+        /// The number of failed attempts before the connection was opened.
+        failures: u32,
+    },
+
+    /// The content was passed through without being scanned, because the circuit was open with
+    /// [`FailurePolicy::FailOpen`](crate::FailurePolicy::FailOpen).
+    FailOpen,
+
+    /// The content was passed through without being scanned, because it was not sampled by the
+    /// [`SampleRate`](crate::SampleRate) of the scanner.
+    SampledOut,
+
+    /// The input ended before the expected length was reached, so only part of the content
+    /// was scanned.
+    Truncated {
+        /// The number of content bytes scanned.
+        bytes_scanned: u64,
+        /// The declared length of the content.
+        expected_len: u64,
+    },
+
+    /// The input yielded more than the expected length, so content beyond what was declared
+    /// was scanned.
+    Overrun {
+        /// The number of content bytes scanned.
+        bytes_scanned: u64,
+        /// The declared length of the content.
+        expected_len: u64,
+    },
+
+    /// The clamav took longer than the threshold to reply with the verdict, see
+    /// [`ScannerBuilder::slow_scan_threshold`](crate::ScannerBuilder::slow_scan_threshold).
+    SlowScan {
+        /// The time to verdict.
+        elapsed: Duration,
+        /// The number of content bytes scanned.
+        bytes_scanned: u64,
+        /// The address of the clamav.
+        backend: String,
+    },
+
+    /// The stream was dropped in the middle of the content, see
+    /// [`ScannedStream::with_drop_behavior`](crate::ScannedStream::with_drop_behavior).
+    Dropped {
+        /// What the scan did on the drop.
+        behavior: DropBehavior,
+        /// What became of the scan.
+        result: DropResult,
+    },
+
+    /// The tenant of the scan had exhausted its quota, but the content was scanned anyway with
+    /// [`QuotaPolicy::FailOpen`](crate::QuotaPolicy::FailOpen).
+    QuotaExceeded {
+        /// The exhausted limit.
+        quota: Quota,
+    },
+
+    /// The clamav appended a note to its clean reply, e.g. a heuristics note, see
+    /// [`TrailingNotes`](crate::TrailingNotes).
+    ClamdNote {
+        /// The line of the note.
+        note: String,
+    },
+
+    /// The [`HashLookup`](crate::HashLookup) was skipped and the content sent to the clamav,
+    /// because the version of its signature database is unknown, e.g. after a failed
+    /// `VERSION`, and a known verdict could be stale.
+    LookupDisabled {
+        /// Why the version is unknown.
+        reason: String,
+    },
+}
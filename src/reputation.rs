@@ -0,0 +1,97 @@
+use crate::lookup::Sha256Digest;
+
+use std::{future::Future, pin::Pin};
+
+/// The future returned by [`ReputationProvider::reputation`].
+pub type ReputationFuture<'a> = Pin<Box<dyn Future<Output = Option<Reputation>> + Send + 'a>>;
+
+/// An external reputation service, such as VirusTotal, asked about the SHA-256 digest of the
+/// contents scanned with [`Scanner::scan_stream_report`](crate::Scanner::scan_stream_report).
+/// Its answer is merged into the [`ScanReport`](crate::ScanReport).
+///
+/// A provider handles its own errors and timeouts, and returns `None` if the content is unknown
+/// or the service is unavailable, so that an outage never fails the scan.
+///
+/// ```
+/// use clamav_stream::{Reputation, ReputationFuture, ReputationProvider, ReputationVerdict};
+///
+/// struct VirusTotal {
+///     api_key: String,
+/// }
+///
+/// impl ReputationProvider for VirusTotal {
+///     fn reputation<'a>(&'a self, digest: &'a [u8; 32]) -> ReputationFuture<'a> {
+///         Box::pin(async move {
+///             let hash: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+///             // GET https://www.virustotal.com/api/v3/files/{hash} with the `x-apikey` header,
+///             // and read `data.attributes.last_analysis_stats` from the reply.
+///             let (malicious, total) = lookup_file(&self.api_key, &hash).await?;
+///             Some(Reputation {
+///                 provider: "VirusTotal".into(),
+///                 verdict: match malicious {
+///                     0 => ReputationVerdict::Harmless,
+///                     1..=2 => ReputationVerdict::Suspicious,
+///                     _ => ReputationVerdict::Malicious,
+///                 },
+///                 detail: Some(format!("{malicious}/{total} engines")),
+///             })
+///         })
+///     }
+/// }
+/// # async fn lookup_file(_api_key: &str, _hash: &str) -> Option<(u32, u32)> {
+/// #     None
+/// # }
+/// ```
+pub trait ReputationProvider: Send + Sync {
+    /// Look up the content by its SHA-256 digest.
+    fn reputation<'a>(&'a self, digest: &'a Sha256Digest) -> ReputationFuture<'a>;
+}
+
+/// A [`ReputationProvider`] which knows nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoReputation;
+
+impl ReputationProvider for NoReputation {
+    fn reputation<'a>(&'a self, _digest: &'a Sha256Digest) -> ReputationFuture<'a> {
+        Box::pin(async { None })
+    }
+}
+
+/// When the [`ReputationProvider`] is asked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReputationPolicy {
+    /// Ask the provider after the clamav, only to enrich the report.
+    #[default]
+    AfterScan,
+
+    /// Ask the provider before the clamav. A [`ReputationVerdict::Malicious`] content is not
+    /// sent to the clamav and is reported as [`ScanOutcome::Infected`](crate::ScanOutcome::Infected)
+    /// with the `Reputation.Malicious` signature.
+    BeforeScan,
+}
+
+/// The answer of a [`ReputationProvider`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reputation {
+    /// The name of the service, e.g. `VirusTotal`.
+    pub provider: String,
+
+    /// What the service thinks of the content.
+    pub verdict: ReputationVerdict,
+
+    /// A free-form detail from the service, e.g. the number of engines flagging the content.
+    pub detail: Option<String>,
+}
+
+/// What a [`ReputationProvider`] thinks of a content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReputationVerdict {
+    /// Known to be harmless.
+    Harmless,
+
+    /// Flagged by a few sources.
+    Suspicious,
+
+    /// Known to be malicious.
+    Malicious,
+}
@@ -0,0 +1,194 @@
+use crate::{
+    drive::drive,
+    task::{self, RESCAN_TASK},
+    Error, Priority, ScanOutcome, Scanner,
+};
+
+use bytes::Bytes;
+use std::{
+    error::Error as StdError,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
+use tokio_stream::Stream;
+
+/// The outcome of the scan of an entry of a [`RescanQueue`].
+#[derive(Debug)]
+pub struct RescanReport<Id> {
+    /// The id the entry was pushed with.
+    pub id: Id,
+    /// The verdict of the clamav, or the error which prevented the scan.
+    pub outcome: Result<ScanOutcome, Error>,
+}
+
+/// Scans stored contents in the background with a bounded number of concurrent scans, e.g. to
+/// re-scan an object store after a signature update.
+///
+/// Every entry pushed is scanned on its own connection opened by the [`Scanner`], and its
+/// [`RescanReport`] is yielded by the [`RescanReports`] returned along with the queue. The
+/// reports end once the queue is dropped and every entry has been scanned.
+///
+/// If the scanner has a [`ScanLimiter`](crate::ScanLimiter), the entries also wait for it with
+/// [`Priority::Low`], so that interactive scans go first.
+#[derive(Debug)]
+pub struct RescanQueue<Id> {
+    scanner: Scanner,
+    permits: Arc<Semaphore>,
+    reports: mpsc::UnboundedSender<RescanReport<Id>>,
+}
+
+impl<Id: Send + 'static> RescanQueue<Id> {
+    /// Create a queue running at most `concurrency` scans at a time.
+    pub fn new(scanner: Scanner, concurrency: usize) -> (Self, RescanReports<Id>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let queue = Self {
+            scanner,
+            permits: Arc::new(Semaphore::new(concurrency)),
+            reports: tx,
+        };
+        (queue, RescanReports { inner: rx })
+    }
+
+    /// Queue an entry to be scanned. The stream is created by the factory only once a scan is
+    /// available for it, so that no more than `concurrency` stored objects are open at a time.
+    ///
+    /// This must be called within a tokio runtime, on which the scan is spawned in a task named
+    /// [`RESCAN_TASK`]. The returned handle completes once the report has been sent, and can
+    /// abort the scan.
+    pub fn push<F, St, B, E>(&self, id: Id, factory: F) -> JoinHandle<()>
+    where
+        F: FnOnce() -> St + Send + 'static,
+        St: Stream<Item = Result<B, E>> + Send + 'static,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.spawn(None, id, async move { factory() })
+    }
+
+    /// Wait until a scan is available, then queue the entry on it like [`RescanQueue::push`].
+    /// No task is spawned before then, so that a producer awaiting each entry is held back to
+    /// the pace of the scans. The stream is opened by the future once the scan is available.
+    pub(crate) async fn push_ready<Fut, St, B, E>(&self, id: Id, open: Fut) -> JoinHandle<()>
+    where
+        Fut: Future<Output = St> + Send + 'static,
+        St: Stream<Item = Result<B, E>> + Send + 'static,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let permit = Arc::clone(&self.permits).acquire_owned().await.ok();
+        self.spawn(permit, id, open)
+    }
+
+    fn spawn<Fut, St, B, E>(
+        &self,
+        permit: Option<OwnedSemaphorePermit>,
+        id: Id,
+        open: Fut,
+    ) -> JoinHandle<()>
+    where
+        Fut: Future<Output = St> + Send + 'static,
+        St: Stream<Item = Result<B, E>> + Send + 'static,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let scanner = self.scanner.clone();
+        let permits = Arc::clone(&self.permits);
+        let reports = self.reports.clone();
+
+        task::spawn(RESCAN_TASK, async move {
+            let _permit = match permit {
+                Some(permit) => permit,
+                None => match permits.acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => return,
+                },
+            };
+            let admitted = scanner
+                .wrap_async_with_priority(open.await, Priority::Low)
+                .await;
+            let outcome = match admitted {
+                Ok(stream) => drive(stream).await,
+                Err(err) => Err(err),
+            };
+            let _ = reports.send(RescanReport { id, outcome });
+        })
+    }
+}
+
+/// The stream of the [`RescanReport`]s of a [`RescanQueue`], in the order the scans complete.
+#[derive(Debug)]
+pub struct RescanReports<Id> {
+    inner: mpsc::UnboundedReceiver<RescanReport<Id>>,
+}
+
+impl<Id> Stream for RescanReports<Id> {
+    type Item = RescanReport<Id>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_clamd_many;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_reports_the_outcome_of_every_entry() {
+        let (addr, server) = fake_clamd_many(b"stream: OK\0", 2);
+        let scanner = Scanner::tcp(addr).unwrap();
+
+        let (queue, reports) = RescanQueue::new(scanner, 1);
+        for id in 0..2 {
+            queue.push(id, move || {
+                tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from(format!("object {id}")))])
+            });
+        }
+        drop(queue);
+
+        let mut reports: Vec<_> = reports.collect().await;
+        reports.sort_by_key(|report| report.id);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].id, 0);
+        assert_eq!(reports[1].id, 1);
+        assert!(reports
+            .iter()
+            .all(|report| report.outcome == Ok(ScanOutcome::Clean)));
+
+        let received = server.join().unwrap();
+        assert!(received.iter().all(|r| r.starts_with(b"zINSTREAM\0")));
+    }
+
+    #[tokio::test]
+    async fn it_aborts_an_entry_with_its_handle() {
+        let scanner = Scanner::tcp("127.0.0.1:1").unwrap();
+
+        // No scan is ever available, so the entry waits until it is aborted.
+        let (queue, reports) = RescanQueue::new(scanner, 0);
+        let task = queue.push(0, tokio_stream::empty::<Result<Bytes, Error>>);
+        drop(queue);
+
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(reports.collect::<Vec<_>>().await.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_holds_back_the_producer_until_a_scan_is_available() {
+        let scanner = Scanner::tcp("127.0.0.1:1").unwrap();
+
+        // No scan is ever available, so the entry is never spawned.
+        let (queue, _reports) = RescanQueue::new(scanner, 0);
+        let push = queue.push_ready(0, async { tokio_stream::empty::<Result<Bytes, Error>>() });
+        let waited = tokio::time::timeout(std::time::Duration::from_millis(20), push).await;
+        assert!(waited.is_err());
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{Error, Progress, Warning};
+
+use std::sync::Arc;
+
+/// The verdict of the clamav on a scanned content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanOutcome {
+    /// No virus was found.
+    Clean,
+
+    /// A virus was found. Holds the message from the clamav.
+    Infected(String),
+
+    /// The content was passed through without being scanned, because the circuit of the
+    /// [`Scanner`](crate::Scanner) was open with
+    /// [`FailurePolicy::FailOpen`](crate::FailurePolicy::FailOpen).
+    Skipped,
+}
+
+impl ScanOutcome {
+    /// The signatures found, one per `<name> FOUND` line of the reply. An all-match scan may
+    /// report several. Empty unless the content is [`ScanOutcome::Infected`].
+    pub fn detections(&self) -> Vec<Detection> {
+        let Self::Infected(message) = self else {
+            return vec![];
+        };
+
+        message
+            .split(['\0', '\n'])
+            .filter_map(|line| line.strip_suffix(" FOUND"))
+            .map(|found| {
+                let signature = match found.rsplit_once(": ") {
+                    Some((_, signature)) => signature,
+                    None => found,
+                };
+                Detection {
+                    signature: signature.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A signature found by the clamav in a scanned content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Detection {
+    /// The name of the signature, e.g. `Win.Test.EICAR_HDB-1`.
+    pub signature: String,
+}
+
+impl Detection {
+    /// The category of the signature, parsed from its name following the clamav naming
+    /// convention `{platform}.{category}.{name}`.
+    pub fn category(&self) -> DetectionCategory {
+        let signature = self.signature.as_str();
+        let mut parts = signature.split('.');
+
+        if signature.to_ascii_uppercase().contains("EICAR") || parts.clone().nth(1) == Some("Test")
+        {
+            DetectionCategory::Test
+        } else if parts.clone().any(|part| part == "Phishing") {
+            DetectionCategory::Phishing
+        } else if signature.starts_with("PUA.") {
+            DetectionCategory::Pua
+        } else if signature.starts_with("Heuristics.") {
+            DetectionCategory::Heuristics
+        } else if parts.any(|part| part == "Trojan") {
+            DetectionCategory::Trojan
+        } else {
+            DetectionCategory::Other
+        }
+    }
+}
+
+/// The payload of the [`io::Error`](std::io::Error) a reader fails with on an infected
+/// content, e.g. the reader of [`ScannedStream::into_async_read`](crate::ScannedStream::into_async_read),
+/// recovered with [`downcast_ref`](std::error::Error::downcast_ref) on
+/// [`get_ref`](std::io::Error::get_ref).
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct DetectionError {
+    /// The message from the clamav, as in [`Error::Scan`].
+    pub message: String,
+    /// The signatures found, see [`ScanOutcome::detections`].
+    pub detections: Vec<Detection>,
+}
+
+impl DetectionError {
+    #[cfg(feature = "tokio")]
+    pub(crate) fn new(message: String) -> Self {
+        let detections = ScanOutcome::Infected(message.clone()).detections();
+        Self {
+            message,
+            detections,
+        }
+    }
+}
+
+/// The category of a [`Detection`], see [`Detection::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DetectionCategory {
+    /// A potentially unwanted application, e.g. `PUA.Win.Packer.Upx-1`.
+    Pua,
+
+    /// A heuristic detection, e.g. `Heuristics.Encrypted.Zip`.
+    Heuristics,
+
+    /// A phishing content, e.g. `Heuristics.Phishing.Email.SpoofedDomain`.
+    Phishing,
+
+    /// A trojan, e.g. `Win.Trojan.Agent-123`.
+    Trojan,
+
+    /// A test signature, e.g. `Win.Test.EICAR_HDB-1` or `Eicar-Signature`.
+    Test,
+
+    /// Any other signature.
+    Other,
+}
+
+/// Maps the raw reply from the clamav to a [`ScanOutcome`].
+///
+/// Implemented for closures, so a custom parser can be supplied as
+/// `|reply: &[u8]| -> Result<ScanOutcome, Error> { ... }` for clamd-compatible scanners which
+/// format their replies differently.
+pub trait ResponseParser: Send + Sync {
+    /// Parse the whole reply read from the connection.
+    fn parse(&self, reply: &[u8]) -> Result<ScanOutcome, Error>;
+
+    /// The notes some clamd builds append to a clean reply, e.g. heuristics notes, treated as
+    /// set with [`TrailingNotes`]. None by default.
+    fn notes(&self, _reply: &[u8]) -> Vec<String> {
+        vec![]
+    }
+}
+
+impl<F> ResponseParser for F
+where
+    F: Fn(&[u8]) -> Result<ScanOutcome, Error> + Send + Sync,
+{
+    fn parse(&self, reply: &[u8]) -> Result<ScanOutcome, Error> {
+        self(reply)
+    }
+}
+
+/// The standard parser for clamd replies such as `stream: OK` and
+/// `stream: Eicar-Signature FOUND`. Replies such as `INSTREAM size limit exceeded. ERROR` are
+/// returned as [`Error::Clamd`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClamdParser;
+
+impl ResponseParser for ClamdParser {
+    fn parse(&self, reply: &[u8]) -> Result<ScanOutcome, Error> {
+        let res = std::str::from_utf8(reply)?;
+
+        if let Some(message) = res.trim_end_matches(['\0', '\n']).strip_suffix(" ERROR") {
+            return Err(Error::clamd(message));
+        }
+
+        if res.contains("OK") && !res.contains("FOUND") {
+            Ok(ScanOutcome::Clean)
+        } else {
+            Ok(ScanOutcome::Infected(res.to_string()))
+        }
+    }
+
+    /// Every line of the reply after the `stream: OK` line.
+    fn notes(&self, reply: &[u8]) -> Vec<String> {
+        let Ok(res) = std::str::from_utf8(reply) else {
+            return vec![];
+        };
+
+        res.split(['\0', '\n'])
+            .map(str::trim)
+            .skip_while(|line| !line.ends_with("OK"))
+            .skip(1)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// A parser accepting only the replies which match the grammar of clamd exactly: a single
+/// `<name>: OK` line, one or more `<name>: <signature> FOUND` lines, or a `<message> ERROR`
+/// line, each ended by `\0` or `\n`. Any other reply, e.g. a clean reply followed by notes, is
+/// returned as [`Error::Protocol`] instead of being guessed at like [`ClamdParser`] does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StrictClamdParser;
+
+impl ResponseParser for StrictClamdParser {
+    fn parse(&self, reply: &[u8]) -> Result<ScanOutcome, Error> {
+        let res = std::str::from_utf8(reply)?;
+        let protocol = || Error::Protocol {
+            reply: res.to_string(),
+        };
+
+        let lines = res
+            .strip_suffix(['\0', '\n'])
+            .ok_or_else(protocol)?
+            .split(['\0', '\n'])
+            .collect::<Vec<_>>();
+
+        match lines.as_slice() {
+            [line] if line.ends_with(" ERROR") => {
+                Err(Error::clamd(line.trim_end_matches(" ERROR")))
+            }
+            [line] if matches!(line.rsplit_once(": "), Some((name, "OK")) if !name.is_empty()) => {
+                Ok(ScanOutcome::Clean)
+            }
+            lines if lines.iter().all(|line| is_detection(line)) => {
+                Ok(ScanOutcome::Infected(res.to_string()))
+            }
+            _ => Err(protocol()),
+        }
+    }
+}
+
+/// Whether the line is a `<name>: <signature> FOUND` line.
+fn is_detection(line: &str) -> bool {
+    let Some((name, found)) = line.rsplit_once(": ") else {
+        return false;
+    };
+    let Some(signature) = found.strip_suffix(" FOUND") else {
+        return false;
+    };
+    !name.is_empty() && !signature.is_empty() && !signature.contains(char::is_whitespace)
+}
+
+/// Which replies of the clamav a [`Scanner`](crate::Scanner) accepts, see
+/// [`ScannerBuilder::reply_grammar`](crate::ScannerBuilder::reply_grammar).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplyGrammar {
+    /// Accept only the replies of the clamd grammar, with [`StrictClamdParser`], e.g. for a
+    /// deployment which must notice a clamd-compatible scanner replying differently.
+    Strict,
+    /// Guess the verdict from the words of the reply, with [`ClamdParser`], e.g. for
+    /// clamd-compatible scanners whose replies vary.
+    #[default]
+    Lenient,
+}
+
+impl ReplyGrammar {
+    pub(crate) fn parser(self) -> Arc<dyn ResponseParser> {
+        match self {
+            Self::Strict => Arc::new(StrictClamdParser),
+            Self::Lenient => Arc::new(ClamdParser),
+        }
+    }
+}
+
+/// What a scan does with the notes the clamav appended to a clean reply, see
+/// [`ResponseParser::notes`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrailingNotes {
+    /// Keep the clean verdict and report each note as a [`Warning::ClamdNote`].
+    #[default]
+    Report,
+    /// Keep the clean verdict and discard the notes.
+    Ignore,
+    /// Fail the scan with [`Error::Clamd`] holding the notes, for callers which only trust a
+    /// bare `OK`.
+    Reject,
+}
+
+impl TrailingNotes {
+    /// Treat the notes of a clean reply.
+    pub(crate) fn apply(
+        self,
+        parser: &dyn ResponseParser,
+        reply: &[u8],
+        progress: &Progress,
+    ) -> Result<(), Error> {
+        if self == Self::Ignore {
+            return Ok(());
+        }
+        let notes = parser.notes(reply);
+        match self {
+            Self::Reject if !notes.is_empty() => Err(Error::clamd(notes.join("\n"))),
+            Self::Reject | Self::Ignore => Ok(()),
+            Self::Report => {
+                for note in notes {
+                    progress.warn(Warning::ClamdNote { note });
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_clean_replies() {
+        let outcome = ClamdParser.parse(b"stream: OK\0").unwrap();
+        assert_eq!(outcome, ScanOutcome::Clean);
+    }
+
+    #[test]
+    fn it_parses_infected_replies() {
+        let outcome = ClamdParser
+            .parse(b"stream: Eicar-Signature FOUND\0")
+            .unwrap();
+        assert_eq!(
+            outcome,
+            ScanOutcome::Infected("stream: Eicar-Signature FOUND\0".into())
+        );
+    }
+
+    #[test]
+    fn it_returns_an_error_for_error_replies() {
+        let result = ClamdParser.parse(b"INSTREAM size limit exceeded. ERROR\0");
+        assert!(matches!(
+            result,
+            Err(Error::Clamd { message, .. }) if message == "INSTREAM size limit exceeded."
+        ));
+    }
+
+    #[test]
+    fn it_lists_every_detection_of_an_all_match_reply() {
+        let outcome = ClamdParser
+            .parse(b"/tmp/file: Eicar-Signature FOUND\0/tmp/file: Other.Sig FOUND\0")
+            .unwrap();
+        assert_eq!(
+            outcome.detections(),
+            vec![
+                Detection {
+                    signature: "Eicar-Signature".into()
+                },
+                Detection {
+                    signature: "Other.Sig".into()
+                },
+            ]
+        );
+        assert!(ScanOutcome::Clean.detections().is_empty());
+    }
+
+    #[test]
+    fn it_categorizes_detections_by_signature_name() {
+        let category = |signature: &str| {
+            Detection {
+                signature: signature.into(),
+            }
+            .category()
+        };
+        assert_eq!(category("Win.Test.EICAR_HDB-1"), DetectionCategory::Test);
+        assert_eq!(category("Eicar-Signature"), DetectionCategory::Test);
+        assert_eq!(category("PUA.Win.Packer.Upx-1"), DetectionCategory::Pua);
+        assert_eq!(
+            category("Heuristics.Phishing.Email.SpoofedDomain"),
+            DetectionCategory::Phishing
+        );
+        assert_eq!(
+            category("Html.Phishing.Bank-123"),
+            DetectionCategory::Phishing
+        );
+        assert_eq!(
+            category("Heuristics.Encrypted.Zip"),
+            DetectionCategory::Heuristics
+        );
+        assert_eq!(category("Win.Trojan.Agent-123"), DetectionCategory::Trojan);
+        assert_eq!(category("Unix.Malware.Agent-1"), DetectionCategory::Other);
+    }
+
+    #[test]
+    fn it_treats_the_notes_after_a_clean_reply() {
+        let reply = b"stream: OK\nLibClamAV Warning: heuristics note\n";
+        assert_eq!(ClamdParser.parse(reply).unwrap(), ScanOutcome::Clean);
+        assert_eq!(
+            ClamdParser.notes(reply),
+            vec!["LibClamAV Warning: heuristics note".to_string()]
+        );
+        assert!(ClamdParser.notes(b"stream: OK\0").is_empty());
+
+        let progress = Progress::default();
+        TrailingNotes::Report
+            .apply(&ClamdParser, reply, &progress)
+            .unwrap();
+        assert_eq!(
+            progress.warnings(),
+            vec![Warning::ClamdNote {
+                note: "LibClamAV Warning: heuristics note".into()
+            }]
+        );
+        assert!(TrailingNotes::Ignore
+            .apply(&ClamdParser, reply, &progress)
+            .is_ok());
+        assert!(matches!(
+            TrailingNotes::Reject.apply(&ClamdParser, reply, &progress),
+            Err(Error::Clamd { message, .. }) if message == "LibClamAV Warning: heuristics note"
+        ));
+    }
+
+    #[test]
+    fn it_accepts_only_the_clamd_grammar_when_strict() {
+        let parse = |reply: &[u8]| StrictClamdParser.parse(reply);
+        assert_eq!(parse(b"stream: OK\0").unwrap(), ScanOutcome::Clean);
+        assert_eq!(
+            parse(b"stream: Eicar-Signature FOUND\nstream: Other.Sig FOUND\n").unwrap(),
+            ScanOutcome::Infected(
+                "stream: Eicar-Signature FOUND\nstream: Other.Sig FOUND\n".into()
+            )
+        );
+        assert!(matches!(
+            parse(b"INSTREAM size limit exceeded. ERROR\0"),
+            Err(Error::Clamd { message, .. }) if message == "INSTREAM size limit exceeded."
+        ));
+
+        for reply in [
+            &b"stream: OK"[..],
+            b"stream: OK\0heuristics note\0",
+            b"stream: OK, NOT FOUND\0",
+            b"all good\0",
+            b": OK\0",
+        ] {
+            assert!(
+                matches!(parse(reply), Err(Error::Protocol { .. })),
+                "{reply:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_returns_an_error_for_invalid_utf8() {
+        assert!(matches!(
+            ClamdParser.parse(&[0xff, 0xfe]),
+            Err(Error::Utf8(_))
+        ));
+    }
+}
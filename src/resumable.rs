@@ -0,0 +1,225 @@
+use crate::{
+    protocol::ChunkSize,
+    response::{ResponseParser, ScanOutcome},
+    scan::{Scan, ScanPhase},
+    Error, Progress,
+};
+
+use bytes::Bytes;
+use pin_project::pin_project;
+use std::{
+    error::Error as StdError,
+    io::{Read, Write},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio_stream::Stream;
+
+/// A scan of an object uploaded in successive byte ranges, e.g. a tus upload or a multipart
+/// upload restarted after the connection dropped, which keeps one connection to the clamav
+/// across the ranges so that the verdict covers the whole object.
+///
+/// Each range starts at an offset into the object. A range may overlap the content received
+/// so far, e.g. when the client resends the part of a chunk whose acknowledgement was lost,
+/// and only the bytes after the end of the content received are scanned. A range starting
+/// beyond the end is rejected with [`Error::RangeGap`], leaving the scan as it was.
+///
+/// Store the [`ResumableScan`] with the state of the upload, wrap the input of every request
+/// with [`ResumableScan::resume`], and poll [`ResumableScan::poll_complete`] for the verdict
+/// once the last range has been received.
+pub struct ResumableScan<RW> {
+    scan: Scan<RW>,
+    received: u64,
+}
+
+impl<RW: Read + Write> ResumableScan<RW> {
+    /// Create a new [`ResumableScan`] over a connection to the clamav.
+    pub fn new(inner: RW) -> Self {
+        Self::with_scan(Scan::new(inner))
+    }
+
+    pub(crate) fn with_scan(scan: Scan<RW>) -> Self {
+        Self { scan, received: 0 }
+    }
+
+    /// Split the content into chunks of at most the given size before sending it. Defaults to
+    /// [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE).
+    pub fn with_chunk_size(mut self, chunk_size: ChunkSize) -> Self {
+        self.scan.set_chunk_size(chunk_size);
+        self
+    }
+
+    /// Use the given parser instead of [`ClamdParser`](crate::ClamdParser) to map the reply
+    /// from the clamav to a [`ScanOutcome`].
+    pub fn with_response_parser(mut self, parser: impl ResponseParser + 'static) -> Self {
+        self.scan.set_parser(Arc::new(parser));
+        self
+    }
+
+    /// The number of content bytes received so far, i.e. the offset the next range is
+    /// expected at.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Send the range of the content starting at the offset, skipping the bytes already
+    /// received.
+    pub fn append(&mut self, offset: u64, bytes: &[u8]) -> Result<(), Error> {
+        if offset > self.received {
+            return Err(Error::RangeGap {
+                received: self.received,
+                offset,
+            });
+        }
+        let skip = (self.received - offset).min(bytes.len() as u64) as usize;
+        let rest = &bytes[skip..];
+        if rest.is_empty() {
+            return Ok(());
+        }
+        self.scan.send(rest)?;
+        self.received += rest.len() as u64;
+        Ok(())
+    }
+
+    /// Wrap the input of a re-established connection, starting at the offset into the object,
+    /// to send its chunks while passing them through. The verdict is not read when the input
+    /// ends, so that a later input can resume the content.
+    pub fn resume<St>(&mut self, offset: u64, input: St) -> ResumedInput<'_, St, RW> {
+        ResumedInput {
+            input,
+            scan: self,
+            offset,
+        }
+    }
+
+    /// Write the chunks a non-blocking connection has not accepted yet.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        match self.scan.resume() {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    /// Terminate the content and read the verdict. Returns `None` once the scan has ended,
+    /// i.e. after the verdict or an error has been returned.
+    pub fn poll_complete(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ScanOutcome, Error>>> {
+        if self.scan.is_finished() {
+            return Poll::Ready(None);
+        }
+        if let Err(err) = std::task::ready!(self.poll_flush(cx)) {
+            return Poll::Ready(Some(Err(err)));
+        }
+        let polled = self.scan.poll_conclude();
+        if polled.is_pending() {
+            cx.waker().wake_by_ref();
+        }
+        polled
+    }
+
+    /// Where the scan is in the clamav protocol.
+    pub fn phase(&self) -> ScanPhase {
+        self.scan.phase()
+    }
+
+    /// A clonable handle to follow the scan.
+    pub fn progress(&self) -> Progress {
+        self.scan.progress().clone()
+    }
+}
+
+/// The input of one connection of a [`ResumableScan`], see [`ResumableScan::resume`].
+///
+/// An error of the input is returned as [`Error::Stream`] and ends the input, while the scan
+/// can be resumed from [`ResumableScan::received`] with the next connection.
+#[pin_project]
+pub struct ResumedInput<'a, St, RW> {
+    #[pin]
+    input: St,
+    scan: &'a mut ResumableScan<RW>,
+    offset: u64,
+}
+
+impl<St, RW, B, E> Stream for ResumedInput<'_, St, RW>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    RW: Read + Write,
+    E: StdError + Send + Sync + 'static,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.project();
+        if let Err(err) = std::task::ready!(me.scan.poll_flush(cx)) {
+            return Poll::Ready(Some(Err(err)));
+        }
+
+        match std::task::ready!(me.input.poll_next(cx)) {
+            Some(Ok(bytes)) => {
+                let bytes = bytes.into();
+                me.scan.append(*me.offset, &bytes)?;
+                *me.offset += bytes.len() as u64;
+                Poll::Ready(Some(Ok(bytes)))
+            }
+            Some(Err(err)) => Poll::Ready(Some(Err(Error::Stream(Box::new(err))))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTransport;
+    use std::{future::poll_fn, io};
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_scans_the_ranges_of_a_resumed_upload() {
+        let mut transport = FakeTransport::new("stream: Eicar-Signature FOUND\0");
+        let mut scan = ResumableScan::new(&mut transport);
+
+        // The first connection drops after "Hello ".
+        let first = tokio_stream::iter(vec![
+            Ok(Bytes::from("Hello ")),
+            Err(io::Error::from(io::ErrorKind::ConnectionReset)),
+        ]);
+        let mut input = scan.resume(0, first);
+        assert_eq!(input.next().await, Some(Ok(Bytes::from("Hello "))));
+        assert!(matches!(input.next().await, Some(Err(Error::Stream(_)))));
+        assert_eq!(scan.received(), 6);
+
+        // A range beyond the content received is rejected.
+        assert_eq!(
+            scan.append(8, b"rld"),
+            Err(Error::RangeGap {
+                received: 6,
+                offset: 8,
+            })
+        );
+
+        // The client resends from an earlier offset.
+        let second = tokio_stream::iter(vec![Ok::<_, io::Error>(Bytes::from("o World"))]);
+        let passed: Vec<_> = scan.resume(4, second).collect().await;
+        assert_eq!(passed, vec![Ok(Bytes::from("o World"))]);
+        assert_eq!(scan.received(), 11);
+
+        let outcome = poll_fn(|cx| scan.poll_complete(cx)).await;
+        assert_eq!(
+            outcome.unwrap().unwrap(),
+            ScanOutcome::Infected("stream: Eicar-Signature FOUND\0".into())
+        );
+        drop(scan);
+
+        assert_eq!(transport.chunks(), vec!["Hello ", "World"]);
+        assert!(transport.is_terminated());
+    }
+}
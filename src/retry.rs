@@ -0,0 +1,60 @@
+use std::{thread, time::Duration};
+
+/// The delay after the first retry which made no progress, doubled on every later one.
+const MIN_DELAY: Duration = Duration::from_millis(1);
+const MAX_DELAY: Duration = Duration::from_millis(64);
+
+/// Backs off between the attempts to use a non-blocking connection which is not ready, for
+/// the callers which cannot return [`Poll::Pending`](std::task::Poll::Pending), e.g. a
+/// [`ScannedReader`](crate::ScannedReader) or a scan completed on drop.
+///
+/// The thread yields right away the first time, then sleeps for a delay doubled on every
+/// attempt, instead of spinning while a slow clamav scans. The poll paths never sleep: they
+/// return `Pending` and wake their task once instead.
+#[derive(Debug, Default)]
+pub(crate) struct Retry {
+    attempts: u32,
+}
+
+impl Retry {
+    /// Block the thread for the next delay.
+    pub(crate) fn sleep(&mut self) {
+        match self.next_delay() {
+            Some(delay) => thread::sleep(delay),
+            None => thread::yield_now(),
+        }
+    }
+
+    fn next_delay(&mut self) -> Option<Duration> {
+        let attempts = self.attempts;
+        self.attempts = self.attempts.saturating_add(1);
+        let exp = attempts.checked_sub(1)?.min(31);
+        Some(MIN_DELAY.saturating_mul(1 << exp).min(MAX_DELAY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_doubles_the_delay_after_the_first_retry() {
+        let mut retry = Retry::default();
+        let delays: Vec<_> = (0..9).map(|_| retry.next_delay()).collect();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(
+            delays,
+            vec![
+                None,
+                ms(1),
+                ms(2),
+                ms(4),
+                ms(8),
+                ms(16),
+                ms(32),
+                ms(64),
+                ms(64)
+            ]
+        );
+    }
+}
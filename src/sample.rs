@@ -0,0 +1,119 @@
+use crate::backoff::random;
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Which streams a [`Scanner`](crate::Scanner) scans, for high-volume pipelines of low-risk
+/// contents where scanning everything costs too much, see
+/// [`ScannerBuilder::sample_rate`](crate::ScannerBuilder::sample_rate).
+///
+/// A stream which is not sampled is passed through without being scanned, its verdict being
+/// [`ScanOutcome::Skipped`](crate::ScanOutcome::Skipped) with a
+/// [`Warning::SampledOut`](crate::Warning::SampledOut).
+#[derive(Clone, Default)]
+pub enum SampleRate {
+    /// Scan every stream.
+    #[default]
+    All,
+
+    /// Scan one in every `n` streams of the scanner and its clones, starting with the first.
+    OneIn(u64),
+
+    /// Scan each stream with the probability, between `0.0` and `1.0`.
+    Probability(f64),
+
+    /// Scan each stream with the probability returned for the [`SampleHint`] of the handle it
+    /// is scanned with, see [`Scanner::with_sample_hint`](crate::Scanner::with_sample_hint),
+    /// e.g. to always scan executables but only some of the large media files.
+    ByHint(Arc<dyn Fn(&SampleHint) -> f64 + Send + Sync>),
+}
+
+impl SampleRate {
+    /// A [`SampleRate::ByHint`] with the given probability function.
+    pub fn by_hint(probability: impl Fn(&SampleHint) -> f64 + Send + Sync + 'static) -> Self {
+        Self::ByHint(Arc::new(probability))
+    }
+}
+
+impl fmt::Debug for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => f.write_str("All"),
+            Self::OneIn(n) => f.debug_tuple("OneIn").field(n).finish(),
+            Self::Probability(p) => f.debug_tuple("Probability").field(p).finish(),
+            Self::ByHint(_) => f.write_str("ByHint(..)"),
+        }
+    }
+}
+
+/// What is known about a stream before it is scanned, for a [`SampleRate::ByHint`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleHint {
+    /// The declared length of the content, e.g. from the `content-length` header.
+    pub len: Option<u64>,
+    /// The media type of the content, e.g. from the `content-type` header.
+    pub content_type: Option<String>,
+}
+
+/// Decides whether each stream of a scanner is scanned according to its [`SampleRate`].
+#[derive(Debug, Default)]
+pub(crate) struct Sampler {
+    rate: SampleRate,
+    streams: AtomicU64,
+}
+
+impl Sampler {
+    pub(crate) fn new(rate: SampleRate) -> Self {
+        Self {
+            rate,
+            streams: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `true` if the stream with the hint is to be scanned.
+    pub(crate) fn is_sampled(&self, hint: &SampleHint) -> bool {
+        match &self.rate {
+            SampleRate::All => true,
+            SampleRate::OneIn(n) => self
+                .streams
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of((*n).max(1)),
+            SampleRate::Probability(p) => random() < *p,
+            SampleRate::ByHint(probability) => random() < probability(hint),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_samples_the_streams_at_the_rate() {
+        let hint = SampleHint::default();
+
+        let one_in_three = Sampler::new(SampleRate::OneIn(3));
+        let sampled: Vec<_> = (0..6).map(|_| one_in_three.is_sampled(&hint)).collect();
+        assert_eq!(sampled, vec![true, false, false, true, false, false]);
+
+        assert!(Sampler::new(SampleRate::Probability(1.0)).is_sampled(&hint));
+        assert!(!Sampler::new(SampleRate::Probability(0.0)).is_sampled(&hint));
+
+        let executables = Sampler::new(SampleRate::by_hint(|hint| {
+            match hint.content_type.as_deref() {
+                Some("application/x-msdownload") => 1.0,
+                _ => 0.0,
+            }
+        }));
+        assert!(!executables.is_sampled(&hint));
+        assert!(executables.is_sampled(&SampleHint {
+            len: Some(1024),
+            content_type: Some("application/x-msdownload".into()),
+        }));
+    }
+}
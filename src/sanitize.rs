@@ -0,0 +1,152 @@
+use crate::{protocol::CHUNK_SIZE, spool::Spool, Error, ScanOutcome, ScanReport};
+
+use bytes::Bytes;
+use std::{
+    fmt,
+    io::Read,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio_stream::Stream;
+
+/// What [`Scanner::scan_first`](crate::Scanner::scan_first) does with an infected content.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OnDetection {
+    /// Return [`Error::Scan`] instead of the content.
+    #[default]
+    Reject,
+
+    /// Yield the given payload instead of the content, e.g. a "removed by the antivirus"
+    /// notice, for mail or file proxies which must deliver something.
+    Replace(Bytes),
+}
+
+/// The content released by [`Scanner::scan_first`](crate::Scanner::scan_first) after it has
+/// been scanned: the content itself if it is clean, or the replacement payload of
+/// [`OnDetection::Replace`] if it is infected.
+pub struct ReleasedStream {
+    outcome: ScanOutcome,
+    report: ScanReport,
+    body: Body,
+}
+
+enum Body {
+    Content(Box<dyn Read + Send>),
+    Replacement(Option<Bytes>),
+}
+
+impl ReleasedStream {
+    pub(crate) fn new(
+        outcome: ScanOutcome,
+        report: ScanReport,
+        spool: Spool,
+        on_detection: OnDetection,
+    ) -> Result<Self, Error> {
+        let body = match (&outcome, on_detection) {
+            (ScanOutcome::Infected(message), OnDetection::Reject) => {
+                return Err(Error::Scan(message.clone()));
+            }
+            (ScanOutcome::Infected(_), OnDetection::Replace(payload)) => {
+                Body::Replacement(Some(payload))
+            }
+            _ => Body::Content(spool.into_reader()?),
+        };
+
+        Ok(Self {
+            outcome,
+            report,
+            body,
+        })
+    }
+
+    /// The verdict of the clamav on the original content.
+    pub fn outcome(&self) -> &ScanOutcome {
+        &self.outcome
+    }
+
+    /// The summary of the scan of the original content.
+    pub fn report(&self) -> &ScanReport {
+        &self.report
+    }
+
+    /// Returns `true` if the content was replaced with the payload of [`OnDetection::Replace`].
+    pub fn is_replaced(&self) -> bool {
+        matches!(self.body, Body::Replacement(_))
+    }
+}
+
+impl fmt::Debug for ReleasedStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReleasedStream")
+            .field("outcome", &self.outcome)
+            .field("report", &self.report)
+            .field("replaced", &self.is_replaced())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stream for ReleasedStream {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut self.get_mut().body {
+            Body::Content(reader) => {
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                match reader.read(&mut buf) {
+                    Ok(0) => Poll::Ready(None),
+                    Ok(n) => {
+                        buf.truncate(n);
+                        Poll::Ready(Some(Ok(Bytes::from(buf))))
+                    }
+                    Err(err) => Poll::Ready(Some(Err(Error::Io(err)))),
+                }
+            }
+            Body::Replacement(payload) => Poll::Ready(payload.take().map(Ok)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd, Address, Scanner};
+    use tokio_stream::StreamExt;
+
+    async fn released(reply: &'static [u8], on_detection: OnDetection) -> Result<Vec<u8>, Error> {
+        let (addr, server) = fake_clamd(reply);
+        let scanner = Scanner::new(Address::tcp(addr).unwrap());
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+
+        let result = scanner.scan_first(input, on_detection).await;
+        server.join().unwrap();
+
+        let mut stream = result?;
+        let mut content = vec![];
+        while let Some(chunk) = stream.next().await {
+            content.extend_from_slice(&chunk?);
+        }
+        Ok(content)
+    }
+
+    #[tokio::test]
+    async fn it_releases_clean_contents() {
+        let content = released(b"stream: OK\0", OnDetection::Reject).await;
+        assert_eq!(content.unwrap(), b"Hello World");
+    }
+
+    #[tokio::test]
+    async fn it_replaces_infected_contents() {
+        let on_detection = OnDetection::Replace(Bytes::from("removed by the antivirus"));
+        let content = released(b"stream: Eicar-Signature FOUND\0", on_detection).await;
+        assert_eq!(content.unwrap(), b"removed by the antivirus");
+
+        let result = released(b"stream: Eicar-Signature FOUND\0", OnDetection::Reject).await;
+        assert_eq!(
+            result.unwrap_err(),
+            Error::Scan("stream: Eicar-Signature FOUND\0".into())
+        );
+    }
+}
@@ -0,0 +1,1304 @@
+#[cfg(feature = "checksum")]
+use crate::checksum::Checksum;
+#[cfg(feature = "checksum")]
+use crate::checksum::Hasher;
+#[cfg(feature = "dedup")]
+use crate::dedup::BlockDedup;
+#[cfg(feature = "dedup")]
+use crate::dedup::Deduper;
+#[cfg(feature = "passthrough-check")]
+use crate::integrity::Crc32;
+#[cfg(feature = "journal")]
+use crate::journal::Recording;
+#[cfg(feature = "protocol-debug")]
+use crate::trace::Frame;
+use crate::{
+    adaptive::{AdaptiveChunkSize, ChunkSizer},
+    buffer::BufferPool,
+    circuit::Circuit,
+    decode::{Decoder, Decoding},
+    drop_behavior::{DropBehavior, DropResult},
+    latency::Timing,
+    memory::{MemoryBudget, MemoryCharge},
+    pool::Pool,
+    progress::Progress,
+    protocol::{
+        chunk_header, split_request_id, ChunkSize, Command, CommandFormat, CHUNK_SIZE,
+        END_OF_STREAM,
+    },
+    quota::QuotaCharge,
+    report::{LengthPolicy, Warning},
+    response::{ClamdParser, ResponseParser, ScanOutcome, TrailingNotes},
+    retry::Retry,
+    shutdown::InFlight,
+    Error, Phase,
+};
+#[cfg(feature = "tokio")]
+use crate::{
+    limiter::ScanPermit,
+    scope::{MemberVerdict, ScopeMember},
+};
+#[cfg(feature = "spool")]
+use crate::{
+    mode::{LocalFile, ScanMode},
+    protocol::{all_match_scan_command, scan_command},
+    spool::{Spool, SpoolConfig},
+};
+
+use std::{
+    io::{self, IoSlice, Read, Write},
+    mem,
+    sync::Arc,
+    task::{ready, Poll},
+    time::{Duration, Instant},
+};
+
+/// The state of a scan over a connection to the clamav, shared by the wrappers which feed it
+/// with the content they pass through.
+pub(crate) struct Scan<RW> {
+    inner: Option<RW>,
+    start: Option<CommandFormat>,
+    stage: Stage,
+    bytes_sent: u64,
+    progress: Progress,
+    parser: Arc<dyn ResponseParser>,
+    #[cfg(feature = "spool")]
+    spool: Option<Spool>,
+    #[cfg(feature = "spool")]
+    local_file: Option<LocalFile>,
+    #[cfg(feature = "spool")]
+    all_match: bool,
+    decoder: Option<Decoder>,
+    #[cfg(feature = "dedup")]
+    dedup: Option<Deduper>,
+    buffers: BufferPool,
+    #[cfg(feature = "checksum")]
+    hasher: Option<Hasher>,
+    outcome: Option<ScanOutcome>,
+    circuit: Option<Arc<Circuit>>,
+    #[cfg(feature = "tokio")]
+    permit: Option<ScanPermit>,
+    quota: Option<QuotaCharge>,
+    session: bool,
+    pool: Option<(Arc<Pool>, PutBack<RW>)>,
+    guard: Option<InFlight>,
+    #[cfg(feature = "tokio")]
+    member: Option<ScopeMember>,
+    #[cfg(feature = "journal")]
+    recording: Option<Recording>,
+    timing: Option<Timing>,
+    outbox: Vec<u8>,
+    outbox_phase: Phase,
+    outbox_charge: MemoryCharge,
+    /// Whether the caller resumes the outbox before sending more, see [`Scan::set_resumable`].
+    resumable: bool,
+    /// The reply read so far.
+    reply: Vec<u8>,
+    /// When the reply started to be read, and the deadline of the read.
+    reading_since: Option<(Instant, Option<Instant>)>,
+    /// Whether the clamav replied before the end of the content.
+    replied_early: bool,
+    budget: Option<MemoryBudget>,
+    strict: bool,
+    chunk_size: ChunkSize,
+    sizer: Option<ChunkSizer>,
+    cut_short: bool,
+    early_verdict: bool,
+    write_timeout: Option<Duration>,
+    verdict_timeout: Option<Duration>,
+    stalled_since: Option<Instant>,
+    deadline: Option<Instant>,
+    drop_behavior: DropBehavior,
+    length_policy: LengthPolicy,
+    trailing_notes: TrailingNotes,
+    dropper: Option<Abandon<RW>>,
+    completer: Option<Complete<RW>>,
+    /// The checksum of the content written to the clamav, or `None` if the content written
+    /// differs from the content by design.
+    #[cfg(feature = "passthrough-check")]
+    scanned: Option<Crc32>,
+}
+
+/// Where a scan is in the clamav protocol, see
+/// [`ScannedStream::phase`](crate::ScannedStream::phase).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanPhase {
+    /// Nothing has been sent to the clamav yet.
+    Idle,
+
+    /// The content is being sent.
+    Streaming {
+        /// The number of content bytes sent so far.
+        bytes_sent: u64,
+    },
+
+    /// The whole content has been sent, and the verdict is being read.
+    AwaitingVerdict,
+
+    /// The scan is over. Holds the verdict, or `None` if the scan failed.
+    Done(Option<ScanOutcome>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Streaming,
+    /// The end of the request is queued, but not written yet.
+    Finishing,
+    AwaitingVerdict,
+    Done,
+}
+
+/// Puts a connection whose verdict has been read back to the pool.
+type PutBack<RW> = fn(&Pool, RW);
+
+/// Applies the [`DropBehavior`] to a dropped scan. A function pointer, since the [`Drop`] of
+/// the scan cannot require the connection to be [`Read`] + [`Write`].
+type Abandon<RW> = fn(&mut Scan<RW>);
+
+/// Reads the verdict of a scan dropped with [`DropBehavior::Complete`] elsewhere.
+pub(crate) type Complete<RW> = fn(Scan<RW>);
+
+impl<RW: Read + Write> Scan<RW> {
+    pub(crate) fn new(inner: RW) -> Self {
+        Self::with_inner(Some(inner))
+    }
+
+    /// A scan which passes the content through without sending it to the clamav.
+    pub(crate) fn bypass() -> Self {
+        Self::with_inner(None)
+    }
+
+    fn with_inner(inner: Option<RW>) -> Self {
+        #[cfg(feature = "passthrough-check")]
+        let scanned = inner.is_some().then(Crc32::default);
+        Self {
+            inner,
+            start: Some(CommandFormat::Null),
+            stage: Stage::Idle,
+            bytes_sent: 0,
+            progress: Progress::default(),
+            parser: Arc::new(ClamdParser),
+            #[cfg(feature = "spool")]
+            spool: None,
+            #[cfg(feature = "spool")]
+            local_file: None,
+            #[cfg(feature = "spool")]
+            all_match: false,
+            decoder: None,
+            #[cfg(feature = "dedup")]
+            dedup: None,
+            buffers: BufferPool::unpooled(),
+            #[cfg(feature = "checksum")]
+            hasher: None,
+            outcome: None,
+            circuit: None,
+            #[cfg(feature = "tokio")]
+            permit: None,
+            quota: None,
+            session: false,
+            pool: None,
+            guard: None,
+            #[cfg(feature = "tokio")]
+            member: None,
+            #[cfg(feature = "journal")]
+            recording: None,
+            timing: None,
+            outbox: vec![],
+            outbox_phase: Phase::Start,
+            outbox_charge: MemoryCharge::default(),
+            resumable: false,
+            reply: vec![],
+            reading_since: None,
+            replied_early: false,
+            budget: None,
+            strict: false,
+            chunk_size: ChunkSize::default(),
+            sizer: None,
+            cut_short: false,
+            early_verdict: false,
+            write_timeout: None,
+            verdict_timeout: None,
+            stalled_since: None,
+            deadline: None,
+            drop_behavior: DropBehavior::default(),
+            length_policy: LengthPolicy::default(),
+            trailing_notes: TrailingNotes::default(),
+            dropper: Some(Self::abandon),
+            completer: None,
+            #[cfg(feature = "passthrough-check")]
+            scanned,
+        }
+    }
+
+    /// Apply the [`DropBehavior`] to a scan dropped in the middle of the content.
+    fn abandon(&mut self) {
+        if !matches!(self.stage, Stage::Streaming | Stage::Finishing) || self.inner.is_none() {
+            return;
+        }
+
+        let behavior = self.drop_behavior;
+        let result = match behavior {
+            DropBehavior::Abort => DropResult::Aborted,
+            DropBehavior::Finish => {
+                let terminated = match self.stage {
+                    Stage::Finishing => Ok(()),
+                    _ => self.terminate(),
+                };
+                match terminated.and_then(|_| self.flush_blocking()) {
+                    Ok(()) => DropResult::Finished,
+                    Err(err) => DropResult::Failed(err.to_string()),
+                }
+            }
+            DropBehavior::Complete => {
+                match self.completer {
+                    Some(complete) => complete(mem::replace(self, Self::bypass())),
+                    None => self.complete_dropped(),
+                }
+                return;
+            }
+        };
+        self.stage = Stage::Done;
+        self.progress.warn(Warning::Dropped { behavior, result });
+    }
+
+    /// Terminate the content of a scan dropped with [`DropBehavior::Complete`] and read the
+    /// verdict.
+    pub(crate) fn complete_dropped(&mut self) {
+        let Some(result) = self.conclude() else {
+            return;
+        };
+        let result = match result {
+            Ok(outcome) => DropResult::Completed(outcome),
+            Err(err) => DropResult::Failed(err.to_string()),
+        };
+        self.progress.warn(Warning::Dropped {
+            behavior: DropBehavior::Complete,
+            result,
+        });
+    }
+
+    /// Send a chunk of the content to the clamav. An empty chunk sends nothing, since the
+    /// clamav takes a zero-length chunk as the end of the content.
+    pub(crate) fn send(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if self.stage == Stage::Done {
+            // The terminating chunk has been sent, so the clamav would never see the content.
+            return match self.strict && !bytes.is_empty() {
+                true => Err(Error::TrailingData { len: bytes.len() }),
+                false => Ok(()),
+            };
+        }
+
+        let checked = self
+            .check_deadline()
+            .and_then(|_| self.check_overrun(bytes.len()));
+        if let Err(err) = checked {
+            return Err(self.cut_short(Err(err)));
+        }
+
+        let result = self.send_content(bytes);
+        self.update_buffered();
+        result.map_err(|err| self.end_early(err))
+    }
+
+    /// Fail with [`Error::DeadlineExceeded`] once the deadline of the enclosing request has
+    /// passed.
+    fn check_deadline(&self) -> Result<(), Error> {
+        match self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            true => Err(Error::deadline_exceeded(self.bytes_sent)),
+            false => Ok(()),
+        }
+    }
+
+    /// Fail before a chunk which would overrun the declared length is sent, with
+    /// [`LengthPolicy::Fail`].
+    fn check_overrun(&self, len: usize) -> Result<(), Error> {
+        let Some(expected_len) = self.progress.expected_len() else {
+            return Ok(());
+        };
+        let bytes_scanned = self.progress.bytes_scanned() + len as u64;
+        match self.length_policy == LengthPolicy::Fail && bytes_scanned > expected_len {
+            true => Err(Error::LengthMismatch {
+                expected_len,
+                bytes_scanned,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Fail instead of a clean verdict on a content shorter than the declared length, with
+    /// [`LengthPolicy::Fail`].
+    fn check_truncated(&self, outcome: ScanOutcome) -> Result<ScanOutcome, Error> {
+        let (Some(expected_len), LengthPolicy::Fail, ScanOutcome::Clean | ScanOutcome::Skipped) =
+            (self.progress.expected_len(), self.length_policy, &outcome)
+        else {
+            return Ok(outcome);
+        };
+        let bytes_scanned = self.progress.bytes_scanned();
+        match bytes_scanned < expected_len {
+            true => Err(Error::LengthMismatch {
+                expected_len,
+                bytes_scanned,
+            }),
+            false => Ok(outcome),
+        }
+    }
+
+    /// Write the chunks a non-blocking connection did not accept before. Returns `true` once
+    /// none are left.
+    pub(crate) fn resume(&mut self) -> Result<bool, Error> {
+        let result = self.flush_outbox();
+        self.update_buffered();
+        result.map_err(|err| self.end_early(err))
+    }
+
+    fn update_buffered(&self) {
+        #[allow(unused_mut)]
+        let mut buffered = self.outbox.len();
+        #[cfg(feature = "spool")]
+        {
+            buffered += self.spool.as_ref().map_or(0, Spool::memory_len);
+        }
+        #[cfg(feature = "dedup")]
+        {
+            buffered += self.dedup.as_ref().map_or(0, Deduper::buffered);
+        }
+        self.progress.set_buffered(buffered);
+    }
+
+    fn send_content(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        match &mut self.decoder {
+            Some(decoder) => {
+                let mut decoded = self.buffers.take(bytes.len());
+                decoder.decode(bytes, &mut decoded)?;
+                self.forward(&decoded)?;
+                self.buffers.give(decoded);
+            }
+            None => self.forward(bytes)?,
+        }
+        self.progress.add(bytes.len() as u64);
+
+        #[cfg(feature = "checksum")]
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(bytes);
+        }
+        #[cfg(feature = "journal")]
+        if let Some(recording) = &mut self.recording {
+            recording.update(bytes);
+        }
+
+        #[cfg(feature = "spool")]
+        if let Some(spool) = &mut self.spool {
+            spool.write(bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the whole content read from the reader, e.g. a [`Spool`](crate::Spool).
+    pub(crate) fn send_reader(&mut self, mut reader: impl Read) -> Result<(), Error> {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            match reader.read(&mut buf)? {
+                0 => return Ok(()),
+                n => self.send(&buf[..n])?,
+            }
+        }
+    }
+
+    fn forward(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if self.inner.is_none() {
+            return Ok(());
+        }
+
+        #[cfg(feature = "dedup")]
+        if let Some(dedup) = &mut self.dedup {
+            let (blocks, skipped) = dedup.push(bytes, &self.buffers);
+            self.progress.add_deduplicated(skipped);
+            for block in blocks {
+                self.transmit(&block)?;
+                self.buffers.give(block);
+            }
+            return Ok(());
+        }
+        self.transmit(bytes)
+    }
+
+    /// Send the rest of the content a [`BlockDedup`](crate::BlockDedup) kept until the end of
+    /// its block.
+    #[cfg(feature = "dedup")]
+    fn forward_dedup_rest(&mut self) -> Result<(), Error> {
+        let Some(mut dedup) = self.dedup.take() else {
+            return Ok(());
+        };
+        let (block, skipped) = dedup.finish();
+        self.progress.add_deduplicated(skipped);
+        match block {
+            Some(block) => self.transmit(&block),
+            None => Ok(()),
+        }
+    }
+
+    fn transmit(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        #[cfg(feature = "spool")]
+        if let Some(file) = &mut self.local_file {
+            self.stage = Stage::Streaming;
+            file.write(bytes)?;
+            self.bytes_sent += bytes.len() as u64;
+            #[cfg(feature = "passthrough-check")]
+            if let Some(scanned) = &mut self.scanned {
+                scanned.update(bytes);
+            }
+            return Ok(());
+        }
+
+        self.start()?;
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            // The chunk size fits the u32 length prefix.
+            let (chunk, tail) = rest.split_at(self.frame_len().min(rest.len()));
+            #[cfg(feature = "protocol-debug")]
+            self.progress.trace(Frame::Chunk {
+                len: chunk.len() as u32,
+            });
+            let started = Instant::now();
+            let header = chunk_header(chunk.len() as u32);
+            self.write_vectored([&header, chunk], Phase::Chunk)?;
+            self.bytes_sent += chunk.len() as u64;
+            #[cfg(feature = "passthrough-check")]
+            if let Some(scanned) = &mut self.scanned {
+                scanned.update(chunk);
+            }
+            if let Some(sizer) = &mut self.sizer {
+                sizer.record(chunk.len(), started.elapsed(), !self.outbox.is_empty());
+            }
+            rest = tail;
+        }
+
+        Ok(())
+    }
+
+    /// Terminate the content and read the verdict. Returns `None` if the scan has already been
+    /// finished.
+    pub(crate) fn finish(&mut self) -> Option<Result<(), Error>> {
+        self.conclude().map(into_verdict)
+    }
+
+    /// Like [`Scan::finish`], but returns [`Poll::Pending`] instead of waiting for a
+    /// non-blocking connection, see [`Scan::poll_conclude`].
+    pub(crate) fn poll_finish(&mut self) -> Poll<Option<Result<(), Error>>> {
+        self.poll_conclude().map(|result| result.map(into_verdict))
+    }
+
+    /// Terminate the content and return the verdict as a [`ScanOutcome`]. Returns `None` if the
+    /// scan has already been finished.
+    ///
+    /// Waits for a non-blocking connection, for the callers which cannot return
+    /// [`Poll::Pending`], see [`Scan::poll_conclude`].
+    pub(crate) fn conclude(&mut self) -> Option<Result<ScanOutcome, Error>> {
+        let mut retry = Retry::default();
+        loop {
+            match self.poll_conclude() {
+                Poll::Ready(result) => return result,
+                Poll::Pending => retry.sleep(),
+            }
+        }
+    }
+
+    /// Terminate the content and return the verdict as a [`ScanOutcome`], returning
+    /// [`Poll::Pending`] while a non-blocking connection does not accept the end of the request
+    /// or has not replied yet. The caller polls again later, e.g. after waking its task.
+    /// Returns `None` if the scan has already been finished.
+    pub(crate) fn poll_conclude(&mut self) -> Poll<Option<Result<ScanOutcome, Error>>> {
+        if self.replied_early {
+            let Some(err) = ready!(self.poll_early_verdict()) else {
+                return Poll::Ready(None);
+            };
+            return Poll::Ready(Some(match &self.outcome {
+                Some(outcome) => Ok(outcome.clone()),
+                None => Err(err),
+            }));
+        }
+
+        let result = match (self.stage, &self.inner) {
+            (Stage::Done, _) => return Poll::Ready(None),
+            (_, None) => Ok(ScanOutcome::Skipped),
+            (Stage::Idle | Stage::Streaming, Some(_)) => match self.terminate() {
+                Ok(()) => {
+                    self.stage = Stage::Finishing;
+                    return self.poll_conclude();
+                }
+                Err(err) => self.early_verdict(err).map(ScanOutcome::Infected),
+            },
+            // The verdict is read once the whole request has been written.
+            (Stage::Finishing, Some(_)) => match self.flush_outbox() {
+                Ok(false) => return Poll::Pending,
+                Ok(true) => {
+                    self.stage = Stage::AwaitingVerdict;
+                    self.reading_since = Some((Instant::now(), self.verdict_deadline()));
+                    return self.poll_conclude();
+                }
+                Err(err) => self.early_verdict(err).map(ScanOutcome::Infected),
+            },
+            (Stage::AwaitingVerdict, Some(_)) => {
+                let result = ready!(self.poll_verdict());
+                if let (Ok(_), Some((started, _))) = (&result, self.reading_since) {
+                    self.record_time_to_verdict(started.elapsed());
+                }
+                result
+            }
+        };
+        Poll::Ready(Some(self.complete(result)))
+    }
+
+    /// End the scan with the verdict read, or the error it failed with.
+    fn complete(&mut self, result: Result<ScanOutcome, Error>) -> Result<ScanOutcome, Error> {
+        self.stage = Stage::Done;
+        self.guard = None;
+        #[cfg(feature = "tokio")]
+        {
+            self.permit = None;
+        }
+        self.quota = None;
+        self.progress.finish();
+        if let Ok(outcome) = &result {
+            self.outcome = Some(outcome.clone());
+        }
+        // The connection has been left as it was before the command. Only the session of a
+        // clean content is reused, the one which has seen a detection is closed.
+        if matches!(result, Ok(ScanOutcome::Clean | ScanOutcome::Skipped)) {
+            if let (Some((pool, put)), Some(inner)) = (self.pool.take(), self.inner.take()) {
+                put(&pool, inner);
+            }
+        }
+
+        // The verdict takes precedence over the checksum of an infected content.
+        #[cfg(feature = "checksum")]
+        let result = result.and_then(|outcome| match (&outcome, self.hasher.take()) {
+            (ScanOutcome::Clean | ScanOutcome::Skipped, Some(hasher)) => {
+                hasher.verify().map(|_| outcome)
+            }
+            _ => Ok(outcome),
+        });
+        let result = result
+            .and_then(|outcome| self.check_truncated(outcome))
+            .map_err(|err| err.with_tenant(self.progress.tenant().as_deref()));
+        #[cfg(feature = "journal")]
+        let result = match self.record(result.as_ref()) {
+            Ok(()) => result,
+            Err(err) => Err(err),
+        };
+
+        #[cfg(feature = "tokio")]
+        self.resolve_member(match &result {
+            Ok(outcome) => MemberVerdict::Scanned(outcome.clone()),
+            Err(err) => MemberVerdict::Failed(err.to_string()),
+        });
+        result
+    }
+
+    /// Report the verdict to the [`ScanScope`](crate::ScanScope) the scan is a member of.
+    #[cfg(feature = "tokio")]
+    fn resolve_member(&mut self, verdict: MemberVerdict) {
+        if let Some(member) = self.member.take() {
+            member.resolve(verdict, self.progress.report());
+        }
+    }
+
+    /// Record the end of the scan in the journal, if it is recorded.
+    #[cfg(feature = "journal")]
+    fn record(&mut self, outcome: Result<&ScanOutcome, &Error>) -> Result<(), Error> {
+        match self.recording.take() {
+            Some(recording) => recording.record(
+                self.progress.bytes_scanned(),
+                outcome,
+                self.progress.tenant(),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// The verdict of the clamav, once the scan has been finished and the reply read.
+    pub(crate) fn outcome(&self) -> Option<&ScanOutcome> {
+        self.outcome.as_ref()
+    }
+
+    fn terminate(&mut self) -> Result<(), Error> {
+        if let Some(mut decoder) = self.decoder.take() {
+            let rest = decoder.finish()?;
+            self.forward(&rest)?;
+        }
+        #[cfg(feature = "dedup")]
+        self.forward_dedup_rest()?;
+
+        #[cfg(feature = "spool")]
+        if let Some(file) = &mut self.local_file {
+            let path = file.finish()?;
+            let command = if self.all_match {
+                all_match_scan_command(&path)
+            } else {
+                scan_command(&path)
+            };
+            #[cfg(feature = "protocol-debug")]
+            self.progress.trace(Frame::Command(command.clone()));
+            return self.write(&command, Phase::Finish);
+        }
+
+        // An empty content still needs the command before its terminating chunk.
+        self.start()?;
+        #[cfg(feature = "protocol-debug")]
+        self.progress.trace(Frame::EndOfStream);
+        self.write(&END_OF_STREAM, Phase::Finish)
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        if self.stage != Stage::Idle {
+            return Ok(());
+        }
+
+        self.stage = Stage::Streaming;
+        match self.start {
+            Some(format) => {
+                let command = Command::Instream.encode(format);
+                #[cfg(feature = "protocol-debug")]
+                self.progress.trace(Frame::Command(command.clone()));
+                self.write(&command, Phase::Start)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record_time_to_verdict(&self, elapsed: Duration) {
+        self.progress.set_time_to_verdict(elapsed);
+        if let Some(timing) = &self.timing {
+            if let Some(warning) = timing.record(elapsed, self.progress.bytes_scanned()) {
+                self.progress.warn(warning);
+            }
+        }
+    }
+
+    /// Read the verdict, returning [`Poll::Pending`] while a non-blocking connection has not
+    /// replied yet.
+    fn poll_verdict(&mut self) -> Poll<Result<ScanOutcome, Error>> {
+        if let Err(err) = ready!(self.poll_reply()) {
+            if err.kind() != io::ErrorKind::TimedOut {
+                return Poll::Ready(Err(self.transport_error(err, Phase::Reply)));
+            }
+            // The clamav is not to blame for the deadline of the request.
+            if let Err(err) = self.check_deadline() {
+                return Poll::Ready(Err(err));
+            }
+            return Poll::Ready(Err(match self.verdict_timeout {
+                Some(timeout) => {
+                    if let Some(circuit) = &self.circuit {
+                        circuit.failure();
+                    }
+                    Error::VerdictTimeout {
+                        timeout,
+                        tenant: None,
+                    }
+                }
+                None => self.transport_error(err, Phase::Reply),
+            }));
+        }
+
+        if let Some(circuit) = &self.circuit {
+            circuit.success();
+        }
+        let body = mem::take(&mut self.reply);
+        #[cfg(feature = "protocol-debug")]
+        self.progress.trace(Frame::Reply(body.clone()));
+        let reply = match split_request_id(&body) {
+            Some((_, reply)) if self.session => reply,
+            _ => &body,
+        };
+        let outcome = self.parser.parse(reply)?;
+        if outcome == ScanOutcome::Clean {
+            self.trailing_notes
+                .apply(&*self.parser, reply, &self.progress)?;
+        }
+        Poll::Ready(Ok(outcome))
+    }
+
+    /// Read the reply until the clamav closes the connection, or up to its delimiter inside a
+    /// session, since the connection is not closed after it. Returns [`Poll::Pending`] while a
+    /// non-blocking connection has nothing to read, and fails with
+    /// [`io::ErrorKind::TimedOut`] once it still has nothing after the deadline of the read,
+    /// including a blocking connection with a read timeout set on its socket.
+    fn poll_reply(&mut self) -> Poll<io::Result<()>> {
+        let Some(inner) = &mut self.inner else {
+            return Poll::Ready(Ok(()));
+        };
+        let delimiter = match (self.session, self.start.unwrap_or_default()) {
+            (false, _) => None,
+            (true, CommandFormat::Null) => Some(b'\0'),
+            (true, CommandFormat::Newline) => Some(b'\n'),
+        };
+        let deadline = self.reading_since.and_then(|(_, deadline)| deadline);
+
+        let mut buf = [0u8; 256];
+        loop {
+            if delimiter.is_some() && self.reply.last() == delimiter.as_ref() {
+                return Poll::Ready(Ok(()));
+            }
+            match inner.read(&mut buf) {
+                Ok(0) if delimiter.is_some() => {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                Ok(0) => return Poll::Ready(Ok(())),
+                Ok(n) => self.reply.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+                    }
+                    return Poll::Pending;
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+
+    /// Write the bytes, keeping the part a non-blocking connection does not accept yet in the
+    /// outbox, to be written before anything else. Without the memory to keep them, wait for
+    /// the connection to accept them instead.
+    fn write(&mut self, buf: &[u8], phase: Phase) -> Result<(), Error> {
+        self.write_vectored([buf], phase)
+    }
+
+    /// Write the parts in order with vectored writes, so that e.g. the content of a chunk is
+    /// written from the buffer of the caller after its length prefix instead of being copied.
+    /// Only the parts kept in the outbox are copied.
+    fn write_vectored<const N: usize>(
+        &mut self,
+        parts: [&[u8]; N],
+        phase: Phase,
+    ) -> Result<(), Error> {
+        let len = parts.iter().map(|part| part.len()).sum::<usize>();
+        if !self.outbox.is_empty() {
+            if self.keep(len) {
+                parts
+                    .iter()
+                    .for_each(|part| self.outbox.extend_from_slice(part));
+                return self.flush_outbox().map(|_| ());
+            }
+            self.flush_blocking()?;
+        }
+
+        let mut slices = parts.map(IoSlice::new);
+        let mut rest = &mut slices[..];
+        let mut written = 0;
+        let mut retry = Retry::default();
+        loop {
+            let Some(inner) = &mut self.inner else {
+                return Ok(());
+            };
+            let started = Instant::now();
+            let result = write_nonblocking(inner, &mut rest);
+            let n = result.map_err(|err| self.transport_error(err, phase))?;
+            written += n;
+            self.check_stall(n > 0 || len == 0, started)?;
+            if written == len {
+                return Ok(());
+            }
+
+            if self.keep(len - written) {
+                rest.iter()
+                    .for_each(|slice| self.outbox.extend_from_slice(slice));
+                self.outbox_phase = phase;
+                return Ok(());
+            }
+            retry.sleep();
+        }
+    }
+
+    /// Reserve the memory to keep more bytes in the outbox. A resumable scan keeps them even
+    /// past the memory budget, since its caller waits for the outbox to be written before
+    /// sending more instead.
+    fn keep(&mut self, len: usize) -> bool {
+        if self.outbox_charge.grow(len) {
+            return true;
+        }
+        if self.resumable {
+            self.outbox_charge.overdraw(len);
+        }
+        self.resumable
+    }
+
+    /// Wait for a non-blocking connection to accept the whole outbox, for the callers which
+    /// cannot return [`Poll::Pending`].
+    fn flush_blocking(&mut self) -> Result<(), Error> {
+        let mut retry = Retry::default();
+        while !self.flush_outbox()? {
+            retry.sleep();
+        }
+        Ok(())
+    }
+
+    /// Write the outbox as far as the connection accepts it without blocking. Returns `true`
+    /// once the outbox is empty.
+    fn flush_outbox(&mut self) -> Result<bool, Error> {
+        let Some(inner) = &mut self.inner else {
+            return Ok(true);
+        };
+        if self.outbox.is_empty() {
+            return Ok(true);
+        }
+
+        let started = Instant::now();
+        let result = write_nonblocking(inner, &mut &mut [IoSlice::new(&self.outbox)][..]);
+        let n = result.map_err(|err| self.transport_error(err, self.outbox_phase))?;
+        self.outbox.drain(..n);
+        self.outbox_charge.shrink(n);
+        self.check_stall(n > 0, started)?;
+        Ok(self.outbox.is_empty())
+    }
+
+    /// Fail with [`Error::WriteTimeout`] once the connection has accepted nothing for the write
+    /// timeout. A blocking connection with the timeout set on its socket fails a write with
+    /// [`io::ErrorKind::WouldBlock`] after blocking that long, so the first stall times out.
+    fn check_stall(&mut self, progressed: bool, started: Instant) -> Result<(), Error> {
+        if progressed {
+            self.stalled_since = None;
+            return Ok(());
+        }
+        self.check_deadline()?;
+        let Some(timeout) = self.write_timeout else {
+            return Ok(());
+        };
+
+        let stalled_since = *self.stalled_since.get_or_insert(started);
+        if stalled_since.elapsed() < timeout {
+            return Ok(());
+        }
+        if let Some(circuit) = &self.circuit {
+            circuit.failure();
+        }
+        Err(Error::WriteTimeout {
+            timeout,
+            bytes_sent: self.bytes_sent,
+            tenant: None,
+        })
+    }
+
+    fn verdict_deadline(&self) -> Option<Instant> {
+        let timeout = self.verdict_timeout.map(|timeout| Instant::now() + timeout);
+        timeout.into_iter().chain(self.deadline).min()
+    }
+
+    /// End the scan after an error while sending the content, so that neither the terminating
+    /// chunk is sent nor the reply read afterwards. If the clamav closed the connection after
+    /// replying with a detection, the detection is returned instead of the write error.
+    fn end_early(&mut self, err: Error) -> Error {
+        let result = self.early_verdict(err);
+        self.cut_short(result)
+    }
+
+    /// End the scan before the end of the content with the message of a detection, or an
+    /// error.
+    fn cut_short(&mut self, result: Result<String, Error>) -> Error {
+        self.stage = Stage::Done;
+        self.cut_short = true;
+        self.guard = None;
+        #[cfg(feature = "tokio")]
+        {
+            self.permit = None;
+        }
+        self.quota = None;
+        self.outbox.clear();
+        self.outbox_charge.clear();
+        self.update_buffered();
+        self.progress.finish();
+
+        let err = match result {
+            Ok(message) => {
+                self.outcome = Some(ScanOutcome::Infected(message.clone()));
+                Error::Scan(message)
+            }
+            Err(err) => err,
+        };
+        let err = err.with_tenant(self.progress.tenant().as_deref());
+        #[cfg(feature = "journal")]
+        let err = {
+            let outcome = self.outcome.clone();
+            match self.record(outcome.as_ref().ok_or(&err)) {
+                Ok(()) => err,
+                Err(recording) => recording,
+            }
+        };
+
+        #[cfg(feature = "tokio")]
+        self.resolve_member(match &self.outcome {
+            Some(outcome) => MemberVerdict::Scanned(outcome.clone()),
+            None => MemberVerdict::Failed(err.to_string()),
+        });
+        err
+    }
+
+    /// Check without blocking whether the clamav has replied in the middle of the content, and
+    /// return the error to end the stream with if it has. Returns [`Poll::Pending`] while the
+    /// rest of the reply is yet to be read. Only checks with [`Scan::set_early_verdict`] and a
+    /// non-blocking connection.
+    pub(crate) fn poll_early_verdict(&mut self) -> Poll<Option<Error>> {
+        if !self.early_verdict || self.session {
+            return Poll::Ready(None);
+        }
+        match self.stage {
+            Stage::Streaming => {
+                let Some(inner) = self.inner.as_mut() else {
+                    return Poll::Ready(None);
+                };
+                let mut buf = [0u8; 256];
+                let err = match inner.read(&mut buf) {
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        return Poll::Ready(None)
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                        return Poll::Ready(None)
+                    }
+                    Ok(0) => io::ErrorKind::UnexpectedEof.into(),
+                    Ok(n) => {
+                        self.reply.extend_from_slice(&buf[..n]);
+                        self.stage = Stage::AwaitingVerdict;
+                        self.replied_early = true;
+                        self.reading_since = Some((Instant::now(), self.verdict_deadline()));
+                        return self.poll_early_verdict();
+                    }
+                    Err(err) => err,
+                };
+                let err = self.transport_error(err, Phase::Chunk);
+                return Poll::Ready(Some(self.cut_short(Err(err))));
+            }
+            Stage::AwaitingVerdict if self.replied_early => {}
+            _ => return Poll::Ready(None),
+        }
+
+        // The clamav closes the connection after its reply.
+        if let Err(err) = ready!(self.poll_reply()) {
+            let err = self.transport_error(err, Phase::Chunk);
+            return Poll::Ready(Some(self.cut_short(Err(err))));
+        }
+
+        let body = mem::take(&mut self.reply);
+        #[cfg(feature = "protocol-debug")]
+        self.progress.trace(Frame::Reply(body.clone()));
+        let result = match self.parser.parse(&body) {
+            Ok(ScanOutcome::Infected(message)) => {
+                if let Some(circuit) = &self.circuit {
+                    circuit.success();
+                }
+                Ok(message)
+            }
+            // A clean verdict would only cover the content received so far.
+            Ok(ScanOutcome::Clean | ScanOutcome::Skipped) => Err(Error::send(
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "verdict before the end of the content",
+                ),
+                self.bytes_sent,
+                Phase::Chunk,
+            )),
+            Err(err) => Err(err),
+        };
+        Poll::Ready(Some(self.cut_short(result)))
+    }
+
+    /// Read the reply the clamav sent before closing the connection in the middle of the
+    /// request, e.g. when it detects a signature before the end of the content. Returns the
+    /// message of the detection, or the error to fail the scan with otherwise.
+    fn early_verdict(&mut self, err: Error) -> Result<String, Error> {
+        let closed = matches!(&err, Error::Send { source, .. } if is_closed(source));
+        if !closed || self.session || self.inner.is_none() {
+            return Err(err);
+        }
+
+        // The connection is gone, so whatever can be read without waiting is the whole reply.
+        let _ = self.poll_reply();
+        let body = mem::take(&mut self.reply);
+        if body.is_empty() {
+            return Err(err);
+        }
+
+        #[cfg(feature = "protocol-debug")]
+        self.progress.trace(Frame::Reply(body.clone()));
+        match self.parser.parse(&body) {
+            Ok(ScanOutcome::Infected(message)) => {
+                if let Some(circuit) = &self.circuit {
+                    circuit.success();
+                }
+                Ok(message)
+            }
+            // A clean verdict would only cover the content received before the close.
+            Ok(ScanOutcome::Clean | ScanOutcome::Skipped) => Err(err),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn transport_error(&self, err: io::Error, phase: Phase) -> Error {
+        if let Some(circuit) = &self.circuit {
+            circuit.failure();
+        }
+        Error::send(err, self.bytes_sent, phase)
+    }
+
+    pub(crate) fn phase(&self) -> ScanPhase {
+        match self.stage {
+            Stage::Idle => ScanPhase::Idle,
+            Stage::Streaming | Stage::Finishing => ScanPhase::Streaming {
+                bytes_sent: self.bytes_sent,
+            },
+            Stage::AwaitingVerdict => ScanPhase::AwaitingVerdict,
+            Stage::Done => ScanPhase::Done(self.outcome.clone()),
+        }
+    }
+
+    /// Whether the scan ended before the end of the input, after an error or a detection
+    /// the clamav replied with early.
+    pub(crate) fn is_cut_short(&self) -> bool {
+        self.cut_short
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.stage == Stage::Done
+    }
+
+    pub(crate) fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    pub(crate) fn set_parser(&mut self, parser: Arc<dyn ResponseParser>) {
+        self.parser = parser;
+    }
+
+    #[cfg(feature = "spool")]
+    pub(crate) fn set_mode(&mut self, mode: ScanMode) {
+        self.local_file = match mode {
+            ScanMode::Instream => None,
+            ScanMode::LocalFile(dir) => Some(LocalFile::new(dir)),
+        };
+    }
+
+    /// Choose the format of the `INSTREAM` command, or `None` not to send it at all because the
+    /// transport already has.
+    pub(crate) fn set_start(&mut self, start: Option<CommandFormat>) {
+        self.start = start;
+    }
+
+    #[cfg(feature = "spool")]
+    pub(crate) fn set_all_match(&mut self, all_match: bool) {
+        self.all_match = all_match;
+    }
+
+    pub(crate) fn set_decoding(&mut self, decoding: Option<Decoding>) {
+        self.decoder = decoding.map(Decoder::new);
+        #[cfg(feature = "passthrough-check")]
+        if self.decoder.is_some() {
+            self.scanned = None;
+        }
+    }
+
+    #[cfg(feature = "checksum")]
+    pub(crate) fn set_checksum(&mut self, checksum: Checksum) {
+        self.hasher = Some(Hasher::new(checksum));
+    }
+
+    #[cfg(feature = "spool")]
+    pub(crate) fn set_spool(&mut self, config: SpoolConfig) {
+        let mut spool = Spool::new(config);
+        if let Some(budget) = &self.budget {
+            spool.set_budget(budget.clone());
+        }
+        self.spool = Some(spool);
+    }
+
+    /// Count the outbox and the part of the spool kept in memory against the budget. Only set
+    /// before anything is sent.
+    pub(crate) fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.outbox_charge = MemoryCharge::new(Some(budget.clone()));
+        #[cfg(feature = "spool")]
+        if let Some(spool) = &mut self.spool {
+            spool.set_budget(budget.clone());
+        }
+        self.budget = Some(budget);
+    }
+
+    #[cfg(feature = "spool")]
+    pub(crate) fn spool(&self) -> Option<&Spool> {
+        self.spool.as_ref()
+    }
+
+    #[cfg(feature = "spool")]
+    pub(crate) fn into_spool(mut self) -> Option<Spool> {
+        self.spool.take()
+    }
+
+    pub(crate) fn set_circuit(&mut self, circuit: Arc<Circuit>) {
+        self.circuit = Some(circuit);
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn set_permit(&mut self, permit: ScanPermit) {
+        self.permit = Some(permit);
+    }
+
+    pub(crate) fn set_quota(&mut self, mut quota: QuotaCharge) {
+        quota.track(self.progress.clone());
+        self.quota = Some(quota);
+    }
+
+    /// Read a single delimited reply, because the connection is inside an `IDSESSION` and is
+    /// not closed after it, and put the connection back to the pool once the verdict is read.
+    pub(crate) fn set_session(&mut self, pool: Arc<Pool>, put: PutBack<RW>) {
+        self.session = true;
+        self.pool = Some((pool, put));
+    }
+
+    pub(crate) fn set_guard(&mut self, guard: InFlight) {
+        self.guard = Some(guard);
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn set_member(&mut self, member: ScopeMember) {
+        self.member = Some(member);
+    }
+
+    pub(crate) fn set_chunk_size(&mut self, chunk_size: ChunkSize) {
+        self.chunk_size = chunk_size;
+        self.sizer = None;
+    }
+
+    /// The length of the next `INSTREAM` chunk of a content long enough to fill it.
+    pub(crate) fn frame_len(&self) -> usize {
+        match &self.sizer {
+            Some(sizer) => sizer.current(),
+            None => self.chunk_size.get(),
+        }
+    }
+
+    pub(crate) fn set_adaptive_chunk_size(&mut self, config: AdaptiveChunkSize) {
+        self.sizer = Some(ChunkSizer::new(config));
+    }
+
+    /// Let the caller resume the outbox with [`Scan::resume`] before sending more, instead of
+    /// the scan waiting for the connection once the memory budget is spent.
+    pub(crate) fn set_resumable(&mut self, resumable: bool) {
+        self.resumable = resumable;
+    }
+
+    pub(crate) fn set_early_verdict(&mut self, early_verdict: bool) {
+        self.early_verdict = early_verdict;
+    }
+
+    pub(crate) fn set_timeouts(&mut self, write: Option<Duration>, verdict: Option<Duration>) {
+        self.write_timeout = write;
+        self.verdict_timeout = verdict;
+    }
+
+    pub(crate) fn set_drop_behavior(&mut self, behavior: DropBehavior) {
+        self.drop_behavior = behavior;
+    }
+
+    #[cfg(feature = "dedup")]
+    pub(crate) fn set_block_dedup(&mut self, config: &BlockDedup) {
+        self.dedup = Some(Deduper::new(config));
+        #[cfg(feature = "passthrough-check")]
+        {
+            self.scanned = None;
+        }
+    }
+
+    /// The checksum of the content written to the clamav, to compare with the content passed
+    /// through. `None` if it differs from the content by design, or has not been sent at all.
+    #[cfg(feature = "passthrough-check")]
+    pub(crate) fn scanned(&self) -> Option<Crc32> {
+        self.scanned
+    }
+
+    pub(crate) fn set_buffer_pool(&mut self, buffers: BufferPool) {
+        self.buffers = buffers;
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    pub(crate) fn set_length_policy(&mut self, policy: LengthPolicy) {
+        self.length_policy = policy;
+    }
+
+    pub(crate) fn set_trailing_notes(&mut self, notes: TrailingNotes) {
+        self.trailing_notes = notes;
+    }
+
+    pub(crate) fn set_completer(&mut self, complete: Complete<RW>) {
+        self.completer = Some(complete);
+    }
+
+    pub(crate) fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    #[cfg(feature = "journal")]
+    pub(crate) fn set_recording(&mut self, recording: Recording) {
+        self.recording = Some(recording);
+    }
+
+    pub(crate) fn set_timing(&mut self, timing: Timing) {
+        self.timing = Some(timing);
+    }
+}
+
+impl<RW> Drop for Scan<RW> {
+    fn drop(&mut self) {
+        if let Some(abandon) = self.dropper.take() {
+            abandon(self);
+        }
+    }
+}
+
+/// Write as much of the slices as the connection accepts without blocking, advancing them past
+/// the bytes written, and return the number of bytes written.
+fn write_nonblocking(inner: &mut impl Write, bufs: &mut &mut [IoSlice<'_>]) -> io::Result<usize> {
+    // An empty slice would be taken for a connection accepting nothing.
+    IoSlice::advance_slices(bufs, 0);
+    let mut written = 0;
+    while !bufs.is_empty() {
+        match inner.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                written += n;
+                IoSlice::advance_slices(bufs, n);
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(written)
+}
+
+/// Whether the error means that the clamav has closed the connection.
+fn is_closed(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::WriteZero
+    )
+}
+
+/// The result of a scan as the error of a detection.
+fn into_verdict(result: Result<ScanOutcome, Error>) -> Result<(), Error> {
+    match result? {
+        ScanOutcome::Clean | ScanOutcome::Skipped => Ok(()),
+        ScanOutcome::Infected(message) => Err(Error::Scan(message)),
+    }
+}
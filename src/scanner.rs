@@ -0,0 +1,2926 @@
+#[cfg(feature = "dedup")]
+use crate::dedup::BlockDedup;
+#[cfg(all(feature = "journal", feature = "spool", feature = "tokio"))]
+use crate::journal::ScanRecord;
+#[cfg(feature = "journal")]
+use crate::journal::{Recording, ScanJournal};
+use crate::{
+    adaptive::AdaptiveChunkSize,
+    backoff::{Backoff, Breaker, ScannerHealth},
+    buffer::BufferPool,
+    circuit::{Circuit, CircuitBreaker, CircuitState, FailurePolicy},
+    config::{ConfigError, ConfigIssue},
+    connection::{Address, Connection, TcpOptions, Transport},
+    decode::Decoding,
+    drop_behavior::DropBehavior,
+    latency::{ResponseTimes, Timing},
+    manual::ManualScan,
+    memory::MemoryBudget,
+    pool::Pool,
+    protocol::{ChunkSize, Command, CommandFormat, Reply, Version, STREAM_MAX_LENGTH},
+    quota::{QuotaCharge, QuotaManager},
+    report::{LengthPolicy, Warning},
+    response::{ClamdParser, ReplyGrammar, ResponseParser, TrailingNotes},
+    sample::{SampleHint, SampleRate, Sampler},
+    scan::Scan,
+    shutdown::Tracker,
+    Error, ScannedStream,
+};
+#[cfg(feature = "tokio")]
+use crate::{
+    async_stream::AsyncScannedStream,
+    channel::{ChannelInput, TryChannelInput},
+    connection::{AsyncConnection, AsyncTransport},
+    drive::drive,
+    duplex::{duplex_with, ScannedDuplex},
+    limiter::{Priority, ScanLimiter, ScanPermit},
+    response::ScanOutcome,
+    resumable::ResumableScan,
+    session::SessionMux,
+    shutdown::ShutdownReport,
+    task::DROP_COMPLETION_TASK,
+    verdict::{StaticClamd, StaticVerdict},
+};
+#[cfg(all(feature = "spool", feature = "tokio"))]
+use crate::{
+    lookup::{DatabaseVersion, HashLookup, LookupKey, Sha256Digest},
+    multipart::MultipartScan,
+    report::ScanReport,
+    reputation::{Reputation, ReputationPolicy, ReputationProvider, ReputationVerdict},
+    sanitize::{OnDetection, ReleasedStream},
+    signature::{SignatureMetadata, SignatureMetadataProvider},
+    spool::Spool,
+};
+#[cfg(feature = "spool")]
+use crate::{mode::ScanMode, spool::SpoolConfig};
+#[cfg(feature = "webhook")]
+use crate::{
+    task::WEBHOOK_TASK,
+    webhook::{Payload, WebhookNotifier},
+};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use std::{
+    error::Error as StdError,
+    fmt,
+    io::{self, Read, Write},
+    net::ToSocketAddrs,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+#[cfg(all(feature = "spool", feature = "tokio"))]
+use {
+    sha2::{Digest, Sha256},
+    tokio_stream::StreamExt,
+};
+#[cfg(feature = "tokio")]
+use {
+    std::{future::Future, pin::Pin},
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+
+#[cfg(unix)]
+use crate::connection::UnixSocketOptions;
+#[cfg(unix)]
+use std::path::Path;
+
+/// The default interval between the `VERSION` commands keying the verdicts of a
+/// [`HashLookup`].
+#[cfg(all(feature = "spool", feature = "tokio"))]
+const DEFAULT_DATABASE_REFRESH: Duration = Duration::from_secs(60);
+
+type Connector = Arc<dyn Fn() -> io::Result<Connection> + Send + Sync>;
+#[cfg(feature = "tokio")]
+type AsyncConnector = Arc<
+    dyn Fn() -> Pin<Box<dyn Future<Output = io::Result<AsyncConnection>> + Send>> + Send + Sync,
+>;
+
+/// A cheap, clonable handle to a clamav server.
+///
+/// Keep one in the application state and call [`Scanner::wrap`] for every stream to be scanned.
+/// Cloning a [`Scanner`] only increments a reference count, so every clone shares the same
+/// configuration.
+///
+/// A [`Scanner`] is [`Send`] and [`Sync`], and the futures of its methods are [`Send`] whenever
+/// their inputs are, so that they can be awaited in a spawned task.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    inner: Arc<Inner>,
+    tenant: Option<Arc<str>>,
+    hint: Option<Arc<SampleHint>>,
+    deadline: Option<Instant>,
+}
+
+struct Inner {
+    address: Address,
+    parser: Arc<dyn ResponseParser>,
+    #[cfg(feature = "spool")]
+    spool: Option<SpoolConfig>,
+    #[cfg(feature = "spool")]
+    mode: ScanMode,
+    #[cfg(feature = "spool")]
+    all_match: bool,
+    decoding: Option<Decoding>,
+    tcp: TcpOptions,
+    #[cfg(unix)]
+    unix: UnixSocketOptions,
+    connector: Option<Connector>,
+    #[cfg(feature = "tokio")]
+    async_connector: Option<AsyncConnector>,
+    command_format: CommandFormat,
+    breaker: Option<Breaker>,
+    circuit: Option<Arc<Circuit>>,
+    #[cfg(feature = "tokio")]
+    limiter: Option<ScanLimiter>,
+    quotas: Option<QuotaManager>,
+    pool: Option<Arc<Pool>>,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    lookup: Option<Arc<dyn HashLookup>>,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    database: DatabaseVersion,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    reputation: Option<(Arc<dyn ReputationProvider>, ReputationPolicy)>,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    signatures: Option<Arc<dyn SignatureMetadataProvider>>,
+    sampler: Sampler,
+    #[cfg(feature = "webhook")]
+    webhook: Option<Arc<WebhookNotifier>>,
+    #[cfg(feature = "journal")]
+    journal: Option<Arc<dyn ScanJournal>>,
+    response_times: ResponseTimes,
+    slow_scan_threshold: Option<Duration>,
+    chunk_size: ChunkSize,
+    adaptive_chunk_size: Option<AdaptiveChunkSize>,
+    #[cfg(feature = "dedup")]
+    block_dedup: Option<BlockDedup>,
+    buffer_pool: Option<BufferPool>,
+    early_verdict: bool,
+    write_timeout: Option<Duration>,
+    verdict_timeout: Option<Duration>,
+    memory_budget: Option<MemoryBudget>,
+    drop_behavior: DropBehavior,
+    length_policy: LengthPolicy,
+    trailing_notes: TrailingNotes,
+    tracker: Arc<Tracker>,
+}
+
+/// The permit of the limiter and the quota charge taken by [`Scanner::admit_spooled`] before
+/// the input is read, held until the scan ends.
+#[cfg(all(feature = "spool", feature = "tokio"))]
+struct Admission {
+    permit: Option<ScanPermit>,
+    quota: Option<QuotaCharge>,
+}
+
+/// A content scanned by [`Scanner::scan_spooled`].
+#[cfg(all(feature = "spool", feature = "tokio"))]
+pub(crate) struct Spooled {
+    pub(crate) outcome: ScanOutcome,
+    pub(crate) report: ScanReport,
+    pub(crate) spool: Spool,
+    pub(crate) digest: Sha256Digest,
+}
+
+impl fmt::Debug for Inner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Inner");
+        debug.field("address", &self.address);
+        #[cfg(feature = "spool")]
+        debug
+            .field("spool", &self.spool)
+            .field("mode", &self.mode)
+            .field("all_match", &self.all_match);
+        debug
+            .field("decoding", &self.decoding)
+            .field("tcp", &self.tcp)
+            .field("connector", &self.connector.is_some());
+        #[cfg(feature = "tokio")]
+        debug.field("async_connector", &self.async_connector.is_some());
+        debug
+            .field("command_format", &self.command_format)
+            .field("breaker", &self.breaker)
+            .field("circuit", &self.circuit);
+        #[cfg(feature = "tokio")]
+        debug.field("limiter", &self.limiter);
+        debug
+            .field("quotas", &self.quotas)
+            .field("pool", &self.pool);
+        #[cfg(all(feature = "spool", feature = "tokio"))]
+        debug.field("database", &self.database);
+        debug
+            .field("sampler", &self.sampler)
+            .field("response_times", &self.response_times)
+            .field("slow_scan_threshold", &self.slow_scan_threshold)
+            .field("chunk_size", &self.chunk_size)
+            .field("adaptive_chunk_size", &self.adaptive_chunk_size);
+        #[cfg(feature = "dedup")]
+        debug.field("block_dedup", &self.block_dedup);
+        debug
+            .field("buffer_pool", &self.buffer_pool)
+            .field("early_verdict", &self.early_verdict)
+            .field("write_timeout", &self.write_timeout)
+            .field("verdict_timeout", &self.verdict_timeout)
+            .field("memory_budget", &self.memory_budget)
+            .field("drop_behavior", &self.drop_behavior)
+            .field("length_policy", &self.length_policy)
+            .field("trailing_notes", &self.trailing_notes)
+            .field("tracker", &self.tracker)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scanner {
+    /// Create a new [`Scanner`] for the given clamav address.
+    pub fn new(address: Address) -> Self {
+        Self::builder(address).build()
+    }
+
+    /// Create a [`ScannerBuilder`] to configure a new [`Scanner`].
+    pub fn builder(address: Address) -> ScannerBuilder {
+        ScannerBuilder {
+            address,
+            parser: Arc::new(ClamdParser),
+            #[cfg(feature = "spool")]
+            spool: None,
+            #[cfg(feature = "spool")]
+            mode: ScanMode::default(),
+            #[cfg(feature = "spool")]
+            all_match: false,
+            decoding: None,
+            tcp: TcpOptions::default(),
+            #[cfg(unix)]
+            unix: UnixSocketOptions::default(),
+            connector: None,
+            #[cfg(feature = "tokio")]
+            async_connector: None,
+            command_format: CommandFormat::default(),
+            backoff: None,
+            circuit: None,
+            #[cfg(feature = "tokio")]
+            limiter: None,
+            quotas: None,
+            max_idle: None,
+            #[cfg(all(feature = "spool", feature = "tokio"))]
+            lookup: None,
+            #[cfg(all(feature = "spool", feature = "tokio"))]
+            database_refresh: DEFAULT_DATABASE_REFRESH,
+            #[cfg(all(feature = "spool", feature = "tokio"))]
+            reputation: None,
+            #[cfg(all(feature = "spool", feature = "tokio"))]
+            signatures: None,
+            sample_rate: SampleRate::All,
+            #[cfg(feature = "webhook")]
+            webhook: None,
+            #[cfg(feature = "journal")]
+            journal: None,
+            slow_scan_threshold: None,
+            chunk_size: ChunkSize::default(),
+            adaptive_chunk_size: None,
+            #[cfg(feature = "dedup")]
+            block_dedup: None,
+            buffer_pool: None,
+            early_verdict: false,
+            write_timeout: None,
+            verdict_timeout: None,
+            memory_budget: None,
+            drop_behavior: DropBehavior::default(),
+            length_policy: LengthPolicy::default(),
+            trailing_notes: TrailingNotes::default(),
+        }
+    }
+
+    /// Create a new [`Scanner`] connecting to clamav server with tcp socket.
+    pub fn tcp(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        Ok(Self::new(Address::tcp(addr)?))
+    }
+
+    /// Create a new [`Scanner`] which gives the verdict to every content without connecting
+    /// to a clamav, see [`ScannerBuilder::static_verdict`].
+    #[cfg(feature = "tokio")]
+    pub fn static_verdict(verdict: StaticVerdict) -> Self {
+        Self::builder(Address::Tcp(vec![]))
+            .static_verdict(verdict)
+            .build()
+    }
+
+    /// Create a new [`Scanner`] connecting to clamav server with unix socket.
+    #[cfg(unix)]
+    pub fn socket(path: impl AsRef<Path>) -> Self {
+        Self::new(Address::Unix(path.as_ref().to_path_buf()))
+    }
+
+    /// Create a new [`Scanner`] connecting to clamav server with a unix socket in the abstract
+    /// namespace of Linux.
+    #[cfg(target_os = "linux")]
+    pub fn abstract_socket(name: impl AsRef<[u8]>) -> Self {
+        Self::new(Address::UnixAbstract(name.as_ref().to_vec()))
+    }
+
+    /// The address of the clamav server.
+    pub fn address(&self) -> &Address {
+        &self.inner.address
+    }
+
+    /// Returns `true` once [`Scanner::shutdown`] has been called on any clone.
+    pub fn is_shut_down(&self) -> bool {
+        self.inner.tracker.is_closed()
+    }
+
+    /// The number of idle connections kept for the next scans. Always 0 unless
+    /// [`ScannerBuilder::pool`] is configured.
+    pub fn idle_connections(&self) -> usize {
+        self.inner.pool.as_ref().map_or(0, |pool| pool.len())
+    }
+
+    /// The health of the connections to the clamav server, e.g. for a health endpoint.
+    /// Always [`ScannerHealth::Healthy`] unless a reconnect [`Backoff`] is configured.
+    pub fn health(&self) -> ScannerHealth {
+        match &self.inner.breaker {
+            Some(breaker) => breaker.health(),
+            None => ScannerHealth::Healthy,
+        }
+    }
+
+    /// Open a new connection to the clamav server and wrap the input with a [`ScannedStream`].
+    ///
+    /// The connection is a blocking std socket, so polling the stream blocks the task while a
+    /// chunk is written and while the verdict is read, unless the scanner is built with
+    /// [`ScannerBuilder::early_verdict`]. See [`Scanner::wrap_async`] to wrap it with an
+    /// [`AsyncScannedStream`](crate::AsyncScannedStream) over a tokio transport instead.
+    ///
+    /// Once the cooldown of the [`circuit_breaker`](ScannerBuilder::circuit_breaker) has
+    /// elapsed, the clamav is probed with a `PING` on the calling thread before connecting,
+    /// blocking it until the clamav answers. [`Scanner::wrap_with_priority`] probes it without
+    /// blocking.
+    ///
+    /// Returns [`Error::Shutdown`] once [`Scanner::shutdown`] has been called on any clone.
+    pub fn wrap<St, B, E>(&self, input: St) -> Result<ScannedStream<St, Connection>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError,
+    {
+        Ok(ScannedStream::with_scan(input, self.scan()?))
+    }
+
+    /// Open a new connection to the clamav server and wrap the chunks received from a tokio
+    /// channel with a [`ScannedStream`], see [`ScannedStream::from_channel`].
+    #[cfg(feature = "tokio")]
+    pub fn wrap_channel<B>(
+        &self,
+        rx: impl Into<ChannelInput<B>>,
+    ) -> Result<ScannedStream<ChannelInput<B>, Connection>, Error>
+    where
+        B: Into<Bytes>,
+    {
+        self.wrap(rx.into())
+    }
+
+    /// Open a new connection to the clamav server and wrap the fallible chunks received from a
+    /// tokio channel with a [`ScannedStream`], see [`ScannedStream::from_try_channel`].
+    #[cfg(feature = "tokio")]
+    pub fn wrap_try_channel<B, E>(
+        &self,
+        rx: impl Into<TryChannelInput<B, E>>,
+    ) -> Result<ScannedStream<TryChannelInput<B, E>, Connection>, Error>
+    where
+        B: Into<Bytes>,
+        E: StdError,
+    {
+        self.wrap(rx.into())
+    }
+
+    /// Scan many small contents, e.g. the attachments of an email, over a single `IDSESSION`
+    /// connection, see [`SessionMux::scan_batch`]. The session is ended once every verdict has
+    /// been read.
+    ///
+    /// Returns an error only if the session cannot be started. The contents are sent in chunks
+    /// of [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE), and a custom connection, which cannot
+    /// be read and written concurrently, is refused.
+    #[cfg(feature = "tokio")]
+    pub async fn scan_batch<I>(&self, contents: I) -> Result<Vec<Result<ScanOutcome, Error>>, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        if self.inner.tracker.is_closed() {
+            return Err(Error::Shutdown);
+        }
+
+        let parser = Arc::clone(&self.inner.parser);
+        let session = SessionMux::with_response_parser(self.open()?, move |reply: &[u8]| {
+            parser.parse(reply)
+        })?;
+        Ok(session.scan_batch(contents).await)
+    }
+
+    /// Open a new connection to the clamav server for a [`ManualScan`], which is fed the
+    /// content by the caller instead of wrapping a stream.
+    pub fn manual(&self) -> Result<ManualScan<Connection>, Error> {
+        Ok(ManualScan::with_scan(self.scan()?))
+    }
+
+    /// Open a new connection to the clamav server for a [`ResumableScan`], which scans an
+    /// object uploaded in successive byte ranges over one connection.
+    #[cfg(feature = "tokio")]
+    pub fn resumable(&self) -> Result<ResumableScan<Connection>, Error> {
+        Ok(ResumableScan::with_scan(self.scan()?))
+    }
+
+    /// Open a new asynchronous connection to the clamav server and wrap the input with an
+    /// [`AsyncScannedStream`](crate::AsyncScannedStream).
+    ///
+    /// The scan is admitted like one of [`Scanner::wrap_with_priority`] at the default
+    /// priority: it counts against the limiter, the quota of the tenant and the shutdown of the
+    /// scanner until its verdict has been read, and is subject to the sampling, the circuit
+    /// breaker, the reconnection backoff and the deadline of the handle. Its time to verdict
+    /// is recorded in the [`Scanner::response_times`].
+    #[cfg(feature = "tokio")]
+    pub async fn wrap_async<St, B, E>(
+        &self,
+        input: St,
+    ) -> Result<AsyncScannedStream<St, AsyncConnection>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.wrap_async_with_priority(input, Priority::default())
+            .await
+    }
+
+    /// Like [`Scanner::wrap_async`], waiting for the limiter with the given priority.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn wrap_async_with_priority<St, B, E>(
+        &self,
+        input: St,
+        priority: Priority,
+    ) -> Result<AsyncScannedStream<St, AsyncConnection>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let connect = async {
+            let (conn, failures) = self.connect_async().await?;
+            let socket = conn.try_clone_blocking().ok();
+            Ok((conn, failures, socket))
+        };
+        self.admit_async(input, priority, self.inner.address.to_string(), connect)
+            .await
+            .map_err(|err| err.with_tenant(self.tenant()))
+    }
+
+    /// Admit an [`AsyncScannedStream`] over the connection opened by `connect`, which returns
+    /// the number of failed attempts and a handle to close the socket on shutdown. The timing
+    /// samples of the scan are labelled with the backend.
+    #[cfg(feature = "tokio")]
+    async fn admit_async<St, IO, B, E, C>(
+        &self,
+        input: St,
+        priority: Priority,
+        backend: String,
+        connect: C,
+    ) -> Result<AsyncScannedStream<St, IO>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        E: StdError + Send + Sync + 'static,
+        C: Future<Output = Result<(IO, u32, Option<Connection>), Error>>,
+    {
+        if self.inner.tracker.is_closed() {
+            return Err(Error::Shutdown);
+        }
+        if self.deadline_passed() {
+            return Err(Error::deadline_exceeded(0));
+        }
+        let permit = self.acquire(priority).await?;
+
+        let unhinted = SampleHint::default();
+        if !self
+            .inner
+            .sampler
+            .is_sampled(self.hint.as_deref().unwrap_or(&unhinted))
+        {
+            let stream = self.configure_async(AsyncScannedStream::bypass(input));
+            stream.progress().warn(Warning::SampledOut);
+            return Ok(stream);
+        }
+
+        let quota = self.admit_quota()?;
+
+        if let Some(circuit) = &self.inner.circuit {
+            let probe = async { self.ping_async().await.is_ok() };
+            if let Err(retry_in) = circuit.admit_async(probe).await {
+                return match circuit.policy() {
+                    FailurePolicy::FailClosed => Err(Error::unavailable(retry_in)),
+                    FailurePolicy::FailOpen => {
+                        let stream = self.configure_async(AsyncScannedStream::bypass(input));
+                        stream.progress().warn(Warning::FailOpen);
+                        Ok(stream)
+                    }
+                };
+            }
+        }
+
+        let (io, failures, socket) = connect.await.inspect_err(|err| {
+            // The clamav is not to blame for the deadline of the request.
+            if let (Some(circuit), false) = (
+                &self.inner.circuit,
+                matches!(err, Error::DeadlineExceeded { .. }),
+            ) {
+                circuit.failure();
+            }
+        })?;
+        let guard = self.inner.tracker.track(socket);
+
+        let mut stream = self
+            .configure_async(AsyncScannedStream::new(input, io))
+            .with_guard(guard)
+            .with_timing(Timing {
+                times: self.inner.response_times.clone(),
+                slow_threshold: self.inner.slow_scan_threshold,
+                backend,
+                tenant: self.tenant().map(String::from),
+            });
+        if failures > 0 {
+            stream.progress().warn(Warning::Reconnected { failures });
+        }
+        if let Some(permit) = permit {
+            stream = stream.with_permit(permit);
+        }
+        if let Some(quota) = quota {
+            stream = stream.with_quota(quota);
+        }
+        if let Some(circuit) = &self.inner.circuit {
+            stream = stream.with_circuit(Arc::clone(circuit));
+        }
+
+        Ok(stream)
+    }
+
+    /// Wait for the [`ScanLimiter`] to allow a scan of the given priority, then open a new
+    /// connection to the clamav server and wrap the input with a [`ScannedStream`].
+    ///
+    /// The scan counts against the limiter until its verdict has been read. Without a limiter
+    /// configured, this is the same as [`Scanner::wrap`].
+    #[cfg(feature = "tokio")]
+    pub async fn wrap_with_priority<St, B, E>(
+        &self,
+        input: St,
+        priority: Priority,
+    ) -> Result<ScannedStream<St, Connection>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError,
+    {
+        Ok(ScannedStream::with_scan(
+            input,
+            self.scan_with_priority(priority).await?,
+        ))
+    }
+
+    /// Wrap the input with an [`AsyncScannedStream`] over a transport opened by the caller,
+    /// e.g. a TLS stream to a remote clamav.
+    ///
+    /// The scan is admitted like one of [`Scanner::wrap_async`], except that the transport is
+    /// not reconnected and cannot be closed by [`Scanner::shutdown`], which still waits for its
+    /// verdict. Its timing samples are labelled with the `transport` backend.
+    #[cfg(feature = "tokio")]
+    pub async fn wrap_transport<St, IO, B, E>(
+        &self,
+        input: St,
+        io: IO,
+    ) -> Result<AsyncScannedStream<St, IO>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        E: StdError + Send + Sync + 'static,
+    {
+        let connect = async { Ok((io, 0, None)) };
+        self.admit_async(input, Priority::default(), "transport".into(), connect)
+            .await
+            .map_err(|err| err.with_tenant(self.tenant()))
+    }
+
+    /// Open a new asynchronous connection to the clamav server and consume the input only to
+    /// scan it, admitted like [`Scanner::wrap_async`]. See [`scan_stream`](crate::scan_stream).
+    ///
+    /// With a [`HashLookup`], a [`ReputationProvider`] or a webhook configured, this behaves as
+    /// [`Scanner::scan_stream_report`] without the report: the whole input is spooled, see
+    /// [`ScannerBuilder::spool`], and hashed before it is sent to the clamav, instead of being
+    /// streamed to it as it is read.
+    #[cfg(feature = "tokio")]
+    pub async fn scan_stream<St, B, E>(&self, input: St) -> Result<ScanOutcome, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        #[cfg(feature = "spool")]
+        {
+            #[cfg(feature = "webhook")]
+            let notifies = self.inner.webhook.is_some();
+            #[cfg(not(feature = "webhook"))]
+            let notifies = false;
+
+            if self.inner.lookup.is_some() || self.inner.reputation.is_some() || notifies {
+                return self
+                    .scan_stream_report(input)
+                    .await
+                    .map(|(outcome, _)| outcome);
+            }
+        }
+
+        drive(self.wrap_async(input).await?).await
+    }
+
+    /// Consume the input only to scan it like [`Scanner::scan_stream`], and return the
+    /// verdict together with a [`ScanReport`].
+    ///
+    /// The input is spooled and its SHA-256 digest is computed first. A [`HashLookup`] may
+    /// then answer without asking the clamav, and a [`ReputationProvider`] is asked according
+    /// to its [`ReputationPolicy`], its answer being set to [`ScanReport::reputation`].
+    ///
+    /// The scan is admitted before the input is read: it fails with [`Error::Shutdown`] once
+    /// the scanner has been shut down or with [`Error::QuotaExceeded`], and waits for the
+    /// [`limiter`](ScannerBuilder::limiter), even when its verdict is known without the clamav.
+    /// The part of the spool beyond its memory limit is written to the temp file on a blocking
+    /// task, off the runtime worker.
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub async fn scan_stream_report<St, B, E>(
+        &self,
+        input: St,
+    ) -> Result<(ScanOutcome, ScanReport), Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.scan_spooled(input)
+            .await
+            .map(|spooled| (spooled.outcome, spooled.report))
+    }
+
+    /// Consume and scan the whole input first, then pass the content through only if it is
+    /// clean. An infected content is rejected with [`Error::Scan`] or replaced with a payload,
+    /// according to the [`OnDetection`] policy.
+    ///
+    /// The content is spooled meanwhile, see [`ScannerBuilder::spool`].
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub async fn scan_first<St, B, E>(
+        &self,
+        input: St,
+        on_detection: OnDetection,
+    ) -> Result<ReleasedStream, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let spooled = self.scan_spooled(input).await?;
+        ReleasedStream::new(spooled.outcome, spooled.report, spooled.spool, on_detection)
+    }
+
+    /// Spool the input and scan it, see [`Scanner::scan_stream_report`].
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub(crate) async fn scan_spooled<St, B, E>(&self, input: St) -> Result<Spooled, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let spooled = self.spool_and_scan(input).await?;
+        #[cfg(feature = "webhook")]
+        if let Some(webhook) = self
+            .inner
+            .webhook
+            .as_ref()
+            .filter(|webhook| webhook.is_notified(&spooled.outcome))
+        {
+            let webhook = Arc::clone(webhook);
+            let payload = Payload::new(&spooled.outcome, &spooled.report, Some(&spooled.digest));
+            crate::task::spawn(WEBHOOK_TASK, async move { webhook.send(&payload).await });
+        }
+        Ok(spooled)
+    }
+
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    async fn spool_and_scan<St, B, E>(&self, input: St) -> Result<Spooled, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        // A scan refused later would have read the whole input for nothing, and a verdict
+        // known without the clamav is admitted like any other.
+        let admission = self
+            .admit_spooled()
+            .await
+            .map_err(|err| err.with_tenant(self.tenant()))?;
+
+        #[cfg(feature = "journal")]
+        let started = Instant::now();
+        let mut spool = Spool::new(self.inner.spool.clone().unwrap_or_default());
+        let mut hasher = Sha256::new();
+
+        let mut input = std::pin::pin!(input);
+        while let Some(chunk) = input.next().await {
+            let chunk: Bytes = chunk.map_err(|err| Error::Stream(Box::new(err)))?.into();
+            if spool.fits_in_memory(chunk.len()) {
+                spool.write(&chunk)?;
+            } else {
+                // Writing to the temp file blocks, so it is done off the runtime worker.
+                let bytes = chunk.clone();
+                spool = tokio::task::spawn_blocking(move || spool.write(&bytes).map(|_| spool))
+                    .await
+                    .unwrap_or_else(|err| Err(io::Error::other(err)))?;
+            }
+            hasher.update(&chunk);
+        }
+
+        let digest: Sha256Digest = hasher.finalize().into();
+        // A verdict known without the clamav is recorded here, since no scan is opened.
+        let known = |outcome: ScanOutcome, reputation: Option<Reputation>, spool: Spool| {
+            #[cfg(feature = "journal")]
+            if let Some(journal) = &self.inner.journal {
+                journal.record(ScanRecord {
+                    digest,
+                    size: spool.len(),
+                    outcome: Ok(outcome.clone()),
+                    duration: started.elapsed(),
+                    tenant: self.tenant().map(String::from),
+                })?;
+            }
+            let report = ScanReport {
+                bytes_scanned: spool.len(),
+                expected_len: None,
+                warnings: vec![],
+                reputation,
+                signatures: self.signatures(&outcome),
+                time_to_verdict: None,
+                tenant: self.tenant().map(String::from),
+            };
+            Ok(Spooled {
+                outcome,
+                report,
+                spool,
+                digest,
+            })
+        };
+
+        let mut reputation = None;
+        if let Some((provider, ReputationPolicy::BeforeScan)) = &self.inner.reputation {
+            reputation = provider.reputation(&digest).await;
+            if let Some(found) = reputation
+                .as_ref()
+                .filter(|found| found.verdict == ReputationVerdict::Malicious)
+            {
+                let message = format!("{}: Reputation.Malicious FOUND", found.provider);
+                return known(ScanOutcome::Infected(message), reputation, spool);
+            }
+        }
+
+        // Without the database version, a verdict could be stale, so the clamav is asked.
+        let mut lookup = None;
+        let mut disabled = None;
+        if let Some(provider) = &self.inner.lookup {
+            let fetch = || {
+                let scanner = self.clone();
+                async move {
+                    tokio::task::spawn_blocking(move || scanner.version())
+                        .await
+                        .unwrap_or_else(|err| Err(io::Error::other(err).into()))
+                }
+            };
+            match self.inner.database.get(fetch).await {
+                Ok(database) => lookup = Some((provider, LookupKey { digest, database })),
+                Err(reason) => disabled = Some(Warning::LookupDisabled { reason }),
+            }
+        }
+        if let Some(outcome) = lookup.as_ref().and_then(|(lookup, key)| lookup.lookup(key)) {
+            return known(outcome, reputation, spool);
+        }
+
+        // The spool is sent over a blocking connection, off the runtime worker.
+        let scanner = self.clone();
+        let (outcome, mut report, spool) = tokio::task::spawn_blocking(move || {
+            let (outcome, report) = scanner.scan_spool(&spool, disabled, admission)?;
+            Ok::<_, Error>((outcome, report, spool))
+        })
+        .await
+        .unwrap_or_else(|err| Err(io::Error::other(err).into()))?;
+        if let Some((lookup, key)) = &lookup {
+            lookup.record(key, &outcome);
+        }
+
+        if let Some((provider, ReputationPolicy::AfterScan)) = &self.inner.reputation {
+            reputation = provider.reputation(&digest).await;
+        }
+
+        report.reputation = reputation;
+        report.signatures = self.signatures(&outcome);
+        Ok(Spooled {
+            outcome,
+            report,
+            spool,
+            digest,
+        })
+    }
+
+    /// Send the spooled content to the clamav and read the verdict, blocking the thread.
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    fn scan_spool(
+        &self,
+        spool: &Spool,
+        warning: Option<Warning>,
+        admission: Admission,
+    ) -> Result<(ScanOutcome, ScanReport), Error> {
+        let mut scan = self
+            .open_scan(admission.quota)
+            .map_err(|err| err.with_tenant(self.tenant()))?;
+        if let Some(permit) = admission.permit {
+            scan.set_permit(permit);
+        }
+        if let Some(warning) = warning {
+            scan.progress().warn(warning);
+        }
+        scan.send_reader(spool.reader()?)?;
+
+        let outcome = scan
+            .conclude()
+            .expect("the scan is finished only once, after the spool")?;
+        let report = scan
+            .progress()
+            .report()
+            .expect("the report is set when the scan is concluded");
+        Ok((outcome, report))
+    }
+
+    /// The metadata of the signatures found, if the scanner has a
+    /// [`SignatureMetadataProvider`].
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    fn signatures(&self, outcome: &ScanOutcome) -> Vec<SignatureMetadata> {
+        let Some(provider) = &self.inner.signatures else {
+            return vec![];
+        };
+        outcome
+            .detections()
+            .iter()
+            .filter_map(|detection| provider.metadata(&detection.signature))
+            .collect()
+    }
+
+    /// The histogram of the time the clamav took to reply with the verdict, over every scan of
+    /// this [`Scanner`] and its clones.
+    pub fn response_times(&self) -> ResponseTimes {
+        self.inner.response_times.clone()
+    }
+
+    /// Start scanning a multipart upload whose parts arrive as separate streams.
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub fn multipart(&self) -> MultipartScan {
+        MultipartScan::new(self.clone())
+    }
+
+    /// Open a new connection to the clamav server and create a pipe whose content is scanned.
+    /// See [`scanned_duplex`](crate::scanned_duplex).
+    #[cfg(feature = "tokio")]
+    pub fn duplex(&self, max_buf_size: usize) -> Result<ScannedDuplex<Connection>, Error> {
+        Ok(duplex_with(max_buf_size, self.scan()?))
+    }
+
+    /// Open a new connection to the clamav server and wrap the body with a
+    /// [`ScannedBody`](crate::ScannedBody).
+    #[cfg(feature = "http-body")]
+    pub fn wrap_body<B>(&self, body: B) -> Result<crate::ScannedBody<B, Connection>, Error>
+    where
+        B: http_body::Body,
+    {
+        Ok(crate::ScannedBody::with_scan(body, self.scan()?))
+    }
+
+    /// Open a new connection to the clamav server and wrap the stream of body frames with a
+    /// [`ScannedFrames`](crate::ScannedFrames).
+    #[cfg(feature = "http-body")]
+    pub fn wrap_frames<St, D, E>(
+        &self,
+        input: St,
+    ) -> Result<crate::ScannedFrames<St, Connection>, Error>
+    where
+        St: Stream<Item = Result<http_body::Frame<D>, E>>,
+        D: bytes::Buf,
+    {
+        Ok(crate::ScannedFrames::with_scan(input, self.scan()?))
+    }
+
+    /// Wrap a `tokio-tungstenite` message stream with a [`ScannedMessages`](crate::ScannedMessages)
+    /// scanning its binary messages according to the policy.
+    #[cfg(feature = "ws")]
+    pub fn wrap_messages<St>(
+        &self,
+        input: St,
+        policy: crate::MessagePolicy,
+    ) -> crate::ScannedMessages<St> {
+        crate::ScannedMessages::new(input, self.clone(), policy)
+    }
+
+    /// Read a raw RFC822 message and scan each of its attachments on its own connection.
+    #[cfg(feature = "mail")]
+    pub async fn scan_mail<St, B, E>(
+        &self,
+        input: St,
+    ) -> Result<Vec<crate::AttachmentReport>, Error>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        E: StdError + Send + Sync + 'static,
+    {
+        crate::mail::scan_mail(self, input).await
+    }
+
+    /// The state of the circuit breaker. Always [`CircuitState::Closed`] unless a
+    /// [`CircuitBreaker`] is configured.
+    pub fn circuit_state(&self) -> CircuitState {
+        match &self.inner.circuit {
+            Some(circuit) => circuit.state(),
+            None => CircuitState::Closed,
+        }
+    }
+
+    /// Check the clamav server is alive with the `PING` command.
+    pub fn ping(&self) -> Result<(), Error> {
+        let mut conn = self.open()?;
+        conn.write_all(Command::Ping.as_bytes())?;
+
+        let mut reply = vec![];
+        conn.read_to_end(&mut reply)?;
+        pong(&reply)
+    }
+
+    /// Like [`Scanner::ping`], over an asynchronous connection. The reply is awaited for the
+    /// verdict timeout at most, and cannot outlast the deadline.
+    #[cfg(feature = "tokio")]
+    async fn ping_async(&self) -> Result<(), Error> {
+        let ping = async {
+            let mut conn = match &self.inner.async_connector {
+                Some(connect) => connect().await?,
+                None => self.inner.address.connect_async(&self.inner.tcp).await?,
+            };
+            #[cfg(unix)]
+            conn.verify_peer(&self.inner.unix)?;
+            conn.write_all(Command::Ping.as_bytes()).await?;
+
+            let mut reply = vec![];
+            conn.read_to_end(&mut reply).await?;
+            pong(&reply)
+        };
+        let now = tokio::time::Instant::now();
+        let timeout = self.inner.verdict_timeout.map(|timeout| now + timeout);
+        let deadline = self.deadline.map(tokio::time::Instant::from_std);
+        match timeout.into_iter().chain(deadline).min() {
+            Some(deadline) => tokio::time::timeout_at(deadline, ping)
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?,
+            None => ping.await,
+        }
+    }
+
+    /// Probe the clamav if the circuit is half-open, so that the scan opened next over a
+    /// blocking connection does not probe it on the runtime worker.
+    #[cfg(feature = "tokio")]
+    async fn probe_circuit(&self) {
+        if let Some(circuit) = &self.inner.circuit {
+            // The scan is refused by the circuit again if it may not connect.
+            let probe = async { self.ping_async().await.is_ok() };
+            let _ = circuit.admit_async(probe).await;
+        }
+    }
+
+    /// Ask the clamav server for the versions of the program and its signature database.
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut conn = self.open()?;
+        conn.write_all(Command::Version.as_bytes())?;
+
+        let mut reply = vec![];
+        conn.read_to_end(&mut reply)?;
+        Version::parse(&reply)
+    }
+
+    /// Open a new connection to the clamav server for a scan configured by this scanner.
+    pub(crate) fn scan(&self) -> Result<Scan<Connection>, Error> {
+        self.open_scan(None)
+            .map_err(|err| err.with_tenant(self.tenant()))
+    }
+
+    /// Open a scan, counted by the quota of the tenant unless its charge has been taken
+    /// before, see [`Scanner::admit_spooled`].
+    fn open_scan(&self, quota: Option<QuotaCharge>) -> Result<Scan<Connection>, Error> {
+        if self.inner.tracker.is_closed() {
+            return Err(Error::Shutdown);
+        }
+        if self.deadline_passed() {
+            return Err(Error::deadline_exceeded(0));
+        }
+
+        let unhinted = SampleHint::default();
+        if !self
+            .inner
+            .sampler
+            .is_sampled(self.hint.as_deref().unwrap_or(&unhinted))
+        {
+            let scan = self.configure(Scan::bypass());
+            scan.progress().warn(Warning::SampledOut);
+            return Ok(scan);
+        }
+
+        let quota = match quota {
+            Some(quota) => Some(quota),
+            None => self.admit_quota()?,
+        };
+
+        if let Some(circuit) = &self.inner.circuit {
+            if let Err(retry_in) = circuit.admit(|| self.ping().is_ok()) {
+                return match circuit.policy() {
+                    FailurePolicy::FailClosed => Err(Error::unavailable(retry_in)),
+                    FailurePolicy::FailOpen => {
+                        let scan = self.configure(Scan::bypass());
+                        scan.progress().warn(Warning::FailOpen);
+                        Ok(scan)
+                    }
+                };
+            }
+        }
+
+        let (inner, failures) = self.connect().inspect_err(|_| {
+            if let Some(circuit) = &self.inner.circuit {
+                circuit.failure();
+            }
+        })?;
+        let guard = self.inner.tracker.register(&inner);
+
+        // Session connections go back to the pool, which expects them in blocking mode.
+        let early_verdict = self.inner.early_verdict
+            && self.inner.pool.is_none()
+            && !matches!(inner, Connection::Custom(_));
+        if early_verdict {
+            inner.set_nonblocking(true)?;
+        }
+
+        let mut scan = self.configure(Scan::new(inner));
+        scan.set_early_verdict(early_verdict);
+        scan.set_guard(guard);
+        scan.set_timing(Timing {
+            times: self.inner.response_times.clone(),
+            slow_threshold: self.inner.slow_scan_threshold,
+            backend: self.inner.address.to_string(),
+            tenant: self.tenant().map(String::from),
+        });
+        if failures > 0 {
+            scan.progress().warn(Warning::Reconnected { failures });
+        }
+        if let Some(quota) = quota {
+            scan.set_quota(quota);
+        }
+        if let Some(pool) = &self.inner.pool {
+            scan.set_session(Arc::clone(pool), Pool::put);
+        }
+        if let Some(circuit) = &self.inner.circuit {
+            scan.set_circuit(Arc::clone(circuit));
+        }
+
+        Ok(scan)
+    }
+
+    /// Count a scan of the tenant against its quota, if the scanner has any.
+    fn admit_quota(&self) -> Result<Option<QuotaCharge>, Error> {
+        match (&self.tenant, &self.inner.quotas) {
+            (Some(tenant), Some(quotas)) => quotas.admit(tenant).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn deadline_passed(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Admit a scan whose input is consumed before it is scanned, before reading the input:
+    /// refuse it once the scanner has been shut down or the tenant has exhausted its quota,
+    /// and wait for the limiter.
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    async fn admit_spooled(&self) -> Result<Admission, Error> {
+        if self.inner.tracker.is_closed() {
+            return Err(Error::Shutdown);
+        }
+        let permit = self.acquire(Priority::default()).await?;
+        let quota = self.admit_quota()?;
+        Ok(Admission { permit, quota })
+    }
+
+    /// Like [`Scanner::scan`], but wait for the limiter first if one is configured.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn scan_with_priority(
+        &self,
+        priority: Priority,
+    ) -> Result<Scan<Connection>, Error> {
+        let permit = self
+            .acquire(priority)
+            .await
+            .map_err(|err| err.with_tenant(self.tenant()))?;
+        self.probe_circuit().await;
+
+        let mut scan = self.scan()?;
+        if let Some(permit) = permit {
+            scan.set_permit(permit);
+        }
+        Ok(scan)
+    }
+
+    /// Wait for the limiter, if one is configured, to allow a scan of the given priority.
+    #[cfg(feature = "tokio")]
+    async fn acquire(&self, priority: Priority) -> Result<Option<ScanPermit>, Error> {
+        match (&self.inner.limiter, self.deadline) {
+            (Some(limiter), Some(deadline)) => {
+                let deadline = tokio::time::Instant::from_std(deadline);
+                let acquired = tokio::time::timeout_at(deadline, limiter.acquire(priority)).await;
+                Ok(Some(acquired.map_err(|_| Error::deadline_exceeded(0))?))
+            }
+            (Some(limiter), None) => Ok(Some(limiter.acquire(priority).await)),
+            (None, _) => Ok(None),
+        }
+    }
+
+    fn connect(&self) -> Result<(Connection, u32), Error> {
+        if let Some(conn) = self.inner.pool.as_ref().and_then(|pool| pool.take()) {
+            return Ok((conn, 0));
+        }
+
+        #[cfg(unix)]
+        self.check_socket()?;
+
+        let connect = || {
+            let mut conn = self.open()?;
+            if self.inner.pool.is_some() {
+                conn.write_all(&Command::IdSession.encode(self.inner.command_format))?;
+            }
+            Ok(conn)
+        };
+        match &self.inner.breaker {
+            Some(breaker) => breaker.connect(connect),
+            None => Ok((connect().map_err(Error::connect)?, 0)),
+        }
+    }
+
+    /// Like `connect`, for an [`AsyncScannedStream`]. Connecting cannot outlast the deadline.
+    #[cfg(feature = "tokio")]
+    async fn connect_async(&self) -> Result<(AsyncConnection, u32), Error> {
+        #[cfg(unix)]
+        self.check_socket()?;
+
+        let connect = async {
+            let conn = match &self.inner.async_connector {
+                Some(connect) => connect().await?,
+                None => self.inner.address.connect_async(&self.inner.tcp).await?,
+            };
+            #[cfg(unix)]
+            conn.verify_peer(&self.inner.unix)?;
+            Ok(conn)
+        };
+        let connect = async {
+            match &self.inner.breaker {
+                Some(breaker) => breaker.connect_async(connect).await,
+                None => Ok((connect.await.map_err(Error::connect)?, 0)),
+            }
+        };
+        match self.deadline {
+            Some(deadline) => {
+                let deadline = tokio::time::Instant::from_std(deadline);
+                tokio::time::timeout_at(deadline, connect)
+                    .await
+                    .map_err(|_| Error::deadline_exceeded(0))?
+            }
+            None => connect.await,
+        }
+    }
+
+    /// Check the unix socket file before connecting, see [`UnixSocketOptions::check_socket`].
+    #[cfg(unix)]
+    fn check_socket(&self) -> Result<(), Error> {
+        match &self.inner.address {
+            Address::Unix(path) if self.inner.connector.is_none() => {
+                self.inner.unix.check_path(path)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Open a new connection to the clamav server, checking the peer of a unix socket.
+    fn open(&self) -> io::Result<Connection> {
+        let conn = match &self.inner.connector {
+            Some(connect) => connect()?,
+            None => self.inner.address.connect_with(&self.inner.tcp)?,
+        };
+        #[cfg(unix)]
+        conn.verify_peer(&self.inner.unix)?;
+        if !matches!(conn, Connection::Custom(_)) {
+            // A blocking read or write cannot outlast the deadline either.
+            let remaining = self.deadline.map(|deadline| {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                remaining.max(Duration::from_millis(1))
+            });
+            let earliest = |timeout: Option<Duration>| timeout.into_iter().chain(remaining).min();
+            conn.set_write_timeout(earliest(self.inner.write_timeout))?;
+            conn.set_read_timeout(earliest(self.inner.verdict_timeout))?;
+        }
+        Ok(conn)
+    }
+
+    fn configure(&self, mut scan: Scan<Connection>) -> Scan<Connection> {
+        scan.set_parser(Arc::clone(&self.inner.parser));
+        #[cfg(feature = "spool")]
+        {
+            scan.set_mode(self.inner.mode.clone());
+            scan.set_all_match(self.inner.all_match);
+        }
+        scan.set_start(Some(self.inner.command_format));
+        scan.set_decoding(self.inner.decoding);
+        scan.set_chunk_size(self.inner.chunk_size);
+        if let Some(config) = &self.inner.adaptive_chunk_size {
+            scan.set_adaptive_chunk_size(config.clone());
+        }
+        #[cfg(feature = "dedup")]
+        if let Some(config) = &self.inner.block_dedup {
+            scan.set_block_dedup(config);
+        }
+        if let Some(buffers) = &self.inner.buffer_pool {
+            scan.set_buffer_pool(buffers.clone());
+        }
+        scan.set_timeouts(self.inner.write_timeout, self.inner.verdict_timeout);
+        if let Some(deadline) = self.deadline {
+            scan.set_deadline(deadline);
+        }
+        if let Some(budget) = &self.inner.memory_budget {
+            scan.set_memory_budget(budget.clone());
+        }
+        if let Some(tenant) = &self.tenant {
+            scan.progress().set_tenant(tenant);
+        }
+        scan.set_drop_behavior(self.inner.drop_behavior);
+        scan.set_length_policy(self.inner.length_policy);
+        scan.set_trailing_notes(self.inner.trailing_notes);
+        scan.set_completer(complete_in_background);
+        #[cfg(feature = "spool")]
+        if let Some(config) = &self.inner.spool {
+            scan.set_spool(config.clone());
+        }
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.inner.journal {
+            scan.set_recording(Recording::new(Arc::clone(journal)));
+        }
+        scan
+    }
+
+    #[cfg(feature = "tokio")]
+    fn configure_async<St, IO, B, E>(
+        &self,
+        stream: AsyncScannedStream<St, IO>,
+    ) -> AsyncScannedStream<St, IO>
+    where
+        St: Stream<Item = Result<B, E>>,
+        B: Into<Bytes>,
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        E: StdError + Send + Sync + 'static,
+    {
+        let mut stream = stream
+            .with_parser(Arc::clone(&self.inner.parser))
+            .with_chunk_size(self.inner.chunk_size)
+            .with_trailing_notes(self.inner.trailing_notes)
+            .with_timeouts(self.inner.write_timeout, self.inner.verdict_timeout)
+            .with_drop_behavior(self.inner.drop_behavior);
+        if let Some(config) = &self.inner.adaptive_chunk_size {
+            stream = stream.with_adaptive_chunk_size(config.clone());
+        }
+        #[cfg(feature = "dedup")]
+        if let Some(config) = &self.inner.block_dedup {
+            stream = stream.with_block_dedup(config.clone());
+        }
+        if let Some(buffers) = &self.inner.buffer_pool {
+            stream = stream.with_buffer_pool(buffers.clone());
+        }
+        if let Some(deadline) = self.deadline {
+            stream = stream.with_deadline(deadline);
+        }
+        if let Some(tenant) = &self.tenant {
+            stream.progress().set_tenant(tenant);
+        }
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.inner.journal {
+            stream = stream.with_recording(Recording::new(Arc::clone(journal)));
+        }
+        stream
+    }
+
+    /// Stop accepting new streams and wait for the scans in flight to receive their verdicts.
+    ///
+    /// The connections of the scans still in flight after the timeout are closed, and their
+    /// number is reported in the [`ShutdownReport`].
+    #[cfg(feature = "tokio")]
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let tracker = &self.inner.tracker;
+        tracker.close();
+
+        let unresolved = match tokio::time::timeout(timeout, tracker.drained()).await {
+            Ok(()) => 0,
+            Err(_) => tracker.abort(),
+        };
+        if let Some(pool) = &self.inner.pool {
+            pool.clear();
+        }
+
+        ShutdownReport { unresolved }
+    }
+
+    /// Create a [`WeakScanner`] handle which does not keep the configuration, the pooled
+    /// connections and the tasks of the scanner alive, e.g. for a background task which must
+    /// not outlive the service.
+    pub fn downgrade(&self) -> WeakScanner {
+        WeakScanner {
+            inner: Arc::downgrade(&self.inner),
+            tenant: self.tenant.clone(),
+            hint: self.hint.clone(),
+            deadline: self.deadline,
+        }
+    }
+
+    /// Create a handle to the same clamav, with the same configuration, whose scans are labeled
+    /// with the tenant, e.g. a customer id, in multi-tenant services.
+    ///
+    /// The tenant is set to the [`ScanReport`] and the [`Progress`](crate::Progress) of each
+    /// scan, and so to the journal records and the webhook notifications. With
+    /// [`ScannerBuilder::quotas`], the scans count against the quota of the tenant.
+    pub fn for_tenant(&self, tenant: impl Into<String>) -> Scanner {
+        Scanner {
+            inner: Arc::clone(&self.inner),
+            tenant: Some(tenant.into().into()),
+            hint: self.hint.clone(),
+            deadline: self.deadline,
+        }
+    }
+
+    /// The tenant of this handle, see [`Scanner::for_tenant`].
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Create a handle to the same clamav, with the same configuration, whose scans are
+    /// sampled with the hint by a [`SampleRate::ByHint`], e.g. a handle per request with the
+    /// length and the media type of its body.
+    pub fn with_sample_hint(&self, hint: SampleHint) -> Scanner {
+        Scanner {
+            inner: Arc::clone(&self.inner),
+            tenant: self.tenant.clone(),
+            hint: Some(Arc::new(hint)),
+            deadline: self.deadline,
+        }
+    }
+
+    /// Create a handle to the same clamav, with the same configuration, whose scans fail with
+    /// [`Error::DeadlineExceeded`] once the deadline has passed, e.g. a handle per request with
+    /// its deadline, so that the scans end in step with the request timeout rather than only
+    /// with their own timeouts. A `tokio::time::Instant` converts with `into_std`. The other
+    /// handles of the scanner are not affected.
+    ///
+    /// The deadline bounds the wait for a [`limiter`](ScannerBuilder::limiter) permit, and caps
+    /// the timeouts of the connections. See [`ScannedStream::with_deadline`] to give a deadline
+    /// to a single stream.
+    pub fn with_deadline(&self, deadline: Instant) -> Scanner {
+        Scanner {
+            inner: Arc::clone(&self.inner),
+            tenant: self.tenant.clone(),
+            hint: self.hint.clone(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// The deadline of this handle, see [`Scanner::with_deadline`].
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+/// A handle to a [`Scanner`] which does not keep it alive, created by [`Scanner::downgrade`].
+#[derive(Debug, Clone)]
+pub struct WeakScanner {
+    inner: Weak<Inner>,
+    tenant: Option<Arc<str>>,
+    hint: Option<Arc<SampleHint>>,
+    deadline: Option<Instant>,
+}
+
+impl WeakScanner {
+    /// Get a [`Scanner`] back, or [`Error::Shutdown`] once every [`Scanner`] has been dropped
+    /// or [`Scanner::shutdown`] has been called on any of them.
+    pub fn upgrade(&self) -> Result<Scanner, Error> {
+        match self.inner.upgrade() {
+            Some(inner) if !inner.tracker.is_closed() => Ok(Scanner {
+                inner,
+                tenant: self.tenant.clone(),
+                hint: self.hint.clone(),
+                deadline: self.deadline,
+            }),
+            _ => Err(Error::Shutdown),
+        }
+    }
+}
+
+/// Check the reply of the clamav to `PING`.
+fn pong(reply: &[u8]) -> Result<(), Error> {
+    match Reply::parse(reply)? {
+        Reply::Other(pong) if pong == "PONG" => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected reply to PING").into()),
+    }
+}
+
+/// Read the verdict of a scan dropped with [`DropBehavior::Complete`] on a blocking task, so
+/// that the dropping task is not held up by the clamav.
+fn complete_in_background(mut scan: Scan<Connection>) {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        drop(crate::task::spawn_blocking(
+            DROP_COMPLETION_TASK,
+            move || scan.complete_dropped(),
+        ));
+        return;
+    }
+    scan.complete_dropped();
+}
+
+/// A builder to configure a [`Scanner`], created by [`Scanner::builder`].
+pub struct ScannerBuilder {
+    address: Address,
+    parser: Arc<dyn ResponseParser>,
+    #[cfg(feature = "spool")]
+    spool: Option<SpoolConfig>,
+    #[cfg(feature = "spool")]
+    mode: ScanMode,
+    #[cfg(feature = "spool")]
+    all_match: bool,
+    decoding: Option<Decoding>,
+    tcp: TcpOptions,
+    #[cfg(unix)]
+    unix: UnixSocketOptions,
+    connector: Option<Connector>,
+    #[cfg(feature = "tokio")]
+    async_connector: Option<AsyncConnector>,
+    command_format: CommandFormat,
+    backoff: Option<Backoff>,
+    circuit: Option<CircuitBreaker>,
+    #[cfg(feature = "tokio")]
+    limiter: Option<ScanLimiter>,
+    quotas: Option<QuotaManager>,
+    max_idle: Option<usize>,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    lookup: Option<Arc<dyn HashLookup>>,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    database_refresh: Duration,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    reputation: Option<(Arc<dyn ReputationProvider>, ReputationPolicy)>,
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    signatures: Option<Arc<dyn SignatureMetadataProvider>>,
+    sample_rate: SampleRate,
+    #[cfg(feature = "webhook")]
+    webhook: Option<WebhookNotifier>,
+    #[cfg(feature = "journal")]
+    journal: Option<Arc<dyn ScanJournal>>,
+    slow_scan_threshold: Option<Duration>,
+    chunk_size: ChunkSize,
+    adaptive_chunk_size: Option<AdaptiveChunkSize>,
+    #[cfg(feature = "dedup")]
+    block_dedup: Option<BlockDedup>,
+    buffer_pool: Option<BufferPool>,
+    early_verdict: bool,
+    write_timeout: Option<Duration>,
+    verdict_timeout: Option<Duration>,
+    memory_budget: Option<MemoryBudget>,
+    drop_behavior: DropBehavior,
+    length_policy: LengthPolicy,
+    trailing_notes: TrailingNotes,
+}
+
+impl ScannerBuilder {
+    /// Use the given parser instead of [`ClamdParser`] for the replies from the clamav.
+    pub fn response_parser(mut self, parser: impl ResponseParser + 'static) -> Self {
+        self.parser = Arc::new(parser);
+        self
+    }
+
+    /// Accept only the replies of the clamd grammar, or guess the verdict from the words of
+    /// the replies. Defaults to [`ReplyGrammar::Lenient`]. This replaces the
+    /// [`response_parser`](Self::response_parser).
+    pub fn reply_grammar(mut self, grammar: ReplyGrammar) -> Self {
+        self.parser = grammar.parser();
+        self
+    }
+
+    /// Keep a copy of the content of every wrapped stream in a [`Spool`](crate::Spool). Only
+    /// applies to the [`ScannedStream`]s of [`Scanner::wrap`], and configures the spool of the
+    /// contents consumed before they are scanned, e.g. by [`Scanner::scan_stream_report`].
+    #[cfg(feature = "spool")]
+    pub fn spool(mut self, config: SpoolConfig) -> Self {
+        self.spool = Some(config);
+        self
+    }
+
+    /// Choose how the content is sent to the clamav. Defaults to [`ScanMode::Instream`].
+    #[cfg(feature = "spool")]
+    pub fn scan_mode(mut self, mode: ScanMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Ask the clamav to report every signature found instead of only the first one. Only takes
+    /// effect with [`ScanMode::LocalFile`].
+    #[cfg(feature = "spool")]
+    pub fn all_match(mut self, all_match: bool) -> Self {
+        self.all_match = all_match;
+        self
+    }
+
+    /// Decode the content of every wrapped stream before it is sent to the clamav. Only applies
+    /// to the [`ScannedStream`]s of [`Scanner::wrap`].
+    pub fn decoding(mut self, decoding: Decoding) -> Self {
+        self.decoding = Some(decoding);
+        self
+    }
+
+    /// Apply the socket options to the tcp connections to the clamav.
+    pub fn tcp_options(mut self, options: TcpOptions) -> Self {
+        self.tcp = options;
+        self
+    }
+
+    /// Check the peer of the unix socket connections to the clamav.
+    #[cfg(unix)]
+    pub fn unix_socket_options(mut self, options: UnixSocketOptions) -> Self {
+        self.unix = options;
+        self
+    }
+
+    /// Open the connections to the clamav with the given connector instead of connecting to the
+    /// address, e.g. to wrap them in TLS or tunnel them through a proxy.
+    ///
+    /// The connector is called for every new connection, including the reconnects after a
+    /// [`reconnect_backoff`](Self::reconnect_backoff) and the sessions of a
+    /// [`pool`](Self::pool). The address is still reported as the backend of the scans. The
+    /// connections it opens are not [`shutdown`](Scanner::shutdown) forcibly and ignore
+    /// [`early_verdict`](Self::early_verdict).
+    pub fn connector<RW>(
+        mut self,
+        connect: impl Fn() -> io::Result<RW> + Send + Sync + 'static,
+    ) -> Self
+    where
+        RW: Transport + 'static,
+    {
+        self.connector = Some(Arc::new(move || {
+            connect().map(|rw| Connection::Custom(Box::new(rw)))
+        }));
+        self
+    }
+
+    /// Open the connections of [`Scanner::wrap_async`] with the given connector instead of
+    /// connecting to the address.
+    #[cfg(feature = "tokio")]
+    pub fn async_connector<F, Fut, IO>(mut self, connect: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<IO>> + Send + 'static,
+        IO: AsyncTransport + 'static,
+    {
+        self.async_connector = Some(Arc::new(move || {
+            let conn = connect();
+            Box::pin(async move { Ok(AsyncConnection::Custom(Box::new(conn.await?))) })
+        }));
+        self
+    }
+
+    /// Give the verdict to every content instead of connecting to the clamav, e.g. to run the
+    /// whole code path of an application in a development environment without clamd. This
+    /// replaces the [`connector`](Self::connector) and the
+    /// [`async_connector`](Self::async_connector).
+    #[cfg(feature = "tokio")]
+    pub fn static_verdict(self, verdict: StaticVerdict) -> Self {
+        let async_verdict = verdict.clone();
+        self.connector(move || Ok(StaticClamd::new(verdict.clone())))
+            .async_connector(move || {
+                let clamd = StaticClamd::new(async_verdict.clone());
+                async move { Ok(clamd) }
+            })
+    }
+
+    /// Send the `INSTREAM` command in the given format. Defaults to [`CommandFormat::Null`].
+    pub fn command_format(mut self, format: CommandFormat) -> Self {
+        self.command_format = format;
+        self
+    }
+
+    /// Back off after failing to connect to the clamav, refusing new streams with
+    /// [`Error::Unavailable`] until the next attempt instead of hammering the clamav.
+    pub fn reconnect_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Open the circuit after consecutive transport failures, handling new streams by its
+    /// [`FailurePolicy`] without attempting a connection until the clamav answers a `PING`.
+    ///
+    /// The asynchronous entry points, e.g. [`Scanner::wrap_async`], probe the clamav without
+    /// blocking. The synchronous ones, e.g. [`Scanner::wrap`], block the calling thread on the
+    /// probe.
+    pub fn circuit_breaker(mut self, circuit: CircuitBreaker) -> Self {
+        self.circuit = Some(circuit);
+        self
+    }
+
+    /// Limit the concurrent scans started with [`Scanner::wrap_with_priority`] and by a
+    /// [`RescanQueue`](crate::RescanQueue), which waits with [`Priority::Low`]. The limiter
+    /// can be shared with other scanners.
+    #[cfg(feature = "tokio")]
+    pub fn limiter(mut self, limiter: ScanLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Count the scans of the [`Scanner::for_tenant`] handles, and refuse them beyond the quota
+    /// of their tenant. The manager can be shared with other scanners.
+    pub fn quotas(mut self, quotas: QuotaManager) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+
+    /// Scan over `IDSESSION` connections, and keep up to `max_idle` of them after a clean
+    /// verdict to reuse them for the next scans instead of connecting every time. The
+    /// connection of an infected content is closed.
+    ///
+    /// Idle connections closed by the clamav meanwhile are detected and dropped. Only the
+    /// blocking connections, e.g. of [`Scanner::wrap`], are pooled.
+    pub fn pool(mut self, max_idle: usize) -> Self {
+        self.max_idle = Some(max_idle);
+        self
+    }
+
+    /// Look up the SHA-256 digest of the contents scanned with [`Scanner::scan_stream`] before
+    /// sending them to the clamav, so that known contents skip it. A [`VerdictCache`] also
+    /// remembers the verdicts of the clamav.
+    ///
+    /// The verdicts are keyed by the version of the signature database as well. While it cannot
+    /// be asked to the clamav, the contents are scanned with a [`Warning::LookupDisabled`].
+    ///
+    /// The digest is computed before the scan, so [`Scanner::scan_stream`] spools the whole
+    /// input first, see [`ScannerBuilder::spool`], instead of streaming it to the clamav.
+    ///
+    /// [`VerdictCache`]: crate::VerdictCache
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub fn hash_lookup(mut self, lookup: impl HashLookup + 'static) -> Self {
+        self.lookup = Some(Arc::new(lookup));
+        self
+    }
+
+    /// Make the streams of this scanner end with the detection as soon as the clamav replies,
+    /// even before the end of their input, see [`ScannedStream::with_early_verdict`]. Has no
+    /// effect with a [`pool`](Self::pool).
+    pub fn early_verdict(mut self, early_verdict: bool) -> Self {
+        self.early_verdict = early_verdict;
+        self
+    }
+
+    /// Ask the clamav for the version of its signature database, which keys the verdicts of
+    /// the [`HashLookup`], at most once per the given interval. Defaults to 60 seconds.
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub fn database_refresh(mut self, interval: Duration) -> Self {
+        self.database_refresh = interval;
+        self
+    }
+
+    /// Ask a reputation service about the SHA-256 digest of the contents scanned with
+    /// [`Scanner::scan_stream_report`], before or after the clamav according to the policy.
+    ///
+    /// Like with a [`hash_lookup`](Self::hash_lookup), [`Scanner::scan_stream`] then spools the
+    /// whole input before scanning it.
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub fn reputation(
+        mut self,
+        provider: impl ReputationProvider + 'static,
+        policy: ReputationPolicy,
+    ) -> Self {
+        self.reputation = Some((Arc::new(provider), policy));
+        self
+    }
+
+    /// Look up the metadata of the signatures found in the contents scanned with
+    /// [`Scanner::scan_stream_report`], set to [`ScanReport::signatures`], e.g. with the
+    /// offline [`BundledSignatures`](crate::BundledSignatures).
+    #[cfg(all(feature = "spool", feature = "tokio"))]
+    pub fn signature_metadata(
+        mut self,
+        provider: impl SignatureMetadataProvider + 'static,
+    ) -> Self {
+        self.signatures = Some(Arc::new(provider));
+        self
+    }
+
+    /// Scan only a sample of the streams, passing the others through without scanning them.
+    /// Defaults to [`SampleRate::All`].
+    pub fn sample_rate(mut self, rate: SampleRate) -> Self {
+        self.sample_rate = rate;
+        self
+    }
+
+    /// Record every scan of the [`Scanner`] in the journal once it ends, with its verdict or the
+    /// error it failed with. A scan whose entry cannot be recorded fails with [`Error::Io`], so
+    /// that no verdict escapes the audit trail. A stream dropped before the end of its input
+    /// is only recorded if its scan is completed, see [`DropBehavior::Complete`].
+    #[cfg(feature = "journal")]
+    pub fn journal(mut self, journal: impl ScanJournal + 'static) -> Self {
+        self.journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Report the scans of [`Scanner::scan_stream`], [`Scanner::scan_stream_report`] and
+    /// [`Scanner::scan_first`] to a webhook. The notifications are sent in the background, and
+    /// dropped once all the attempts of the notifier have failed.
+    ///
+    /// The notifications carry the SHA-256 digest of the content, so [`Scanner::scan_stream`]
+    /// then spools the whole input before scanning it, see [`ScannerBuilder::spool`], instead
+    /// of streaming it to the clamav.
+    #[cfg(feature = "webhook")]
+    pub fn webhook(mut self, notifier: WebhookNotifier) -> Self {
+        self.webhook = Some(notifier);
+        self
+    }
+
+    /// Report a [`Warning::SlowScan`] when the clamav takes longer than the threshold to reply
+    /// with the verdict, which is a common sign of an overloaded server.
+    pub fn slow_scan_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_scan_threshold = Some(threshold);
+        self
+    }
+
+    /// Fail a scan with [`Error::WriteTimeout`] once the clamav has accepted none of the content
+    /// for the timeout, e.g. when it stops reading a connection. Applies to each write rather
+    /// than to the whole transfer, so a large content may take longer. No timeout by default.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail a scan with [`Error::VerdictTimeout`] once the clamav has not replied within the
+    /// timeout after the end of the content. The clamav may take a while to scan a complex
+    /// archive, so this is usually longer than the [`write_timeout`](Self::write_timeout).
+    /// No timeout by default.
+    pub fn verdict_timeout(mut self, timeout: Duration) -> Self {
+        self.verdict_timeout = Some(timeout);
+        self
+    }
+
+    /// Share the budget between the streams of the [`Scanner`], capping the bytes they buffer
+    /// in memory altogether. See [`MemoryBudget`]. Only applies to the [`ScannedStream`]s of
+    /// [`Scanner::wrap`].
+    pub fn memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    /// Choose what the scans do when their streams are dropped before the end of the input.
+    /// Defaults to [`DropBehavior::Abort`]. See [`ScannedStream::with_drop_behavior`] and
+    /// [`AsyncScannedStream::with_drop_behavior`].
+    pub fn drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.drop_behavior = behavior;
+        self
+    }
+
+    /// Choose what the scans do when a content does not match its declared length. Defaults
+    /// to [`LengthPolicy::Warn`]. See [`ScannedStream::with_length_policy`].
+    pub fn length_policy(mut self, policy: LengthPolicy) -> Self {
+        self.length_policy = policy;
+        self
+    }
+
+    /// Choose what the scans do with the notes the clamav appends to a clean reply. Defaults
+    /// to [`TrailingNotes::Report`]. See [`ScannedStream::with_trailing_notes`].
+    pub fn trailing_notes(mut self, notes: TrailingNotes) -> Self {
+        self.trailing_notes = notes;
+        self
+    }
+
+    /// Split the contents into chunks of at most the given size before sending them. Defaults to
+    /// [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE).
+    pub fn chunk_size(mut self, chunk_size: ChunkSize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Adapt the size of the chunks sent to the throughput of each connection instead of
+    /// splitting the contents at the [`chunk_size`](Self::chunk_size). See
+    /// [`AdaptiveChunkSize`].
+    pub fn adaptive_chunk_size(mut self, config: AdaptiveChunkSize) -> Self {
+        self.adaptive_chunk_size = Some(config);
+        self
+    }
+
+    /// Skip the blocks of each content identical to a block already sent to the clamav in the
+    /// same scan. See [`BlockDedup`] for what the clamav misses then.
+    #[cfg(feature = "dedup")]
+    pub fn block_dedup(mut self, config: BlockDedup) -> Self {
+        self.block_dedup = Some(config);
+        self
+    }
+
+    /// Share the buffers the contents are copied into for a decoding or a block dedup between
+    /// the scans, and reuse them instead of allocating new ones. See [`BufferPool`].
+    pub fn buffer_pool(mut self, buffers: BufferPool) -> Self {
+        self.buffer_pool = Some(buffers);
+        self
+    }
+
+    /// Check the configuration for mistakes which would only show up once streams are scanned,
+    /// e.g. a missing unix socket or a backoff whose initial delay exceeds its maximum, and
+    /// report all of them at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut issues = vec![];
+        if self.connector.is_none() {
+            self.address.validate(&mut issues);
+        }
+        if self.chunk_size.get() > STREAM_MAX_LENGTH {
+            issues.push(ConfigIssue::ChunkSizeTooLarge {
+                size: self.chunk_size.get(),
+                max: STREAM_MAX_LENGTH,
+            });
+        }
+        if let Some(adaptive) = &self.adaptive_chunk_size {
+            adaptive.validate(&mut issues);
+        }
+        #[cfg(feature = "tcp-options")]
+        self.tcp.validate(&mut issues);
+        #[cfg(feature = "spool")]
+        {
+            self.mode.validate(&mut issues);
+            if let Some(spool) = &self.spool {
+                spool.validate(&mut issues);
+            }
+        }
+        if let Some(backoff) = &self.backoff {
+            backoff.validate(&mut issues);
+        }
+        if let Some(circuit) = &self.circuit {
+            circuit.validate(&mut issues);
+        }
+        if self.max_idle == Some(0) {
+            issues.push(ConfigIssue::ZeroPoolSize);
+        }
+        if self
+            .slow_scan_threshold
+            .is_some_and(|threshold| threshold.is_zero())
+        {
+            issues.push(ConfigIssue::ZeroDuration("slow scan threshold"));
+        }
+        if self.write_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            issues.push(ConfigIssue::ZeroDuration("write timeout"));
+        }
+        if self
+            .verdict_timeout
+            .is_some_and(|timeout| timeout.is_zero())
+        {
+            issues.push(ConfigIssue::ZeroDuration("verdict timeout"));
+        }
+        ConfigError::from_issues(issues)
+    }
+
+    /// [`validate`](Self::validate) the configuration, then create the [`Scanner`].
+    pub fn try_build(self) -> Result<Scanner, ConfigError> {
+        self.validate()?;
+        Ok(self.build())
+    }
+
+    /// Create the [`Scanner`].
+    pub fn build(self) -> Scanner {
+        Scanner {
+            inner: Arc::new(Inner {
+                address: self.address,
+                parser: self.parser,
+                #[cfg(feature = "spool")]
+                spool: self.spool,
+                #[cfg(feature = "spool")]
+                mode: self.mode,
+                #[cfg(feature = "spool")]
+                all_match: self.all_match,
+                decoding: self.decoding,
+                tcp: self.tcp,
+                #[cfg(unix)]
+                unix: self.unix,
+                connector: self.connector,
+                #[cfg(feature = "tokio")]
+                async_connector: self.async_connector,
+                command_format: self.command_format,
+                breaker: self.backoff.map(Breaker::new),
+                circuit: self.circuit.map(|config| Arc::new(Circuit::new(config))),
+                #[cfg(feature = "tokio")]
+                limiter: self.limiter,
+                quotas: self.quotas,
+                pool: self.max_idle.map(|max_idle| Arc::new(Pool::new(max_idle))),
+                #[cfg(all(feature = "spool", feature = "tokio"))]
+                lookup: self.lookup,
+                #[cfg(all(feature = "spool", feature = "tokio"))]
+                database: DatabaseVersion::new(self.database_refresh),
+                #[cfg(all(feature = "spool", feature = "tokio"))]
+                reputation: self.reputation,
+                #[cfg(all(feature = "spool", feature = "tokio"))]
+                signatures: self.signatures,
+                sampler: Sampler::new(self.sample_rate),
+                #[cfg(feature = "webhook")]
+                webhook: self.webhook.map(Arc::new),
+                #[cfg(feature = "journal")]
+                journal: self.journal,
+                response_times: ResponseTimes::default(),
+                slow_scan_threshold: self.slow_scan_threshold,
+                chunk_size: self.chunk_size,
+                adaptive_chunk_size: self.adaptive_chunk_size,
+                #[cfg(feature = "dedup")]
+                block_dedup: self.block_dedup,
+                buffer_pool: self.buffer_pool,
+                early_verdict: self.early_verdict,
+                write_timeout: self.write_timeout,
+                verdict_timeout: self.verdict_timeout,
+                memory_budget: self.memory_budget,
+                drop_behavior: self.drop_behavior,
+                length_policy: self.length_policy,
+                trailing_notes: self.trailing_notes,
+                tracker: Arc::default(),
+            }),
+            tenant: None,
+            hint: None,
+            deadline: None,
+        }
+    }
+}
+
+impl fmt::Debug for ScannerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ScannerBuilder");
+        debug.field("address", &self.address);
+        #[cfg(feature = "spool")]
+        debug
+            .field("spool", &self.spool)
+            .field("mode", &self.mode)
+            .field("all_match", &self.all_match);
+        debug
+            .field("decoding", &self.decoding)
+            .field("tcp", &self.tcp)
+            .field("connector", &self.connector.is_some());
+        #[cfg(feature = "tokio")]
+        debug.field("async_connector", &self.async_connector.is_some());
+        debug
+            .field("command_format", &self.command_format)
+            .field("backoff", &self.backoff)
+            .field("circuit", &self.circuit);
+        #[cfg(feature = "tokio")]
+        debug.field("limiter", &self.limiter);
+        debug
+            .field("quotas", &self.quotas)
+            .field("max_idle", &self.max_idle);
+        #[cfg(all(feature = "spool", feature = "tokio"))]
+        debug.field("database_refresh", &self.database_refresh);
+        debug
+            .field("sample_rate", &self.sample_rate)
+            .field("slow_scan_threshold", &self.slow_scan_threshold)
+            .field("chunk_size", &self.chunk_size)
+            .field("adaptive_chunk_size", &self.adaptive_chunk_size);
+        #[cfg(feature = "dedup")]
+        debug.field("block_dedup", &self.block_dedup);
+        debug
+            .field("buffer_pool", &self.buffer_pool)
+            .field("early_verdict", &self.early_verdict)
+            .field("write_timeout", &self.write_timeout)
+            .field("verdict_timeout", &self.verdict_timeout)
+            .field("memory_budget", &self.memory_budget)
+            .field("drop_behavior", &self.drop_behavior)
+            .field("length_policy", &self.length_policy)
+            .field("trailing_notes", &self.trailing_notes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::{test_util::fake_clamd, TenantQuota};
+    #[cfg(feature = "spool")]
+    use crate::{test_util::fake_clamd_many, ReputationFuture, VerdictCache};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_shares_the_configuration_between_clones() {
+        let scanner = Scanner::tcp("127.0.0.1:3310").unwrap();
+        let cloned = scanner.clone();
+
+        assert!(Arc::ptr_eq(&scanner.inner, &cloned.inner));
+        assert_eq!(cloned.address(), scanner.address());
+    }
+
+    #[tokio::test]
+    async fn it_wraps_streams_with_new_connections() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::tcp(addr).unwrap();
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let mut stream = scanner.wrap(&mut input).unwrap();
+
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+
+        let received = server.join().unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0"));
+    }
+
+    #[tokio::test]
+    async fn it_refuses_new_streams_after_shutdown() {
+        let scanner = Scanner::tcp("127.0.0.1:3310").unwrap();
+
+        let report = scanner.clone().shutdown(Duration::from_millis(10)).await;
+        assert!(report.is_drained());
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert_eq!(scanner.wrap(&mut input).err(), Some(Error::Shutdown));
+    }
+
+    #[tokio::test]
+    async fn it_fails_to_upgrade_a_weak_handle_after_shutdown() {
+        let scanner = Scanner::tcp("127.0.0.1:3310").unwrap();
+        let weak = scanner.downgrade();
+        assert!(weak.upgrade().is_ok());
+
+        scanner.shutdown(Duration::from_millis(10)).await;
+        assert_eq!(weak.upgrade().err(), Some(Error::Shutdown));
+
+        let other = Scanner::tcp("127.0.0.1:3310").unwrap().downgrade();
+        assert_eq!(other.upgrade().err(), Some(Error::Shutdown));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn it_fails_with_socket_not_found_before_connecting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clamd.sock");
+        let scanner = Scanner::builder(Address::Unix(path.clone()))
+            .unix_socket_options(UnixSocketOptions::new().check_socket(true))
+            .build();
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert_eq!(
+            scanner.wrap(&mut input).err(),
+            Some(Error::SocketNotFound { path })
+        );
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_labels_the_scans_of_a_tenant_and_enforces_its_quota() {
+        let (addr, _server) = fake_clamd(b"stream: OK\0");
+        let quota = TenantQuota::new().max_scans_per_sec(1);
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .quotas(QuotaManager::new(quota))
+            .build();
+        let acme = scanner.for_tenant("acme");
+        assert_eq!(acme.tenant(), Some("acme"));
+
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let (_, report) = acme.scan_stream_report(input()).await.unwrap();
+        assert_eq!(report.tenant.as_deref(), Some("acme"));
+
+        assert!(matches!(
+            acme.scan_stream(input()).await,
+            Err(Error::QuotaExceeded { tenant, .. }) if tenant == "acme"
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_labels_the_errors_and_the_response_times_with_the_tenant() {
+        let (ok, _server) = fake_clamd(b"stream: OK\0");
+        let (error, _server) = fake_clamd(b"INSTREAM size limit exceeded. ERROR\0");
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+
+        let scanner = Scanner::tcp(ok).unwrap();
+        let items: Vec<_> = scanner
+            .for_tenant("acme")
+            .wrap(input())
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(items, vec![Ok(Bytes::from("Hello World"))]);
+        assert_eq!(scanner.response_times().for_tenant("acme").count(), 1);
+        assert_eq!(scanner.response_times().tenants(), vec!["acme".to_string()]);
+
+        let scanner = Scanner::tcp(error).unwrap().for_tenant("acme");
+        let items: Vec<_> = scanner.wrap(input()).unwrap().collect().await;
+        assert!(matches!(
+            items.last(),
+            Some(Err(err @ Error::Clamd { .. })) if err.tenant() == Some("acme")
+        ));
+
+        let (infected, _server) = fake_clamd(b"stream: Eicar-Signature FOUND\0");
+        let scanner = Scanner::tcp(infected).unwrap().for_tenant("acme");
+        let stream = scanner.wrap(input()).unwrap();
+        let progress = stream.progress();
+        let items: Vec<_> = stream.collect().await;
+        assert!(matches!(items.last(), Some(Err(Error::Scan(_)))));
+        assert_eq!(progress.tenant().as_deref(), Some("acme"));
+
+        let scanner = Scanner::tcp("127.0.0.1:1").unwrap();
+        let acme = scanner.for_tenant("acme");
+        let failure = |scanner: &Scanner| scanner.wrap(input()).err().unwrap();
+        let err = failure(&acme);
+        assert!(matches!(&err, Error::Connect { .. }));
+        assert_eq!(err.tenant(), Some("acme"));
+        // Equal errors carry the same tenant.
+        assert_eq!(err, failure(&acme));
+        assert_ne!(err, failure(&scanner));
+    }
+
+    #[tokio::test]
+    async fn it_admits_async_streams_like_the_blocking_ones() {
+        let (addr, _server) = fake_clamd(b"stream: OK\0");
+        let quota = TenantQuota::new().max_scans_per_sec(1);
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .quotas(QuotaManager::new(quota))
+            .build();
+        let acme = scanner.for_tenant("acme");
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+
+        let stream = acme.wrap_async(input()).await.unwrap();
+        assert_eq!(stream.progress().tenant().as_deref(), Some("acme"));
+        assert!(matches!(
+            acme.wrap_async(input()).await,
+            Err(Error::QuotaExceeded { tenant, .. }) if tenant == "acme"
+        ));
+
+        // The stream is in flight until its verdict has been read.
+        let report = scanner.shutdown(Duration::from_millis(10)).await;
+        assert_eq!(report.unresolved, 1);
+        assert!(matches!(
+            scanner.wrap_async(input()).await,
+            Err(Error::Shutdown)
+        ));
+        drop(stream);
+    }
+
+    #[test]
+    fn it_returns_futures_which_can_be_spawned() {
+        fn assert_send<T: Send>(_: T) {}
+        fn assert_sync<T: Sync>(_: &T) {}
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let scanner = Scanner::tcp("127.0.0.1:3310").unwrap();
+
+        assert_sync(&scanner);
+        assert_send(scanner.clone());
+        assert_send(scanner.downgrade());
+        assert_send(scanner.wrap_async(input()));
+        assert_send(scanner.wrap_with_priority(input(), Priority::Normal));
+        assert_send(scanner.scan_stream(input()));
+        #[cfg(feature = "spool")]
+        assert_send(scanner.scan_stream_report(input()));
+        #[cfg(feature = "spool")]
+        assert_send(scanner.scan_first(input(), OnDetection::Reject));
+        assert_send(scanner.scan_batch([b"Hello World"]));
+        assert_send(scanner.shutdown(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn it_backs_off_after_failing_to_connect() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let backoff = Backoff::new(Duration::from_secs(60), Duration::from_secs(60)).jitter(false);
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .reconnect_backoff(backoff)
+            .build();
+        assert_eq!(scanner.health(), ScannerHealth::Healthy);
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert!(matches!(
+            scanner.wrap(&mut input),
+            Err(Error::Connect { .. })
+        ));
+        assert!(matches!(
+            scanner.wrap(&mut input),
+            Err(Error::Unavailable { .. })
+        ));
+        assert!(matches!(
+            scanner.health(),
+            ScannerHealth::Degraded { failures: 1, retry_in } if retry_in > Duration::from_secs(59)
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_passes_streams_through_unscanned_while_the_circuit_is_open() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let circuit =
+            CircuitBreaker::new(1, Duration::from_secs(60)).policy(FailurePolicy::FailOpen);
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .circuit_breaker(circuit)
+            .build();
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert!(matches!(
+            scanner.wrap(&mut input),
+            Err(Error::Connect { .. })
+        ));
+        assert!(matches!(scanner.circuit_state(), CircuitState::Open { .. }));
+
+        let stream = scanner.wrap(&mut input).unwrap();
+        assert_eq!(stream.progress().warnings(), vec![Warning::FailOpen]);
+        let (_, outcome) = stream.finish().await;
+        assert_eq!(outcome.unwrap(), ScanOutcome::Skipped);
+    }
+
+    #[tokio::test]
+    async fn it_passes_the_streams_sampled_out_through_unscanned() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .sample_rate(SampleRate::by_hint(|hint| match hint.len {
+                Some(len) if len > 1024 => 0.0,
+                _ => 1.0,
+            }))
+            .build();
+
+        let large = scanner.with_sample_hint(SampleHint {
+            len: Some(4096),
+            content_type: None,
+        });
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let stream = large.wrap(&mut input).unwrap();
+        assert_eq!(stream.progress().warnings(), vec![Warning::SampledOut]);
+        let (_, outcome) = stream.finish().await;
+        assert_eq!(outcome.unwrap(), ScanOutcome::Skipped);
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let stream = scanner.wrap(&mut input).unwrap();
+        let (_, outcome) = stream.finish().await;
+        assert_eq!(outcome.unwrap(), ScanOutcome::Clean);
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_reuses_session_connections_after_a_clean_verdict() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::builder(Address::tcp(listener.local_addr().unwrap()).unwrap())
+            .pool(1)
+            .build();
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = vec![0u8; 11];
+            socket.read_exact(&mut received).unwrap();
+
+            for id in 1..=2 {
+                // zINSTREAM\0, a chunk of 11 bytes and the terminating chunk.
+                let mut request = vec![0u8; 10 + 4 + 11 + 4];
+                socket.read_exact(&mut request).unwrap();
+                received.extend(request);
+                socket
+                    .write_all(format!("{id}: stream: OK\0").as_bytes())
+                    .unwrap();
+            }
+            received
+        });
+
+        for _ in 0..2 {
+            let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+            let mut stream = scanner.wrap(&mut input).unwrap();
+            assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+            assert_eq!(stream.next().await, None);
+            assert_eq!(scanner.idle_connections(), 1);
+        }
+
+        let received = server.join().unwrap();
+        assert!(received.starts_with(b"zIDSESSION\0zINSTREAM\0"));
+    }
+
+    #[tokio::test]
+    async fn it_closes_session_connections_after_a_detection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::builder(Address::tcp(listener.local_addr().unwrap()).unwrap())
+            .pool(1)
+            .build();
+
+        let server = thread::spawn(move || {
+            let mut sessions = 0;
+            for reply in ["1: stream: Eicar-Signature FOUND\0", "1: stream: OK\0"] {
+                let (mut socket, _) = listener.accept().unwrap();
+                // zIDSESSION\0, zINSTREAM\0, a chunk of 11 bytes and the terminating chunk.
+                let mut request = vec![0u8; 11 + 10 + 4 + 11 + 4];
+                socket.read_exact(&mut request).unwrap();
+                assert!(request.starts_with(b"zIDSESSION\0zINSTREAM\0"));
+                socket.write_all(reply.as_bytes()).unwrap();
+                sessions += 1;
+            }
+            sessions
+        });
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let items: Vec<_> = scanner.wrap(&mut input).unwrap().collect().await;
+        assert!(matches!(items.last(), Some(Err(Error::Scan(_)))));
+        assert_eq!(scanner.idle_connections(), 0);
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let items: Vec<_> = scanner.wrap(&mut input).unwrap().collect().await;
+        assert_eq!(items.last(), Some(&Ok(Bytes::from("Hello World"))));
+        assert_eq!(scanner.idle_connections(), 1);
+        assert_eq!(server.join().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_wraps_transports_opened_by_the_caller() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (client, mut server) = tokio::io::duplex(64);
+        let clamd = tokio::spawn(async move {
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&[0, 0, 0, 0]) {
+                let n = server.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            server.write_all(b"VIRUS:Test.Sig\0").await.unwrap();
+            received
+        });
+
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .response_parser(|reply: &[u8]| -> Result<ScanOutcome, Error> {
+                Ok(ScanOutcome::Infected(String::from_utf8_lossy(reply).into()))
+            })
+            .chunk_size(ChunkSize::new(4).unwrap())
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let items: Vec<_> = scanner
+            .wrap_transport(input, client)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(
+            items.last(),
+            Some(&Err(Error::Scan("VIRUS:Test.Sig\0".into())))
+        );
+
+        let received = clamd.await.unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0\0\0\0\x04Hell"));
+        assert_eq!(scanner.response_times().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_admits_transports_opened_by_the_caller_like_async_streams() {
+        let quota = TenantQuota::new().max_scans_per_sec(1);
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .quotas(QuotaManager::new(quota))
+            .build();
+        let acme = scanner.for_tenant("acme");
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let transport = || tokio::io::duplex(64).0;
+
+        let stream = acme.wrap_transport(input(), transport()).await.unwrap();
+        assert_eq!(stream.progress().tenant().as_deref(), Some("acme"));
+        assert!(matches!(
+            acme.wrap_transport(input(), transport()).await,
+            Err(Error::QuotaExceeded { tenant, .. }) if tenant == "acme"
+        ));
+
+        // The stream is in flight until its verdict has been read.
+        let report = scanner.shutdown(Duration::from_millis(10)).await;
+        assert_eq!(report.unresolved, 1);
+        drop(stream);
+
+        let past = Scanner::new(Address::tcp("127.0.0.1:1").unwrap()).with_deadline(Instant::now());
+        assert!(matches!(
+            past.wrap_transport(input(), transport()).await,
+            Err(Error::DeadlineExceeded { bytes_sent: 0, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_opens_connections_with_the_connector() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .connector(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                std::net::TcpStream::connect(addr)
+            })
+            .early_verdict(true)
+            .build();
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let mut stream = scanner.wrap(&mut input).unwrap();
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(server.join().unwrap().starts_with(b"zINSTREAM\0"));
+    }
+
+    #[tokio::test]
+    async fn it_probes_the_half_open_circuit_of_async_streams_asynchronously() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .circuit_breaker(CircuitBreaker::new(1, Duration::ZERO))
+            .async_connector(move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+                tokio::net::TcpStream::connect("127.0.0.1:1")
+            })
+            .build();
+
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert!(matches!(
+            scanner.wrap_async(input()).await,
+            Err(Error::Connect { .. })
+        ));
+        assert_eq!(scanner.circuit_state(), CircuitState::HalfOpen);
+
+        // The PING goes through the async connector as well.
+        assert!(matches!(
+            scanner.wrap_async(input()).await,
+            Err(Error::Unavailable { .. })
+        ));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_opens_async_connections_with_the_async_connector() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .async_connector(move || tokio::net::TcpStream::connect(addr))
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let items: Vec<_> = scanner.wrap_async(input).await.unwrap().collect().await;
+        assert_eq!(items, vec![Ok(Bytes::from("Hello World"))]);
+
+        assert!(server.join().unwrap().starts_with(b"zINSTREAM\0"));
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_skips_the_clamav_for_known_contents() {
+        let (addr, server) = fake_clamd_many(b"stream: Eicar-Signature FOUND\0", 2);
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .hash_lookup(VerdictCache::new(16))
+            .build();
+
+        for _ in 0..2 {
+            let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+            let outcome = scanner.scan_stream(input).await.unwrap();
+            assert_eq!(
+                outcome,
+                ScanOutcome::Infected("stream: Eicar-Signature FOUND\0".into())
+            );
+        }
+
+        // The fake clamav only accepts the `VERSION` and a single `INSTREAM` connection.
+        let received = server.join().unwrap();
+        assert_eq!(received[0], b"zVERSION\0");
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_admits_the_verdicts_of_the_hash_lookup_like_the_scans() {
+        // The database version, then the scan which warms the cache.
+        let (addr, _server) = fake_clamd_many(b"stream: OK\0", 2);
+        let quota = TenantQuota::new().max_scans_per_sec(1);
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .hash_lookup(VerdictCache::new(16))
+            .quotas(QuotaManager::new(quota))
+            .build();
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+
+        let acme = scanner.for_tenant("acme");
+        assert_eq!(acme.scan_stream(input()).await.unwrap(), ScanOutcome::Clean);
+        assert!(matches!(
+            acme.scan_stream(input()).await,
+            Err(Error::QuotaExceeded { tenant, .. }) if tenant == "acme"
+        ));
+
+        // The cache still knows the content, but the scanner accepts no more scans.
+        assert_eq!(
+            scanner.scan_stream(input()).await.unwrap(),
+            ScanOutcome::Clean
+        );
+        scanner.shutdown(Duration::from_millis(10)).await;
+        assert!(matches!(
+            scanner.scan_stream(input()).await,
+            Err(Error::Shutdown)
+        ));
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_spools_the_content_beyond_the_memory_limit_to_a_temp_file() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .spool(SpoolConfig::new(4))
+            .build();
+
+        let input =
+            tokio_stream::iter(["Hello", " World"].map(|chunk| Ok::<_, Error>(Bytes::from(chunk))));
+        let (outcome, report) = scanner.scan_stream_report(input).await.unwrap();
+        assert_eq!(outcome, ScanOutcome::Clean);
+        assert_eq!(report.bytes_scanned, 11);
+        let received = server.join().unwrap();
+        assert!(received.windows(11).any(|window| window == b"Hello World"));
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_reports_the_hash_lookup_disabled_without_the_database_version() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .connector(move || match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(io::ErrorKind::ConnectionRefused.into()),
+                _ => std::net::TcpStream::connect(addr),
+            })
+            .hash_lookup(VerdictCache::new(16))
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let (outcome, report) = scanner.scan_stream_report(input).await.unwrap();
+        assert_eq!(outcome, ScanOutcome::Clean);
+        assert!(matches!(
+            report.warnings.as_slice(),
+            [Warning::LookupDisabled { .. }]
+        ));
+        assert!(server.join().unwrap().starts_with(b"zINSTREAM\0"));
+    }
+
+    #[cfg(feature = "journal")]
+    #[tokio::test]
+    async fn it_records_the_scans_in_the_journal() {
+        use crate::{JsonlJournal, ScanJournal};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        let (addr, _server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .journal(JsonlJournal::open(&path).unwrap())
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert_eq!(
+            scanner.scan_stream(input).await.unwrap(),
+            ScanOutcome::Clean
+        );
+
+        let entries = JsonlJournal::open(&path).unwrap().replay().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].scan.size, 11);
+        assert_eq!(entries[0].scan.outcome, Ok(ScanOutcome::Clean));
+        assert_eq!(
+            entries[0].scan.digest,
+            <[u8; 32]>::from(Sha256::digest(b"Hello World"))
+        );
+    }
+
+    #[cfg(feature = "journal")]
+    #[tokio::test]
+    async fn it_records_the_failed_scans_of_every_stream_in_the_journal() {
+        use crate::{JsonlJournal, ScanJournal};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scans.jsonl");
+        let (addr, _server) = fake_clamd(b"INSTREAM size limit exceeded. ERROR\0");
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .journal(JsonlJournal::open(&path).unwrap())
+            .build();
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let items: Vec<_> = scanner.wrap(&mut input).unwrap().collect().await;
+        let Some(Err(err)) = items.last() else {
+            panic!("the scan should fail");
+        };
+
+        let entries = JsonlJournal::open(&path).unwrap().replay().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].scan.outcome, Err(err.to_string()));
+        assert_eq!(
+            entries[0].scan.digest,
+            <[u8; 32]>::from(Sha256::digest(b"Hello World"))
+        );
+    }
+
+    #[cfg(feature = "spool")]
+    struct Flagged(ReputationVerdict);
+
+    #[cfg(feature = "spool")]
+    impl ReputationProvider for Flagged {
+        fn reputation<'a>(&'a self, _digest: &'a Sha256Digest) -> ReputationFuture<'a> {
+            Box::pin(async move {
+                Some(Reputation {
+                    provider: "Test".into(),
+                    verdict: self.0,
+                    detail: None,
+                })
+            })
+        }
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_merges_the_reputation_into_the_report() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .reputation(
+                Flagged(ReputationVerdict::Suspicious),
+                ReputationPolicy::AfterScan,
+            )
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let (outcome, report) = scanner.scan_stream_report(input).await.unwrap();
+        assert_eq!(outcome, ScanOutcome::Clean);
+        assert_eq!(report.bytes_scanned, 11);
+        assert_eq!(
+            report.reputation.map(|found| found.verdict),
+            Some(ReputationVerdict::Suspicious)
+        );
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_enriches_the_report_with_the_signature_metadata() {
+        let (addr, server) = fake_clamd(b"stream: Win.Ransomware.Locky-9952853-0 FOUND\0");
+        let scanner = Scanner::builder(Address::tcp(addr).unwrap())
+            .signature_metadata(crate::BundledSignatures)
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let (_, report) = scanner.scan_stream_report(input).await.unwrap();
+        let families: Vec<_> = report
+            .signatures
+            .iter()
+            .map(|found| (found.family.as_str(), found.severity))
+            .collect();
+        assert_eq!(families, vec![("Locky", crate::Severity::Critical)]);
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_skips_the_clamav_for_malicious_reputations_before_scan() {
+        // Nothing listens on the address.
+        let scanner = Scanner::builder(Address::tcp("127.0.0.1:1").unwrap())
+            .reputation(
+                Flagged(ReputationVerdict::Malicious),
+                ReputationPolicy::BeforeScan,
+            )
+            .build();
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let outcome = scanner.scan_stream(input).await.unwrap();
+        assert_eq!(
+            outcome.detections()[0].signature,
+            "Reputation.Malicious".to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reports_and_closes_unresolved_scans_after_the_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::tcp(listener.local_addr().unwrap()).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            socket.read_to_end(&mut received).unwrap();
+        });
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let mut stream = scanner.wrap(&mut input).unwrap();
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+
+        let report = scanner.shutdown(Duration::from_millis(10)).await;
+        assert_eq!(report, ShutdownReport { unresolved: 1 });
+
+        server.join().unwrap();
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::Send { bytes_sent: 11, .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_ends_the_stream_with_an_early_verdict() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::builder(Address::tcp(listener.local_addr().unwrap()).unwrap())
+            .early_verdict(true)
+            .build();
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = [0u8; 19];
+            socket.read_exact(&mut received).unwrap();
+            socket
+                .write_all(b"stream: Eicar-Signature FOUND\0")
+                .unwrap();
+        });
+
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello")),
+            Ok(Bytes::from(" World")),
+        ]);
+        let mut stream = scanner.wrap(&mut input).unwrap();
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello"))));
+
+        // The clamav has replied and closed the connection after the first chunk.
+        server.join().unwrap();
+        assert_eq!(
+            stream.next().await,
+            Some(Err(Error::Scan("stream: Eicar-Signature FOUND\0".into())))
+        );
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn it_times_out_waiting_for_the_verdict() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::builder(Address::tcp(listener.local_addr().unwrap()).unwrap())
+            .verdict_timeout(Duration::from_millis(50))
+            .build();
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&[0, 0, 0, 0]) {
+                let n = socket.read(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            // Never reply, as if the clamav were stuck on a complex archive.
+            let _ = done_rx.recv();
+        });
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let result = scanner.scan_stream(input).await;
+        assert!(matches!(
+            result,
+            Err(Error::VerdictTimeout { timeout, .. }) if timeout == Duration::from_millis(50)
+        ));
+
+        drop(done_tx);
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_gives_up_on_the_verdict_at_the_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::builder(Address::tcp(listener.local_addr().unwrap()).unwrap())
+            .verdict_timeout(Duration::from_secs(60))
+            .build();
+        let request = scanner.with_deadline(std::time::Instant::now() + Duration::from_millis(100));
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            let mut buf = [0u8; 64];
+            while !received.ends_with(&[0, 0, 0, 0]) {
+                let n = socket.read(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            let _ = done_rx.recv();
+
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = vec![];
+            while !received.ends_with(&[0, 0, 0, 0]) {
+                let n = socket.read(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(b"stream: OK\0").unwrap();
+        });
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let result = request.scan_stream(input).await;
+        assert_eq!(result, Err(Error::deadline_exceeded(11)));
+
+        // Once the deadline has passed, no connection is opened any more for the request.
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert!(matches!(
+            request.wrap(input),
+            Err(Error::DeadlineExceeded { bytes_sent: 0, .. })
+        ));
+        done_tx.send(()).unwrap();
+
+        // The scanner itself has no deadline, and keeps scanning.
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert_eq!(
+            scanner.scan_stream(input).await.unwrap(),
+            ScanOutcome::Clean
+        );
+
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_times_out_writing_to_a_stalled_clamav() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::builder(Address::tcp(listener.local_addr().unwrap()).unwrap())
+            .write_timeout(Duration::from_millis(50))
+            .build();
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            // Accept the connection without ever reading from it.
+            let (_socket, _) = listener.accept().unwrap();
+            let _ = done_rx.recv();
+        });
+
+        let chunks = (0..64).map(|_| Ok::<_, Error>(Bytes::from(vec![0u8; 1 << 20])));
+        let result = scanner.scan_stream(tokio_stream::iter(chunks)).await;
+        assert!(matches!(
+            result,
+            Err(Error::WriteTimeout { bytes_sent, .. }) if bytes_sent > 0
+        ));
+
+        drop(done_tx);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn it_accepts_a_sane_configuration() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let builder = Scanner::builder(Address::tcp(addr).unwrap())
+            .reconnect_backoff(Backoff::default())
+            .circuit_breaker(CircuitBreaker::new(3, Duration::from_secs(10)))
+            .pool(4);
+        assert_eq!(builder.validate(), Ok(()));
+        assert!(builder.try_build().is_ok());
+    }
+
+    #[cfg(feature = "spool")]
+    #[test]
+    fn it_lists_every_issue_of_the_configuration() {
+        let builder = Scanner::builder(Address::Tcp(vec![]))
+            .reconnect_backoff(Backoff::new(Duration::from_secs(5), Duration::from_secs(1)))
+            .circuit_breaker(CircuitBreaker::new(0, Duration::from_secs(10)))
+            .spool(SpoolConfig::default().dir("/nonexistent/spool"))
+            .chunk_size(ChunkSize::new(STREAM_MAX_LENGTH + 1).unwrap())
+            .pool(0);
+
+        let err = builder.try_build().unwrap_err();
+        assert_eq!(
+            err.issues(),
+            [
+                ConfigIssue::NoTcpAddress,
+                ConfigIssue::ChunkSizeTooLarge {
+                    size: STREAM_MAX_LENGTH + 1,
+                    max: STREAM_MAX_LENGTH,
+                },
+                ConfigIssue::DirNotFound {
+                    purpose: "spool",
+                    dir: "/nonexistent/spool".into(),
+                },
+                ConfigIssue::BackoffInitialExceedsMax {
+                    initial: Duration::from_secs(5),
+                    max: Duration::from_secs(1),
+                },
+                ConfigIssue::ZeroCircuitThreshold,
+                ConfigIssue::ZeroPoolSize,
+            ]
+        );
+        assert!(err.to_string().starts_with(
+            "invalid scanner configuration: the tcp address resolved to no socket address; "
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{ScanOutcome, ScanReport};
+
+use std::{
+    pin::pin,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Notify;
+
+/// Groups the scans of the streams making up a single request, e.g. the files of a multi-file
+/// upload, into a single verdict: the request is clean only if every member scan is clean.
+///
+/// Register the streams with [`ScannedStream::with_scope`], consume them, then read the
+/// combined [`ScopeReport`] from [`ScanScope::resolved`].
+///
+/// [`ScannedStream::with_scope`]: crate::ScannedStream::with_scope
+#[derive(Debug, Clone, Default)]
+pub struct ScanScope {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    members: Mutex<Vec<MemberReport>>,
+    resolved: Notify,
+}
+
+impl ScanScope {
+    /// Create a scope without members.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of scans registered so far.
+    pub fn len(&self) -> usize {
+        self.shared.members.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no scan has been registered yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The combined report of the scans registered so far, resolved or not.
+    pub fn report(&self) -> ScopeReport {
+        ScopeReport {
+            members: self.shared.members.lock().unwrap().clone(),
+        }
+    }
+
+    /// Wait until every scan registered so far has resolved, and return the combined report.
+    /// Register every member before waiting, since the scope cannot know about the others.
+    pub async fn resolved(&self) -> ScopeReport {
+        loop {
+            let mut notified = pin!(self.shared.resolved.notified());
+            notified.as_mut().enable();
+
+            let report = self.report();
+            if report.is_resolved() {
+                return report;
+            }
+            notified.await;
+        }
+    }
+
+    pub(crate) fn join(&self, label: String) -> ScopeMember {
+        let mut members = self.shared.members.lock().unwrap();
+        members.push(MemberReport {
+            label,
+            verdict: MemberVerdict::Pending,
+            report: None,
+        });
+        ScopeMember {
+            shared: Arc::clone(&self.shared),
+            index: members.len() - 1,
+            resolved: false,
+        }
+    }
+}
+
+/// The combined report of the scans of a [`ScanScope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeReport {
+    /// The members, in the order they were registered.
+    pub members: Vec<MemberReport>,
+}
+
+impl ScopeReport {
+    /// Returns `true` if every member has resolved and none is infected or has failed. A scope
+    /// without members is clean.
+    pub fn is_clean(&self) -> bool {
+        self.members.iter().all(|member| member.verdict.is_clean())
+    }
+
+    /// Returns `true` if no member is pending any more.
+    pub fn is_resolved(&self) -> bool {
+        self.members
+            .iter()
+            .all(|member| member.verdict != MemberVerdict::Pending)
+    }
+
+    /// The labels of the infected members, in order.
+    pub fn infected(&self) -> Vec<&str> {
+        self.labels(|verdict| matches!(verdict, MemberVerdict::Scanned(ScanOutcome::Infected(_))))
+    }
+
+    /// The labels of the members whose scan failed, in order.
+    pub fn failed(&self) -> Vec<&str> {
+        self.labels(|verdict| matches!(verdict, MemberVerdict::Failed(_)))
+    }
+
+    fn labels(&self, filter: impl Fn(&MemberVerdict) -> bool) -> Vec<&str> {
+        self.members
+            .iter()
+            .filter(|member| filter(&member.verdict))
+            .map(|member| member.label.as_str())
+            .collect()
+    }
+}
+
+/// The scan of a member of a [`ScanScope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberReport {
+    /// The label given on registration, e.g. the file name of an uploaded part.
+    pub label: String,
+
+    /// What became of the scan so far.
+    pub verdict: MemberVerdict,
+
+    /// The summary of the scan, once it has resolved.
+    pub report: Option<ScanReport>,
+}
+
+/// What became of the scan of a member of a [`ScanScope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberVerdict {
+    /// The stream has not been consumed to its verdict yet.
+    Pending,
+
+    /// The verdict of the clamav.
+    Scanned(ScanOutcome),
+
+    /// The scan failed with the error, or the stream was dropped before its verdict.
+    Failed(String),
+}
+
+impl MemberVerdict {
+    fn is_clean(&self) -> bool {
+        matches!(
+            self,
+            Self::Scanned(ScanOutcome::Clean) | Self::Scanned(ScanOutcome::Skipped)
+        )
+    }
+}
+
+/// The place of a scan in a [`ScanScope`], which the scan resolves once it is over. A member
+/// dropped unresolved fails.
+#[derive(Debug)]
+pub(crate) struct ScopeMember {
+    shared: Arc<Shared>,
+    index: usize,
+    resolved: bool,
+}
+
+impl ScopeMember {
+    pub(crate) fn resolve(mut self, verdict: MemberVerdict, report: Option<ScanReport>) {
+        self.set(verdict, report);
+        self.resolved = true;
+    }
+
+    fn set(&self, verdict: MemberVerdict, report: Option<ScanReport>) {
+        let mut members = self.shared.members.lock().unwrap();
+        let member = &mut members[self.index];
+        member.verdict = verdict;
+        member.report = report;
+
+        if members
+            .iter()
+            .all(|member| member.verdict != MemberVerdict::Pending)
+        {
+            self.shared.resolved.notify_waiters();
+        }
+    }
+}
+
+impl Drop for ScopeMember {
+    fn drop(&mut self) {
+        if self.resolved {
+            return;
+        }
+        let verdict = MemberVerdict::Failed("dropped before the verdict".into());
+        self.set(verdict, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeTransport, Error, ScannedStream};
+    use bytes::Bytes;
+    use tokio_stream::StreamExt;
+
+    fn member(
+        scope: &ScanScope,
+        label: &str,
+        reply: &'static str,
+    ) -> impl tokio_stream::Stream<Item = Result<Bytes, Error>> {
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        ScannedStream::new(input, FakeTransport::new(reply)).with_scope(scope, label)
+    }
+
+    #[tokio::test]
+    async fn it_is_clean_only_if_every_member_is_clean() {
+        let scope = ScanScope::new();
+        let clean = member(&scope, "a.txt", "stream: OK\0");
+        let infected = member(&scope, "b.txt", "stream: Eicar-Signature FOUND\0");
+        assert_eq!(scope.len(), 2);
+
+        assert!(clean.collect::<Result<Vec<_>, _>>().await.is_ok());
+        assert!(infected.collect::<Result<Vec<_>, _>>().await.is_err());
+
+        let report = scope.resolved().await;
+        assert!(!report.is_clean());
+        assert_eq!(report.infected(), vec!["b.txt"]);
+        assert_eq!(
+            report.members[0].verdict,
+            MemberVerdict::Scanned(ScanOutcome::Clean)
+        );
+        assert_eq!(report.members[0].report.as_ref().unwrap().bytes_scanned, 11);
+    }
+
+    #[tokio::test]
+    async fn it_resolves_once_every_member_has_resolved() {
+        let scope = ScanScope::new();
+        let first = member(&scope, "a.txt", "stream: OK\0");
+        let second = member(&scope, "b.txt", "stream: OK\0");
+
+        let waiting = tokio::spawn({
+            let scope = scope.clone();
+            async move { scope.resolved().await }
+        });
+        assert!(first.collect::<Result<Vec<_>, _>>().await.is_ok());
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        // A member dropped before its verdict fails the scope.
+        drop(second);
+        let report = waiting.await.unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(report.failed(), vec!["b.txt"]);
+    }
+}
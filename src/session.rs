@@ -0,0 +1,356 @@
+use crate::{
+    connection::{Address, Connection},
+    protocol::{chunk_header, split_request_id, Command, CHUNK_SIZE, END_OF_STREAM},
+    response::{ClamdParser, ResponseParser, ScanOutcome},
+    Error, Phase,
+};
+
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+use tokio::sync::oneshot;
+
+type Reply = Result<ScanOutcome, Error>;
+
+/// Multiplexes `INSTREAM` scans from concurrent tasks over a single `IDSESSION` connection.
+///
+/// The commands are queued on the connection in the order they are submitted, and the numbered
+/// replies are dispatched back to the tasks waiting for them, in whatever order the clamav sends
+/// them. Cloning a [`SessionMux`] shares the same connection, which is ended when the last clone
+/// is dropped.
+///
+/// The commands are written by a thread of the session, like the replies are read, so that
+/// the tasks submitting them are not blocked by the clamav.
+#[derive(Clone)]
+pub struct SessionMux {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    requests: mpsc::Sender<(u64, Bytes)>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+#[derive(Default)]
+struct Pending {
+    next_id: u64,
+    closed: bool,
+    senders: HashMap<u64, oneshot::Sender<Reply>>,
+}
+
+impl SessionMux {
+    /// Open a new connection to the address and start a session on it.
+    pub fn connect(address: &Address) -> Result<Self, Error> {
+        Self::new(address.connect()?)
+    }
+
+    /// Start a session on the connection.
+    pub fn new(conn: Connection) -> Result<Self, Error> {
+        Self::with_response_parser(conn, ClamdParser)
+    }
+
+    /// Start a session on the connection, mapping each reply with the given parser.
+    pub fn with_response_parser(
+        mut conn: Connection,
+        parser: impl ResponseParser + 'static,
+    ) -> Result<Self, Error> {
+        let reader = conn.try_clone()?;
+        conn.write_all(Command::IdSession.as_bytes())?;
+
+        let pending = Arc::new(Mutex::new(Pending {
+            next_id: 1,
+            ..Pending::default()
+        }));
+        let dispatcher = Dispatcher {
+            pending: Arc::clone(&pending),
+            parser: Box::new(parser),
+        };
+        thread::Builder::new()
+            .name("clamav-session".into())
+            .spawn(move || dispatcher.run(reader))?;
+
+        let (requests, queued) = mpsc::channel();
+        let writer = Writer {
+            pending: Arc::clone(&pending),
+        };
+        thread::Builder::new()
+            .name("clamav-session-writer".into())
+            .spawn(move || writer.run(conn, queued))?;
+
+        Ok(Self {
+            inner: Arc::new(Inner { requests, pending }),
+        })
+    }
+
+    /// Scan the content over the session and wait for its verdict.
+    pub async fn scan(&self, content: impl AsRef<[u8]>) -> Result<ScanOutcome, Error> {
+        let rx = self.submit(content.as_ref())?;
+        rx.await.unwrap_or_else(|_| Err(session_closed().into()))
+    }
+
+    /// Scan every content over the session and wait for their verdicts, in the order of the
+    /// contents.
+    ///
+    /// All the contents are queued on the connection before the first verdict is awaited, so
+    /// the clamav scans them back to back instead of waiting for a round trip per content. A
+    /// content which cannot be queued, e.g. after the clamav has closed the session, gets the
+    /// error in place of its verdict.
+    pub async fn scan_batch<I>(&self, contents: I) -> Vec<Result<ScanOutcome, Error>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let submitted: Vec<_> = contents
+            .into_iter()
+            .map(|content| self.submit(content.as_ref()))
+            .collect();
+
+        let mut outcomes = Vec::with_capacity(submitted.len());
+        for rx in submitted {
+            outcomes.push(match rx {
+                Ok(rx) => rx.await.unwrap_or_else(|_| Err(session_closed().into())),
+                Err(err) => Err(err),
+            });
+        }
+        outcomes
+    }
+
+    /// Queue the content for the writer. The ids are numbered in the order the requests are
+    /// queued, which is the order the clamav numbers them in.
+    fn submit(&self, content: &[u8]) -> Result<oneshot::Receiver<Reply>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let mut pending = self.inner.pending.lock().unwrap();
+        if pending.closed {
+            return Err(session_closed().into());
+        }
+        let id = pending.next_id;
+        let request = (id, Bytes::copy_from_slice(content));
+        if self.inner.requests.send(request).is_err() {
+            return Err(session_closed().into());
+        }
+        pending.next_id += 1;
+        pending.senders.insert(id, tx);
+
+        Ok(rx)
+    }
+}
+
+impl fmt::Debug for SessionMux {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pending = self.inner.pending.lock().unwrap();
+        f.debug_struct("SessionMux")
+            .field("pending", &pending.senders.len())
+            .field("closed", &pending.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes the requests queued by the tasks on the session connection, and ends the session
+/// once the last clone of the [`SessionMux`] has been dropped.
+struct Writer {
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl Writer {
+    fn run(self, mut conn: Connection, requests: mpsc::Receiver<(u64, Bytes)>) {
+        for (id, content) in requests {
+            if let Err(err) = write_instream(&mut conn, &content) {
+                if let Some(tx) = self.pending.lock().unwrap().senders.remove(&id) {
+                    let _ = tx.send(Err(err));
+                }
+            }
+        }
+
+        let _ = conn.write_all(Command::End.as_bytes());
+        // The dispatcher holds a clone of the socket, so dropping this one doesn't close it.
+        let _ = conn.shutdown();
+    }
+}
+
+/// Reads the replies on the session connection and dispatches them to the waiting tasks.
+struct Dispatcher {
+    pending: Arc<Mutex<Pending>>,
+    parser: Box<dyn ResponseParser>,
+}
+
+impl Dispatcher {
+    fn run(self, conn: Connection) {
+        let mut reader = BufReader::new(conn);
+        let mut reply = vec![];
+
+        loop {
+            reply.clear();
+            match reader.read_until(b'\0', &mut reply) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => self.dispatch(&reply),
+            }
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.closed = true;
+        for (_, tx) in pending.senders.drain() {
+            let _ = tx.send(Err(session_closed().into()));
+        }
+    }
+
+    fn dispatch(&self, reply: &[u8]) {
+        let Some((id, body)) = split_request_id(reply) else {
+            return;
+        };
+
+        if let Some(tx) = self.pending.lock().unwrap().senders.remove(&id) {
+            let _ = tx.send(self.parser.parse(body));
+        }
+    }
+}
+
+fn write_instream(conn: &mut impl Write, content: &[u8]) -> Result<(), Error> {
+    let mut bytes_sent = 0;
+
+    conn.write_all(Command::Instream.as_bytes())
+        .map_err(|err| Error::send(err, bytes_sent, Phase::Start))?;
+
+    for chunk in content.chunks(CHUNK_SIZE) {
+        conn.write_all(&chunk_header(chunk.len() as u32))
+            .and_then(|_| conn.write_all(chunk))
+            .map_err(|err| Error::send(err, bytes_sent, Phase::Chunk))?;
+        bytes_sent += chunk.len() as u64;
+    }
+
+    conn.write_all(&END_OF_STREAM)
+        .map_err(|err| Error::send(err, bytes_sent, Phase::Finish))
+}
+
+fn session_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "clamav session closed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, net::TcpListener, time::Duration};
+
+    #[tokio::test]
+    async fn it_demultiplexes_replies_of_concurrent_scans() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = Address::tcp(listener.local_addr().unwrap()).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            // zIDSESSION\0 followed by two INSTREAMs of a single byte each.
+            let mut received = vec![0u8; 11 + 2 * 19];
+            socket.read_exact(&mut received).unwrap();
+
+            socket.write_all(b"2: stream: OK\0").unwrap();
+            socket
+                .write_all(b"1: stream: Eicar-Signature FOUND\0")
+                .unwrap();
+
+            let mut end = vec![];
+            socket.read_to_end(&mut end).unwrap();
+            (received, end)
+        });
+
+        let mux = SessionMux::connect(&address).unwrap();
+        let other = mux.clone();
+        let (first, second) = tokio::join!(mux.scan("a"), other.scan("b"));
+
+        assert_eq!(
+            first.unwrap(),
+            ScanOutcome::Infected("stream: Eicar-Signature FOUND\0".into())
+        );
+        assert_eq!(second.unwrap(), ScanOutcome::Clean);
+
+        drop(mux);
+        drop(other);
+
+        let (received, end) = server.join().unwrap();
+        assert!(received.starts_with(b"zIDSESSION\0zINSTREAM\0"));
+        assert_eq!(end, b"zEND\0");
+    }
+
+    #[tokio::test]
+    async fn it_pipelines_a_batch_of_scans() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = Address::tcp(listener.local_addr().unwrap()).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            // Every INSTREAM is queued before the first reply is sent.
+            let mut received = vec![0u8; 11 + 19 + 20 + 14];
+            socket.read_exact(&mut received).unwrap();
+
+            socket.write_all(b"3: stream: OK\0").unwrap();
+            socket
+                .write_all(b"2: stream: Eicar-Signature FOUND\0")
+                .unwrap();
+            socket.write_all(b"1: stream: OK\0").unwrap();
+
+            let mut end = vec![];
+            socket.read_to_end(&mut end).unwrap();
+            end
+        });
+
+        let mux = SessionMux::connect(&address).unwrap();
+        let outcomes = mux.scan_batch(["a", "bb", ""]).await;
+        assert_eq!(
+            outcomes,
+            vec![
+                Ok(ScanOutcome::Clean),
+                Ok(ScanOutcome::Infected(
+                    "stream: Eicar-Signature FOUND\0".into()
+                )),
+                Ok(ScanOutcome::Clean),
+            ]
+        );
+
+        drop(mux);
+        assert_eq!(server.join().unwrap(), b"zEND\0");
+    }
+
+    #[tokio::test]
+    async fn it_does_not_block_the_task_while_the_clamav_is_not_reading() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = Address::tcp(listener.local_addr().unwrap()).unwrap();
+
+        let (release, released) = mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let _ = released.recv();
+            drop(socket);
+        });
+
+        let mux = SessionMux::connect(&address).unwrap();
+        let content = vec![0u8; 16 * 1024 * 1024];
+        let scanned = tokio::time::timeout(Duration::from_millis(100), mux.scan(&content)).await;
+        assert!(scanned.is_err());
+
+        release.send(()).unwrap();
+        server.join().unwrap();
+        assert!(mux.scan("a").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_fails_pending_scans_when_the_session_is_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = Address::tcp(listener.local_addr().unwrap()).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = vec![0u8; 11 + 19];
+            socket.read_exact(&mut received).unwrap();
+        });
+
+        let mux = SessionMux::connect(&address).unwrap();
+        let result = mux.scan("a").await;
+        assert!(matches!(result, Err(Error::Io(_))));
+
+        server.join().unwrap();
+        assert!(mux.scan("b").await.is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{CircuitState, ConfigError, ConfigIssue, Scanner, ScannerHealth};
+
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+
+/// Routes scans across several clamav servers, so that the same key always goes to the same
+/// server as long as it is available.
+///
+/// A server is unavailable while its scanner backs off from reconnecting, see
+/// [`Scanner::health`], or while its circuit breaker is open, see [`Scanner::circuit_state`].
+/// Its keys then go to the next server in their order, and come back once it recovers.
+///
+/// Routing by the content hash lets identical contents hit the same server and its cache, and
+/// keeps per-server verdict caches coherent. The routing is consistent: adding or removing a
+/// server only moves the keys routed to that server.
+///
+/// The weight of a server for a key is the SHA-256 digest of the key and of the address of the
+/// server, so that every instance of a service routes a key to the same server.
+#[derive(Debug, Clone)]
+pub struct ShardedScanner {
+    shards: Vec<Scanner>,
+}
+
+impl ShardedScanner {
+    /// Route across the given scanners. Fails with [`ConfigIssue::NoShards`] if there is none.
+    pub fn new(shards: Vec<Scanner>) -> Result<Self, ConfigError> {
+        if shards.is_empty() {
+            ConfigError::from_issues(vec![ConfigIssue::NoShards])?;
+        }
+        Ok(Self { shards })
+    }
+
+    /// The scanners routed across.
+    pub fn shards(&self) -> &[Scanner] {
+        &self.shards
+    }
+
+    /// The scanner for a caller supplied key, such as an object id. If no server is available,
+    /// the scanner the key goes to when they all are.
+    pub fn route(&self, key: impl AsRef<[u8]>) -> &Scanner {
+        let key = key.as_ref();
+        // Rendezvous hashing: the shard with the highest weight for the key wins.
+        let mut ranked: Vec<_> = self.shards.iter().collect();
+        ranked.sort_by_key(|scanner| Reverse(weight(key, &scanner.address().to_string())));
+        ranked
+            .iter()
+            .find(|scanner| is_available(scanner))
+            .unwrap_or(&ranked[0])
+    }
+
+    /// The scanner for the SHA-256 digest of the content.
+    pub fn route_content(&self, content: &[u8]) -> &Scanner {
+        self.route(Sha256::digest(content))
+    }
+}
+
+fn is_available(scanner: &Scanner) -> bool {
+    let backing_off = matches!(
+        scanner.health(),
+        ScannerHealth::Degraded { retry_in, .. } if !retry_in.is_zero()
+    );
+    !backing_off && !matches!(scanner.circuit_state(), CircuitState::Open { .. })
+}
+
+/// The first 8 bytes of the SHA-256 digest of the key, prefixed with its length, and of the
+/// address.
+fn weight(key: &[u8], address: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update((key.len() as u64).to_le_bytes())
+        .chain_update(key)
+        .chain_update(address)
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("a digest is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connection::Address, Backoff, Error};
+    use bytes::Bytes;
+    use std::{net::TcpListener, time::Duration};
+
+    fn scanners(ports: impl IntoIterator<Item = u16>) -> Vec<Scanner> {
+        ports
+            .into_iter()
+            .map(|port| Scanner::tcp(("127.0.0.1", port)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_routes_the_same_key_to_the_same_scanner() {
+        let sharded = ShardedScanner::new(scanners(3310..3314)).unwrap();
+
+        let first = sharded.route("object-1").address();
+        assert_eq!(sharded.route("object-1").address(), first);
+        assert_eq!(
+            sharded.route_content(b"Hello").address(),
+            sharded.route_content(b"Hello").address()
+        );
+    }
+
+    #[test]
+    fn it_only_moves_the_keys_of_a_removed_scanner() {
+        let all = ShardedScanner::new(scanners(3310..3314)).unwrap();
+        let fewer = ShardedScanner::new(scanners(3310..3313)).unwrap();
+        let removed = all.shards()[3].address();
+
+        for key in 0..100 {
+            let key = key.to_string();
+            let before = all.route(&key).address();
+            if before != removed {
+                assert_eq!(fewer.route(&key).address(), before);
+            }
+        }
+    }
+
+    #[test]
+    fn it_weighs_the_keys_the_same_way_across_processes() {
+        assert_eq!(
+            weight(b"object-1", "127.0.0.1:3310"),
+            weight(b"object-1", "127.0.0.1:3310")
+        );
+        // The length prefix keeps the key and the address apart.
+        assert_ne!(weight(b"a", "bc"), weight(b"ab", "c"));
+        // The first 8 bytes of the SHA-256 digest of `8u64.to_le_bytes()`, the key and the
+        // address.
+        assert_eq!(weight(b"object-1", "127.0.0.1:3310"), 0x7e8d_9b5b_7a6c_0484);
+    }
+
+    #[test]
+    fn it_refuses_to_route_across_no_scanner() {
+        let err = ShardedScanner::new(vec![]).unwrap_err();
+        assert_eq!(err.issues(), [ConfigIssue::NoShards]);
+    }
+
+    #[tokio::test]
+    async fn it_routes_the_keys_of_an_unavailable_scanner_to_the_next_one() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let backoff = Backoff::new(Duration::from_secs(60), Duration::from_secs(60));
+        let down = Scanner::builder(Address::tcp(addr).unwrap())
+            .reconnect_backoff(backoff)
+            .build();
+        let mut shards = scanners(3310..3313);
+        shards.push(down.clone());
+        let sharded = ShardedScanner::new(shards).unwrap();
+
+        let keys: Vec<_> = (0..100)
+            .map(|key: u32| key.to_string())
+            .filter(|key| sharded.route(key).address() == down.address())
+            .collect();
+        assert!(!keys.is_empty());
+
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        assert!(down.wrap(&mut input).is_err());
+        for key in keys {
+            assert_ne!(sharded.route(key).address(), down.address());
+        }
+    }
+}
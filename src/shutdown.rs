@@ -0,0 +1,119 @@
+use crate::connection::Connection;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+#[cfg(feature = "tokio")]
+use tokio::sync::Notify;
+
+/// The result of [`Scanner::shutdown`](crate::Scanner::shutdown).
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The number of scans which had not received their verdicts before the deadline.
+    /// Their connections are closed, so those streams return an error instead of a verdict.
+    pub unresolved: usize,
+}
+
+#[cfg(feature = "tokio")]
+impl ShutdownReport {
+    /// Returns `true` if every in-flight scan received its verdict before the deadline.
+    pub fn is_drained(&self) -> bool {
+        self.unresolved == 0
+    }
+}
+
+/// Keeps track of the scans in flight for a [`Scanner`](crate::Scanner).
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    closed: AtomicBool,
+    scans: Mutex<Scans>,
+    #[cfg(feature = "tokio")]
+    notify: Notify,
+}
+
+#[derive(Debug, Default)]
+struct Scans {
+    next_id: u64,
+    sockets: HashMap<u64, Option<Connection>>,
+}
+
+impl Tracker {
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Register a scan using the connection. The scan is in flight until the returned guard is
+    /// dropped.
+    pub(crate) fn register(self: &Arc<Self>, conn: &Connection) -> InFlight {
+        self.track(conn.try_clone().ok())
+    }
+
+    /// Register a scan whose connection is closed through the given socket, if any, on
+    /// [`Tracker::abort`].
+    pub(crate) fn track(self: &Arc<Self>, socket: Option<Connection>) -> InFlight {
+        let mut scans = self.scans.lock().unwrap();
+        let id = scans.next_id;
+        scans.next_id += 1;
+        scans.sockets.insert(id, socket);
+
+        InFlight {
+            id,
+            tracker: Arc::clone(self),
+        }
+    }
+
+    /// Wait until no scans are in flight.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn drained(&self) {
+        loop {
+            let notified = self.notify.notified();
+
+            if self.scans.lock().unwrap().sockets.is_empty() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Close the connections of every scan in flight and return how many there were.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn abort(&self) -> usize {
+        let scans = self.scans.lock().unwrap();
+
+        for conn in scans.sockets.values().flatten() {
+            let _ = conn.shutdown();
+        }
+
+        scans.sockets.len()
+    }
+
+    fn release(&self, id: u64) {
+        self.scans.lock().unwrap().sockets.remove(&id);
+        #[cfg(feature = "tokio")]
+        self.notify.notify_waiters();
+    }
+}
+
+/// A guard marking a scan as in flight.
+#[derive(Debug)]
+pub(crate) struct InFlight {
+    id: u64,
+    tracker: Arc<Tracker>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.tracker.release(self.id);
+    }
+}
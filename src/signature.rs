@@ -0,0 +1,145 @@
+/// How harmful a detected signature is, see [`SignatureMetadata::severity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// E.g. a test signature, an adware or a packer.
+    Low,
+    /// E.g. a phishing content, a coin miner or a heuristic detection.
+    Medium,
+    /// E.g. a trojan, a worm or an exploit.
+    High,
+    /// E.g. a ransomware or a backdoor.
+    Critical,
+}
+
+/// What is known about a signature found by the clamav, e.g. to group detections on a
+/// dashboard, see [`ScanReport::signatures`](crate::ScanReport::signatures).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignatureMetadata {
+    /// The name of the signature, e.g. `Win.Trojan.Agent-123`.
+    pub signature: String,
+    /// The malware family, e.g. `Agent`.
+    pub family: String,
+    /// The platform targeted, e.g. `Win`, if the signature names one.
+    pub platform: Option<String>,
+    /// How harmful the detection is.
+    pub severity: Severity,
+}
+
+/// A source of [`SignatureMetadata`] by signature name, e.g. a threat intelligence database.
+///
+/// See [`ScannerBuilder::signature_metadata`](crate::ScannerBuilder::signature_metadata), and
+/// [`BundledSignatures`] for an offline table.
+pub trait SignatureMetadataProvider: Send + Sync {
+    /// The metadata of the signature, if known.
+    fn metadata(&self, signature: &str) -> Option<SignatureMetadata>;
+}
+
+/// The signature types of the clamav naming convention and their severity.
+const TYPES: &[(&str, Severity)] = &[
+    ("Adware", Severity::Low),
+    ("Backdoor", Severity::Critical),
+    ("Coinminer", Severity::Medium),
+    ("Downloader", Severity::High),
+    ("Dropper", Severity::High),
+    ("Exploit", Severity::High),
+    ("Keylogger", Severity::High),
+    ("Malware", Severity::High),
+    ("Packed", Severity::Low),
+    ("Packer", Severity::Low),
+    ("Phishing", Severity::Medium),
+    ("Ransomware", Severity::Critical),
+    ("Rootkit", Severity::Critical),
+    ("Spyware", Severity::High),
+    ("Test", Severity::Low),
+    ("Tool", Severity::Low),
+    ("Trojan", Severity::High),
+    ("Virus", Severity::High),
+    ("Worm", Severity::High),
+];
+
+/// An offline [`SignatureMetadataProvider`] reading the metadata from the name of the
+/// signature, following the clamav naming convention `{platform}.{type}.{family}-{id}` with
+/// the `PUA.` and `Heuristics.` prefixes, e.g. `Win.Ransomware.Locky-1` is a critical
+/// detection of the `Locky` family on `Win`.
+///
+/// Signatures whose type is not a common one, or which do not follow the convention, are
+/// unknown, except for the EICAR test signatures.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BundledSignatures;
+
+impl SignatureMetadataProvider for BundledSignatures {
+    fn metadata(&self, signature: &str) -> Option<SignatureMetadata> {
+        let metadata = |family: &str, platform: Option<&str>, severity| SignatureMetadata {
+            signature: signature.to_string(),
+            family: family.to_string(),
+            platform: platform.map(String::from),
+            severity,
+        };
+
+        if signature.eq_ignore_ascii_case("Eicar-Signature") {
+            return Some(metadata("EICAR", None, Severity::Low));
+        }
+        if let Some(rest) = signature.strip_prefix("Heuristics.") {
+            return Some(metadata(rest, None, Severity::Medium));
+        }
+
+        let (parts, pua) = match signature.strip_prefix("PUA.") {
+            Some(rest) => (rest, true),
+            None => (signature, false),
+        };
+        let mut parts = parts.splitn(3, '.');
+        let (platform, kind, name) = (parts.next()?, parts.next()?, parts.next()?);
+        let severity = if pua {
+            Severity::Low
+        } else {
+            TYPES
+                .iter()
+                .find(|(known, _)| *known == kind)
+                .map(|(_, severity)| *severity)?
+        };
+
+        // The family is the name without its numeric ids, e.g. `Locky` of `Locky-9952853-0`,
+        // and without the suffix of the database, e.g. `EICAR` of `EICAR_HDB-1`.
+        let mut family = name;
+        while let Some((rest, id)) = family.rsplit_once('-') {
+            if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+                break;
+            }
+            family = rest;
+        }
+        let family = family.split('_').next().unwrap_or(family);
+        Some(metadata(family, Some(platform), severity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_the_metadata_from_the_signature_name() {
+        let bundled = BundledSignatures;
+
+        let locky = bundled.metadata("Win.Ransomware.Locky-9952853-0").unwrap();
+        assert_eq!(
+            locky,
+            SignatureMetadata {
+                signature: "Win.Ransomware.Locky-9952853-0".into(),
+                family: "Locky".into(),
+                platform: Some("Win".into()),
+                severity: Severity::Critical,
+            }
+        );
+
+        let eicar = bundled.metadata("Win.Test.EICAR_HDB-1").unwrap();
+        assert_eq!(eicar.family, "EICAR");
+        assert_eq!(eicar.severity, Severity::Low);
+
+        let upx = bundled.metadata("PUA.Win.Packer.Upx-1").unwrap();
+        assert_eq!(upx.platform.as_deref(), Some("Win"));
+        assert_eq!(upx.severity, Severity::Low);
+
+        assert_eq!(bundled.metadata("Custom-Signature"), None);
+        assert_eq!(bundled.metadata("Win.Unknown.Thing-1"), None);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::memory::MemoryCharge;
+use crate::{memory::MemoryBudget, ConfigIssue};
+
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// The default number of bytes a [`Spool`] keeps in memory before moving to a temp file.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1024 * 1024;
+
+/// Configuration of a [`Spool`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpoolConfig {
+    memory_limit: usize,
+    dir: Option<PathBuf>,
+}
+
+impl SpoolConfig {
+    /// Keep up to `memory_limit` bytes in memory, then move the content to a temp file.
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            memory_limit,
+            dir: None,
+        }
+    }
+
+    /// Create the temp file in the given directory instead of [`std::env::temp_dir`].
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// The number of bytes kept in memory before moving to a temp file.
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub(crate) fn validate(&self, issues: &mut Vec<ConfigIssue>) {
+        if let Some(dir) = self.dir.as_ref().filter(|dir| !dir.is_dir()) {
+            issues.push(ConfigIssue::DirNotFound {
+                purpose: "spool",
+                dir: dir.clone(),
+            });
+        }
+    }
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+/// A copy of the streamed content, kept in memory up to a limit and in a temp file beyond it,
+/// so that the content can be replayed after it has been passed through.
+///
+/// The temp file is removed when the [`Spool`] is dropped.
+#[derive(Debug)]
+pub struct Spool {
+    config: SpoolConfig,
+    memory: Vec<u8>,
+    file: Option<File>,
+    len: u64,
+    charge: MemoryCharge,
+}
+
+impl Spool {
+    /// Create an empty [`Spool`].
+    pub fn new(config: SpoolConfig) -> Self {
+        Self {
+            config,
+            memory: vec![],
+            file: None,
+            len: 0,
+            charge: MemoryCharge::default(),
+        }
+    }
+
+    /// Count the bytes kept in memory against the budget, moving to the temp file once it is
+    /// exhausted. Only set before anything is written.
+    pub(crate) fn set_budget(&mut self, budget: MemoryBudget) {
+        self.charge = MemoryCharge::new(Some(budget));
+    }
+
+    /// Append bytes to the content.
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let over_limit = self.memory.len() + bytes.len() > self.config.memory_limit;
+        if self.file.is_none() && (over_limit || !self.charge.grow(bytes.len())) {
+            let mut file = match &self.config.dir {
+                Some(dir) => tempfile::tempfile_in(dir)?,
+                None => tempfile::tempfile()?,
+            };
+            file.write_all(&self.memory)?;
+            self.memory = vec![];
+            self.charge.clear();
+            self.file = Some(file);
+        }
+
+        match self.file.as_mut() {
+            Some(file) => {
+                // A reader shares the file offset, so move back to the end first.
+                file.seek(SeekFrom::End(0))?;
+                file.write_all(bytes)?;
+            }
+            None => self.memory.extend_from_slice(bytes),
+        }
+
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// The number of bytes of the content.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if no bytes have been written.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bytes of the content kept in memory.
+    pub(crate) fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Whether writing `len` more bytes keeps the content in memory, without touching the temp
+    /// file. Only accurate without a memory budget.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn fits_in_memory(&self, len: usize) -> bool {
+        self.file.is_none() && self.memory.len() + len <= self.config.memory_limit
+    }
+
+    /// Returns `true` if the content is still in memory.
+    pub fn is_in_memory(&self) -> bool {
+        self.file.is_none()
+    }
+
+    /// Read the content from the beginning.
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.file {
+            Some(file) => {
+                let mut file = file.try_clone()?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(file.take(self.len)))
+            }
+            None => Ok(Box::new(Cursor::new(self.memory.as_slice()))),
+        }
+    }
+
+    /// Read the content from the beginning, consuming the [`Spool`].
+    #[cfg(feature = "tokio")]
+    pub(crate) fn into_reader(self) -> io::Result<Box<dyn Read + Send>> {
+        match self.file {
+            Some(mut file) => {
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(file.take(self.len)))
+            }
+            None => Ok(Box::new(Cursor::new(self.memory))),
+        }
+    }
+
+    /// Copy the content to a file at the given path, e.g. to quarantine an infected content.
+    pub fn persist(&self, path: impl AsRef<Path>) -> io::Result<u64> {
+        let mut dest = File::create(path)?;
+        io::copy(&mut self.reader()?, &mut dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_small_contents_in_memory() {
+        let mut spool = Spool::new(SpoolConfig::new(16));
+        spool.write(b"Hello ").unwrap();
+        spool.write(b"World").unwrap();
+
+        assert!(spool.is_in_memory());
+        assert_eq!(spool.len(), 11);
+        assert_eq!(read_all(&spool), b"Hello World");
+    }
+
+    #[test]
+    fn it_moves_to_a_temp_file_beyond_the_memory_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spool = Spool::new(SpoolConfig::new(8).dir(dir.path()));
+        spool.write(b"Hello ").unwrap();
+        spool.write(b"World").unwrap();
+
+        assert!(!spool.is_in_memory());
+        assert_eq!(read_all(&spool), b"Hello World");
+        // The content can be replayed any number of times.
+        assert_eq!(read_all(&spool), b"Hello World");
+
+        let path = dir.path().join("quarantined");
+        assert_eq!(spool.persist(&path).unwrap(), 11);
+        assert_eq!(std::fs::read(path).unwrap(), b"Hello World");
+    }
+
+    fn read_all(spool: &Spool) -> Vec<u8> {
+        let mut buf = vec![];
+        spool.reader().unwrap().read_to_end(&mut buf).unwrap();
+        buf
+    }
+}
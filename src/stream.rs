@@ -0,0 +1,1688 @@
+use crate::{
+    adaptive::AdaptiveChunkSize,
+    drop_behavior::DropBehavior,
+    error::StreamErrors,
+    lookahead::Lookahead,
+    protocol::{ChunkSize, CommandFormat},
+    scan::Scan,
+    BufferPool, Decoding, Error, LengthPolicy, MemoryBudget, Progress, ResponseParser, ScanOutcome,
+    ScanPhase, StreamErrorAction, TrailingNotes,
+};
+
+#[cfg(feature = "dedup")]
+use crate::BlockDedup;
+#[cfg(feature = "checksum")]
+use crate::Checksum;
+#[cfg(feature = "spool")]
+use crate::{ScanMode, Spool, SpoolConfig};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use pin_project::pin_project;
+use std::{
+    error::Error as StdError,
+    future::poll_fn,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+#[cfg(feature = "tokio")]
+use {
+    crate::{
+        channel::{ChannelInput, TryChannelInput},
+        ScanScope,
+    },
+    tokio::io::AsyncRead,
+    tokio_stream::StreamExt,
+    tokio_util::io::{ReaderStream, StreamReader},
+};
+
+#[cfg(feature = "passthrough-check")]
+use crate::integrity::Passthrough;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+/// The default number of `INSTREAM` chunks a [`ScannedStream`] sends per poll, see
+/// [`ScannedStream::with_frames_per_poll`].
+pub const DEFAULT_FRAMES_PER_POLL: usize = 256;
+
+/// A [`ScannedStream`] with its type parameters erased by [`ScannedStream::into_boxed`], e.g.
+/// to store it in a response type or a trait object.
+pub type BoxScannedStream<'a> = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send + 'a>>;
+
+/// The default size of the buffer a [`ScannedStream::from_reader`] reads the content into.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A wrapper stream holding byte stream. This sends the inner stream to [clamav](https://www.clamav.net/) to scan it while passes it through to the consumer.
+///
+/// A [`ScannedStream`] is [`Send`] whenever its input and its transport are, so that it can be
+/// consumed in a task spawned on a multi-threaded runtime.
+///
+/// Its transport is not registered with a reactor, so while a non-blocking one is not ready,
+/// the stream returns [`Poll::Pending`] and wakes its task right away, polling the transport
+/// again on the next turn of the executor. Use an
+/// [`AsyncScannedStream`](crate::AsyncScannedStream) to be woken by tokio once the connection
+/// is ready instead.
+#[pin_project]
+pub struct ScannedStream<St, RW: Read + Write> {
+    #[pin]
+    input: St,
+    scan: Scan<RW>,
+    frames_per_poll: usize,
+    /// A chunk of the input only partly sent, and the length sent so far.
+    pending: Option<(bytes::Bytes, usize)>,
+    stream_errors: StreamErrors,
+    lookahead: Option<Lookahead>,
+    /// Whether the verdict has been returned, after which neither the input nor the
+    /// connection are polled again.
+    done: bool,
+    #[cfg(feature = "passthrough-check")]
+    passthrough: Passthrough,
+}
+
+impl<St, RW, B, E> Stream for ScannedStream<St, RW>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<bytes::Bytes>,
+    RW: Read + Write,
+    E: StdError + Send + Sync + 'static,
+{
+    type Item = Result<bytes::Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_scan(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The chunks held back are yielded after the input, unless the content is infected.
+        let held = self.lookahead.as_ref().map_or(0, Lookahead::chunks);
+        if self.scan.is_cut_short() {
+            return (0, Some(0));
+        }
+        if self.done {
+            // The content passed through is checked once every chunk has been released.
+            let check =
+                usize::from(cfg!(feature = "passthrough-check") && self.lookahead.is_some());
+            // Only a strict scan polls the input again, to reject its trailing content.
+            let upper = (!self.scan.is_strict()).then_some(held + check);
+            return (held, upper);
+        }
+        // The scan may end the stream before its input, e.g. with a detection, an error or
+        // the deadline, and the verdict may add an error after the last chunk.
+        let upper = self
+            .input
+            .size_hint()
+            .1
+            .and_then(|upper| upper.checked_add(held));
+        (0, upper.and_then(|upper| upper.checked_add(1)))
+    }
+}
+
+impl<St, RW, B, E> ScannedStream<St, RW>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<bytes::Bytes>,
+    RW: Read + Write,
+    E: StdError + Send + Sync + 'static,
+{
+    fn poll_scan(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<bytes::Bytes, Error>>> {
+        let me = self.project();
+
+        // A scan cut short has returned its error, and neither sends nor reads anything more.
+        if me.scan.is_cut_short() {
+            return Poll::Ready(None);
+        }
+
+        // Once the verdict has been returned, only the chunks still held back are left.
+        if *me.done {
+            if let Some(lookahead) = me.lookahead.as_mut() {
+                if let Some(bytes) = lookahead.release(true) {
+                    #[cfg(feature = "passthrough-check")]
+                    me.passthrough.passed(&bytes);
+                    return Poll::Ready(Some(Ok(bytes)));
+                }
+                *me.lookahead = None;
+                #[cfg(feature = "passthrough-check")]
+                if let Err(err) = me.passthrough.verify() {
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+            // Only a strict scan polls the input after its end, to reject the content of an
+            // input which is not fused.
+            if !me.scan.is_strict() {
+                return Poll::Ready(None);
+            }
+            return match me.input.poll_next(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(Some(Ok(bytes))) => {
+                    let len = bytes.into().len();
+                    Poll::Ready((len > 0).then_some(Err(Error::TrailingData { len })))
+                }
+                Poll::Ready(Some(Err(_)) | None) => Poll::Ready(None),
+            };
+        }
+
+        // Resume the chunks a non-blocking connection did not accept before reading more.
+        match me.scan.resume() {
+            Ok(true) => {}
+            Ok(false) => {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        }
+
+        match me.scan.poll_early_verdict() {
+            Poll::Ready(None) => {}
+            Poll::Ready(Some(err)) => {
+                if let Some(lookahead) = me.lookahead.as_mut() {
+                    lookahead.discard();
+                }
+                return Poll::Ready(Some(Err(err)));
+            }
+            Poll::Pending => {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        }
+
+        // Release the chunks held back once enough content has followed them, or all of them
+        // once the content has been found clean.
+        if let Some(bytes) = me
+            .lookahead
+            .as_mut()
+            .and_then(|lookahead| lookahead.release(me.scan.is_finished()))
+        {
+            #[cfg(feature = "passthrough-check")]
+            me.passthrough.passed(&bytes);
+            return Poll::Ready(Some(Ok(bytes)));
+        }
+
+        let (bytes, sent) = match me.pending.take() {
+            Some(pending) => pending,
+            None => match me.input.poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(bytes))) => (bytes.into(), 0),
+                Poll::Ready(Some(Err(err))) => {
+                    return match me.stream_errors.handle(err) {
+                        Some(err) => Poll::Ready(Some(Err(err))),
+                        None => {
+                            cx.waker().wake_by_ref();
+                            Poll::Pending
+                        }
+                    };
+                }
+                Poll::Ready(None) => {
+                    let finished = match me.scan.poll_finish() {
+                        Poll::Ready(finished) => finished,
+                        Poll::Pending => {
+                            cx.waker().wake_by_ref();
+                            return Poll::Pending;
+                        }
+                    };
+                    #[cfg(feature = "passthrough-check")]
+                    if let Some(Ok(())) = finished {
+                        me.passthrough.expect(me.scan.scanned());
+                    }
+                    *me.done = true;
+                    let Some(result) = finished else {
+                        // Every chunk held back has been released since the verdict.
+                        *me.lookahead = None;
+                        #[cfg(feature = "passthrough-check")]
+                        return Poll::Ready(me.passthrough.verify().err().map(Err));
+                        #[cfg(not(feature = "passthrough-check"))]
+                        return Poll::Ready(None);
+                    };
+                    let Some(lookahead) = me.lookahead.as_mut().filter(|held| held.chunks() > 0)
+                    else {
+                        *me.lookahead = None;
+                        #[cfg(feature = "passthrough-check")]
+                        let result = result.and_then(|_| me.passthrough.verify());
+                        return Poll::Ready(result.err().map(Err));
+                    };
+                    return match result {
+                        Ok(()) => {
+                            let bytes = lookahead.release(true);
+                            #[cfg(feature = "passthrough-check")]
+                            if let Some(bytes) = &bytes {
+                                me.passthrough.passed(bytes);
+                            }
+                            Poll::Ready(bytes.map(Ok))
+                        }
+                        Err(err) => {
+                            lookahead.discard();
+                            *me.lookahead = None;
+                            Poll::Ready(Some(Err(err)))
+                        }
+                    };
+                }
+            },
+        };
+
+        // Send a huge chunk over several polls, so that the task does not starve the others
+        // on its executor.
+        let budget = me.scan.frame_len().saturating_mul(*me.frames_per_poll);
+        let end = sent.saturating_add(budget).min(bytes.len());
+        let part = &bytes[sent..end];
+
+        if let Err(err) = me.scan.send(part) {
+            return Poll::Ready(Some(Err(err)));
+        }
+        if end < bytes.len() {
+            *me.pending = Some((bytes, end));
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        match me.lookahead.as_mut() {
+            Some(lookahead) => {
+                lookahead.hold(bytes);
+                match lookahead.release(false) {
+                    Some(bytes) => {
+                        #[cfg(feature = "passthrough-check")]
+                        me.passthrough.passed(&bytes);
+                        Poll::Ready(Some(Ok(bytes)))
+                    }
+                    None => {
+                        cx.waker().wake_by_ref();
+                        Poll::Pending
+                    }
+                }
+            }
+            None => {
+                #[cfg(feature = "passthrough-check")]
+                me.passthrough.passed(&bytes);
+                Poll::Ready(Some(Ok(bytes)))
+            }
+        }
+    }
+}
+
+impl<St, RW, B, E> ScannedStream<St, RW>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<bytes::Bytes>,
+    RW: Read + Write,
+    E: StdError,
+{
+    /// Create a new [`ScannedStream`]
+    ///
+    /// The input can be either owned or a mutable reference to an [`Unpin`] stream. An owned
+    /// input does not need to be [`Unpin`], so the [`ScannedStream`] itself has to be pinned
+    /// before polling in that case.
+    ///
+    /// The chunks of the input can be of any type convertible into [`Bytes`](bytes::Bytes),
+    /// so that e.g. a `FramedRead` yielding `BytesMut` with a codec error can be wrapped as it is.
+    pub fn new(input: St, inner: RW) -> Self {
+        Self::with_scan(input, Scan::new(inner))
+    }
+
+    pub(crate) fn with_scan(input: St, mut scan: Scan<RW>) -> Self {
+        scan.set_resumable(true);
+        Self {
+            input,
+            scan,
+            frames_per_poll: DEFAULT_FRAMES_PER_POLL,
+            pending: None,
+            stream_errors: StreamErrors::default(),
+            lookahead: None,
+            done: false,
+            #[cfg(feature = "passthrough-check")]
+            passthrough: Passthrough::default(),
+        }
+    }
+
+    /// Choose how the content is sent to the clamav. Defaults to [`ScanMode::Instream`].
+    #[cfg(feature = "spool")]
+    pub fn with_scan_mode(mut self, mode: ScanMode) -> Self {
+        self.scan.set_mode(mode);
+        self
+    }
+
+    /// Send the `INSTREAM` command in the given format. Defaults to [`CommandFormat::Null`].
+    pub fn with_command_format(mut self, format: CommandFormat) -> Self {
+        self.scan.set_start(Some(format));
+        self
+    }
+
+    /// Don't send the `INSTREAM` command, because it has already been sent over the inner
+    /// connection, e.g. by a transport managing a pre-established `IDSESSION`.
+    pub fn without_start_command(mut self) -> Self {
+        self.scan.set_start(None);
+        self
+    }
+
+    /// Ask the clamav to report every signature found instead of only the first one, see
+    /// [`ScanOutcome::detections`]. Only takes effect with [`ScanMode::LocalFile`], because
+    /// clamd has no all-match variant of `INSTREAM`.
+    #[cfg(feature = "spool")]
+    pub fn with_all_match(mut self) -> Self {
+        self.scan.set_all_match(true);
+        self
+    }
+
+    /// Decode the content before it is sent to the clamav. The stream still yields the
+    /// content as it is.
+    pub fn with_decoding(mut self, decoding: Decoding) -> Self {
+        self.scan.set_decoding(Some(decoding));
+        self
+    }
+
+    /// Verify the digest of the content once it has been passed through. A mismatch is
+    /// returned as [`Error::ChecksumMismatch`] in place of a clean verdict.
+    #[cfg(feature = "checksum")]
+    pub fn expect_checksum(mut self, checksum: Checksum) -> Self {
+        self.scan.set_checksum(checksum);
+        self
+    }
+
+    /// Verify the SHA-256 digest of the content once it has been passed through.
+    #[cfg(feature = "checksum")]
+    pub fn expect_sha256(self, digest: [u8; 32]) -> Self {
+        self.expect_checksum(Checksum::Sha256(digest))
+    }
+
+    /// Keep a copy of the content passed through in a [`Spool`], so that it can be replayed
+    /// after the scan, e.g. to quarantine an infected content.
+    #[cfg(feature = "spool")]
+    pub fn with_spool(mut self, config: SpoolConfig) -> Self {
+        self.scan.set_spool(config);
+        self
+    }
+
+    /// The copy of the content passed through so far, if spooling is enabled.
+    #[cfg(feature = "spool")]
+    pub fn spool(&self) -> Option<&Spool> {
+        self.scan.spool()
+    }
+
+    /// Take the copy of the content passed through, if spooling is enabled.
+    #[cfg(feature = "spool")]
+    pub fn into_spool(self) -> Option<Spool> {
+        self.scan.into_spool()
+    }
+
+    /// Use the given parser instead of [`ClamdParser`](crate::ClamdParser) to map the reply from the clamav to a
+    /// [`ScanOutcome`].
+    pub fn with_response_parser(mut self, parser: impl ResponseParser + 'static) -> Self {
+        self.scan.set_parser(Arc::new(parser));
+        self
+    }
+
+    /// Choose what the scan does with the notes the clamav appends to a clean reply. Defaults
+    /// to [`TrailingNotes::Report`].
+    pub fn with_trailing_notes(mut self, notes: TrailingNotes) -> Self {
+        self.scan.set_trailing_notes(notes);
+        self
+    }
+
+    /// Where the scan is in the clamav protocol.
+    pub fn phase(&self) -> ScanPhase {
+        self.scan.phase()
+    }
+
+    /// Split the content into chunks of at most the given size before sending it. Defaults to
+    /// [`CHUNK_SIZE`](crate::protocol::CHUNK_SIZE).
+    pub fn with_chunk_size(mut self, chunk_size: ChunkSize) -> Self {
+        self.scan.set_chunk_size(chunk_size);
+        self
+    }
+
+    /// Adapt the size of the chunks sent to the throughput of the connection instead of
+    /// splitting the content at a fixed [`ChunkSize`]. See [`AdaptiveChunkSize`].
+    pub fn with_adaptive_chunk_size(mut self, config: AdaptiveChunkSize) -> Self {
+        self.scan.set_adaptive_chunk_size(config);
+        self
+    }
+
+    /// Send at most the given number of `INSTREAM` chunks per poll, returning
+    /// [`Poll::Pending`] and waking the task to send the rest of a longer input chunk. Defaults
+    /// to [`DEFAULT_FRAMES_PER_POLL`]. Pass [`usize::MAX`] to send every input chunk at once.
+    pub fn with_frames_per_poll(mut self, frames: usize) -> Self {
+        self.frames_per_poll = frames.max(1);
+        self
+    }
+
+    /// Take the buffers the content is copied into for a [`Decoding`] or a [`BlockDedup`]
+    /// from the pool instead of allocating them. See [`BufferPool`].
+    pub fn with_buffer_pool(mut self, buffers: BufferPool) -> Self {
+        self.scan.set_buffer_pool(buffers);
+        self
+    }
+
+    /// Register the scan as a member of the scope under the label, e.g. the file name of an
+    /// uploaded part, so that the scope is only clean if this scan is.
+    #[cfg(feature = "tokio")]
+    pub fn with_scope(mut self, scope: &ScanScope, label: impl Into<String>) -> Self {
+        self.scan.set_member(scope.join(label.into()));
+        self
+    }
+
+    /// Choose what the scan does if the stream is dropped before the end of the input.
+    /// Defaults to [`DropBehavior::Abort`].
+    pub fn with_drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.scan.set_drop_behavior(behavior);
+        self
+    }
+
+    /// Return [`Error::TrailingData`] if the input yields content after it has ended. Only an
+    /// input which is not fused can do so, and only a strict stream polls its input again
+    /// after the end, instead of ending for good.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.scan.set_strict(strict);
+        self
+    }
+
+    /// Check whether the clamav has replied before polling each chunk of the input, and end
+    /// the stream with its detection as soon as it has, instead of passing the rest of the
+    /// content through until the end of the input.
+    ///
+    /// The connection has to be in non-blocking mode, e.g. with
+    /// [`Connection::set_nonblocking`], or the check blocks until the clamav replies.
+    /// [`ScannerBuilder::early_verdict`] sets it up for the streams of a [`Scanner`].
+    pub fn with_early_verdict(mut self, early_verdict: bool) -> Self {
+        self.scan.set_early_verdict(early_verdict);
+        self
+    }
+
+    /// Count the chunks queued for a non-blocking connection and the part of the [`Spool`] kept
+    /// in memory against the budget. Set it before the stream is polled.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.scan.set_memory_budget(budget);
+        self
+    }
+
+    /// Declare the length of the whole content, e.g. from the `Content-Length` header, so that
+    /// the [`Progress`] can report percent complete and flag truncated or overrun inputs.
+    pub fn with_expected_len(self, len: u64) -> Self {
+        self.scan.progress().set_expected_len(len);
+        self
+    }
+
+    /// Fail the scan with [`Error::DeadlineExceeded`] once the deadline has passed, e.g. the
+    /// deadline of the enclosing request. Only a connection with a read timeout set on its
+    /// socket stops waiting for the verdict at the deadline, see
+    /// [`Scanner::with_deadline`](crate::Scanner::with_deadline).
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.scan.set_deadline(deadline);
+        self
+    }
+
+    /// Skip the blocks of the content identical to a block already sent to the clamav. See
+    /// [`BlockDedup`] for what the clamav misses then. Set it before the stream is polled.
+    #[cfg(feature = "dedup")]
+    pub fn with_block_dedup(mut self, config: BlockDedup) -> Self {
+        self.scan.set_block_dedup(&config);
+        self
+    }
+
+    /// Choose what the scan does when the content does not match the
+    /// [expected length](Self::with_expected_len). Defaults to [`LengthPolicy::Warn`].
+    pub fn with_length_policy(mut self, policy: LengthPolicy) -> Self {
+        self.scan.set_length_policy(policy);
+        self
+    }
+
+    /// Hold back the chunks from the consumer until the clamav has been sent at least `bytes`
+    /// more of the content after them, and the last ones until the content has been found
+    /// clean, so that less of an infected content leaks downstream before the verdict, with a
+    /// delay bounded by the lookahead instead of the whole content. Works best with
+    /// [`ScannedStream::with_early_verdict`], whose detections drop the chunks held back.
+    pub fn with_lookahead(mut self, bytes: usize) -> Self {
+        self.lookahead = Some(Lookahead::new(bytes));
+        self
+    }
+
+    /// Decide what an error of the input becomes, instead of returning it as it is in an
+    /// [`Error::Stream`]: convert it into another payload with [`StreamErrorAction::Fail`], or
+    /// drop it and poll the input again with [`StreamErrorAction::Ignore`].
+    pub fn with_stream_errors<F>(mut self, hook: F) -> Self
+    where
+        E: Send + Sync + 'static,
+        F: FnMut(E) -> StreamErrorAction + Send + Sync + 'static,
+    {
+        self.stream_errors = StreamErrors::new(hook);
+        self
+    }
+
+    /// A clonable handle to follow the scan while the stream is consumed.
+    pub fn progress(&self) -> Progress {
+        self.scan.progress().clone()
+    }
+
+    /// Adapt the stream into an [`AsyncBufRead`](tokio::io::AsyncBufRead) with a
+    /// [`StreamReader`], for APIs which take a reader. An infected content fails the last read
+    /// with an [`io::ErrorKind::InvalidData`] error carrying a
+    /// [`DetectionError`](crate::DetectionError) with the signatures found, and the other
+    /// errors are returned as [`io::Error`]s wrapping the [`Error`].
+    #[cfg(feature = "tokio")]
+    pub fn into_async_read(self) -> StreamReader<impl Stream<Item = io::Result<Bytes>>, Bytes>
+    where
+        E: Send + Sync + 'static,
+    {
+        StreamReader::new(self.map(|item| item.map_err(Error::into_io)))
+    }
+
+    /// Box and pin the stream, erasing its type parameters.
+    pub fn into_boxed<'a>(self) -> BoxScannedStream<'a>
+    where
+        Self: Send + 'a,
+        E: Send + Sync + 'static,
+    {
+        Box::pin(self)
+    }
+
+    /// Stop reading the input and terminate the scan, returning the input with whatever it has
+    /// not yielded yet and the verdict on the content passed through so far.
+    ///
+    /// If the stream has already been consumed to the end, the verdict it yielded is returned
+    /// again. If that scan failed, the verdict is unknown and an error is returned instead.
+    pub async fn finish(mut self) -> (St, Result<ScanOutcome, Error>) {
+        let concluded = poll_fn(|cx| {
+            let polled = self.scan.poll_conclude();
+            if polled.is_pending() {
+                cx.waker().wake_by_ref();
+            }
+            polled
+        })
+        .await;
+        let result = match concluded {
+            Some(result) => result,
+            None => self
+                .scan
+                .outcome()
+                .cloned()
+                .ok_or_else(|| io::Error::other("the scan has already failed").into()),
+        };
+        (self.input, result)
+    }
+
+    /// Create a new [`ScannedStream`] connecting to clamav server with tcp socket.
+    ///
+    /// The socket is a blocking std one, see [`AsyncScannedStream::tcp`](crate::AsyncScannedStream::tcp)
+    /// for a tokio one.
+    pub fn tcp(input: St, addr: impl ToSocketAddrs) -> Result<ScannedStream<St, TcpStream>, Error> {
+        let inner = TcpStream::connect(addr)?;
+        Ok(ScannedStream::new(input, inner))
+    }
+
+    /// Create a new [`ScannedStream`] connecting to clamav server with unix socket.
+    #[cfg(unix)]
+    pub fn socket(
+        input: St,
+        path: impl AsRef<Path>,
+    ) -> Result<ScannedStream<St, UnixStream>, Error> {
+        let inner = UnixStream::connect(path)?;
+        Ok(ScannedStream::new(input, inner))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead> ScannedStream<ReaderStream<R>, TcpStream> {
+    /// Create a new [`ScannedStream`] over the content read from the reader, e.g. a file,
+    /// connecting to clamav server with tcp socket. The content is read in chunks of up to
+    /// [`DEFAULT_READ_BUFFER_SIZE`] bytes.
+    pub fn from_reader(reader: R, addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        Self::from_reader_with_capacity(reader, DEFAULT_READ_BUFFER_SIZE, addr)
+    }
+
+    /// Like [`ScannedStream::from_reader`], but read the content in chunks of up to `capacity`
+    /// bytes.
+    pub fn from_reader_with_capacity(
+        reader: R,
+        capacity: usize,
+        addr: impl ToSocketAddrs,
+    ) -> Result<Self, Error> {
+        let inner = TcpStream::connect(addr)?;
+        Ok(Self::new(
+            ReaderStream::with_capacity(reader, capacity),
+            inner,
+        ))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<B, RW> ScannedStream<ChannelInput<B>, RW>
+where
+    B: Into<bytes::Bytes>,
+    RW: Read + Write,
+{
+    /// Create a new [`ScannedStream`] over the chunks received from a tokio channel, either a
+    /// bounded `Receiver` or an `UnboundedReceiver`. The stream ends once every sender has been
+    /// dropped.
+    pub fn from_channel(rx: impl Into<ChannelInput<B>>, inner: RW) -> Self {
+        Self::new(rx.into(), inner)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<B, E, RW> ScannedStream<TryChannelInput<B, E>, RW>
+where
+    B: Into<bytes::Bytes>,
+    RW: Read + Write,
+    E: StdError,
+{
+    /// Create a new [`ScannedStream`] over the fallible chunks received from a tokio channel.
+    /// An `Err` sent by the producer is returned as [`Error::Stream`].
+    pub fn from_try_channel(rx: impl Into<TryChannelInput<B, E>>, inner: RW) -> Self {
+        Self::new(rx.into(), inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeTransport, DropResult, Phase, ScanReport, Warning};
+    use bytes::Bytes;
+    use std::{io::Cursor, pin::pin};
+    use tokio_stream::StreamExt;
+    use tokio_util::codec::{BytesCodec, FramedRead};
+
+    #[tokio::test]
+    async fn it_returns_original_inputs_when_success() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let stream = ScannedStream::new(&mut input, &mut inner);
+        let result = consume(stream).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "Hello World");
+
+        assert_eq!(inner.written.len(), 4);
+        assert_eq!(inner.written.first().unwrap(), "zINSTREAM\0");
+        assert_eq!(
+            inner.written.get(1).unwrap(),
+            &String::from_utf8(("Hello World".len() as u32).to_be_bytes().to_vec()).unwrap(),
+        );
+        assert_eq!(inner.written.get(2).unwrap(), "Hello World");
+        assert_eq!(
+            inner.written.get(3).unwrap(),
+            &String::from_utf8(vec![0, 0, 0, 0]).unwrap(),
+        );
+    }
+
+    #[tokio::test]
+    async fn it_returns_an_error_when_found_any_virus() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("FOUND test virus");
+
+        let stream = ScannedStream::new(&mut input, &mut inner);
+        let result = consume(stream).await;
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "FOUND test virus");
+    }
+
+    #[tokio::test]
+    async fn it_reports_the_phase_and_bytes_sent_when_the_transport_fails() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello")),
+            Ok(Bytes::from(" World")),
+        ]);
+        let mut inner = MockStream::failing_after("OK", 3);
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello"))));
+
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Send {
+                bytes_sent: 5,
+                during: Phase::Chunk,
+                ..
+            }
+        ));
+
+        // Neither the terminating chunk is sent nor the reply read again after the error.
+        assert_eq!(stream.next().await, None);
+        assert_eq!(stream.next().await, None);
+        assert_eq!(stream.phase(), ScanPhase::Done(None));
+        drop(stream);
+        assert_eq!(inner.attempts, 4);
+        assert_eq!(inner.output.position(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_returns_the_detection_when_the_clamav_closes_during_the_content() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello")),
+            Ok(Bytes::from(" World")),
+            Ok(Bytes::from("!")),
+        ]);
+        let mut inner = MockStream::failing_after("stream: Eicar-Signature FOUND\0", 3);
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello"))));
+        assert_eq!(
+            stream.next().await,
+            Some(Err(Error::Scan("stream: Eicar-Signature FOUND\0".into())))
+        );
+
+        // The rest of the input is not passed through.
+        assert_eq!(stream.next().await, None);
+        assert_eq!(
+            stream.phase(),
+            ScanPhase::Done(Some(ScanOutcome::Infected(
+                "stream: Eicar-Signature FOUND\0".into()
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_returns_the_detection_when_the_clamav_closes_before_the_terminating_chunk() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::failing_after("stream: Eicar-Signature FOUND\0", 3);
+
+        let stream = ScannedStream::new(&mut input, &mut inner);
+        let err = consume(stream).await.unwrap_err();
+        assert_eq!(err, Error::Scan("stream: Eicar-Signature FOUND\0".into()));
+    }
+
+    #[tokio::test]
+    async fn it_returns_none_repeatedly_after_the_verdict() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("stream: Eicar-Signature FOUND");
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner);
+        assert!(stream.next().await.unwrap().is_ok());
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(stream.next().await, None);
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+        assert_eq!(inner.attempts, 4);
+    }
+
+    #[tokio::test]
+    async fn it_reports_the_finish_phase_when_the_terminating_chunk_fails() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::failing_after("OK", 3);
+
+        let stream = ScannedStream::new(&mut input, &mut inner);
+        let err = consume(stream).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to communicate with clamav during finish after sending 11 bytes: broken pipe",
+        );
+    }
+
+    #[tokio::test]
+    async fn it_hints_the_size_of_the_input_with_the_verdict() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner);
+        assert_eq!(stream.size_hint(), (0, Some(2)));
+
+        while stream.next().await.is_some() {}
+        assert_eq!(stream.size_hint(), (0, Some(0)));
+    }
+
+    #[tokio::test]
+    async fn it_scans_owned_streams_which_are_not_unpin() {
+        let input =
+            tokio_stream::iter(stream_from_str("Hello World")).then(|chunk| async { chunk });
+        let mut inner = MockStream::new("OK");
+
+        {
+            let mut stream = pin!(ScannedStream::new(input, &mut inner));
+            assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+            assert_eq!(stream.next().await, None);
+        }
+        assert_eq!(inner.written.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn it_reports_truncated_inputs() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let stream = ScannedStream::new(&mut input, &mut inner).with_expected_len(20);
+        let progress = stream.progress();
+        assert!(consume(stream).await.is_ok());
+
+        let report = progress.report().unwrap();
+        assert_eq!(
+            report,
+            ScanReport {
+                bytes_scanned: 11,
+                expected_len: Some(20),
+                warnings: vec![Warning::Truncated {
+                    bytes_scanned: 11,
+                    expected_len: 20,
+                }],
+                reputation: None,
+                signatures: vec![],
+                time_to_verdict: report.time_to_verdict,
+                tenant: None,
+            }
+        );
+        assert!(report.is_truncated());
+        assert!(report.time_to_verdict.is_some());
+    }
+
+    #[tokio::test]
+    async fn it_fails_inputs_not_matching_the_expected_length_under_the_fail_policy() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream = ScannedStream::new(&mut input, &mut transport)
+            .with_expected_len(8)
+            .with_length_policy(LengthPolicy::Fail);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello "))));
+        assert_eq!(
+            stream.next().await,
+            Some(Err(Error::LengthMismatch {
+                expected_len: 8,
+                bytes_scanned: 11,
+            }))
+        );
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+        // The overrunning chunk is neither passed through nor sent.
+        assert_eq!(transport.chunks(), vec![Bytes::from("Hello ")]);
+        assert!(!transport.is_terminated());
+
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+        let stream = ScannedStream::new(&mut input, &mut inner)
+            .with_expected_len(20)
+            .with_length_policy(LengthPolicy::Fail);
+        assert_eq!(
+            consume(stream).await,
+            Err(Error::LengthMismatch {
+                expected_len: 20,
+                bytes_scanned: 11,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fails_the_scan_once_the_deadline_has_passed() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream =
+            ScannedStream::new(&mut input, &mut transport).with_deadline(std::time::Instant::now());
+        assert_eq!(stream.next().await, Some(Err(Error::deadline_exceeded(0))));
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+        assert!(transport.written().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_reports_overrun_inputs() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let stream = ScannedStream::new(&mut input, &mut inner).with_expected_len(5);
+        let progress = stream.progress();
+        assert!(consume(stream).await.is_ok());
+
+        let report = progress.report().unwrap();
+        assert!(report.is_overrun());
+        assert_eq!(
+            report.warnings,
+            vec![Warning::Overrun {
+                bytes_scanned: 11,
+                expected_len: 5,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_maps_replies_with_a_custom_parser() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("VIRUS:Test.Sig");
+
+        let stream = ScannedStream::new(&mut input, &mut inner).with_response_parser(
+            |reply: &[u8]| -> Result<ScanOutcome, Error> {
+                match reply.strip_prefix(b"VIRUS:") {
+                    Some(name) => Ok(ScanOutcome::Infected(
+                        String::from_utf8_lossy(name).into_owned(),
+                    )),
+                    None => Ok(ScanOutcome::Clean),
+                }
+            },
+        );
+        let result = consume(stream).await;
+        assert_eq!(result.unwrap_err(), Error::Scan("Test.Sig".into()));
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_decoded_content_and_yields_the_original() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("SGVsbG8g")),
+            Ok(Bytes::from("V29ybGQ=")),
+        ]);
+        let mut inner = MockStream::new("OK");
+
+        let stream = ScannedStream::new(&mut input, &mut inner).with_decoding(Decoding::Base64);
+        let result = consume(stream).await;
+        assert_eq!(result.unwrap(), "SGVsbG8gV29ybGQ=");
+
+        let sent: Vec<&str> = inner.written.iter().map(String::as_str).collect();
+        assert_eq!(sent[2], "Hello ");
+        assert_eq!(sent[4], "Wor");
+        assert_eq!(sent[6], "ld");
+    }
+
+    #[tokio::test]
+    async fn it_writes_the_content_without_copying_it() {
+        let content = Bytes::from(vec![7u8; 100_000]);
+        let mut probe = crate::test_util::ZeroCopyProbe::new(&content);
+
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(content.clone())]);
+        let stream =
+            ScannedStream::new(input, &mut probe).with_chunk_size(ChunkSize::new(4096).unwrap());
+        assert!(stream.collect::<Result<Vec<_>, _>>().await.is_ok());
+
+        // Only the command, the length prefixes and the terminating chunk are not the content.
+        assert_eq!(probe.borrowed, content.len());
+        assert_eq!(probe.written, 10 + 25 * 4 + content.len() + 4);
+    }
+
+    #[tokio::test]
+    async fn it_reuses_the_buffers_of_the_decoded_content() {
+        let input = || {
+            tokio_stream::iter(vec![
+                Ok::<_, Error>(Bytes::from("SGVsbG8g")),
+                Ok(Bytes::from("V29ybGQ=")),
+            ])
+        };
+        let pool = BufferPool::new(1024, 4);
+
+        for _ in 0..2 {
+            let stream = ScannedStream::new(input(), FakeTransport::new("stream: OK\0"))
+                .with_decoding(Decoding::Base64)
+                .with_buffer_pool(pool.clone());
+            assert_eq!(consume(stream).await.unwrap(), "SGVsbG8gV29ybGQ=");
+        }
+        // A single buffer serves every chunk of both scans.
+        assert_eq!(pool.stats().allocated, 1);
+        assert_eq!(pool.stats().reused, 3);
+    }
+
+    #[cfg(feature = "checksum")]
+    #[tokio::test]
+    async fn it_verifies_the_checksum_of_clean_contents() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let checksum = Checksum::sha256_hex(&"0".repeat(64)).unwrap();
+        let stream = ScannedStream::new(&mut input, &mut inner).expect_checksum(checksum);
+        let err = consume(stream).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ChecksumMismatch { actual, .. }
+                if actual == "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e"
+        ));
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_spools_the_content_passed_through() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut inner = MockStream::new("FOUND test virus");
+
+        let mut stream =
+            ScannedStream::new(&mut input, &mut inner).with_spool(SpoolConfig::new(1024));
+        while stream.next().await.is_some() {}
+
+        let spool = stream.into_spool().unwrap();
+        let mut content = vec![];
+        spool.reader().unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"Hello World");
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_moves_the_spool_to_a_temp_file_beyond_the_memory_budget() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut inner = MockStream::new("OK");
+
+        let budget = MemoryBudget::new(8);
+        let mut stream = ScannedStream::new(&mut input, &mut inner)
+            .with_spool(SpoolConfig::new(1024))
+            .with_memory_budget(budget.clone());
+        let progress = stream.progress();
+
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello "))));
+        assert_eq!(progress.buffered_bytes(), 6);
+        assert_eq!(budget.used(), 6);
+
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("World"))));
+        assert_eq!(progress.buffered_bytes(), 0);
+        assert_eq!(progress.peak_buffered_bytes(), 6);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(stream.next().await, None);
+
+        let spool = stream.into_spool().unwrap();
+        assert!(!spool.is_in_memory());
+        let mut content = vec![];
+        spool.reader().unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"Hello World");
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_scans_the_content_from_a_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut inner = MockStream::new("OK");
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner)
+            .with_scan_mode(ScanMode::LocalFile(Some(dir.path().to_path_buf())));
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello "))));
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("World"))));
+        assert_eq!(stream.next().await, None);
+
+        let file = std::fs::read_dir(dir.path())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        assert_eq!(std::fs::read(&file).unwrap(), b"Hello World");
+        drop(stream);
+
+        assert_eq!(inner.written, vec![format!("zSCAN {}\0", file.display())]);
+        assert!(!file.exists());
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_start_command_as_configured() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let stream =
+            ScannedStream::new(&mut input, &mut inner).with_command_format(CommandFormat::Newline);
+        assert!(consume(stream).await.is_ok());
+        assert_eq!(inner.written[0], "nINSTREAM\n");
+
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let stream = ScannedStream::new(&mut input, &mut inner).without_start_command();
+        assert!(consume(stream).await.is_ok());
+        assert_eq!(inner.written.len(), 3);
+        assert_eq!(inner.written[1], "Hello World");
+    }
+
+    #[cfg(feature = "spool")]
+    #[tokio::test]
+    async fn it_asks_for_all_matches_of_a_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("f: Sig.A FOUND\0f: Sig.B FOUND\0");
+
+        let stream = ScannedStream::new(&mut input, &mut inner)
+            .with_scan_mode(ScanMode::LocalFile(Some(dir.path().to_path_buf())))
+            .with_all_match();
+        let (_, outcome) = stream.finish().await;
+        assert_eq!(outcome.unwrap().detections().len(), 2);
+        assert!(inner.written[0].starts_with("zALLMATCHSCAN "));
+    }
+
+    #[tokio::test]
+    async fn it_finishes_the_scan_before_the_input_is_consumed() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut inner = MockStream::new("FOUND test virus");
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello "))));
+
+        let (rest, result) = stream.finish().await;
+        assert_eq!(
+            result.unwrap(),
+            ScanOutcome::Infected("FOUND test virus".into())
+        );
+        assert_eq!(rest.next().await, Some(Ok(Bytes::from("World"))));
+
+        assert_eq!(inner.written.len(), 4);
+        assert_eq!(inner.written.get(2).unwrap(), "Hello ");
+        assert_eq!(inner.written.get(3).unwrap().as_bytes(), [0, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn it_returns_the_verdict_again_when_finished_after_consumption() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut inner = MockStream::new("OK");
+
+        let mut stream = ScannedStream::new(&mut input, &mut inner);
+        while stream.next().await.is_some() {}
+
+        let (_, result) = stream.finish().await;
+        assert_eq!(result.unwrap(), ScanOutcome::Clean);
+    }
+
+    #[tokio::test]
+    async fn it_accepts_framed_inputs_yielding_bytes_mut() {
+        let input = FramedRead::new("Hello World".as_bytes(), BytesCodec::new());
+        let mut inner = MockStream::new("OK");
+
+        let stream = ScannedStream::new(input, &mut inner);
+        assert_eq!(consume(stream).await.unwrap(), "Hello World");
+        assert_eq!(inner.written.get(2).unwrap(), "Hello World");
+    }
+
+    #[tokio::test]
+    async fn it_skips_empty_chunks_instead_of_terminating_the_content() {
+        let mut input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::new()),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let stream = ScannedStream::new(&mut input, &mut transport);
+        assert_eq!(consume(stream).await.unwrap(), "Hello World");
+        assert_eq!(
+            transport.chunks(),
+            vec![Bytes::from("Hello "), Bytes::from("World")]
+        );
+        assert!(transport.is_terminated());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn it_scans_the_chunks_received_from_a_channel() {
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            for chunk in ["Hello", " World"] {
+                tx.send(Bytes::from(chunk)).await.unwrap();
+            }
+        });
+
+        let mut transport = FakeTransport::new("stream: OK\0");
+        let mut stream = ScannedStream::from_channel(rx, &mut transport);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello"))));
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from(" World"))));
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+        assert!(transport.is_terminated());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn it_returns_the_errors_sent_over_a_channel() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tx.send(Ok(Bytes::from("Hello"))).unwrap();
+        tx.send(Err(io::Error::other("producer failed"))).unwrap();
+
+        let mut transport = FakeTransport::new("stream: OK\0");
+        let mut stream = ScannedStream::from_try_channel(rx, &mut transport);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello"))));
+        assert!(matches!(stream.next().await, Some(Err(Error::Stream(_)))));
+    }
+
+    #[tokio::test]
+    async fn it_holds_back_the_lookahead_until_the_clamav_has_seen_it() {
+        let chunks = || {
+            tokio_stream::iter(
+                ["aaaa", "bbbb", "cccc", "dddd"].map(|chunk| Ok::<_, Error>(Bytes::from(chunk))),
+            )
+        };
+
+        let mut input = chunks();
+        let mut transport = FakeTransport::new("stream: OK\0");
+        let mut stream = ScannedStream::new(&mut input, &mut transport).with_lookahead(6);
+        let progress = stream.progress();
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("aaaa"))));
+        assert_eq!(progress.bytes_scanned(), 12);
+        let rest: Vec<_> = stream.collect().await;
+        assert_eq!(
+            rest,
+            vec![
+                Ok(Bytes::from("bbbb")),
+                Ok(Bytes::from("cccc")),
+                Ok(Bytes::from("dddd")),
+            ]
+        );
+
+        // The chunks still held back when the virus is found never reach the consumer.
+        let mut input = chunks();
+        let mut transport = FakeTransport::new("stream: Eicar-Signature FOUND\0");
+        let stream = ScannedStream::new(&mut input, &mut transport).with_lookahead(6);
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(
+            items,
+            vec![
+                Ok(Bytes::from("aaaa")),
+                Ok(Bytes::from("bbbb")),
+                Err(Error::Scan("stream: Eicar-Signature FOUND\0".into())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_never_polls_the_input_again_after_its_end() {
+        /// An input which panics when polled after its end, like `futures::stream::unfold`.
+        struct Once(Option<Bytes>, bool);
+
+        impl Stream for Once {
+            type Item = Result<Bytes, Error>;
+
+            fn poll_next(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+            ) -> Poll<Option<Self::Item>> {
+                assert!(!self.1, "polled after the end");
+                let chunk = self.0.take();
+                self.1 = chunk.is_none();
+                Poll::Ready(chunk.map(Ok))
+            }
+        }
+
+        for (reply, lookahead) in [
+            ("stream: OK\0", None),
+            ("stream: OK\0", Some(6)),
+            ("stream: Eicar-Signature FOUND\0", None),
+            ("stream: Eicar-Signature FOUND\0", Some(6)),
+        ] {
+            let mut transport = FakeTransport::new(reply);
+            let stream =
+                ScannedStream::new(Once(Some(Bytes::from("Hello")), false), &mut transport);
+            let mut stream = match lookahead {
+                Some(bytes) => stream.with_lookahead(bytes),
+                None => stream,
+            };
+            while stream.next().await.is_some() {}
+            assert_eq!(stream.next().await, None);
+            assert_eq!(stream.next().await, None);
+        }
+    }
+
+    #[cfg(feature = "passthrough-check")]
+    #[tokio::test]
+    async fn it_fails_when_the_content_passed_through_differs_from_the_content_scanned() {
+        let mut input =
+            tokio_stream::iter(["aaaa", "bbbb"].map(|chunk| Ok::<_, Error>(Bytes::from(chunk))));
+        let mut transport = FakeTransport::new("stream: OK\0");
+        let mut stream = ScannedStream::new(&mut input, &mut transport).with_lookahead(6);
+        // A lookahead which yields a chunk the clamav has never seen.
+        stream.lookahead.as_mut().unwrap().hold(Bytes::from("zzzz"));
+
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items.len(), 4);
+        assert!(matches!(
+            items.last(),
+            Some(Err(Error::PassthroughMismatch { .. }))
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn it_reads_the_scanned_content_with_an_async_reader() {
+        use tokio::io::AsyncReadExt;
+
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: OK\0");
+        let mut reader = pin!(ScannedStream::new(&mut input, &mut transport).into_async_read());
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "Hello World");
+
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: Eicar-Signature FOUND\0");
+        let mut reader = pin!(ScannedStream::new(&mut input, &mut transport).into_async_read());
+        let err = reader.read_to_end(&mut vec![]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let detected = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<crate::DetectionError>())
+            .unwrap();
+        assert_eq!(detected.detections[0].signature, "Eicar-Signature");
+    }
+
+    #[tokio::test]
+    async fn it_passes_the_input_errors_through_the_hook() {
+        let mut input = tokio_stream::iter(vec![
+            Ok(Bytes::from("Hello ")),
+            Err(io::Error::from(io::ErrorKind::Interrupted)),
+            Ok(Bytes::from("World")),
+            Err(io::Error::other("connection reset")),
+        ]);
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream =
+            ScannedStream::new(&mut input, &mut transport).with_stream_errors(|err: io::Error| {
+                match err.kind() {
+                    io::ErrorKind::Interrupted => StreamErrorAction::Ignore,
+                    _ => StreamErrorAction::Fail(format!("upload aborted: {err}").into()),
+                }
+            });
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello "))));
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("World"))));
+        assert_eq!(
+            stream.next().await,
+            Some(Err(Error::Stream(
+                "upload aborted: connection reset".into()
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_exposes_the_phase_of_the_scan() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream = ScannedStream::new(&mut input, &mut transport);
+        assert_eq!(stream.phase(), ScanPhase::Idle);
+
+        stream.next().await;
+        assert_eq!(stream.phase(), ScanPhase::Streaming { bytes_sent: 11 });
+
+        stream.next().await;
+        assert_eq!(stream.phase(), ScanPhase::Done(Some(ScanOutcome::Clean)));
+    }
+
+    #[tokio::test]
+    async fn it_splits_chunks_longer_than_the_chunk_size() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let stream = ScannedStream::new(&mut input, &mut transport)
+            .with_chunk_size(ChunkSize::new(4).unwrap());
+        assert!(consume(stream).await.is_ok());
+        assert_eq!(
+            transport.chunks(),
+            vec![Bytes::from("Hell"), Bytes::from("o Wo"), Bytes::from("rld")]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_grows_the_chunks_while_the_clamav_keeps_up() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World, Hello Clamav"));
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let adaptive =
+            AdaptiveChunkSize::new(ChunkSize::new(2).unwrap(), ChunkSize::new(8).unwrap())
+                .slow_write(std::time::Duration::from_secs(60));
+        let stream =
+            ScannedStream::new(&mut input, &mut transport).with_adaptive_chunk_size(adaptive);
+        assert!(consume(stream).await.is_ok());
+        assert_eq!(
+            transport.chunks(),
+            vec![
+                Bytes::from("He"),
+                Bytes::from("llo "),
+                Bytes::from("World, H"),
+                Bytes::from("ello Cla"),
+                Bytes::from("mav"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "dedup")]
+    #[tokio::test]
+    async fn it_skips_blocks_already_sent_with_block_dedup() {
+        let run: Vec<u8> = (0u32..256 * 1024)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let content = Bytes::from([&run[..], &run[..], &run[..]].concat());
+        let mut input = tokio_stream::iter(vec![Ok::<_, Error>(content.clone())]);
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let stream =
+            ScannedStream::new(&mut input, &mut transport).with_block_dedup(BlockDedup::default());
+        let progress = stream.progress();
+        let chunks: Vec<Bytes> = stream.collect::<Result<_, _>>().await.unwrap();
+        assert_eq!(chunks.concat(), content);
+
+        let sent: usize = transport.chunks().iter().map(Bytes::len).sum();
+        assert!(progress.deduplicated_bytes() > 0);
+        assert_eq!(
+            sent as u64 + progress.deduplicated_bytes(),
+            content.len() as u64
+        );
+        assert_eq!(progress.bytes_scanned(), content.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn it_yields_to_the_executor_while_sending_a_huge_chunk() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream = ScannedStream::new(&mut input, &mut transport)
+            .with_chunk_size(ChunkSize::new(2).unwrap())
+            .with_frames_per_poll(2);
+        let mut polls = 0;
+        let item = std::future::poll_fn(|cx| {
+            polls += 1;
+            Pin::new(&mut stream).poll_next(cx)
+        })
+        .await;
+        assert_eq!(item, Some(Ok(Bytes::from("Hello World"))));
+        assert_eq!(polls, 3);
+
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+        assert_eq!(transport.chunks().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn it_returns_pending_until_a_non_blocking_connection_replies() {
+        let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+        let mut transport = FakeTransport::new("stream: OK\0").with_slow_reply(2);
+
+        let mut stream = ScannedStream::new(&mut input, &mut transport);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+
+        let mut results = vec![];
+        let item = std::future::poll_fn(|cx| {
+            let polled = Pin::new(&mut stream).poll_next(cx);
+            results.push(polled.is_pending());
+            polled
+        })
+        .await;
+        assert_eq!(item, None);
+        assert_eq!(results, vec![true, true, false]);
+        drop(stream);
+        assert!(transport.is_terminated());
+    }
+
+    #[tokio::test]
+    async fn it_applies_the_drop_behavior_to_a_stream_dropped_mid_content() {
+        const REPLY: &str = "stream: Eicar-Signature FOUND\0";
+        let cases = [
+            (DropBehavior::Abort, false, DropResult::Aborted),
+            (DropBehavior::Finish, true, DropResult::Finished),
+            (
+                DropBehavior::Complete,
+                true,
+                DropResult::Completed(ScanOutcome::Infected(REPLY.into())),
+            ),
+        ];
+        for (behavior, terminated, result) in cases {
+            let mut input = tokio_stream::iter(stream_from_str("Hello World"));
+            let mut transport = FakeTransport::new(REPLY);
+
+            let mut stream =
+                ScannedStream::new(&mut input, &mut transport).with_drop_behavior(behavior);
+            let progress = stream.progress();
+            assert!(stream.next().await.is_some());
+            drop(stream);
+
+            assert_eq!(transport.is_terminated(), terminated);
+            assert_eq!(
+                progress.warnings(),
+                vec![Warning::Dropped { behavior, result }]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn it_rejects_content_after_the_end_in_strict_mode() {
+        let input = Unfused(
+            vec![
+                Some(Ok::<_, Error>(Bytes::from("Hello World"))),
+                None,
+                Some(Ok(Bytes::from("Trailing"))),
+            ]
+            .into(),
+        );
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream = ScannedStream::new(input, &mut transport).with_strict(true);
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+        assert_eq!(stream.next().await, None);
+        assert_eq!(
+            stream.next().await,
+            Some(Err(Error::TrailingData { len: 8 }))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_treats_the_notes_after_a_clean_reply() {
+        let input = || tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let reply = "stream: OK\0heuristics note\0";
+
+        let stream = ScannedStream::new(input(), FakeTransport::new(reply));
+        let progress = stream.progress();
+        assert!(stream.collect::<Result<Vec<_>, _>>().await.is_ok());
+        assert_eq!(
+            progress.warnings(),
+            vec![Warning::ClamdNote {
+                note: "heuristics note".into()
+            }]
+        );
+
+        let stream = ScannedStream::new(input(), FakeTransport::new(reply))
+            .with_trailing_notes(TrailingNotes::Reject);
+        let result: Result<Vec<_>, _> = stream.collect().await;
+        assert!(matches!(result, Err(Error::Clamd { .. })));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn it_scans_the_content_read_from_a_reader() {
+        let (addr, server) = crate::test_util::fake_clamd(b"stream: OK\0");
+        let reader = &b"Hello World"[..];
+
+        let stream = ScannedStream::from_reader_with_capacity(reader, 4, addr).unwrap();
+        let chunks: Vec<_> = stream.collect::<Result<_, _>>().await.unwrap();
+        assert_eq!(chunks, vec!["Hell", "o Wo", "rld"]);
+
+        let received = server.join().unwrap();
+        assert!(received.windows(4).any(|w| w == b"Hell"));
+    }
+
+    #[tokio::test]
+    async fn it_yields_the_same_chunks_once_boxed() {
+        let input = tokio_stream::iter(vec![Ok::<_, Error>(Bytes::from("Hello World"))]);
+        let transport = FakeTransport::new("stream: OK\0");
+
+        let mut stream: BoxScannedStream<'static> =
+            ScannedStream::new(input, transport).into_boxed();
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello World"))));
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[test]
+    fn it_is_send_when_its_input_and_transport_are() {
+        fn assert_send<T: Send>() {}
+        fn stream<St: Send, RW: Read + Write + Send>() {
+            assert_send::<ScannedStream<St, RW>>();
+        }
+        stream::<tokio_stream::Iter<std::vec::IntoIter<Result<Bytes, Error>>>, FakeTransport>();
+        stream::<tokio_util::io::ReaderStream<tokio::fs::File>, crate::Connection>();
+    }
+
+    /// A stream which may yield items after `None`.
+    struct Unfused(std::collections::VecDeque<Option<Result<Bytes, Error>>>);
+
+    impl Stream for Unfused {
+        type Item = Result<Bytes, Error>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.0.pop_front().flatten())
+        }
+    }
+
+    struct MockStream {
+        written: Vec<String>,
+        attempts: usize,
+        output: Cursor<Vec<u8>>,
+        fail_after: Option<usize>,
+    }
+
+    impl MockStream {
+        fn new(value: &str) -> Self {
+            Self {
+                written: vec![],
+                attempts: 0,
+                output: Cursor::new(value.as_bytes().to_vec()),
+                fail_after: None,
+            }
+        }
+
+        fn failing_after(value: &str, writes: usize) -> Self {
+            Self {
+                fail_after: Some(writes),
+                ..Self::new(value)
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.output.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.attempts += 1;
+            if self.fail_after == Some(self.written.len()) {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.written.push(String::from_utf8(buf.to_vec()).unwrap());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stream_from_str(value: &'static str) -> impl Iterator<Item = Result<Bytes, Error>> {
+        [Ok(Bytes::from(value))].into_iter()
+    }
+
+    async fn consume<S>(mut stream: S) -> Result<String, Error>
+    where
+        S: Stream<Item = Result<Bytes, Error>> + Unpin,
+    {
+        let mut bytes: Vec<u8> = vec![];
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            bytes.append(&mut chunk.into());
+        }
+
+        let res = std::str::from_utf8(&bytes)?;
+        Ok(res.to_string())
+    }
+}
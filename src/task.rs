@@ -0,0 +1,63 @@
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+/// The name of the task of a [`RescanQueue`](crate::RescanQueue) scanning an entry.
+pub const RESCAN_TASK: &str = "clamav-stream::rescan";
+
+/// The name of the task of [`scan_dir`](crate::scan_dir) walking the directory.
+pub const SCAN_DIR_TASK: &str = "clamav-stream::scan-dir";
+
+/// The name of the task of a [`DatabaseWatcher`](crate::DatabaseWatcher) polling the clamav.
+pub const DATABASE_WATCHER_TASK: &str = "clamav-stream::database-watcher";
+
+/// The name of the task sending a notification to the webhook of a
+/// [`Scanner`](crate::Scanner).
+pub const WEBHOOK_TASK: &str = "clamav-stream::webhook";
+
+/// The name of the task terminating the content of a stream of a [`Scanner`](crate::Scanner)
+/// or of an [`AsyncScannedStream`](crate::AsyncScannedStream) dropped in the middle of it, and
+/// reading its verdict with [`DropBehavior::Complete`](crate::DropBehavior::Complete).
+pub const DROP_COMPLETION_TASK: &str = "clamav-stream::drop-completion";
+
+/// Spawn a background task of the crate on the tokio runtime.
+///
+/// With the `tokio-console` feature and `RUSTFLAGS="--cfg tokio_unstable"`, the task is named,
+/// so that it can be told apart from the tasks of the application in `tokio-console`.
+#[track_caller]
+pub(crate) fn spawn<F>(name: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    return tokio::task::Builder::new()
+        .name(name)
+        .spawn(future)
+        .expect("failed to spawn a task");
+
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
+
+/// Like [`spawn`], but run the blocking function on the blocking thread pool.
+#[track_caller]
+pub(crate) fn spawn_blocking<F, R>(name: &'static str, function: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    return tokio::task::Builder::new()
+        .name(name)
+        .spawn_blocking(function)
+        .expect("failed to spawn a task");
+
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = name;
+        tokio::task::spawn_blocking(function)
+    }
+}
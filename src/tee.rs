@@ -0,0 +1,302 @@
+use crate::{AsyncScannedStream, Error};
+
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    error::Error as StdError,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_stream::Stream;
+
+/// The default number of chunks a [`Tee`] branch may fall behind the other one.
+pub const DEFAULT_TEE_CAPACITY: usize = 16;
+
+/// The scanned and the raw branches returned by [`tee_scanned`].
+pub type ScannedTee<St> = (AsyncScannedStream<Tee<St>, TcpStream>, Tee<St>);
+
+/// Split the input into two independent streams yielding the same chunks, and scan one of
+/// them. The other one can be consumed at the same time, e.g. to upload the content to storage
+/// while the verdict decides whether to commit it.
+///
+/// Each branch buffers up to [`DEFAULT_TEE_CAPACITY`] chunks the other one has not consumed
+/// yet, so the faster consumer waits for the slower one beyond that.
+pub async fn tee_scanned<St, B, E>(
+    input: St,
+    addr: impl ToSocketAddrs,
+) -> Result<ScannedTee<St>, Error>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    let (scanned, raw) = tee(input, DEFAULT_TEE_CAPACITY);
+    Ok((AsyncScannedStream::tcp(scanned, addr).await?, raw))
+}
+
+/// Split the input into two independent streams yielding the same chunks. Each branch buffers
+/// up to `capacity` chunks the other one has not consumed yet.
+pub fn tee<St, B, E>(input: St, capacity: usize) -> (Tee<St>, Tee<St>)
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    tee_bounded(input, capacity, usize::MAX)
+}
+
+/// Split the input like [`tee`], but also bound the bytes a branch buffers to `max_bytes`, so
+/// that the scanning branch runs at most that far ahead of a slow consumer of the other one,
+/// however large the chunks are. A single chunk larger than `max_bytes` is still buffered.
+pub fn tee_bounded<St, B, E>(input: St, capacity: usize, max_bytes: usize) -> (Tee<St>, Tee<St>)
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        input: Box::pin(input),
+        capacity: capacity.max(1),
+        max_bytes: max_bytes.max(1),
+        done: false,
+        branches: [Branch::default(), Branch::default()],
+    }));
+
+    let left = Tee {
+        shared: Arc::clone(&shared),
+        side: 0,
+    };
+    let right = Tee { shared, side: 1 };
+    (left, right)
+}
+
+/// One of the two branches created by [`tee`] or [`tee_scanned`].
+pub struct Tee<St> {
+    shared: Arc<Mutex<Shared<St>>>,
+    side: usize,
+}
+
+struct Shared<St> {
+    input: Pin<Box<St>>,
+    capacity: usize,
+    max_bytes: usize,
+    done: bool,
+    branches: [Branch; 2],
+}
+
+struct Branch {
+    queue: VecDeque<Result<Bytes, TeeError>>,
+    queued_bytes: usize,
+    waker: Option<Waker>,
+    alive: bool,
+}
+
+impl Default for Branch {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            queued_bytes: 0,
+            waker: None,
+            alive: true,
+        }
+    }
+}
+
+impl Branch {
+    fn push(&mut self, item: Result<Bytes, TeeError>) {
+        if let Ok(chunk) = &item {
+            self.queued_bytes += chunk.len();
+        }
+        self.queue.push_back(item);
+    }
+
+    fn pop(&mut self) -> Option<Result<Bytes, TeeError>> {
+        let item = self.queue.pop_front()?;
+        if let Ok(chunk) = &item {
+            self.queued_bytes -= chunk.len();
+        }
+        Some(item)
+    }
+
+    fn is_full(&self, capacity: usize, max_bytes: usize) -> bool {
+        self.queue.len() >= capacity || self.queued_bytes >= max_bytes
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<St, B, E> Stream for Tee<St>
+where
+    St: Stream<Item = Result<B, E>>,
+    B: Into<Bytes>,
+    E: StdError + Send + Sync + 'static,
+{
+    type Item = Result<Bytes, TeeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        let (me, other) = split(&mut shared.branches, self.side);
+
+        if let Some(item) = me.pop() {
+            other.wake();
+            return Poll::Ready(Some(item));
+        }
+
+        if shared.done {
+            return Poll::Ready(None);
+        }
+
+        if other.alive && other.is_full(shared.capacity, shared.max_bytes) {
+            me.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        match shared.input.as_mut().poll_next(cx) {
+            Poll::Pending => {
+                me.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            Poll::Ready(Some(item)) => {
+                let item = item.map(Into::into).map_err(TeeError::new);
+                if other.alive {
+                    other.push(item.clone());
+                    other.wake();
+                }
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                shared.done = true;
+                other.wake();
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<St> Drop for Tee<St> {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            let (me, other) = split(&mut shared.branches, self.side);
+            me.alive = false;
+            me.queue.clear();
+            me.queued_bytes = 0;
+            other.wake();
+        }
+    }
+}
+
+impl<St> fmt::Debug for Tee<St> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tee")
+            .field("side", &self.side)
+            .finish_non_exhaustive()
+    }
+}
+
+fn split(branches: &mut [Branch; 2], side: usize) -> (&mut Branch, &mut Branch) {
+    let [left, right] = branches;
+    match side {
+        0 => (left, right),
+        _ => (right, left),
+    }
+}
+
+/// An error of the input shared by both branches of a [`Tee`].
+#[derive(Clone)]
+pub struct TeeError(Arc<dyn StdError + Send + Sync>);
+
+impl TeeError {
+    fn new(err: impl StdError + Send + Sync + 'static) -> Self {
+        Self(Arc::new(err))
+    }
+}
+
+impl fmt::Debug for TeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for TeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for TeeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_yields_the_same_chunks_on_both_branches() {
+        let input = tokio_stream::iter(chunks(&["a", "b", "c"]));
+        let (left, right) = tee(input, 1);
+
+        let (left, right) = tokio::join!(collect(left), collect(right));
+        assert_eq!(left, vec!["a", "b", "c"]);
+        assert_eq!(right, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_the_slower_branch_beyond_the_capacity() {
+        let input = tokio_stream::iter(chunks(&["a", "b", "c"]));
+        let (mut left, mut right) = tee(input, 1);
+
+        assert_eq!(left.next().await.unwrap().unwrap(), "a");
+        let blocked = tokio::time::timeout(Duration::from_millis(10), left.next()).await;
+        assert!(blocked.is_err());
+
+        assert_eq!(right.next().await.unwrap().unwrap(), "a");
+        assert_eq!(left.next().await.unwrap().unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_the_slower_branch_beyond_the_byte_limit() {
+        let input = tokio_stream::iter(chunks(&["aaaa", "bbbb", "c"]));
+        let (mut left, mut right) = tee_bounded(input, 16, 6);
+
+        assert_eq!(left.next().await.unwrap().unwrap(), "aaaa");
+        assert_eq!(left.next().await.unwrap().unwrap(), "bbbb");
+        let blocked = tokio::time::timeout(Duration::from_millis(10), left.next()).await;
+        assert!(blocked.is_err());
+
+        assert_eq!(right.next().await.unwrap().unwrap(), "aaaa");
+        assert_eq!(left.next().await.unwrap().unwrap(), "c");
+    }
+
+    #[tokio::test]
+    async fn it_keeps_going_when_a_branch_is_dropped() {
+        let input = tokio_stream::iter(chunks(&["a", "b", "c"]));
+        let (left, right) = tee(input, 1);
+        drop(right);
+
+        assert_eq!(collect(left).await, vec!["a", "b", "c"]);
+    }
+
+    fn chunks(values: &[&'static str]) -> Vec<Result<Bytes, Error>> {
+        values.iter().map(|v| Ok(Bytes::from(*v))).collect()
+    }
+
+    async fn collect<S>(stream: S) -> Vec<Bytes>
+    where
+        S: Stream<Item = Result<Bytes, TeeError>>,
+    {
+        stream.map(|chunk| chunk.unwrap()).collect().await
+    }
+}
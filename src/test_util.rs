@@ -0,0 +1,187 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+};
+use {
+    std::{
+        io::{self, Cursor, IoSlice},
+        ops::Range,
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    },
+    tokio::io::{AsyncRead, AsyncWrite, ReadBuf},
+};
+
+/// The reply to a `VERSION` command received instead of an `INSTREAM` request.
+pub(crate) const VERSION: &[u8] = b"ClamAV 1.0.0/27000/Mon Jan  1 09:00:00 2024\0";
+
+/// Accept a single connection, read an `INSTREAM` request until its terminating chunk, write
+/// the reply and close the connection. Joining the handle returns the bytes received.
+pub(crate) fn fake_clamd(reply: &'static [u8]) -> (SocketAddr, JoinHandle<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let (socket, _) = listener.accept().unwrap();
+        serve(socket, reply)
+    });
+
+    (addr, server)
+}
+
+/// Like [`fake_clamd`], but serve the given number of connections one after another.
+#[cfg(feature = "tokio")]
+pub(crate) fn fake_clamd_many(
+    reply: &'static [u8],
+    connections: usize,
+) -> (SocketAddr, JoinHandle<Vec<Vec<u8>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        (0..connections)
+            .map(|_| serve(listener.accept().unwrap().0, reply))
+            .collect()
+    });
+
+    (addr, server)
+}
+
+fn serve(mut socket: TcpStream, reply: &[u8]) -> Vec<u8> {
+    let mut received = vec![];
+    let mut buf = [0u8; 64];
+    while !received.ends_with(&[0, 0, 0, 0]) {
+        let n = socket.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        received.extend_from_slice(&buf[..n]);
+        if received == b"zVERSION\0" {
+            socket.write_all(VERSION).unwrap();
+            return received;
+        }
+    }
+    socket.write_all(reply).unwrap();
+    received
+}
+
+/// A connection to a clamav which counts how many of the bytes written were written straight
+/// from the memory of the watched buffer, to check that a scan does not copy the content.
+/// Replies `stream: OK` once the terminating chunk has been written.
+#[derive(Debug)]
+pub(crate) struct ZeroCopyProbe {
+    watched: Range<usize>,
+    /// The bytes written from the memory of the watched buffer.
+    pub(crate) borrowed: usize,
+    /// Every byte written.
+    pub(crate) written: usize,
+    tail: Vec<u8>,
+    reply: Cursor<&'static [u8]>,
+    reader: Option<Waker>,
+}
+
+impl ZeroCopyProbe {
+    pub(crate) fn new(watched: &[u8]) -> Self {
+        let start = watched.as_ptr() as usize;
+        Self {
+            watched: start..start + watched.len(),
+            borrowed: 0,
+            written: 0,
+            tail: vec![],
+            reply: Cursor::new(b"stream: OK\0"),
+            reader: None,
+        }
+    }
+
+    fn ended(&self) -> bool {
+        self.tail.ends_with(&[0, 0, 0, 0])
+    }
+
+    fn record(&mut self, bufs: &[IoSlice<'_>]) -> usize {
+        let mut n = 0;
+        for buf in bufs {
+            let start = buf.as_ptr() as usize;
+            if self.watched.contains(&start) && start + buf.len() <= self.watched.end {
+                self.borrowed += buf.len();
+            }
+            self.tail
+                .extend_from_slice(&buf[buf.len().saturating_sub(4)..]);
+            let excess = self.tail.len().saturating_sub(4);
+            self.tail.drain(..excess);
+            n += buf.len();
+        }
+        self.written += n;
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+        n
+    }
+}
+
+impl Read for ZeroCopyProbe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reply.read(buf)
+    }
+}
+
+impl Write for ZeroCopyProbe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(self.record(&[IoSlice::new(buf)]))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        Ok(self.record(bufs))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for ZeroCopyProbe {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.ended() {
+            this.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = this.reply.read(buf.initialize_unfilled())?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ZeroCopyProbe {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write_vectored(bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,268 @@
+//! Utilities to test code built on this crate without a clamav server, available with the
+//! `test-util` feature.
+
+use bytes::Bytes;
+use std::io::{self, Cursor, Read, Write};
+#[cfg(feature = "tokio")]
+use {
+    pin_project::pin_project,
+    std::{
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    tokio_stream::Stream,
+};
+
+/// A stream wrapper which returns [`Poll::Pending`] `n` times before every item of the inner
+/// stream, waking the task each time, to verify that a consumer copes with pending inputs.
+#[cfg(feature = "tokio")]
+#[pin_project]
+#[derive(Debug)]
+pub struct PendingNTimes<St> {
+    #[pin]
+    inner: St,
+    n: usize,
+    remaining: usize,
+    pendings: usize,
+}
+
+#[cfg(feature = "tokio")]
+impl<St> PendingNTimes<St> {
+    /// Return [`Poll::Pending`] `n` times before every item of the stream.
+    pub fn new(inner: St, n: usize) -> Self {
+        Self {
+            inner,
+            n,
+            remaining: n,
+            pendings: 0,
+        }
+    }
+
+    /// The number of times [`Poll::Pending`] has been returned so far.
+    pub fn pendings(&self) -> usize {
+        self.pendings
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<St: Stream> Stream for PendingNTimes<St> {
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.remaining > 0 {
+            *this.remaining -= 1;
+            *this.pendings += 1;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let item = this.inner.poll_next(cx);
+        if let Poll::Ready(Some(_)) = item {
+            *this.remaining = *this.n;
+        }
+        item
+    }
+}
+
+/// An in-memory transport standing in for a connection to the clamav. It records every byte
+/// written, replies with a fixed message, and can accept only a few bytes per write or block
+/// every other write to exercise partial writes.
+#[derive(Debug, Default)]
+pub struct FakeTransport {
+    written: Vec<u8>,
+    write_calls: usize,
+    max_write: Option<usize>,
+    would_block: bool,
+    block_next: bool,
+    blocked: usize,
+    reply_blocks: usize,
+    reply: Cursor<Vec<u8>>,
+}
+
+impl FakeTransport {
+    /// Reply with the given message, e.g. `stream: OK\0`.
+    pub fn new(reply: impl Into<Vec<u8>>) -> Self {
+        Self {
+            reply: Cursor::new(reply.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Accept at most `max_write` bytes per write.
+    pub fn with_max_write(mut self, max_write: usize) -> Self {
+        self.max_write = Some(max_write);
+        self
+    }
+
+    /// Fail every other write with [`io::ErrorKind::WouldBlock`], as a non-blocking socket
+    /// whose send buffer is full.
+    pub fn with_would_block(mut self) -> Self {
+        self.would_block = true;
+        self
+    }
+
+    /// Fail the first `reads` reads of the reply with [`io::ErrorKind::WouldBlock`], as a
+    /// non-blocking socket before the clamav replies.
+    pub fn with_slow_reply(mut self, reads: usize) -> Self {
+        self.reply_blocks = reads;
+        self
+    }
+
+    /// The number of writes failed with [`io::ErrorKind::WouldBlock`] so far.
+    pub fn blocked(&self) -> usize {
+        self.blocked
+    }
+
+    /// Every byte written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// The number of writes so far.
+    pub fn write_calls(&self) -> usize {
+        self.write_calls
+    }
+
+    /// The command written first, without its delimiter, e.g. `zINSTREAM`.
+    pub fn command(&self) -> Option<&[u8]> {
+        let end = self
+            .written
+            .iter()
+            .position(|b| *b == b'\0' || *b == b'\n')?;
+        Some(&self.written[..end])
+    }
+
+    /// The payloads of the `INSTREAM` chunks written after the command, up to the terminating
+    /// zero-length chunk.
+    pub fn chunks(&self) -> Vec<Bytes> {
+        self.frames().0
+    }
+
+    /// Returns `true` if the terminating zero-length chunk has been written.
+    pub fn is_terminated(&self) -> bool {
+        self.frames().1
+    }
+
+    fn frames(&self) -> (Vec<Bytes>, bool) {
+        let mut chunks = vec![];
+        let Some(command) = self.command() else {
+            return (chunks, false);
+        };
+
+        let mut rest = &self.written[command.len() + 1..];
+        while let Some((header, tail)) = rest.split_first_chunk::<4>() {
+            let len = u32::from_be_bytes(*header) as usize;
+            if len == 0 {
+                return (chunks, true);
+            }
+            if tail.len() < len {
+                break;
+            }
+            chunks.push(Bytes::copy_from_slice(&tail[..len]));
+            rest = &tail[len..];
+        }
+        (chunks, false)
+    }
+}
+
+impl Read for FakeTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.reply_blocks > 0 {
+            self.reply_blocks -= 1;
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.reply.read(buf)
+    }
+}
+
+impl Write for FakeTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.would_block {
+            self.block_next = !self.block_next;
+            if self.block_next {
+                self.blocked += 1;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+        }
+
+        let len = match self.max_write {
+            Some(max_write) => buf.len().min(max_write),
+            None => buf.len(),
+        };
+        self.written.extend_from_slice(&buf[..len]);
+        self.write_calls += 1;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::{Error, ScannedStream};
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_wakes_up_and_finishes_with_pending_inputs_and_partial_writes() {
+        let input = PendingNTimes::new(
+            tokio_stream::iter(vec![
+                Ok::<_, Error>(Bytes::from("Hello ")),
+                Ok(Bytes::from("World")),
+            ]),
+            3,
+        );
+        let mut transport = FakeTransport::new("stream: OK\0").with_max_write(3);
+
+        let mut stream = ScannedStream::new(input, &mut transport);
+        let mut content = vec![];
+        // A missing wake-up would hang here.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(chunk) = stream.next().await {
+                content.extend_from_slice(&chunk.unwrap());
+            }
+        })
+        .await
+        .unwrap();
+
+        // A finished stream stays finished.
+        assert_eq!(stream.next().await, None);
+        drop(stream);
+
+        assert_eq!(content, b"Hello World");
+        assert_eq!(transport.command(), Some(&b"zINSTREAM"[..]));
+        assert_eq!(
+            transport.chunks(),
+            vec![Bytes::from("Hello "), Bytes::from("World")]
+        );
+        assert!(transport.is_terminated());
+        assert!(transport.write_calls() > 4);
+    }
+
+    #[tokio::test]
+    async fn it_resumes_writes_which_would_block() {
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut transport = FakeTransport::new("stream: OK\0")
+            .with_max_write(4)
+            .with_would_block();
+
+        let stream = ScannedStream::new(input, &mut transport);
+        let content: Vec<Bytes> = stream.map(Result::unwrap).collect().await;
+
+        assert_eq!(content, vec![Bytes::from("Hello "), Bytes::from("World")]);
+        assert_eq!(
+            transport.chunks(),
+            vec![Bytes::from("Hello "), Bytes::from("World")]
+        );
+        assert!(transport.is_terminated());
+        assert!(transport.blocked() > 0);
+    }
+}
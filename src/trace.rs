@@ -0,0 +1,98 @@
+use std::fmt;
+
+/// A frame of the clamav protocol recorded in a [`ProtocolTrace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// A command sent to the clamav, e.g. `zINSTREAM\0`, as it was written.
+    Command(Vec<u8>),
+
+    /// A chunk of the content. The content itself is redacted, only its length is kept.
+    Chunk {
+        /// The length prefix of the chunk.
+        len: u32,
+    },
+
+    /// The zero-length chunk terminating the content.
+    EndOfStream,
+
+    /// The reply received from the clamav, as it was read.
+    Reply(Vec<u8>),
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Command(command) => write!(f, "> {}", command.escape_ascii()),
+            Self::Chunk { len } => write!(f, "> chunk of {len} bytes"),
+            Self::EndOfStream => write!(f, "> end of stream"),
+            Self::Reply(reply) => write!(f, "< {}", reply.escape_ascii()),
+        }
+    }
+}
+
+/// The frames exchanged with the clamav during a scan, in order, to diagnose interoperability
+/// problems, e.g. with a clamd fork, without capturing packets. Obtained from
+/// [`Progress::protocol_trace`](crate::Progress::protocol_trace).
+///
+/// Displays one frame per line, `>` for the frames sent and `<` for the reply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolTrace {
+    frames: Vec<Frame>,
+}
+
+impl ProtocolTrace {
+    /// The frames recorded so far.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub(crate) fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+}
+
+impl fmt::Display for ProtocolTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for frame in &self.frames {
+            writeln!(f, "{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use crate::{testing::FakeTransport, Error, ScannedStream};
+    use bytes::Bytes;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_records_the_frames_with_the_content_redacted() {
+        let input = tokio_stream::iter(vec![
+            Ok::<_, Error>(Bytes::from("Hello ")),
+            Ok(Bytes::from("World")),
+        ]);
+        let mut transport = FakeTransport::new("stream: OK\0");
+
+        let stream = ScannedStream::new(input, &mut transport);
+        let progress = stream.progress();
+        let _: Vec<_> = stream.collect().await;
+
+        let trace = progress.protocol_trace();
+        assert_eq!(
+            trace.frames(),
+            [
+                Frame::Command(b"zINSTREAM\0".to_vec()),
+                Frame::Chunk { len: 6 },
+                Frame::Chunk { len: 5 },
+                Frame::EndOfStream,
+                Frame::Reply(b"stream: OK\0".to_vec()),
+            ]
+        );
+        assert_eq!(
+            trace.to_string(),
+            "> zINSTREAM\\x00\n> chunk of 6 bytes\n> chunk of 5 bytes\n> end of stream\n< stream: OK\\x00\n"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{
+    protocol::Version,
+    task::{self, DATABASE_WATCHER_TASK},
+    Scanner,
+};
+
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle, time::MissedTickBehavior};
+use tokio_stream::Stream;
+
+type Hook = Arc<dyn Fn(&DatabaseUpdate) + Send + Sync>;
+
+/// A change of the [`Version`] reported by the clamav, such as a signature database reload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseUpdate {
+    /// The version before the update.
+    pub previous: Version,
+    /// The version after the update.
+    pub current: Version,
+}
+
+/// Polls the clamav with the `VERSION` command and emits a [`DatabaseUpdate`] whenever the
+/// reported version changes, e.g. after `freshclam` has loaded new signatures.
+///
+/// The first reply is taken as the baseline. Polls which fail, e.g. while the clamav restarts,
+/// are skipped. Registered hooks are called with every update before it is emitted, so that
+/// e.g. the quarantined contents can be pushed to a [`RescanQueue`](crate::RescanQueue).
+pub struct DatabaseWatcher {
+    scanner: Scanner,
+    interval: Duration,
+    hooks: Vec<Hook>,
+}
+
+impl DatabaseWatcher {
+    /// Create a watcher polling the clamav of the scanner at the given interval.
+    pub fn new(scanner: Scanner, interval: Duration) -> Self {
+        Self {
+            scanner,
+            interval,
+            hooks: vec![],
+        }
+    }
+
+    /// Call the hook with every update.
+    pub fn on_update(mut self, hook: impl Fn(&DatabaseUpdate) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Start polling on the tokio runtime, in a task named [`DATABASE_WATCHER_TASK`]. The
+    /// task is aborted when the returned [`DatabaseUpdates`] is dropped, and ends once the
+    /// scanner has been shut down, releasing its handle.
+    pub fn spawn(self) -> DatabaseUpdates {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let task = task::spawn(DATABASE_WATCHER_TASK, async move {
+            let mut interval = tokio::time::interval(self.interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut previous: Option<Version> = None;
+
+            while !tx.is_closed() {
+                interval.tick().await;
+                if self.scanner.is_shut_down() {
+                    break;
+                }
+
+                let Ok(current) = self.scanner.version() else {
+                    continue;
+                };
+                let Some(last) = previous.replace(current.clone()) else {
+                    continue;
+                };
+                if last == current {
+                    continue;
+                }
+
+                let update = DatabaseUpdate {
+                    previous: last,
+                    current,
+                };
+                for hook in &self.hooks {
+                    hook(&update);
+                }
+                if tx.send(update).is_err() {
+                    break;
+                }
+            }
+        });
+
+        DatabaseUpdates { inner: rx, task }
+    }
+}
+
+impl fmt::Debug for DatabaseWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatabaseWatcher")
+            .field("scanner", &self.scanner)
+            .field("interval", &self.interval)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+/// The stream of the [`DatabaseUpdate`]s emitted by a [`DatabaseWatcher`].
+#[derive(Debug)]
+pub struct DatabaseUpdates {
+    inner: mpsc::UnboundedReceiver<DatabaseUpdate>,
+    task: JoinHandle<()>,
+}
+
+impl DatabaseUpdates {
+    /// Returns `true` while the polling task is running.
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+impl Drop for DatabaseUpdates {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Stream for DatabaseUpdates {
+    type Item = DatabaseUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_stops_polling_once_the_scanner_is_shut_down() {
+        let scanner = Scanner::tcp("127.0.0.1:3310").unwrap();
+        let mut updates = DatabaseWatcher::new(scanner.clone(), Duration::from_millis(5)).spawn();
+
+        scanner.shutdown(Duration::from_millis(10)).await;
+        // The task ends, dropping the sender of the updates.
+        assert_eq!(updates.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn it_emits_an_update_when_the_database_version_changes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scanner = Scanner::tcp(listener.local_addr().unwrap()).unwrap();
+
+        thread::spawn(move || {
+            let replies = [
+                &b"ClamAV 1.0.0/27000/Mon Jan  1 09:00:00 2024\0"[..],
+                b"ClamAV 1.0.0/27000/Mon Jan  1 09:00:00 2024\0",
+                b"ClamAV 1.0.0/27001/Tue Jan  2 09:00:00 2024\0",
+            ];
+            for reply in replies {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut command = [0u8; 9];
+                socket.read_exact(&mut command).unwrap();
+                assert_eq!(&command, b"zVERSION\0");
+                socket.write_all(reply).unwrap();
+            }
+        });
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut updates = DatabaseWatcher::new(scanner, Duration::from_millis(5))
+            .on_update(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .spawn();
+
+        let update = updates.next().await.unwrap();
+        assert_eq!(update.previous.database, Some(27000));
+        assert_eq!(update.current.database, Some(27001));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
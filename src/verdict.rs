@@ -0,0 +1,261 @@
+use std::{
+    io::{self, Read, Write},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The verdict a [`Scanner`](crate::Scanner) built with
+/// [`ScannerBuilder::static_verdict`](crate::ScannerBuilder::static_verdict) gives to every
+/// content without connecting to a clamav, e.g. in development environments without clamd, or
+/// to exercise both branches of an application deterministically in tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaticVerdict {
+    /// Every content is clean.
+    AlwaysClean,
+
+    /// Every content is infected with the signature, e.g. `Win.Test.EICAR_HDB-1`.
+    AlwaysInfected(String),
+}
+
+impl StaticVerdict {
+    fn reply(&self) -> String {
+        match self {
+            Self::AlwaysClean => "stream: OK".into(),
+            Self::AlwaysInfected(signature) => format!("stream: {signature} FOUND"),
+        }
+    }
+}
+
+/// An in-memory clamav which answers the commands it is sent, replying to every `INSTREAM`
+/// with the static verdict.
+#[derive(Debug)]
+pub(crate) struct StaticClamd {
+    verdict: StaticVerdict,
+    state: State,
+    command: Vec<u8>,
+    /// The id of the next reply inside an `IDSESSION`.
+    session: Option<u32>,
+    delimiter: u8,
+    output: Vec<u8>,
+    /// Whether the connection is closed once the output is read, like clamd does after a
+    /// command outside of a session.
+    closed: bool,
+    /// The task waiting for a reply while the connection is open.
+    reader: Option<Waker>,
+}
+
+#[derive(Debug)]
+enum State {
+    Command,
+    Length { prefix: [u8; 4], filled: usize },
+    Chunk { remaining: usize },
+}
+
+impl StaticClamd {
+    pub(crate) fn new(verdict: StaticVerdict) -> Self {
+        Self {
+            verdict,
+            state: State::Command,
+            command: vec![],
+            session: None,
+            delimiter: b'\0',
+            output: vec![],
+            closed: false,
+            reader: None,
+        }
+    }
+
+    fn receive(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            match &mut self.state {
+                State::Command => {
+                    let byte = bytes[0];
+                    bytes = &bytes[1..];
+                    self.command.push(byte);
+                    if byte == delimiter(&self.command) {
+                        self.answer();
+                    }
+                }
+                State::Length { prefix, filled } => {
+                    let len = (prefix.len() - *filled).min(bytes.len());
+                    prefix[*filled..*filled + len].copy_from_slice(&bytes[..len]);
+                    bytes = &bytes[len..];
+                    *filled += len;
+                    if *filled == prefix.len() {
+                        self.state = match u32::from_be_bytes(*prefix) {
+                            0 => {
+                                let reply = self.verdict.reply();
+                                self.reply(&reply);
+                                State::Command
+                            }
+                            len => State::Chunk {
+                                remaining: len as usize,
+                            },
+                        };
+                    }
+                }
+                State::Chunk { remaining } => {
+                    let len = (*remaining).min(bytes.len());
+                    bytes = &bytes[len..];
+                    *remaining -= len;
+                    if *remaining == 0 {
+                        self.state = State::Length {
+                            prefix: [0; 4],
+                            filled: 0,
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    fn answer(&mut self) {
+        let command = std::mem::take(&mut self.command);
+        // The replies are delimited like the command.
+        self.delimiter = delimiter(&command);
+
+        match command.get(1..command.len() - 1).unwrap_or_default() {
+            b"IDSESSION" => self.session = Some(1),
+            b"END" => self.session = None,
+            b"PING" => self.reply("PONG"),
+            b"VERSION" => self.reply("ClamAV static/0/static verdict"),
+            b"INSTREAM" => {
+                self.state = State::Length {
+                    prefix: [0; 4],
+                    filled: 0,
+                }
+            }
+            _ => self.reply("UNKNOWN COMMAND"),
+        }
+    }
+
+    fn reply(&mut self, reply: &str) {
+        match self.session.as_mut() {
+            Some(id) => {
+                self.output.extend_from_slice(format!("{id}: ").as_bytes());
+                *id += 1;
+            }
+            None => self.closed = true,
+        }
+        self.output.extend_from_slice(reply.as_bytes());
+        self.output.push(self.delimiter);
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+}
+
+/// The delimiter of a command, `\0` after a `z` prefix or `\n` after an `n` prefix.
+fn delimiter(command: &[u8]) -> u8 {
+    match command.first() {
+        Some(b'n') => b'\n',
+        _ => b'\0',
+    }
+}
+
+impl Read for StaticClamd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.output.len());
+        buf[..len].copy_from_slice(&self.output[..len]);
+        self.output.drain(..len);
+        Ok(len)
+    }
+}
+
+impl Write for StaticClamd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.receive(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for StaticClamd {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.output.is_empty() && !this.closed {
+            this.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let len = buf.remaining().min(this.output.len());
+        buf.put_slice(&this.output[..len]);
+        this.output.drain(..len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for StaticClamd {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{protocol::CommandFormat, Address, Error, ScanOutcome, Scanner};
+    use bytes::Bytes;
+    use tokio_stream::StreamExt;
+
+    fn input() -> tokio_stream::Iter<std::vec::IntoIter<Result<Bytes, Error>>> {
+        tokio_stream::iter(vec![Ok(Bytes::from("Hello ")), Ok(Bytes::from("World"))])
+    }
+
+    #[tokio::test]
+    async fn it_gives_the_static_verdict_to_every_content() {
+        let clean = Scanner::static_verdict(StaticVerdict::AlwaysClean);
+        assert_eq!(clean.scan_stream(input()).await, Ok(ScanOutcome::Clean));
+        assert!(clean.ping().is_ok());
+
+        let signature = "Win.Test.EICAR_HDB-1".to_string();
+        let infected = Scanner::static_verdict(StaticVerdict::AlwaysInfected(signature));
+        let mut stream = infected.wrap(input()).unwrap();
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("Hello "))));
+        assert_eq!(stream.next().await, Some(Ok(Bytes::from("World"))));
+        assert_eq!(
+            stream.next().await,
+            Some(Err(Error::Scan(
+                "stream: Win.Test.EICAR_HDB-1 FOUND\0".into()
+            )))
+        );
+
+        let stream = infected.wrap_async(input()).await.unwrap();
+        let result: Result<Vec<_>, _> = stream.collect().await;
+        assert!(matches!(result, Err(Error::Scan(_))));
+    }
+
+    #[tokio::test]
+    async fn it_answers_inside_sessions_and_in_every_command_format() {
+        let scanner = Scanner::builder(Address::Tcp(vec![]))
+            .static_verdict(StaticVerdict::AlwaysClean)
+            .command_format(CommandFormat::Newline)
+            .pool(1)
+            .build();
+
+        for _ in 0..2 {
+            let (_, outcome) = scanner.wrap(input()).unwrap().finish().await;
+            assert_eq!(outcome, Ok(ScanOutcome::Clean));
+        }
+        assert_eq!(scanner.idle_connections(), 1);
+    }
+}
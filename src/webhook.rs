@@ -0,0 +1,321 @@
+use crate::{
+    backoff::Backoff,
+    lookup::Sha256Digest,
+    report::ScanReport,
+    response::{Detection, ScanOutcome},
+};
+
+use reqwest::{Client, StatusCode, Url};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Which scans a [`WebhookNotifier`] reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotifyOn {
+    /// Only the scans of infected contents.
+    #[default]
+    Detection,
+
+    /// Every scan, whatever its outcome.
+    Every,
+}
+
+/// An error of a [`WebhookNotifier`], returned once all its attempts have failed.
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    /// The request could not be sent, or no response was received.
+    #[error("webhook request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    /// The endpoint answered with a status other than success.
+    #[error("webhook endpoint answered with {0}")]
+    Status(StatusCode),
+}
+
+/// POSTs a JSON report of a scan to a configured URL, e.g. to alert a SOC on detections.
+///
+/// Failed requests are retried with a [`Backoff`] when the endpoint is unreachable, times out
+/// or answers with a server error or `429 Too Many Requests`. Other client errors are not
+/// retried. Configured with [`ScannerBuilder::webhook`](crate::ScannerBuilder::webhook), the
+/// notifications are sent in the background without delaying the scans.
+///
+/// The body of a request looks like:
+///
+/// ```json
+/// {
+///   "outcome": "infected",
+///   "message": "stream: Win.Test.EICAR_HDB-1 FOUND",
+///   "detections": [{ "signature": "Win.Test.EICAR_HDB-1", "category": "test" }],
+///   "sha256": "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f",
+///   "bytes_scanned": 68,
+///   "truncated": false,
+///   "time_to_verdict_ms": 3
+/// }
+/// ```
+///
+/// The scans of a [`Scanner::for_tenant`](crate::Scanner::for_tenant) handle also have a
+/// `"tenant"`.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    client: Client,
+    url: Url,
+    notify_on: NotifyOn,
+    attempts: u32,
+    backoff: Backoff,
+}
+
+impl WebhookNotifier {
+    /// Create a notifier reporting the detections to the URL, with up to 3 attempts per
+    /// notification and the default [`Backoff`] between them.
+    pub fn new(url: Url) -> Self {
+        Self {
+            client: Client::new(),
+            url,
+            notify_on: NotifyOn::default(),
+            attempts: 3,
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Choose which scans are reported. Defaults to [`NotifyOn::Detection`].
+    pub fn notify_on(mut self, notify_on: NotifyOn) -> Self {
+        self.notify_on = notify_on;
+        self
+    }
+
+    /// Make up to `attempts` requests per notification, waiting by the backoff between them.
+    pub fn retry(mut self, attempts: u32, backoff: Backoff) -> Self {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Send the requests with the given client, e.g. one with a timeout or default headers
+    /// carrying the credentials of the endpoint.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// The URL the reports are POSTed to.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Returns `true` if the scan of the outcome is to be reported.
+    pub fn is_notified(&self, outcome: &ScanOutcome) -> bool {
+        match self.notify_on {
+            NotifyOn::Detection => matches!(outcome, ScanOutcome::Infected(_)),
+            NotifyOn::Every => true,
+        }
+    }
+
+    /// Report the scan, unless it is filtered out by [`NotifyOn`]. Returns whether a report
+    /// was delivered.
+    pub async fn notify(
+        &self,
+        outcome: &ScanOutcome,
+        report: &ScanReport,
+        digest: Option<&Sha256Digest>,
+    ) -> Result<bool, WebhookError> {
+        if !self.is_notified(outcome) {
+            return Ok(false);
+        }
+
+        self.send(&Payload::new(outcome, report, digest)).await?;
+        Ok(true)
+    }
+
+    pub(crate) async fn send(&self, payload: &Payload) -> Result<(), WebhookError> {
+        let mut failures = 0;
+        loop {
+            let err = match self
+                .client
+                .post(self.url.clone())
+                .json(payload)
+                .send()
+                .await
+            {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                        return Err(WebhookError::Status(status));
+                    }
+                    WebhookError::Status(status)
+                }
+                Err(err) => WebhookError::Request(err),
+            };
+
+            failures += 1;
+            if failures >= self.attempts {
+                return Err(err);
+            }
+            tokio::time::sleep(self.backoff.delay(failures)).await;
+        }
+    }
+}
+
+/// The JSON body of a webhook request.
+#[derive(Debug, Serialize)]
+pub(crate) struct Payload {
+    outcome: &'static str,
+    message: Option<String>,
+    detections: Vec<DetectionPayload>,
+    sha256: Option<String>,
+    bytes_scanned: u64,
+    truncated: bool,
+    time_to_verdict_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DetectionPayload {
+    signature: String,
+    category: String,
+}
+
+impl Payload {
+    pub(crate) fn new(
+        outcome: &ScanOutcome,
+        report: &ScanReport,
+        digest: Option<&Sha256Digest>,
+    ) -> Self {
+        let (kind, message) = match outcome {
+            ScanOutcome::Clean => ("clean", None),
+            ScanOutcome::Infected(message) => ("infected", Some(message.clone())),
+            ScanOutcome::Skipped => ("skipped", None),
+        };
+
+        Self {
+            outcome: kind,
+            message,
+            detections: outcome.detections().into_iter().map(Into::into).collect(),
+            sha256: digest.map(|digest| digest.iter().map(|b| format!("{b:02x}")).collect()),
+            bytes_scanned: report.bytes_scanned,
+            truncated: report.is_truncated(),
+            time_to_verdict_ms: report.time_to_verdict.as_ref().map(Duration::as_millis),
+            tenant: report.tenant.clone(),
+        }
+    }
+}
+
+impl From<Detection> for DetectionPayload {
+    fn from(detection: Detection) -> Self {
+        Self {
+            category: format!("{:?}", detection.category()).to_lowercase(),
+            signature: detection.signature,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Answer the requests with the statuses in order, returning the bodies received.
+    async fn endpoint(statuses: &'static [u16]) -> (Url, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut bodies = vec![];
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![];
+                let mut buf = [0u8; 1024];
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let len: usize = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(str::to_owned)
+                        })
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= len {
+                        break body.to_string();
+                    }
+                };
+                bodies.push(body);
+                let response = format!(
+                    "HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            bodies
+        });
+
+        (url.parse().unwrap(), server)
+    }
+
+    fn report() -> ScanReport {
+        ScanReport {
+            bytes_scanned: 68,
+            expected_len: None,
+            warnings: vec![],
+            reputation: None,
+            signatures: vec![],
+            time_to_verdict: Some(Duration::from_millis(3)),
+            tenant: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_retries_the_report_of_a_detection() {
+        let (url, server) = endpoint(&[503, 200]).await;
+        let notifier = WebhookNotifier::new(url).retry(
+            3,
+            Backoff::new(Duration::from_millis(1), Duration::from_millis(1)),
+        );
+
+        let outcome = ScanOutcome::Infected("stream: Win.Test.EICAR_HDB-1 FOUND\0".into());
+        let delivered = notifier
+            .notify(&outcome, &report(), Some(&[0xab; 32]))
+            .await;
+        assert!(delivered.unwrap());
+
+        let bodies = server.await.unwrap();
+        assert_eq!(bodies.len(), 2);
+        let body: serde_json::Value = serde_json::from_str(&bodies[1]).unwrap();
+        assert_eq!(body["outcome"], "infected");
+        assert_eq!(body["detections"][0]["signature"], "Win.Test.EICAR_HDB-1");
+        assert_eq!(body["detections"][0]["category"], "test");
+        assert_eq!(body["sha256"], "ab".repeat(32));
+        assert_eq!(body["bytes_scanned"], 68);
+    }
+
+    #[tokio::test]
+    async fn it_gives_up_on_client_errors() {
+        let (url, server) = endpoint(&[400]).await;
+        let notifier = WebhookNotifier::new(url).notify_on(NotifyOn::Every);
+
+        let result = notifier.notify(&ScanOutcome::Clean, &report(), None).await;
+        assert!(matches!(
+            result,
+            Err(WebhookError::Status(StatusCode::BAD_REQUEST))
+        ));
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_skips_clean_scans_by_default() {
+        let notifier = WebhookNotifier::new("http://127.0.0.1:1/alerts".parse().unwrap());
+
+        let result = notifier.notify(&ScanOutcome::Clean, &report(), None).await;
+        assert!(!result.unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{connection::Connection, scan::Scan, Error, Scanner};
+
+use pin_project::pin_project;
+use std::{
+    error::Error as StdError,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio_stream::Stream;
+use tungstenite::Message;
+
+/// How the binary messages of a [`ScannedMessages`] are sent to the clamav.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessagePolicy {
+    /// Scan each binary message on its own connection and yield it only once it is clean.
+    #[default]
+    PerMessage,
+    /// Scan the binary messages as a single content, passing them through as they arrive. The
+    /// verdict is returned after the input ends.
+    Concatenated,
+}
+
+/// A wrapper of a `tokio-tungstenite` message stream which scans the binary message payloads.
+///
+/// Text and control messages are passed through untouched.
+#[pin_project]
+pub struct ScannedMessages<St> {
+    #[pin]
+    input: St,
+    scanner: Scanner,
+    policy: MessagePolicy,
+    scan: Option<Scan<Connection>>,
+}
+
+impl<St> ScannedMessages<St> {
+    /// Create a new [`ScannedMessages`] opening its connections with the scanner.
+    pub fn new(input: St, scanner: Scanner, policy: MessagePolicy) -> Self {
+        Self {
+            input,
+            scanner,
+            policy,
+            scan: None,
+        }
+    }
+}
+
+impl<St, E> Stream for ScannedMessages<St>
+where
+    St: Stream<Item = Result<Message, E>>,
+    E: StdError + Send + Sync + 'static,
+{
+    type Item = Result<Message, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.project();
+        match me.input.poll_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(Ok(Message::Binary(payload)))) => {
+                let result = match me.policy {
+                    MessagePolicy::PerMessage => me.scanner.scan().and_then(|mut scan| {
+                        scan.send(&payload)?;
+                        scan.finish().unwrap_or(Ok(()))
+                    }),
+                    MessagePolicy::Concatenated => match me.scan {
+                        Some(scan) => scan.send(&payload),
+                        None => me
+                            .scanner
+                            .scan()
+                            .and_then(|scan| me.scan.insert(scan).send(&payload)),
+                    },
+                };
+                Poll::Ready(Some(result.map(|_| Message::Binary(payload))))
+            }
+            Poll::Ready(Some(Ok(message))) => Poll::Ready(Some(Ok(message))),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(Error::Stream(Box::new(err))))),
+            Poll::Ready(None) => match me.scan.as_mut().and_then(Scan::finish) {
+                Some(Err(err)) => Poll::Ready(Some(Err(err))),
+                Some(Ok(())) | None => Poll::Ready(None),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_clamd;
+    use std::io;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn it_scans_each_binary_message_before_yielding_it() {
+        let (addr, server) = fake_clamd(b"stream: Eicar-Signature FOUND\0");
+        let scanner = Scanner::tcp(addr).unwrap();
+        let input = messages(vec![Message::text("hi"), Message::binary("bad")]);
+
+        let mut stream = ScannedMessages::new(input, scanner, MessagePolicy::PerMessage);
+        assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("hi"));
+        assert_eq!(
+            stream.next().await.unwrap().unwrap_err(),
+            Error::Scan("stream: Eicar-Signature FOUND\0".into())
+        );
+        assert!(stream.next().await.is_none());
+
+        assert!(server.join().unwrap().ends_with(b"bad\0\0\0\0"));
+    }
+
+    #[tokio::test]
+    async fn it_scans_the_binary_messages_as_a_single_content() {
+        let (addr, server) = fake_clamd(b"stream: OK\0");
+        let scanner = Scanner::tcp(addr).unwrap();
+        let input = messages(vec![
+            Message::binary("Hello "),
+            Message::Ping("ping".into()),
+            Message::binary("World"),
+        ]);
+
+        let stream = ScannedMessages::new(input, scanner, MessagePolicy::Concatenated);
+        let output: Vec<Message> = stream.map(|message| message.unwrap()).collect().await;
+        assert_eq!(
+            output,
+            vec![
+                Message::binary("Hello "),
+                Message::Ping("ping".into()),
+                Message::binary("World"),
+            ]
+        );
+
+        let received = server.join().unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0"));
+        assert!(received.ends_with(b"World\0\0\0\0"));
+    }
+
+    fn messages(values: Vec<Message>) -> impl Stream<Item = Result<Message, io::Error>> {
+        tokio_stream::iter(values.into_iter().map(Ok))
+    }
+}
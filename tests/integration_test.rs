@@ -44,7 +44,7 @@ async fn read_file(path: &str) -> ReaderStream<File> {
 
 fn scanned_stream(
     input: &mut ReaderStream<File>,
-) -> ScannedStream<'_, ReaderStream<File>, TcpStream> {
+) -> ScannedStream<&mut ReaderStream<File>, TcpStream> {
     let err_msg = format!("Could not connect tcp address {}", HOST_ADDRESS);
     ScannedStream::<_, TcpStream>::tcp(input, HOST_ADDRESS).expect(&err_msg)
 }
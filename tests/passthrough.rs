@@ -0,0 +1,76 @@
+//! The content passed through a `ScannedStream` and the content sent to the clamav are both
+//! byte-identical to its input, whatever its chunking and however the transport accepts the
+//! writes. Run with the `passthrough-check` feature to also have the stream compare them.
+
+use bytes::Bytes;
+use clamav_stream::{
+    protocol::decode_chunks,
+    testing::{FakeTransport, PendingNTimes},
+    Error, ScannedStream,
+};
+use proptest::prelude::*;
+use tokio_stream::StreamExt;
+
+fn chunks() -> impl Strategy<Value = Vec<Vec<u8>>> {
+    prop::collection::vec(prop::collection::vec(any::<u8>(), 0..4096), 0..16)
+}
+
+/// Returns the content passed through to the consumer, and the content sent to the clamav.
+fn pass_through(
+    chunks: Vec<Vec<u8>>,
+    mut transport: FakeTransport,
+    pendings: usize,
+) -> (Vec<u8>, Option<Vec<u8>>) {
+    let input = tokio_stream::iter(
+        chunks
+            .into_iter()
+            .map(|chunk| Ok::<_, Error>(Bytes::from(chunk))),
+    );
+    let stream = ScannedStream::new(PendingNTimes::new(input, pendings), &mut transport);
+
+    let output = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let mut stream = std::pin::pin!(stream);
+            let mut output = vec![];
+            while let Some(chunk) = stream.next().await {
+                output.extend_from_slice(&chunk.unwrap());
+            }
+            output
+        });
+
+    let command = transport.command().map_or(0, |command| command.len() + 1);
+    (output, decode_chunks(&transport.written()[command..]))
+}
+
+proptest! {
+    #[test]
+    fn it_passes_the_content_through_unaltered(chunks in chunks(), pendings in 0..3usize) {
+        let expected = chunks.concat();
+        let (output, sent) = pass_through(chunks, FakeTransport::new("stream: OK\0"), pendings);
+        prop_assert_eq!(sent, Some(expected.clone()));
+        prop_assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn it_passes_the_content_through_unaltered_over_partial_writes(
+        chunks in chunks(),
+        max_write in 1..64usize,
+    ) {
+        let expected = chunks.concat();
+        let transport = FakeTransport::new("stream: OK\0").with_max_write(max_write);
+        let (output, sent) = pass_through(chunks, transport, 0);
+        prop_assert_eq!(sent, Some(expected.clone()));
+        prop_assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn it_passes_the_content_through_unaltered_over_blocked_writes(chunks in chunks()) {
+        let expected = chunks.concat();
+        let transport = FakeTransport::new("stream: OK\0").with_would_block();
+        let (output, sent) = pass_through(chunks, transport, 1);
+        prop_assert_eq!(sent, Some(expected.clone()));
+        prop_assert_eq!(output, expected);
+    }
+}